@@ -1,28 +1,283 @@
+use crate::lexer::Span;
+
 #[derive(Debug, Clone)]
 pub enum Expr {
     Number(i64),
+    Bool(bool),
+    Char(char),
+    Str(String),
     Ident(String),
+    Null,
     BinaryOp(Box<Expr>, String, Box<Expr>),
-}
-
-#[derive(Debug, Clone)]
-pub enum Stmt {
-    Let(String, Expr),
-    Assign(String, Expr),
-    Expr(Expr),
+    Call(String, Vec<Expr>),
+    // `[1, 2, 3]` и `[0; 100]` — массив фиксированной длины
+    Array(Vec<Expr>),
+    ArrayRepeat(Box<Expr>, usize),
+    // span нужен для сообщения о выходе за границы
+    Index {
+        array: Box<Expr>,
+        index: Box<Expr>,
+        span: Span,
+    },
+    // `Point { x: 1, y: 2 }` и `p.x`
+    StructLit(String, Vec<(String, Expr)>),
+    Field(Box<Expr>, String),
+    // `&x` и `*p`; span у `*p` — для сообщения о разыменовании null/освобождённой памяти
+    AddrOf(Box<Expr>),
+    Deref(Box<Expr>, Span),
+    // `|x, y| x + y` или `|x| -> bool { ... }`; без аннотаций — i64, как у fn
+    Lambda {
+        params: Vec<(String, Type)>,
+        ret: Type,
+        body: Vec<Stmt>,
+    },
+    // вызов значения-функции: `make_adder(1)(2)`, `(f)(x)`; `f(x)` по имени — это Call
+    Apply(Box<Expr>, Vec<Expr>),
+    // значение `if` и блока — последнее выражение внутри, если оно без `;`; иначе и без else — 0
     If {
-        cond: Expr,
+        cond: Box<Expr>,
         then_body: Vec<Stmt>,
         else_body: Option<Vec<Stmt>>,
     },
+    Block(Vec<Stmt>),
+    // `match x { 0 => ..., 1 | 2 => ..., 3..=9 => ..., _ => ... }`; span — для ошибки «нет ветки»
+    Match {
+        scrutinee: Box<Expr>,
+        arms: Vec<MatchArm>,
+        span: Span,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct MatchArm {
+    // альтернативы через `|`, у каждой свой span — для предупреждений sema
+    pub patterns: Vec<(Pattern, Span)>,
+    pub body: Expr,
+}
+
+/// Образец в `match` по целым (char — по коду символа).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Int(i64),
+    // всегда включительно: `3..9` парсер превращает в `3..=8`
+    Range(i64, i64),
+    Wildcard,
+}
+
+impl Pattern {
+    /// Покрываемый отрезок [lo, hi]; для пустого диапазона lo > hi.
+    pub fn bounds(&self) -> (i64, i64) {
+        match self {
+            Pattern::Int(n) => (*n, *n),
+            Pattern::Range(lo, hi) => (*lo, *hi),
+            Pattern::Wildcard => (i64::MIN, i64::MAX),
+        }
+    }
+
+    pub fn matches(&self, n: i64) -> bool {
+        let (lo, hi) = self.bounds();
+        lo <= n && n <= hi
+    }
+}
+
+/// Тип в объявлениях: поля структур, параметры функций (по умолчанию `i64`) и `let x: T`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Int,
+    Bool,
+    Char,
+    Str,
+    Array(Box<Type>, usize),
+    // `[T]` — массив любой длины; параметр получает копию переданного массива
+    Slice(Box<Type>),
+    Struct(String),
+    // `*T`
+    Ptr(Box<Type>),
+    // `fn(i64, bool) -> i64` — функция или замыкание
+    Fn(Vec<Type>, Box<Type>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum StmtKind {
+    // тип необязателен: `let p: *Node = null;`
+    Let(String, Option<Type>, Expr),
+    // цель — Ident, Index, Field или Deref (`x = ...`, `a[i] = ...`, `p.x = ...`, `*p = ...`)
+    Assign(Expr, Expr),
+    // выражение без `;` — значение блока, если оно последнее
+    Expr(Expr),
+    // `expr;` — только ради эффекта: блок, который им кончается, значения не имеет (`{ 42; }` — не 42)
+    Semi(Expr),
     While {
         cond: Expr, 
         body: Vec<Stmt>,
     },
+    // `for i in start..end step k { }`; границы вычисляются один раз, шаг — ненулевая константа,
+    // переменная видна только в теле
+    For {
+        var: String,
+        start: Expr,
+        end: Expr,
+        inclusive: bool,
+        step: i64,
+        body: Vec<Stmt>,
+    },
 }
 
 #[derive(Debug, Clone)]
 pub struct Function {
     pub name: String,
+    pub doc: Option<String>,
+    pub params: Vec<(String, Type)>,
+    // `-> T`, по умолчанию i64
+    pub ret: Type,
     pub body: Vec<Stmt>,
+    // от `fn` до `}`; у лямбды — оператор, где она создана
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct StructDef {
+    pub name: String,
+    pub doc: Option<String>,
+    pub fields: Vec<(String, Type)>,
+    pub span: Span,
+}
+
+/// `import "math.src";` — модуль доступен как `math::f`; `use math::abs;` — ещё и как `abs`.
+#[derive(Debug, Clone)]
+pub enum Import {
+    File { path: String, span: Span },
+    Use { module: String, item: String, span: Span },
+}
+
+/// Тип в сигнатуре `extern fn` — так, как его видит C. `i32` и `void` бывают только здесь:
+/// в языке это i64 и 0.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExternType {
+    Void,
+    I32,
+    Value(Type),
+}
+
+impl ExternType {
+    pub fn lang(&self) -> Type {
+        match self {
+            ExternType::Void | ExternType::I32 => Type::Int,
+            ExternType::Value(t) => t.clone(),
+        }
+    }
+}
+
+/// `extern fn puts(s: str) -> i32;` — функция из libc (Codegen) или хоста (Interpreter).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExternFn {
+    pub name: String,
+    pub params: Vec<(String, ExternType)>,
+    // без `-> T` — void
+    pub ret: ExternType,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub imports: Vec<Import>,
+    pub externs: Vec<ExternFn>,
+    pub structs: Vec<StructDef>,
+    pub functions: Vec<Function>,
+}
+
+/// Имена, на которые ссылается тело лямбды (переменные и вызовы по имени) —
+/// кандидаты на захват. Какие из них локальные переменные, решает бэкенд.
+pub fn referenced_names(body: &[Stmt]) -> Vec<String> {
+    let mut out = Vec::new();
+    for s in body {
+        names_in_stmt(s, true, &mut out);
+    }
+    out
+}
+
+/// Имена, которые тело берёт как значение, а не только вызывает: `f` и `g(f)`, но не `f(1)`.
+/// Вложенные лямбды считаются целиком — захват копирует значение. Замыкание, которое
+/// только вызывают, не покидает функцию (Codegen кладёт его окружение на стек).
+pub fn value_names(body: &[Stmt]) -> Vec<String> {
+    let mut out = Vec::new();
+    for s in body {
+        names_in_stmt(s, false, &mut out);
+    }
+    out
+}
+
+fn names_in_stmt(stmt: &Stmt, calls: bool, out: &mut Vec<String>) {
+    match &stmt.kind {
+        StmtKind::Let(_, _, e) | StmtKind::Expr(e) | StmtKind::Semi(e) => names_in_expr(e, calls, out),
+        StmtKind::Assign(target, e) => {
+            names_in_expr(target, calls, out);
+            names_in_expr(e, calls, out);
+        }
+        StmtKind::While { cond, body } => {
+            names_in_expr(cond, calls, out);
+            body.iter().for_each(|s| names_in_stmt(s, calls, out));
+        }
+        StmtKind::For { start, end, body, .. } => {
+            names_in_expr(start, calls, out);
+            names_in_expr(end, calls, out);
+            body.iter().for_each(|s| names_in_stmt(s, calls, out));
+        }
+    }
+}
+
+fn names_in_expr(expr: &Expr, calls: bool, out: &mut Vec<String>) {
+    let mut add = |name: &String| {
+        if !out.contains(name) {
+            out.push(name.clone());
+        }
+    };
+    match expr {
+        Expr::Number(_) | Expr::Bool(_) | Expr::Char(_) | Expr::Str(_) | Expr::Null => {}
+        Expr::Ident(name) => add(name),
+        Expr::Call(name, args) => {
+            if calls {
+                add(name);
+            }
+            args.iter().for_each(|a| names_in_expr(a, calls, out));
+        }
+        Expr::BinaryOp(l, _, r) => {
+            names_in_expr(l, calls, out);
+            names_in_expr(r, calls, out);
+        }
+        Expr::Array(elems) => elems.iter().for_each(|e| names_in_expr(e, calls, out)),
+        Expr::ArrayRepeat(e, _) | Expr::Field(e, _) | Expr::AddrOf(e) | Expr::Deref(e, _) => names_in_expr(e, calls, out),
+        Expr::Index { array, index, .. } => {
+            names_in_expr(array, calls, out);
+            names_in_expr(index, calls, out);
+        }
+        Expr::StructLit(_, fields) => fields.iter().for_each(|(_, e)| names_in_expr(e, calls, out)),
+        // вложенная лямбда захватывает через нас всё, что ей нужно (кроме своих параметров)
+        Expr::Lambda { params, body, .. } => {
+            for name in referenced_names(body) {
+                if !params.iter().any(|(p, _)| *p == name) && !out.contains(&name) {
+                    out.push(name);
+                }
+            }
+        }
+        Expr::Apply(callee, args) => {
+            names_in_expr(callee, calls, out);
+            args.iter().for_each(|a| names_in_expr(a, calls, out));
+        }
+        Expr::If { cond, then_body, else_body } => {
+            names_in_expr(cond, calls, out);
+            then_body.iter().chain(else_body.iter().flatten()).for_each(|s| names_in_stmt(s, calls, out));
+        }
+        Expr::Block(body) => body.iter().for_each(|s| names_in_stmt(s, calls, out)),
+        Expr::Match { scrutinee, arms, .. } => {
+            names_in_expr(scrutinee, calls, out);
+            arms.iter().for_each(|arm| names_in_expr(&arm.body, calls, out));
+        }
+    }
 }
@@ -1,46 +1,375 @@
 use std::collections::HashMap;
+use std::ffi::{c_char, CStr};
+use std::path::Path;
+use std::process::Command;
 
+use inkwell::attributes::{Attribute, AttributeLoc};
+use inkwell::basic_block::BasicBlock;
 use inkwell::builder::Builder;
 use inkwell::context::Context;
-use inkwell::module::Module;
-use inkwell::types::IntType;
-use inkwell::values::{FunctionValue, IntValue, PointerValue};
-use inkwell::IntPredicate;
+use inkwell::module::{Linkage, Module};
+use inkwell::targets::{CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine};
+use inkwell::types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum, FunctionType, IntType, StructType};
+use inkwell::values::{BasicMetadataValueEnum, BasicValue, BasicValueEnum, FunctionValue, IntValue, PointerValue};
+use inkwell::{AddressSpace, IntPredicate, OptimizationLevel};
 
-use crate::ast::{Expr, Stmt, Function};
+use crate::debug_info::DebugInfo;
+use crate::ast::{referenced_names, value_names, Expr, ExternFn, ExternType, MatchArm, Pattern, Stmt, StmtKind, Function, Program, Type};
+use crate::host::HostFunction;
+use crate::lexer::Span;
+use crate::modules::SourceMap;
+
+// Локальные переменные: alloca и тип из AST. Тип нужен, потому что указатели в LLVM
+// непрозрачные — без него не понять, что лежит за `*p`.
+type Locals<'ctx> = HashMap<String, (PointerValue<'ctx>, Type)>;
 
 pub struct Codegen<'ctx> {
     context: &'ctx Context,
     module: Module<'ctx>,
     builder: Builder<'ctx>,
+    // исходники всех модулей — чтобы зашить место в сообщения об ошибках времени выполнения
+    sources: &'ctx SourceMap,
+    // именованные LLVM-структуры и их поля (индекс поля = позиция в векторе)
+    structs: HashMap<String, (StructType<'ctx>, Vec<(String, Type)>)>,
+    // сигнатуры пользовательских функций: типы параметров и результата
+    fn_types: HashMap<String, (Vec<Type>, Type)>,
+    // счётчик для имён lambda.N
+    lambdas: usize,
+    // extern fn: вызов идёт через C-сигнатуру (i32, void), а не через fn_types
+    externs: HashMap<String, ExternFn>,
+    // адреса функций хоста для JIT: имя -> `extern "C" fn`
+    host_natives: HashMap<String, usize>,
+    // DWARF, если включён `-g`
+    debug: Option<DebugInfo<'ctx>>,
+    // имена, которые тело текущей функции берёт как значение (ast::value_names): замыкание
+    // `let f = |..| ..` с другим именем только вызывают, и его окружение живёт на стеке
+    escaping: Vec<String>,
+    // компилируемый оператор — место ошибок без своего span (деление на ноль), как в интерпретаторе
+    span: Span,
+}
+
+// После JIT сбрасываем буферы stdio: printf из скомпилированного кода пишет через libc.
+extern "C" {
+    fn fflush(stream: *mut std::ffi::c_void) -> i32;
 }
 
 impl<'ctx> Codegen<'ctx> {
-    pub fn new(context: &'ctx Context, name: &str) -> Self {
+    pub fn new(context: &'ctx Context, name: &str, sources: &'ctx SourceMap) -> Self {
         let module = context.create_module(name);
         let builder = context.create_builder();
-        Self { context, module, builder }
+        Self {
+            context,
+            module,
+            builder,
+            sources,
+            structs: HashMap::new(),
+            fn_types: HashMap::new(),
+            lambdas: 0,
+            externs: HashMap::new(),
+            host_natives: HashMap::new(),
+            debug: None,
+            escaping: Vec::new(),
+            span: 0..0,
+        }
+    }
+
+    /// Отладочная информация (DWARF) для gdb/lldb; включать до compile_program.
+    pub fn enable_debug_info(&mut self) {
+        self.debug = Some(DebugInfo::new(&self.module, self.context, self.sources));
+    }
+
+    /// Сначала объявляем структуры и все функции (чтобы работали вызовы «вперёд»), потом тела.
+    pub fn compile_program(&mut self, program: &Program) {
+        // сперва пустые именованные типы, чтобы поля могли ссылаться на любые структуры
+        for def in &program.structs {
+            let st = self.context.opaque_struct_type(&def.name);
+            self.structs.insert(def.name.clone(), (st, def.fields.clone()));
+        }
+        for def in &program.structs {
+            let fields: Vec<BasicTypeEnum> = def.fields.iter().map(|(_, t)| self.lower_type(t)).collect();
+            self.structs[&def.name].0.set_body(&fields, false);
+        }
+        for ext in &program.externs {
+            self.declare_extern(ext);
+            let params = ext.params.iter().map(|(_, t)| t.lang()).collect();
+            self.fn_types.insert(ext.name.clone(), (params, ext.ret.lang()));
+            self.externs.insert(ext.name.clone(), ext.clone());
+        }
+        for func in &program.functions {
+            let params = func.params.iter().map(|(_, t)| t.clone()).collect();
+            self.fn_types.insert(func.name.clone(), (params, func.ret.clone()));
+            self.declare_function(func);
+        }
+        for func in &program.functions {
+            self.compile_function(func);
+        }
+        if let Some(d) = &self.debug {
+            d.finalize();
+        }
+    }
+
+    // Числа — i64, как Value::Int в интерпретаторе; main возвращает i32 (код выхода).
+    fn declare_function(&mut self, func: &Function) -> FunctionValue<'ctx> {
+        if let Some(f) = self.module.get_function(&func.name) {
+            return f;
+        }
+        let params: Vec<BasicMetadataTypeEnum> =
+            func.params.iter().map(|(_, t)| self.lower_type(t).into()).collect();
+        let fn_ty = if func.name == "main" {
+            self.context.i32_type().fn_type(&params, false)
+        } else {
+            self.lower_type(&func.ret).fn_type(&params, false)
+        };
+        self.module.add_function(&func.name, fn_ty, None)
+    }
+
+    // Внешнее объявление: символ найдёт линковщик (AOT) или JIT среди символов процесса (libc).
+    fn declare_extern(&self, ext: &ExternFn) -> FunctionValue<'ctx> {
+        if let Some(f) = self.module.get_function(&ext.name) {
+            return f;
+        }
+        let params: Vec<BasicMetadataTypeEnum> = ext
+            .params
+            .iter()
+            .map(|(p, t)| {
+                self.lower_extern(t)
+                    .unwrap_or_else(|| panic!("extern fn {}: parameter {} cannot be void", ext.name, p))
+                    .into()
+            })
+            .collect();
+        let fn_ty = match self.lower_extern(&ext.ret) {
+            Some(t) => t.fn_type(&params, false),
+            None => self.context.void_type().fn_type(&params, false),
+        };
+        self.module.add_function(&ext.name, fn_ty, Some(Linkage::External))
+    }
+
+    fn lower_extern(&self, ty: &ExternType) -> Option<BasicTypeEnum<'ctx>> {
+        match ty {
+            ExternType::Void => None,
+            ExternType::I32 => Some(self.context.i32_type().into()),
+            ExternType::Value(t) => Some(self.lower_type(t)),
+        }
     }
 
     pub fn compile_function(&mut self, func: &Function) {
-        let i32_t = self.context.i32_type();
-        let fn_ty = i32_t.fn_type(&[], false);
-        let f = self.module.add_function(&func.name, fn_ty, None);
+        let int_t = self.context.i64_type();
+        let f = self.declare_function(func);
         let entry = self.context.append_basic_block(f, "entry");
         self.builder.position_at_end(entry);
+        // у функции нет своего спана — место берём у первого оператора
+        let span = func.body.first().map(|s| &s.span);
+        if let Some(d) = &mut self.debug {
+            let loc = d.enter_function(self.context, f, &func.name, span);
+            self.builder.set_current_debug_location(loc);
+        }
 
-        let mut locals: HashMap<String, PointerValue<'ctx>> = HashMap::new();
-        let mut last_value: Option<IntValue<'ctx>> = None;
+        let mut locals: Locals<'ctx> = HashMap::new();
+        let mut last_value: Option<BasicValueEnum<'ctx>> = None;
+        self.escaping = value_names(&func.body);
+
+        for (i, (name, ty)) in func.params.iter().enumerate() {
+            let arg = f.get_nth_param(i as u32).expect("param");
+            let ptr = self.entry_alloca(arg.get_type(), name);
+            self.builder.build_store(ptr, arg).expect("store failed");
+            self.debug_variable(name, ty, Some(i as u32 + 1), ptr, span);
+            locals.insert(name.clone(), (ptr, ty.clone()));
+        }
 
         for stmt in &func.body {
-            last_value = self.compile_stmt(f, stmt, &i32_t, &mut locals);
+            last_value = self.compile_stmt(f, stmt, &int_t, &mut locals);
         }
 
-        if let Some(v) = last_value {
-            let _ = self.builder.build_return(Some(&v));
-        } else {
-            let zero = i32_t.const_int(0, false);
-            let _ = self.builder.build_return(Some(&zero));
+        self.build_return(f, last_value);
+        if let Some(d) = &mut self.debug {
+            d.leave_function();
+        }
+    }
+
+    // Описание слота переменной для отладчика (только с -g).
+    fn debug_variable(&self, name: &str, ty: &Type, arg: Option<u32>, ptr: PointerValue<'ctx>, span: Option<&Span>) {
+        if let Some(d) = &self.debug {
+            let block = self.builder.get_insert_block().expect("no insert block");
+            d.declare_variable(self.context, name, ty, arg, ptr, span, block);
+        }
+    }
+
+    // Значение последнего выражения -> результат функции (для main — обрезанный до i32).
+    fn build_return(&self, f: FunctionValue<'ctx>, last_value: Option<BasicValueEnum<'ctx>>) {
+        let ret_t = f.get_type().get_return_type().expect("non-void");
+        let ret = self.coerce(last_value, ret_t);
+        let _ = self.builder.build_return(Some(&ret));
+    }
+
+    // Приводит значение блока к ожидаемому типу: целые расширяем/обрезаем; блок без
+    // значения (кончается `while` или `;`) — ноль, как Value::Int(0) в интерпретаторе.
+    // Другое несовпадение — ошибка, которую должна была поймать sema: молча подставлять ноль нельзя.
+    fn coerce(&self, v: Option<BasicValueEnum<'ctx>>, ty: BasicTypeEnum<'ctx>) -> BasicValueEnum<'ctx> {
+        let int_t = self.context.i64_type();
+        match (v, ty) {
+            (Some(v @ BasicValueEnum::IntValue(_)), BasicTypeEnum::IntType(t)) => {
+                let v = self.int_operand(v, &int_t);
+                self.builder.build_int_truncate_or_bit_cast(v, t, "coerce").expect("trunc").into()
+            }
+            (Some(v), t) if v.get_type() == t => v,
+            (None, t) => t.const_zero(),
+            (Some(v), t) => panic!("cannot use a value of type {} as {}", v.get_type(), t),
+        }
+    }
+
+    fn lower_type(&self, ty: &Type) -> BasicTypeEnum<'ctx> {
+        match ty {
+            Type::Int => self.context.i64_type().into(),
+            Type::Bool => self.context.bool_type().into(),
+            Type::Char => self.context.i32_type().into(),
+            Type::Str | Type::Ptr(_) => self.context.ptr_type(AddressSpace::default()).into(),
+            Type::Array(elem, n) => self.lower_type(elem).array_type(*n as u32).into(),
+            Type::Slice(_) => self.slice_type().into(),
+            Type::Struct(name) => match self.structs.get(name) {
+                Some((st, _)) => (*st).into(),
+                None => panic!("unknown struct {}", name),
+            },
+            Type::Fn(..) => self.closure_type().into(),
+        }
+    }
+
+    // Замыкание — пара { функция, окружение }. Функция первым аргументом получает окружение.
+    fn closure_type(&self) -> StructType<'ctx> {
+        let ptr_t = self.context.ptr_type(AddressSpace::default());
+        self.context.struct_type(&[ptr_t.into(), ptr_t.into()], false)
+    }
+
+    // `[T]` — { ptr данные, i64 длина }
+    fn slice_type(&self) -> StructType<'ctx> {
+        let ptr_t = self.context.ptr_type(AddressSpace::default());
+        self.context.struct_type(&[ptr_t.into(), self.context.i64_type().into()], false)
+    }
+
+    fn closure_fn_type(&self, params: &[Type], ret: &Type) -> FunctionType<'ctx> {
+        let mut args: Vec<BasicMetadataTypeEnum> = vec![self.context.ptr_type(AddressSpace::default()).into()];
+        args.extend(params.iter().map(|t| BasicMetadataTypeEnum::from(self.lower_type(t))));
+        self.lower_type(ret).fn_type(&args, false)
+    }
+
+    fn struct_fields(&self, name: &str) -> (StructType<'ctx>, Vec<(String, Type)>) {
+        self.structs.get(name).cloned().unwrap_or_else(|| panic!("unknown struct {}", name))
+    }
+
+    // Статический тип выражения: по нему выбираем загрузку, печать и сравнение.
+    fn type_of(&self, expr: &Expr, locals: &Locals<'ctx>) -> Type {
+        match expr {
+            Expr::Number(_) => Type::Int,
+            Expr::Bool(_) => Type::Bool,
+            Expr::Char(_) => Type::Char,
+            Expr::Str(_) => Type::Str,
+            // без аннотации у `let` про null ничего не известно — считаем *i64
+            Expr::Null => Type::Ptr(Box::new(Type::Int)),
+            Expr::Ident(name) => match locals.get(name) {
+                Some((_, ty)) => ty.clone(),
+                None => {
+                    let (params, ret) = self.fn_types.get(name).expect("use of undefined variable").clone();
+                    Type::Fn(params, Box::new(ret))
+                }
+            },
+            Expr::BinaryOp(_, op, _) => match op.as_str() {
+                "==" | "!=" | "<" | "<=" | ">" | ">=" => Type::Bool,
+                _ => Type::Int,
+            },
+            Expr::Call(name, args) => match (name.as_str(), args.as_slice()) {
+                ("alloc", [v]) => Type::Ptr(Box::new(self.type_of(v, locals))),
+                _ => match (locals.get(name), self.fn_types.get(name)) {
+                    (Some((_, Type::Fn(_, ret))), _) => (**ret).clone(),
+                    (None, Some((_, ret))) => ret.clone(),
+                    _ => Type::Int,
+                },
+            },
+            Expr::Apply(callee, _) => match self.type_of(callee, locals) {
+                Type::Fn(_, ret) => *ret,
+                other => panic!("cannot call {:?}", other),
+            },
+            Expr::Lambda { params, ret, .. } => {
+                Type::Fn(params.iter().map(|(_, t)| t.clone()).collect(), Box::new(ret.clone()))
+            }
+            // тип if — по ветке then
+            Expr::If { then_body: body, .. } | Expr::Block(body) => self.block_type(body, locals),
+            // тип match — по первой ветке
+            Expr::Match { arms, .. } => arms.first().map_or(Type::Int, |arm| self.type_of(&arm.body, locals)),
+            Expr::Array(elems) => {
+                let elem = elems.first().map_or(Type::Int, |e| self.type_of(e, locals));
+                Type::Array(Box::new(elem), elems.len())
+            }
+            Expr::ArrayRepeat(elem, n) => Type::Array(Box::new(self.type_of(elem, locals)), *n),
+            Expr::Index { array, .. } => match self.type_of(array, locals) {
+                Type::Array(elem, _) | Type::Slice(elem) => *elem,
+                other => panic!("cannot index into {:?}", other),
+            },
+            Expr::StructLit(name, _) => Type::Struct(name.clone()),
+            Expr::Field(base, field) => {
+                let name = self.struct_of(&self.type_of(base, locals), field);
+                let (_, fields) = self.struct_fields(&name);
+                fields
+                    .into_iter()
+                    .find(|(f, _)| f == field)
+                    .unwrap_or_else(|| panic!("struct {} has no field {}", name, field))
+                    .1
+            }
+            Expr::AddrOf(e) => Type::Ptr(Box::new(self.type_of(e, locals))),
+            Expr::Deref(e, _) => match self.type_of(e, locals) {
+                Type::Ptr(inner) => *inner,
+                other => panic!("cannot dereference {:?}", other),
+            },
+        }
+    }
+
+    // Тип значения блока: тип последнего выражения с учётом `let` внутри блока.
+    fn block_type(&self, body: &[Stmt], locals: &Locals<'ctx>) -> Type {
+        let mut scope = locals.clone();
+        let dummy = self.context.ptr_type(AddressSpace::default()).const_null();
+        for s in body {
+            if let StmtKind::Let(name, ty, e) = &s.kind {
+                let ty = ty.clone().unwrap_or_else(|| self.type_of(e, &scope));
+                scope.insert(name.clone(), (dummy, ty));
+            }
+        }
+        match body.last().map(|s| &s.kind) {
+            Some(StmtKind::Expr(e)) => self.type_of(e, &scope),
+            _ => Type::Int,
+        }
+    }
+
+    // `p.x` работает и для структуры, и для указателя на неё (автоматическое разыменование).
+    fn struct_of(&self, ty: &Type, field: &str) -> String {
+        match ty {
+            Type::Struct(n) => n.clone(),
+            Type::Ptr(inner) => self.struct_of(inner, field),
+            other => panic!("cannot access field {} of {:?}", field, other),
+        }
+    }
+
+    // alloca всегда в entry-блоке: не растёт стек в циклах, и mem2reg их подхватит.
+    fn entry_alloca(&self, ty: BasicTypeEnum<'ctx>, name: &str) -> PointerValue<'ctx> {
+        let func = self.current_function();
+        let entry = func.get_first_basic_block().expect("function has no entry");
+        let tmp = self.context.create_builder();
+        match entry.get_first_instruction() {
+            Some(inst) => tmp.position_before(&inst),
+            None => tmp.position_at_end(entry),
+        }
+        tmp.build_alloca(ty, name).expect("alloca failed")
+    }
+
+    fn current_function(&self) -> FunctionValue<'ctx> {
+        self.builder
+            .get_insert_block()
+            .and_then(|bb| bb.get_parent())
+            .expect("no current function")
+    }
+
+    // Ветка в target, если текущий блок ещё не закрыт (например, после вложенного if).
+    fn branch_if_open(&self, target: BasicBlock<'ctx>) {
+        let bb = self.builder.get_insert_block().expect("no insert block");
+        if bb.get_terminator().is_none() {
+            self.builder.build_unconditional_branch(target).expect("br");
         }
     }
 
@@ -48,91 +377,132 @@ impl<'ctx> Codegen<'ctx> {
         &mut self,
         func: FunctionValue<'ctx>,
         stmt: &Stmt,
-        i32_t: &IntType<'ctx>,
-        locals: &mut HashMap<String, PointerValue<'ctx>>,
-    ) -> Option<IntValue<'ctx>> {
-        match stmt {
-            Stmt::Let(name, expr) => {
-                let val = self.compile_expr(expr, i32_t, locals);
-                let ptr = self.builder.build_alloca(*i32_t, name).expect("alloca failed");
+        int_t: &IntType<'ctx>,
+        locals: &mut Locals<'ctx>,
+    ) -> Option<BasicValueEnum<'ctx>> {
+        self.span = stmt.span.clone();
+        // строка оператора — для всех его инструкций (пошаговое выполнение в gdb)
+        if let Some(loc) = self.debug.as_ref().and_then(|d| d.location(self.context, &stmt.span)) {
+            self.builder.set_current_debug_location(loc);
+        }
+        match &stmt.kind {
+            StmtKind::Let(name, ty, expr) => {
+                let ty = ty.clone().unwrap_or_else(|| self.type_of(expr, locals));
+                let val = match expr {
+                    Expr::Lambda { params, ret, body } if !self.escaping.contains(name) => {
+                        self.compile_lambda(params, ret, body, locals, true)
+                    }
+                    _ => self.compile_arg(expr, &ty, int_t, locals),
+                };
+                let ptr = self.entry_alloca(self.lower_type(&ty), name);
                 self.builder.build_store(ptr, val).expect("store failed");
-                locals.insert(name.clone(), ptr);
+                self.debug_variable(name, &ty, None, ptr, Some(&stmt.span));
+                locals.insert(name.clone(), (ptr, ty));
                 None
             }
-            Stmt::Assign(name, expr) => {
-                let val = self.compile_expr(expr, i32_t, locals);
-                let ptr = *locals.get(name).expect("assign to undefined var");
+            StmtKind::Assign(target, expr) => {
+                // значение под тип места, как у `let x: T`; вычисляется до индексов места
+                let ty = self.type_of(target, locals);
+                let val = self.compile_arg(expr, &ty, int_t, locals);
+                let (ptr, _) = self.compile_lvalue(target, int_t, locals);
                 self.builder.build_store(ptr, val).expect("store failed");
                 None
             }
-            Stmt::Expr(expr) => {
-                let val = self.compile_expr(expr, i32_t, locals);
+            StmtKind::Expr(expr) => {
+                let val = self.compile_expr(expr, int_t, locals);
                 Some(val)
             }
-            Stmt::If { cond, then_body, else_body } => {
-                let cond = self.compile_expr(cond, i32_t, locals);
-                let cond_val = self.to_bool(cond, i32_t);
+            StmtKind::Semi(expr) => {
+                self.compile_expr(expr, int_t, locals);
+                None
+            }
+            StmtKind::While { cond, body } => {
+                let cond_bb = self.context.append_basic_block(func, "loop.cond");
+                let body_bb = self.context.append_basic_block(func, "loop.body");
+                let end_bb  = self.context.append_basic_block(func, "loop.end");
 
+                self.builder.build_unconditional_branch(cond_bb).expect("br -> cond");
 
-                let then_bb = self.context.append_basic_block(func, "then");
-                let else_bb = self.context.append_basic_block(func, "else");
-                let cont_bb = self.context.append_basic_block(func, "ifend");
+                // cond
+                self.builder.position_at_end(cond_bb);
+                let cond = self.compile_expr(cond, int_t, locals);
+                let cond_val = self.to_bool(cond.into_int_value());
 
                 self.builder
-                    .build_conditional_branch(cond_val, then_bb, else_bb)
-                    .expect("brcond failed");
-
-                // then
-                self.builder.position_at_end(then_bb);
-                for s in then_body {
-                    self.compile_stmt(func, s, i32_t, locals);
-                }
-                if then_bb.get_terminator().is_none() {
-                    self.builder.build_unconditional_branch(cont_bb).expect("br then->cont");
-                }
+                    .build_conditional_branch(cond_val, body_bb, end_bb)
+                    .expect("brcond while");
 
-                // else
-                self.builder.position_at_end(else_bb);
-                if let Some(eb) = else_body {
-                    for s in eb {
-                        self.compile_stmt(func, s, i32_t, locals);
-                    }
-                }
-                if else_bb.get_terminator().is_none() {
-                    self.builder.build_unconditional_branch(cont_bb).expect("br else->cont");
+                // body
+                self.builder.position_at_end(body_bb);
+                for s in body {
+                    self.compile_stmt(func, s, int_t, locals);
                 }
+                self.branch_if_open(cond_bb);
 
-                // continuation
-                self.builder.position_at_end(cont_bb);
+                // end
+                self.builder.position_at_end(end_bb);
 
                 None
             }
-            Stmt::While { cond, body } => {
-                let cond_bb = self.context.append_basic_block(func, "loop.cond");
-                let body_bb = self.context.append_basic_block(func, "loop.body");
-                let end_bb  = self.context.append_basic_block(func, "loop.end");
+            StmtKind::For { var, start, end, inclusive, step, body } => {
+                let start_v = self.compile_expr(start, int_t, locals);
+                let start_v = self.int_operand(start_v, int_t);
+                let end_v = self.compile_expr(end, int_t, locals);
+                let end_v = self.int_operand(end_v, int_t);
+                let pre_bb = self.builder.get_insert_block().expect("no insert block");
 
+                let cond_bb = self.context.append_basic_block(func, "for.cond");
+                let body_bb = self.context.append_basic_block(func, "for.body");
+                let latch_bb = self.context.append_basic_block(func, "for.latch");
+                let end_bb = self.context.append_basic_block(func, "for.end");
                 self.builder.build_unconditional_branch(cond_bb).expect("br -> cond");
 
-                // cond
+                // cond: индуктивная переменная — phi, её узнают loop-проходы LLVM
                 self.builder.position_at_end(cond_bb);
-                let cond = self.compile_expr(cond, i32_t, locals);
-                let cond_val = self.to_bool(cond, i32_t);
-
+                let phi = self.builder.build_phi(*int_t, var).expect("phi");
+                let i = phi.as_basic_value().into_int_value();
+                let pred = match (*step > 0, *inclusive) {
+                    (true, false) => IntPredicate::SLT,
+                    (true, true) => IntPredicate::SLE,
+                    (false, false) => IntPredicate::SGT,
+                    (false, true) => IntPredicate::SGE,
+                };
+                let cond_val = self.builder.build_int_compare(pred, i, end_v, "for.cmp").expect("cmp");
                 self.builder
                     .build_conditional_branch(cond_val, body_bb, end_bb)
-                    .expect("brcond while");
+                    .expect("brcond for");
 
-                // body
+                // body: тело видит копию i в alloca (mem2reg её уберёт); присваивание
+                // в i внутри тела не влияет на следующую итерацию
                 self.builder.position_at_end(body_bb);
+                let slot = self.entry_alloca((*int_t).into(), var);
+                self.builder.build_store(slot, i).expect("store failed");
+                self.debug_variable(var, &Type::Int, None, slot, Some(&stmt.span));
+                let shadowed = locals.insert(var.clone(), (slot, Type::Int));
                 for s in body {
-                    self.compile_stmt(func, s, i32_t, locals);
+                    self.compile_stmt(func, s, int_t, locals);
                 }
-                if body_bb.get_terminator().is_none() {
-                    self.builder.build_unconditional_branch(cond_bb).expect("br body->cond");
+                self.branch_if_open(latch_bb);
+                locals.remove(var);
+                if let Some(outer) = shadowed {
+                    locals.insert(var.clone(), outer);
                 }
 
-                // end
+                // latch: если i + step переполнится, следующей итерации нет (как checked_add
+                // в интерпретаторе), поэтому и add без nsw
+                self.builder.position_at_end(latch_bb);
+                let step_v = int_t.const_int(*step as u64, true);
+                let (pred, limit) = if *step > 0 {
+                    (IntPredicate::SLE, i64::MAX - step)
+                } else {
+                    (IntPredicate::SGE, i64::MIN - step)
+                };
+                let limit = int_t.const_int(limit as u64, true);
+                let more = self.builder.build_int_compare(pred, i, limit, "for.more").expect("cmp");
+                let next = self.builder.build_int_add(i, step_v, "for.next").expect("add");
+                self.builder.build_conditional_branch(more, cond_bb, end_bb).expect("brcond latch");
+                phi.add_incoming(&[(&start_v, pre_bb), (&next, latch_bb)]);
+
                 self.builder.position_at_end(end_bb);
 
                 None
@@ -143,27 +513,134 @@ impl<'ctx> Codegen<'ctx> {
     fn compile_expr(
         &mut self,
         expr: &Expr,
-        i32_t: &IntType<'ctx>,
-        locals: &mut HashMap<String, PointerValue<'ctx>>,
-    ) -> IntValue<'ctx> {
+        int_t: &IntType<'ctx>,
+        locals: &mut Locals<'ctx>,
+    ) -> BasicValueEnum<'ctx> {
         match expr {
-            Expr::Number(n) => i32_t.const_int(*n as u64, true),
+            Expr::Number(n) => int_t.const_int(*n as u64, true).into(),
+            Expr::Bool(b) => self.context.bool_type().const_int(*b as u64, false).into(),
+            // char — i32 (код символа), чтобы отличать его от i64-чисел при печати
+            Expr::Char(c) => self.context.i32_type().const_int(*c as u64, false).into(),
+            // строковый литерал — глобальная константа, значение — указатель на неё
+            Expr::Str(s) => self.global_str(s),
+            Expr::Null => self.context.ptr_type(AddressSpace::default()).const_null().into(),
+            Expr::Ident(name) if !locals.contains_key(name) && self.fn_types.contains_key(name) => {
+                self.function_closure(name)
+            }
             Expr::Ident(name) => {
-                let ptr = *locals.get(name).expect("use of undefined variable");
-                let loaded = self
-                    .builder
-                    .build_load(*i32_t, ptr, &format!("load_{}", name))
-                    .expect("load failed");
-                loaded.into_int_value()
+                let (ptr, ty) = locals.get(name).expect("use of undefined variable").clone();
+                self.builder
+                    .build_load(self.lower_type(&ty), ptr, &format!("load_{}", name))
+                    .expect("load failed")
+            }
+            Expr::Index { .. } | Expr::Field(..) | Expr::Deref(..) => {
+                let (ptr, ty) = self.compile_lvalue(expr, int_t, locals);
+                self.builder.build_load(self.lower_type(&ty), ptr, "elem").expect("load failed")
+            }
+            Expr::AddrOf(target) => self.compile_lvalue(target, int_t, locals).0.into(),
+            Expr::Lambda { params, ret, body } => self.compile_lambda(params, ret, body, locals, false),
+            // ветки сходятся в ifend, значение — phi по тем, что дошли до конца
+            Expr::If { cond, then_body, else_body } => {
+                let ty = self.lower_type(&self.type_of(expr, locals));
+                let func = self.current_function();
+                let cond = self.compile_expr(cond, int_t, locals);
+                let cond_val = self.to_bool(cond.into_int_value());
+
+                let then_bb = self.context.append_basic_block(func, "then");
+                let else_bb = self.context.append_basic_block(func, "else");
+                let cont_bb = self.context.append_basic_block(func, "ifend");
+                self.builder
+                    .build_conditional_branch(cond_val, then_bb, else_bb)
+                    .expect("brcond failed");
+
+                let mut incoming = Vec::new();
+                for (bb, body) in [(then_bb, Some(then_body)), (else_bb, else_body.as_ref())] {
+                    self.builder.position_at_end(bb);
+                    let v = match body {
+                        Some(b) => self.compile_block(b, int_t, locals),
+                        None => None,
+                    };
+                    let end = self.builder.get_insert_block().expect("no insert block");
+                    if end.get_terminator().is_none() {
+                        incoming.push((self.coerce(v, ty), end));
+                        self.builder.build_unconditional_branch(cont_bb).expect("br");
+                    }
+                }
+
+                self.builder.position_at_end(cont_bb);
+                self.build_merge(ty, &incoming, "ifval")
+            }
+            Expr::Match { scrutinee, arms, span } => {
+                let ty = self.lower_type(&self.type_of(expr, locals));
+                self.compile_match(scrutinee, arms, span, ty, int_t, locals)
+            }
+            Expr::Block(body) => {
+                let ty = self.lower_type(&self.type_of(expr, locals));
+                let v = self.compile_block(body, int_t, locals);
+                self.coerce(v, ty)
+            }
+            Expr::Apply(callee, args) => {
+                let ty = self.type_of(callee, locals);
+                let closure = self.compile_expr(callee, int_t, locals);
+                self.call_closure(closure, &ty, args, int_t, locals)
+            }
+            Expr::StructLit(name, inits) => {
+                let (st, fields) = self.struct_fields(name);
+                let mut agg = st.get_undef();
+                for (i, (field, fty)) in fields.iter().enumerate() {
+                    let init = inits
+                        .iter()
+                        .find(|(f, _)| f == field)
+                        .unwrap_or_else(|| panic!("missing field {} in {} literal", field, name));
+                    // поле `[T]` из массива — как аргумент-срез
+                    let v = self.compile_arg(&init.1, fty, int_t, locals);
+                    agg = self
+                        .builder
+                        .build_insert_value(agg, v, i as u32, field)
+                        .expect("insertvalue")
+                        .into_struct_value();
+                }
+                agg.into()
+            }
+            Expr::Array(elems) => {
+                let mut vals = Vec::new();
+                for e in elems {
+                    vals.push(self.compile_expr(e, int_t, locals));
+                }
+                let elem_ty = vals.first().map_or(BasicTypeEnum::from(*int_t), |v| v.get_type());
+                self.build_array(elem_ty, &vals)
+            }
+            Expr::ArrayRepeat(elem, n) => {
+                let v = self.compile_expr(elem, int_t, locals);
+                let array_ty = v.get_type().array_type(*n as u32);
+                // [0; N] — просто zeroinitializer, без N insertvalue
+                match v {
+                    BasicValueEnum::IntValue(i) if i.get_zero_extended_constant() == Some(0) => {
+                        array_ty.const_zero().into()
+                    }
+                    _ => self.build_array(v.get_type(), &vec![v; *n]),
+                }
             }
             Expr::BinaryOp(l, op, r) => {
-                let a = self.compile_expr(l, i32_t, locals);
-                let b = self.compile_expr(r, i32_t, locals);
-                match op.as_str() {
+                let is_str = self.type_of(l, locals) == Type::Str && self.type_of(r, locals) == Type::Str;
+                let a = self.compile_expr(l, int_t, locals);
+                let b = self.compile_expr(r, int_t, locals);
+                let (a, b) = match (a, b) {
+                    (BasicValueEnum::PointerValue(x), BasicValueEnum::PointerValue(y)) if is_str => {
+                        return self.compile_str_cmp(op, x, y).into();
+                    }
+                    // указатели сравниваем по адресу
+                    (BasicValueEnum::PointerValue(x), BasicValueEnum::PointerValue(y)) => (
+                        self.builder.build_ptr_to_int(x, *int_t, "pa").expect("ptrtoint"),
+                        self.builder.build_ptr_to_int(y, *int_t, "pb").expect("ptrtoint"),
+                    ),
+                    (a, b) => (self.int_operand(a, int_t), self.int_operand(b, int_t)),
+                };
+                let v = match op.as_str() {
                     "+" => self.builder.build_int_add(a, b, "add").expect("add"),
                     "-" => self.builder.build_int_sub(a, b, "sub").expect("sub"),
                     "*" => self.builder.build_int_mul(a, b, "mul").expect("mul"),
-                    "/" => self.builder.build_int_signed_div(a, b, "div").expect("div"),
+                    "/" => self.checked_div(a, b),
 
                     "==" => self.builder.build_int_compare(IntPredicate::EQ,  a, b, "cmpeq").expect("cmp"),
                     "!=" => self.builder.build_int_compare(IntPredicate::NE,  a, b, "cmpne").expect("cmp"),
@@ -173,17 +650,871 @@ impl<'ctx> Codegen<'ctx> {
                     ">=" => self.builder.build_int_compare(IntPredicate::SGE, a, b, "cmpge").expect("cmp"),
 
                     other => panic!("unsupported op {}", other),
+                };
+                v.into()
+            }
+            Expr::Call(name, args) => {
+                if let Some(v) = self.compile_builtin(name, args, int_t, locals) {
+                    return v;
+                }
+                // локальная переменная-замыкание перекрывает функцию с тем же именем
+                if let Some((ptr, ty @ Type::Fn(..))) = locals.get(name).cloned() {
+                    let closure = self.builder.build_load(self.lower_type(&ty), ptr, name).expect("load failed");
+                    return self.call_closure(closure, &ty, args, int_t, locals);
+                }
+                if let Some(ext) = self.externs.get(name).cloned() {
+                    return self.call_extern(&ext, args, int_t, locals);
+                }
+                let callee = self
+                    .module
+                    .get_function(name)
+                    .unwrap_or_else(|| panic!("call to undefined function {}", name));
+                let (params, _) = self.fn_types.get(name).cloned().expect("signature of declared function");
+                let mut argv: Vec<BasicMetadataValueEnum> = Vec::new();
+                for (a, param_t) in args.iter().zip(&params) {
+                    argv.push(self.compile_arg(a, param_t, int_t, locals).into());
+                }
+                self.builder
+                    .build_call(callee, &argv, "call")
+                    .expect("call")
+                    .try_as_basic_value()
+                    .left()
+                    .expect("function returns a value")
+            }
+        }
+    }
+
+    // Замыкание по значению: захваченные переменные копируются в окружение в куче
+    // (оно может пережить кадр), тело компилируется в отдельную функцию lambda.N.
+    fn compile_lambda(
+        &mut self,
+        params: &[(String, Type)],
+        ret: &Type,
+        body: &[Stmt],
+        locals: &mut Locals<'ctx>,
+        on_stack: bool,
+    ) -> BasicValueEnum<'ctx> {
+        let int_t = self.context.i64_type();
+        let ptr_t = self.context.ptr_type(AddressSpace::default());
+        let captures: Vec<(String, PointerValue<'ctx>, Type)> = referenced_names(body)
+            .into_iter()
+            .filter(|name| !params.iter().any(|(p, _)| p == name))
+            .filter_map(|name| locals.get(&name).map(|(ptr, ty)| (name, *ptr, ty.clone())))
+            .collect();
+        let field_types: Vec<BasicTypeEnum> = captures.iter().map(|(_, _, t)| self.lower_type(t)).collect();
+        let env_t = self.context.struct_type(&field_types, false);
+
+        // Окружение замыкания, которое не покидает функцию, — на стеке. Остальные живут в куче
+        // до конца программы: владельца у значения fn нет, и освободить его некому.
+        let env = if captures.is_empty() {
+            ptr_t.const_null()
+        } else if on_stack {
+            let env = self.entry_alloca(env_t.into(), "env");
+            self.store_captures(env_t, env, &captures);
+            env
+        } else {
+            let size = env_t.size_of().expect("sized env");
+            let malloc = self.libc_function("malloc", ptr_t.fn_type(&[int_t.into()], false));
+            let env = self
+                .builder
+                .build_call(malloc, &[size.into()], "env")
+                .expect("call malloc")
+                .try_as_basic_value()
+                .left()
+                .expect("malloc returns ptr")
+                .into_pointer_value();
+            self.store_captures(env_t, env, &captures);
+            env
+        };
+
+        let param_types: Vec<Type> = params.iter().map(|(_, t)| t.clone()).collect();
+        let name = format!("lambda.{}", self.lambdas);
+        let f = self.module.add_function(&name, self.closure_fn_type(&param_types, ret), None);
+        self.lambdas += 1;
+
+        let saved = self.builder.get_insert_block().expect("no insert block");
+        // без -g места нет: inkwell отдаёт пустой узел, и вернуть его нельзя — верификатор не примет
+        let saved_loc = self.debug.as_ref().and(self.builder.get_current_debug_location());
+        let saved_escaping = std::mem::replace(&mut self.escaping, value_names(body));
+        let saved_span = self.span.clone();
+        let entry = self.context.append_basic_block(f, "entry");
+        self.builder.position_at_end(entry);
+        let span = body.first().map(|s| &s.span);
+        if let Some(d) = &mut self.debug {
+            let loc = d.enter_function(self.context, f, &name, span);
+            self.builder.set_current_debug_location(loc);
+        }
+
+        // внутри лямбды захваченные значения — обычные локальные переменные
+        let mut inner: Locals<'ctx> = HashMap::new();
+        let env_arg = f.get_nth_param(0).expect("env").into_pointer_value();
+        for (i, (name, _, ty)) in captures.iter().enumerate() {
+            let slot = self.builder.build_struct_gep(env_t, env_arg, i as u32, name).expect("struct gep");
+            let v = self.builder.build_load(self.lower_type(ty), slot, name).expect("load failed");
+            let ptr = self.entry_alloca(v.get_type(), name);
+            self.builder.build_store(ptr, v).expect("store failed");
+            self.debug_variable(name, ty, None, ptr, span);
+            inner.insert(name.clone(), (ptr, ty.clone()));
+        }
+        for (i, (name, ty)) in params.iter().enumerate() {
+            let arg = f.get_nth_param(i as u32 + 1).expect("param");
+            let ptr = self.entry_alloca(arg.get_type(), name);
+            self.builder.build_store(ptr, arg).expect("store failed");
+            self.debug_variable(name, ty, Some(i as u32 + 2), ptr, span);
+            inner.insert(name.clone(), (ptr, ty.clone()));
+        }
+        let mut last_value = None;
+        for stmt in body {
+            last_value = self.compile_stmt(f, stmt, &int_t, &mut inner);
+        }
+        self.build_return(f, last_value);
+        if let Some(d) = &mut self.debug {
+            d.leave_function();
+        }
+
+        self.builder.position_at_end(saved);
+        if let Some(loc) = saved_loc {
+            self.builder.set_current_debug_location(loc);
+        }
+        self.escaping = saved_escaping;
+        self.span = saved_span;
+        self.make_closure(f.as_global_value().as_pointer_value(), env)
+    }
+
+    // Захваченные значения копируются в окружение в момент создания замыкания.
+    fn store_captures(
+        &self,
+        env_t: StructType<'ctx>,
+        env: PointerValue<'ctx>,
+        captures: &[(String, PointerValue<'ctx>, Type)],
+    ) {
+        for (i, (name, ptr, ty)) in captures.iter().enumerate() {
+            let v = self.builder.build_load(self.lower_type(ty), *ptr, name).expect("load failed");
+            let slot = self.builder.build_struct_gep(env_t, env, i as u32, name).expect("struct gep");
+            self.builder.build_store(slot, v).expect("store failed");
+        }
+    }
+
+    // Функция верхнего уровня как значение: обёртка name.closure с лишним параметром env.
+    fn function_closure(&mut self, name: &str) -> BasicValueEnum<'ctx> {
+        if self.externs.contains_key(name) {
+            panic!("extern fn {} cannot be used as a value", name);
+        }
+        let wrapper_name = format!("{}.closure", name);
+        let wrapper = match self.module.get_function(&wrapper_name) {
+            Some(w) => w,
+            None => {
+                let (params, ret) = self.fn_types[name].clone();
+                let w = self.module.add_function(&wrapper_name, self.closure_fn_type(&params, &ret), None);
+                let saved = self.builder.get_insert_block().expect("no insert block");
+                // у обёртки нет subprogram: место из чужой функции верификатор LLVM не примет
+                let saved_loc = self.debug.as_ref().and(self.builder.get_current_debug_location());
+                self.builder.unset_current_debug_location();
+                self.builder.position_at_end(self.context.append_basic_block(w, "entry"));
+                let target = self.module.get_function(name).expect("declared function");
+                let args: Vec<BasicMetadataValueEnum> = w.get_param_iter().skip(1).map(|p| p.into()).collect();
+                let v = self
+                    .builder
+                    .build_call(target, &args, "call")
+                    .expect("call")
+                    .try_as_basic_value()
+                    .left()
+                    .expect("function returns a value");
+                self.builder.build_return(Some(&v)).expect("ret");
+                self.builder.position_at_end(saved);
+                if let Some(loc) = saved_loc {
+                    self.builder.set_current_debug_location(loc);
+                }
+                w
+            }
+        };
+        let null = self.context.ptr_type(AddressSpace::default()).const_null();
+        self.make_closure(wrapper.as_global_value().as_pointer_value(), null)
+    }
+
+    fn make_closure(&self, func: PointerValue<'ctx>, env: PointerValue<'ctx>) -> BasicValueEnum<'ctx> {
+        let agg = self.closure_type().get_undef();
+        let agg = self
+            .builder
+            .build_insert_value(agg, func, 0, "closure.fn")
+            .expect("insertvalue")
+            .into_struct_value();
+        self.builder.build_insert_value(agg, env, 1, "closure.env").expect("insertvalue").into_struct_value().into()
+    }
+
+    // Косвенный вызов: fn(env, args...).
+    fn call_closure(
+        &mut self,
+        closure: BasicValueEnum<'ctx>,
+        ty: &Type,
+        args: &[Expr],
+        int_t: &IntType<'ctx>,
+        locals: &mut Locals<'ctx>,
+    ) -> BasicValueEnum<'ctx> {
+        let (params, ret) = match ty {
+            Type::Fn(params, ret) => (params.clone(), (**ret).clone()),
+            other => panic!("cannot call {:?}", other),
+        };
+        if params.len() != args.len() {
+            panic!("closure expects {} arguments, got {}", params.len(), args.len());
+        }
+        let closure = closure.into_struct_value();
+        let func = self.builder.build_extract_value(closure, 0, "fn").expect("extractvalue").into_pointer_value();
+        let env = self.builder.build_extract_value(closure, 1, "env").expect("extractvalue");
+        let mut argv: Vec<BasicMetadataValueEnum> = vec![env.into()];
+        for (a, param_t) in args.iter().zip(&params) {
+            argv.push(self.compile_arg(a, param_t, int_t, locals).into());
+        }
+        self.builder
+            .build_indirect_call(self.closure_fn_type(&params, &ret), func, &argv, "call.closure")
+            .expect("indirect call")
+            .try_as_basic_value()
+            .left()
+            .expect("closure returns a value")
+    }
+
+    // i32-аргументы обрезаются, i32-результат расширяется до i64, void даёт 0 —
+    // так же, как Interpreter::call_extern.
+    fn call_extern(
+        &mut self,
+        ext: &ExternFn,
+        args: &[Expr],
+        int_t: &IntType<'ctx>,
+        locals: &mut Locals<'ctx>,
+    ) -> BasicValueEnum<'ctx> {
+        if ext.params.len() != args.len() {
+            panic!("{} expects {} arguments, got {}", ext.name, ext.params.len(), args.len());
+        }
+        let callee = self.module.get_function(&ext.name).expect("declared extern");
+        let i32_t = self.context.i32_type();
+        let mut argv: Vec<BasicMetadataValueEnum> = Vec::new();
+        for (a, (_, ty)) in args.iter().zip(&ext.params) {
+            let v = match ty {
+                ExternType::I32 => {
+                    let v = self.compile_expr(a, int_t, locals);
+                    let v = self.int_operand(v, int_t);
+                    self.builder.build_int_truncate(v, i32_t, "c.int").expect("trunc").into()
                 }
+                _ => self.compile_arg(a, &ty.lang(), int_t, locals),
+            };
+            argv.push(v.into());
+        }
+        // у void-вызова не может быть имени
+        let name = if ext.ret == ExternType::Void { "" } else { "c.call" };
+        let result = self.builder.build_call(callee, &argv, name).expect("call").try_as_basic_value().left();
+        match (&ext.ret, result) {
+            (ExternType::I32, Some(v)) => self
+                .builder
+                .build_int_s_extend(v.into_int_value(), *int_t, "c.ret")
+                .expect("sext")
+                .into(),
+            (ExternType::Value(_), Some(v)) => v,
+            _ => int_t.const_zero().into(),
+        }
+    }
+
+    // Значение под тип параметра (или `let x: T`): bool/char в i64 расширяем, массив в `[T]`
+    // копируем во временный слот и передаём { ptr, len } — как и в интерпретаторе,
+    // вызываемый получает копию. Остальное передаём как есть.
+    fn compile_arg(
+        &mut self,
+        arg: &Expr,
+        param: &Type,
+        int_t: &IntType<'ctx>,
+        locals: &mut Locals<'ctx>,
+    ) -> BasicValueEnum<'ctx> {
+        let arg_ty = self.type_of(arg, locals);
+        let v = self.compile_expr(arg, int_t, locals);
+        match (param, arg_ty) {
+            (Type::Int, _) => self.int_operand(v, int_t).into(),
+            (Type::Slice(_), Type::Array(_, n)) => {
+                let data = self.entry_alloca(v.get_type(), "slice.data");
+                self.builder.build_store(data, v).expect("store failed");
+                let slice = self.slice_type().get_undef();
+                let slice = self.builder.build_insert_value(slice, data, 0, "slice").expect("insertvalue");
+                let len = int_t.const_int(n as u64, false);
+                self.builder
+                    .build_insert_value(slice, len, 1, "slice")
+                    .expect("insertvalue")
+                    .into_struct_value()
+                    .into()
             }
+            _ => v,
+        }
+    }
+
+    // phi в точке слияния по веткам, дошедшим до конца; если не дошла ни одна — ноль.
+    fn build_merge(
+        &self,
+        ty: BasicTypeEnum<'ctx>,
+        incoming: &[(BasicValueEnum<'ctx>, BasicBlock<'ctx>)],
+        name: &str,
+    ) -> BasicValueEnum<'ctx> {
+        if incoming.is_empty() {
+            return ty.const_zero();
+        }
+        let phi = self.builder.build_phi(ty, name).expect("phi");
+        let incoming: Vec<(&dyn BasicValue<'ctx>, BasicBlock<'ctx>)> =
+            incoming.iter().map(|(v, bb)| (v as &dyn BasicValue<'ctx>, *bb)).collect();
+        phi.add_incoming(&incoming);
+        phi.as_basic_value()
+    }
+
+    // Целые образцы — case'ы LLVM switch; диапазоны и `_` — цепочка сравнений в default
+    // в порядке веток. Целый образец, уже покрытый более ранней веткой, в switch не попадает,
+    // так что порядок веток соблюдается (такие образцы sema помечает как недостижимые).
+    fn compile_match(
+        &mut self,
+        scrutinee: &Expr,
+        arms: &[MatchArm],
+        span: &Span,
+        ty: BasicTypeEnum<'ctx>,
+        int_t: &IntType<'ctx>,
+        locals: &mut Locals<'ctx>,
+    ) -> BasicValueEnum<'ctx> {
+        let func = self.current_function();
+        let v = self.compile_expr(scrutinee, int_t, locals);
+        let v = self.int_operand(v, int_t);
+
+        let arm_bbs: Vec<BasicBlock<'ctx>> = (0..arms.len())
+            .map(|i| self.context.append_basic_block(func, &format!("arm.{}", i)))
+            .collect();
+        let default_bb = self.context.append_basic_block(func, "match.default");
+        let cont_bb = self.context.append_basic_block(func, "match.end");
+
+        let mut covered: Vec<(i64, i64)> = Vec::new();
+        let mut cases = Vec::new();
+        // (диапазон или None для `_`, номер ветки)
+        let mut chain = Vec::new();
+        for (i, arm) in arms.iter().enumerate() {
+            for (pat, _) in &arm.patterns {
+                let (lo, hi) = pat.bounds();
+                match pat {
+                    Pattern::Int(n) if !covered.iter().any(|(a, b)| a <= n && n <= b) => {
+                        cases.push((int_t.const_int(*n as u64, true), arm_bbs[i]));
+                    }
+                    Pattern::Int(_) => {}
+                    Pattern::Range(..) => chain.push((Some((lo, hi)), i)),
+                    Pattern::Wildcard => chain.push((None, i)),
+                }
+                covered.push((lo, hi));
+            }
+        }
+        self.builder.build_switch(v, default_bb, &cases).expect("switch");
+
+        self.builder.position_at_end(default_bb);
+        let mut exhaustive = false;
+        for (range, arm) in chain {
+            let (lo, hi) = match range {
+                Some(r) => r,
+                None => {
+                    self.builder.build_unconditional_branch(arm_bbs[arm]).expect("br");
+                    exhaustive = true;
+                    break;
+                }
+            };
+            let ge = self
+                .builder
+                .build_int_compare(IntPredicate::SGE, v, int_t.const_int(lo as u64, true), "range.lo")
+                .expect("cmp");
+            let le = self
+                .builder
+                .build_int_compare(IntPredicate::SLE, v, int_t.const_int(hi as u64, true), "range.hi")
+                .expect("cmp");
+            let in_range = self.builder.build_and(ge, le, "in.range").expect("and");
+            let next_bb = self.context.append_basic_block(func, "match.next");
+            self.builder.build_conditional_branch(in_range, arm_bbs[arm], next_bb).expect("brcond range");
+            self.builder.position_at_end(next_bb);
+        }
+        if !exhaustive {
+            let msg = format!("runtime error at {}: no match arm for value %lld\n", self.location(span));
+            self.runtime_error(&msg, &[v.into()]);
+        }
+
+        let mut incoming = Vec::new();
+        for (arm, bb) in arms.iter().zip(arm_bbs) {
+            self.builder.position_at_end(bb);
+            let v = self.compile_expr(&arm.body, int_t, locals);
+            let end = self.builder.get_insert_block().expect("no insert block");
+            if end.get_terminator().is_none() {
+                incoming.push((self.coerce(Some(v), ty), end));
+                self.builder.build_unconditional_branch(cont_bb).expect("br");
+            }
+        }
+        self.builder.position_at_end(cont_bb);
+        self.build_merge(ty, &incoming, "matchval")
+    }
+
+    // Значение блока — значение последнего оператора-выражения.
+    fn compile_block(
+        &mut self,
+        body: &[Stmt],
+        int_t: &IntType<'ctx>,
+        locals: &mut Locals<'ctx>,
+    ) -> Option<BasicValueEnum<'ctx>> {
+        let func = self.current_function();
+        let mut last_value = None;
+        for s in body {
+            last_value = self.compile_stmt(func, s, int_t, locals);
+        }
+        last_value
+    }
+
+    fn build_array(&self, elem_ty: BasicTypeEnum<'ctx>, vals: &[BasicValueEnum<'ctx>]) -> BasicValueEnum<'ctx> {
+        let mut agg = elem_ty.array_type(vals.len() as u32).get_undef();
+        for (i, v) in vals.iter().enumerate() {
+            agg = self
+                .builder
+                .build_insert_value(agg, *v, i as u32, "arr")
+                .expect("insertvalue")
+                .into_array_value();
+        }
+        agg.into()
+    }
+
+    // Адрес, в который можно писать/из которого читать: переменная, элемент массива,
+    // поле (в том числе через указатель) или `*p`. Индекс проверяется на выход за границы
+    // до GEP, указатель — на null.
+    fn compile_lvalue(
+        &mut self,
+        expr: &Expr,
+        int_t: &IntType<'ctx>,
+        locals: &mut Locals<'ctx>,
+    ) -> (PointerValue<'ctx>, Type) {
+        match expr {
+            Expr::Ident(name) => locals.get(name).expect("use of undefined variable").clone(),
+            Expr::Index { array, index, span } => {
+                let (base, base_ty) = self.compile_place(array, int_t, locals);
+                let idx = self.compile_expr(index, int_t, locals);
+                let idx = self.int_operand(idx, int_t);
+                match base_ty {
+                    Type::Array(elem, n) => {
+                        let array_ty = self.lower_type(&elem).array_type(n as u32);
+                        self.bounds_check(idx, int_t.const_int(n as u64, false), span);
+                        let zero = int_t.const_zero();
+                        let ptr = unsafe {
+                            self.builder
+                                .build_in_bounds_gep(array_ty, base, &[zero, idx], "elem.ptr")
+                                .expect("gep")
+                        };
+                        (ptr, *elem)
+                    }
+                    // у `[T]` длина известна только во время выполнения
+                    Type::Slice(elem) => {
+                        let slice = self.builder.build_load(self.slice_type(), base, "slice").expect("load failed");
+                        let slice = slice.into_struct_value();
+                        let data = self.builder.build_extract_value(slice, 0, "data").expect("extractvalue");
+                        let len = self.builder.build_extract_value(slice, 1, "len").expect("extractvalue");
+                        self.bounds_check(idx, len.into_int_value(), span);
+                        let ptr = unsafe {
+                            self.builder
+                                .build_in_bounds_gep(self.lower_type(&elem), data.into_pointer_value(), &[idx], "elem.ptr")
+                                .expect("gep")
+                        };
+                        (ptr, *elem)
+                    }
+                    other => panic!("cannot index into {:?}", other),
+                }
+            }
+            Expr::Field(base, field) => {
+                let (mut ptr, mut ty) = self.compile_place(base, int_t, locals);
+                // p.x при p: *Point — сначала загружаем сам указатель
+                while let Type::Ptr(inner) = ty {
+                    ptr = self
+                        .builder
+                        .build_load(self.context.ptr_type(AddressSpace::default()), ptr, "deref")
+                        .expect("load failed")
+                        .into_pointer_value();
+                    ty = *inner;
+                }
+                let name = self.struct_of(&ty, field);
+                let (st, fields) = self.struct_fields(&name);
+                let idx = fields
+                    .iter()
+                    .position(|(f, _)| f == field)
+                    .unwrap_or_else(|| panic!("struct {} has no field {}", name, field));
+                let ptr = self.builder.build_struct_gep(st, ptr, idx as u32, field).expect("struct gep");
+                (ptr, fields[idx].1.clone())
+            }
+            Expr::Deref(inner, span) => {
+                let pointee = match self.type_of(inner, locals) {
+                    Type::Ptr(t) => *t,
+                    other => panic!("cannot dereference {:?}", other),
+                };
+                let ptr = self.compile_expr(inner, int_t, locals).into_pointer_value();
+                self.null_check(ptr, span);
+                (ptr, pointee)
+            }
+            other => panic!("not an lvalue: {:?}", other),
+        }
+    }
+
+    // Как compile_lvalue, но временное значение (`f()[0]`, `make().x`) сначала кладём в память.
+    fn compile_place(
+        &mut self,
+        expr: &Expr,
+        int_t: &IntType<'ctx>,
+        locals: &mut Locals<'ctx>,
+    ) -> (PointerValue<'ctx>, Type) {
+        match expr {
+            Expr::Ident(_) | Expr::Index { .. } | Expr::Field(..) | Expr::Deref(..) => {
+                self.compile_lvalue(expr, int_t, locals)
+            }
+            _ => {
+                let ty = self.type_of(expr, locals);
+                let v = self.compile_expr(expr, int_t, locals);
+                let tmp = self.entry_alloca(v.get_type(), "tmp");
+                self.builder.build_store(tmp, v).expect("store failed");
+                (tmp, ty)
+            }
+        }
+    }
+
+    // idx >= len (беззнаково, так что и отрицательные) -> сообщение в stderr и exit(101).
+    fn bounds_check(&mut self, idx: IntValue<'ctx>, len: IntValue<'ctx>, span: &Span) {
+        let func = self.current_function();
+        let oob = self
+            .builder
+            .build_int_compare(IntPredicate::UGE, idx, len, "oob")
+            .expect("cmp");
+        let fail_bb = self.context.append_basic_block(func, "bounds.fail");
+        let ok_bb = self.context.append_basic_block(func, "bounds.ok");
+        self.builder.build_conditional_branch(oob, fail_bb, ok_bb).expect("brcond bounds");
+
+        self.builder.position_at_end(fail_bb);
+        let msg = format!("runtime error at {}: index %lld out of bounds for length %lld\n", self.location(span));
+        self.runtime_error(&msg, &[idx.into(), len.into()]);
+
+        self.builder.position_at_end(ok_bb);
+    }
+
+    // b == 0 -> ошибка времени выполнения, как bounds_check. i64::MIN / -1 у sdiv — UB
+    // (SIGFPE на x86), поэтому на -1 делим как 0 - a: результат с переполнением, как wrapping_div.
+    fn checked_div(&mut self, a: IntValue<'ctx>, b: IntValue<'ctx>) -> IntValue<'ctx> {
+        let func = self.current_function();
+        let int_t = b.get_type();
+        let zero = self.builder.build_int_compare(IntPredicate::EQ, b, int_t.const_zero(), "divzero").expect("cmp");
+        let fail_bb = self.context.append_basic_block(func, "div.fail");
+        let ok_bb = self.context.append_basic_block(func, "div.ok");
+        self.builder.build_conditional_branch(zero, fail_bb, ok_bb).expect("brcond div");
+
+        self.builder.position_at_end(fail_bb);
+        let msg = format!("runtime error at {}: division by zero\n", self.location(&self.span));
+        self.runtime_error(&msg, &[]);
+
+        self.builder.position_at_end(ok_bb);
+        let minus_one = int_t.const_all_ones();
+        let is_neg = self.builder.build_int_compare(IntPredicate::EQ, b, minus_one, "divneg").expect("cmp");
+        let safe = self.builder.build_select(is_neg, int_t.const_int(1, false), b, "divisor").expect("select");
+        let q = self.builder.build_int_signed_div(a, safe.into_int_value(), "div").expect("div");
+        let neg = self.builder.build_int_sub(int_t.const_zero(), a, "neg").expect("sub");
+        self.builder.build_select(is_neg, neg, q, "quot").expect("select").into_int_value()
+    }
+
+    // Место для сообщения: путь к модулю может содержать `%`, а это строка формата.
+    fn location(&self, span: &Span) -> String {
+        self.sources.location(span.start).replace('%', "%%")
+    }
+
+    // Разыменование null -> то же сообщение, что и в интерпретаторе, и exit(101).
+    fn null_check(&mut self, ptr: PointerValue<'ctx>, span: &Span) {
+        let func = self.current_function();
+        let is_null = self.builder.build_is_null(ptr, "isnull").expect("isnull");
+        let fail_bb = self.context.append_basic_block(func, "null.fail");
+        let ok_bb = self.context.append_basic_block(func, "null.ok");
+        self.builder.build_conditional_branch(is_null, fail_bb, ok_bb).expect("brcond null");
+
+        self.builder.position_at_end(fail_bb);
+        let msg = format!("runtime error at {}: null pointer dereference\n", self.location(span));
+        self.runtime_error(&msg, &[]);
+
+        self.builder.position_at_end(ok_bb);
+    }
+
+    // Сообщение + exit(101) + unreachable — общий хвост для ошибок в скомпилированном коде.
+    fn runtime_error(&self, fmt: &str, args: &[BasicMetadataValueEnum<'ctx>]) {
+        let fmt = self.global_str(fmt).into_pointer_value();
+        build_runtime_error(self.context, &self.module, &self.builder, fmt, args);
+        self.builder.build_unreachable().expect("unreachable");
+    }
+
+    fn global_str(&self, s: &str) -> BasicValueEnum<'ctx> {
+        self.builder
+            .build_global_string_ptr(s, "str")
+            .expect("global string")
+            .as_pointer_value()
+            .into()
+    }
+
+    fn libc_function(&self, name: &str, fn_ty: FunctionType<'ctx>) -> FunctionValue<'ctx> {
+        libc_function(&self.module, name, fn_ty)
+    }
+
+    fn printf(&self, args: &[BasicMetadataValueEnum<'ctx>]) {
+        let ptr_t = self.context.ptr_type(AddressSpace::default());
+        let printf = self.libc_function("printf", self.context.i32_type().fn_type(&[ptr_t.into()], true));
+        self.builder.build_call(printf, args, "printf").expect("call printf");
+    }
+
+    // Печать в том же формате, что и Display для Value: массивы — `[1, 2, 3]`,
+    // указатели — `null` или `<ptr>`. Тип берём из AST: по LLVM-значению строку от указателя не отличить.
+    fn print_value(&self, v: BasicValueEnum<'ctx>, ty: &Type) {
+        let text = |s: &str| self.printf(&[self.global_str(s).into()]);
+        let select = |cond: IntValue<'ctx>, t: &str, f: &str| {
+            let t = self.global_str(t).into_pointer_value();
+            let f = self.global_str(f).into_pointer_value();
+            self.builder.build_select(cond, t, f, "sel").expect("select")
+        };
+        let (fmt, v): (&str, BasicMetadataValueEnum) = match ty {
+            Type::Str => ("%s", v.into()),
+            Type::Bool => ("%s", select(v.into_int_value(), "true", "false").into()),
+            Type::Char => {
+                let (buf, len) = self.encode_utf8(v.into_int_value());
+                let fmt = self.global_str("%.*s");
+                self.printf(&[fmt.into(), len.into(), buf.into()]);
+                return;
+            }
+            Type::Int => ("%lld", v.into()),
+            Type::Fn(..) => ("%s", self.global_str("<fn>").into()),
+            Type::Ptr(_) => {
+                let is_null = self.builder.build_is_null(v.into_pointer_value(), "isnull").expect("isnull");
+                ("%s", select(is_null, "null", "<ptr>").into())
+            }
+            Type::Array(elem, n) => {
+                text("[");
+                for i in 0..*n as u32 {
+                    if i > 0 {
+                        text(", ");
+                    }
+                    let e = self.builder.build_extract_value(v.into_array_value(), i, "elem").expect("extractvalue");
+                    self.print_value(e, elem);
+                }
+                text("]");
+                return;
+            }
+            // длина известна только во время выполнения — печатаем циклом
+            Type::Slice(elem) => {
+                let int_t = self.context.i64_type();
+                let slice = v.into_struct_value();
+                let data = self.builder.build_extract_value(slice, 0, "data").expect("extractvalue");
+                let len = self.builder.build_extract_value(slice, 1, "len").expect("extractvalue").into_int_value();
+                let func = self.current_function();
+                let i_slot = self.entry_alloca(int_t.into(), "print.i");
+                self.builder.build_store(i_slot, int_t.const_zero()).expect("store failed");
+                let cond_bb = self.context.append_basic_block(func, "print.cond");
+                let body_bb = self.context.append_basic_block(func, "print.body");
+                let end_bb = self.context.append_basic_block(func, "print.end");
+                text("[");
+                self.builder.build_unconditional_branch(cond_bb).expect("br");
+
+                self.builder.position_at_end(cond_bb);
+                let i = self.builder.build_load(int_t, i_slot, "i").expect("load failed").into_int_value();
+                let more = self.builder.build_int_compare(IntPredicate::SLT, i, len, "more").expect("cmp");
+                self.builder.build_conditional_branch(more, body_bb, end_bb).expect("brcond");
+
+                self.builder.position_at_end(body_bb);
+                let first = self.builder.build_int_compare(IntPredicate::EQ, i, int_t.const_zero(), "first").expect("cmp");
+                let fmt = self.global_str("%s");
+                self.printf(&[fmt.into(), select(first, "", ", ").into()]);
+                let elem_t = self.lower_type(elem);
+                let ptr = unsafe {
+                    self.builder
+                        .build_in_bounds_gep(elem_t, data.into_pointer_value(), &[i], "elem.ptr")
+                        .expect("gep")
+                };
+                let e = self.builder.build_load(elem_t, ptr, "elem").expect("load failed");
+                self.print_value(e, elem);
+                let next = self.builder.build_int_add(i, int_t.const_int(1, false), "i.next").expect("add");
+                self.builder.build_store(i_slot, next).expect("store failed");
+                self.builder.build_unconditional_branch(cond_bb).expect("br");
+
+                self.builder.position_at_end(end_bb);
+                text("]");
+                return;
+            }
+            Type::Struct(name) => {
+                let (_, fields) = self.struct_fields(name);
+                text(&format!("{} {{ ", name));
+                for (i, (field, fty)) in fields.iter().enumerate() {
+                    text(&format!("{}{}: ", if i > 0 { ", " } else { "" }, field));
+                    let e = self
+                        .builder
+                        .build_extract_value(v.into_struct_value(), i as u32, field)
+                        .expect("extractvalue");
+                    self.print_value(e, fty);
+                }
+                text(" }");
+                return;
+            }
+        };
+        let fmt = self.global_str(fmt);
+        self.printf(&[fmt.into(), v]);
+    }
+
+    // char (код i32) -> байты UTF-8 в буфере на стеке и их число: %c печатает только один байт.
+    fn encode_utf8(&self, c: IntValue<'ctx>) -> (PointerValue<'ctx>, IntValue<'ctx>) {
+        let i8_t = self.context.i8_type();
+        let i32_t = self.context.i32_type();
+        let k = |n: u64| i32_t.const_int(n, false);
+        let b = &self.builder;
+        let below = |limit: u64| b.build_int_compare(IntPredicate::ULT, c, k(limit), "utf8.lt").expect("cmp");
+        let pick = |cond, t: IntValue<'ctx>, f: IntValue<'ctx>| {
+            b.build_select(cond, t, f, "utf8.sel").expect("select").into_int_value()
+        };
+        let len = pick(below(0x80), k(1), pick(below(0x800), k(2), pick(below(0x10000), k(3), k(4))));
+        let lead = pick(below(0x80), k(0), pick(below(0x800), k(0xC0), pick(below(0x10000), k(0xE0), k(0xF0))));
+        let buf = self.entry_alloca(i8_t.array_type(4).into(), "utf8.buf");
+        for i in 0..4u64 {
+            // байт i несёт биты c >> 6*(len-1-i); за пределами len сдвиг 0, байт не печатается
+            let rest = b.build_int_sub(len, k(i + 1), "utf8.rest").expect("sub");
+            let inside = b.build_int_compare(IntPredicate::SGE, rest, k(0), "utf8.in").expect("cmp");
+            let rest = pick(inside, rest, k(0));
+            let shift = b.build_int_mul(rest, k(6), "utf8.shift").expect("mul");
+            let bits = b.build_right_shift(c, shift, false, "utf8.bits").expect("lshr");
+            let byte = if i == 0 {
+                b.build_or(bits, lead, "utf8.lead").expect("or")
+            } else {
+                let low = b.build_and(bits, k(0x3F), "utf8.low").expect("and");
+                b.build_or(low, k(0x80), "utf8.cont").expect("or")
+            };
+            let byte = b.build_int_truncate(byte, i8_t, "utf8.byte").expect("trunc");
+            let ptr = unsafe {
+                b.build_in_bounds_gep(i8_t.array_type(4), buf, &[k(0), k(i)], "utf8.ptr").expect("gep")
+            };
+            b.build_store(ptr, byte).expect("store failed");
+        }
+        (buf, len)
+    }
+
+    // print/println/read_int/exit/len/alloc/free — те же встроенные, что и в Interpreter::call_builtin.
+    // None — это не встроенная функция.
+    fn compile_builtin(
+        &mut self,
+        name: &str,
+        args: &[Expr],
+        int_t: &IntType<'ctx>,
+        locals: &mut Locals<'ctx>,
+    ) -> Option<BasicValueEnum<'ctx>> {
+        let zero: BasicValueEnum = int_t.const_int(0, false).into();
+        match (name, args) {
+            ("print" | "println", [arg]) => {
+                let ty = self.type_of(arg, locals);
+                let v = self.compile_expr(arg, int_t, locals);
+                self.print_value(v, &ty);
+                if name == "println" {
+                    let fmt = self.global_str("\n");
+                    self.printf(&[fmt.into()]);
+                }
+            }
+            ("println", []) => {
+                let fmt = self.global_str("\n");
+                self.printf(&[fmt.into()]);
+            }
+            ("read_int", []) => {
+                let ptr_t = self.context.ptr_type(AddressSpace::default());
+                let scanf = self.libc_function("scanf", self.context.i32_type().fn_type(&[ptr_t.into()], true));
+                let slot = self.entry_alloca((*int_t).into(), "read_int");
+                self.builder.build_store(slot, int_t.const_int(0, false)).expect("store");
+                let fmt = self.global_str("%lld");
+                self.builder.build_call(scanf, &[fmt.into(), slot.into()], "scanf").expect("call scanf");
+                return Some(self.builder.build_load(*int_t, slot, "read_int").expect("load"));
+            }
+            // у [N x T] длина известна из типа, у `[T]` — второе поле
+            ("len", [arr]) => {
+                let len = match self.type_of(arr, locals) {
+                    Type::Array(_, n) => int_t.const_int(n as u64, false).into(),
+                    Type::Slice(_) => {
+                        let slice = self.compile_expr(arr, int_t, locals).into_struct_value();
+                        self.builder.build_extract_value(slice, 1, "len").expect("extractvalue")
+                    }
+                    other => panic!("len() expects an array, got {:?}", other),
+                };
+                return Some(len);
+            }
+            ("exit", [code]) => {
+                let v = self.compile_expr(code, int_t, locals);
+                let v = self.int_operand(v, int_t);
+                let i32_t = self.context.i32_type();
+                let code = self.builder.build_int_truncate(v, i32_t, "code").expect("trunc");
+                let exit = self.libc_function("exit", self.context.void_type().fn_type(&[i32_t.into()], false));
+                self.builder.build_call(exit, &[code.into()], "").expect("call exit");
+            }
+            // alloc(v): malloc(sizeof v) и сразу кладём туда v
+            ("alloc", [init]) => {
+                let v = self.compile_expr(init, int_t, locals);
+                let ptr_t = self.context.ptr_type(AddressSpace::default());
+                let size = v.get_type().size_of().expect("sized type");
+                let malloc = self.libc_function("malloc", ptr_t.fn_type(&[(*int_t).into()], false));
+                let p = self
+                    .builder
+                    .build_call(malloc, &[size.into()], "alloc")
+                    .expect("call malloc")
+                    .try_as_basic_value()
+                    .left()
+                    .expect("malloc returns ptr")
+                    .into_pointer_value();
+                self.builder.build_store(p, v).expect("store failed");
+                return Some(p.into());
+            }
+            ("free", [p]) => {
+                let p = self.compile_expr(p, int_t, locals);
+                let ptr_t = self.context.ptr_type(AddressSpace::default());
+                let free = self.libc_function("free", self.context.void_type().fn_type(&[ptr_t.into()], false));
+                self.builder.build_call(free, &[p.into()], "").expect("call free");
+            }
+            ("print" | "println" | "read_int" | "exit" | "len" | "alloc" | "free", _) => {
+                panic!("wrong number of arguments to {}", name)
+            }
+            _ => return None,
+        }
+        Some(zero)
+    }
+
+    // Строки сравниваются через libc strcmp, как и в интерпретаторе — по содержимому.
+    fn compile_str_cmp(
+        &mut self,
+        op: &str,
+        a: PointerValue<'ctx>,
+        b: PointerValue<'ctx>,
+    ) -> IntValue<'ctx> {
+        let i32_t = self.context.i32_type();
+        let ptr_t = self.context.ptr_type(AddressSpace::default());
+        let strcmp = self.libc_function("strcmp", i32_t.fn_type(&[ptr_t.into(), ptr_t.into()], false));
+        let res = self
+            .builder
+            .build_call(strcmp, &[a.into(), b.into()], "strcmp")
+            .expect("call strcmp")
+            .try_as_basic_value()
+            .left()
+            .expect("strcmp returns i32")
+            .into_int_value();
+        let zero = i32_t.const_int(0, false);
+        let pred = match op {
+            "==" => IntPredicate::EQ,
+            "!=" => IntPredicate::NE,
+            other => panic!("operator {} is not defined for strings", other),
+        };
+        self.builder.build_int_compare(pred, res, zero, "strcmp.res").expect("cmp")
+    }
+
+    // bool (i1) и char (i32) расширяем до i64, чтобы их можно было складывать и сравнивать с числами.
+    fn int_operand(&self, v: BasicValueEnum<'ctx>, int_t: &IntType<'ctx>) -> IntValue<'ctx> {
+        match v {
+            BasicValueEnum::IntValue(i) if i.get_type() != *int_t => self
+                .builder
+                .build_int_z_extend(i, *int_t, "zext")
+                .expect("zext"),
+            BasicValueEnum::IntValue(i) => i,
+            other => panic!("expected integer operand, got {:?}", other.get_type()),
         }
     }
 
     // Любое IntValue -> i1 (булево для условий).
-    fn to_bool(&self, v: IntValue<'ctx>, i32_t: &IntType<'ctx>) -> IntValue<'ctx> {
+    fn to_bool(&self, v: IntValue<'ctx>) -> IntValue<'ctx> {
         if v.get_type().get_bit_width() == 1 {
             v
         } else {
-            let zero = i32_t.const_int(0, false);
+            let zero = v.get_type().const_zero();
             self.builder
                 .build_int_compare(IntPredicate::NE, v, zero, "tobool")
                 .expect("cmp to bool")
@@ -192,5 +1523,608 @@ impl<'ctx> Codegen<'ctx> {
 
     pub fn dump_ir(&self) { self.module.print_to_stderr(); }
 
+    /// Запоминает адрес функции хоста: run_jit подставит его вместо внешнего символа.
+    /// Объявление берётся из program.externs, куда сигнатуру кладёт загрузчик.
+    /// Вызов функции без адреса отклоняет sema::check_compiled.
+    pub fn register_host(&mut self, f: &HostFunction) {
+        if let Some(addr) = f.native {
+            self.host_natives.insert(f.sig.name.clone(), addr);
+        }
+    }
+
+    /// Выполняет `main` через MCJIT и возвращает её код выхода.
+    pub fn run_jit(&self) -> Result<i32, String> {
+        run_jit(&self.module, &self.host_natives)
+    }
+
+    pub fn write_executable(&self, out: &Path) -> Result<(), String> {
+        write_executable(&self.module, out)
+    }
+
     pub fn module(&self) -> &Module<'ctx> { &self.module }
 }
+
+/// Сообщение об ошибке времени выполнения собирается snprintf в буфер `rt.message`
+/// и уходит в `rt.error`: в исполняемом файле она печатает его в stderr и делает exit(101),
+/// а run_jit подменяет её возвратом в хост. Блок после вызова завершает вызывающий.
+pub fn build_runtime_error<'ctx>(
+    context: &'ctx Context,
+    module: &Module<'ctx>,
+    builder: &Builder<'ctx>,
+    fmt: PointerValue<'ctx>,
+    args: &[BasicMetadataValueEnum<'ctx>],
+) {
+    let i32_t = context.i32_type();
+    let i64_t = context.i64_type();
+    let ptr_t = context.ptr_type(AddressSpace::default());
+    let buf_t = context.i8_type().array_type(RUNTIME_MESSAGE_LEN);
+    let buf = module.get_global("rt.message").unwrap_or_else(|| {
+        let g = module.add_global(buf_t, None, "rt.message");
+        g.set_linkage(Linkage::Internal);
+        g.set_initializer(&buf_t.const_zero());
+        g
+    });
+    let report = module.get_function("rt.error").unwrap_or_else(|| {
+        let f = module.add_function("rt.error", context.void_type().fn_type(&[ptr_t.into()], false), Some(Linkage::Internal));
+        let b = context.create_builder();
+        b.position_at_end(context.append_basic_block(f, "entry"));
+        let dprintf = libc_function(module, "dprintf", i32_t.fn_type(&[i32_t.into(), ptr_t.into()], true));
+        let percent_s = b.build_global_string_ptr("%s", "str").expect("global string").as_pointer_value();
+        let message = f.get_first_param().expect("message");
+        b.build_call(dprintf, &[i32_t.const_int(2, false).into(), percent_s.into(), message.into()], "dprintf")
+            .expect("call dprintf");
+        let exit = libc_function(module, "exit", context.void_type().fn_type(&[i32_t.into()], false));
+        b.build_call(exit, &[i32_t.const_int(101, false).into()], "").expect("call exit");
+        b.build_unreachable().expect("unreachable");
+        f
+    });
+    let snprintf = libc_function(module, "snprintf", i32_t.fn_type(&[ptr_t.into(), i64_t.into(), ptr_t.into()], true));
+    let mut argv: Vec<BasicMetadataValueEnum> =
+        vec![buf.as_pointer_value().into(), i64_t.const_int(RUNTIME_MESSAGE_LEN as u64, false).into(), fmt.into()];
+    argv.extend_from_slice(args);
+    builder.build_call(snprintf, &argv, "snprintf").expect("call snprintf");
+    builder.build_call(report, &[buf.as_pointer_value().into()], "").expect("call rt.error");
+}
+
+// Сообщения короткие: место, текст и пара чисел.
+const RUNTIME_MESSAGE_LEN: u32 = 512;
+
+// Объявление функции из libc (printf, scanf, exit, ...), если её ещё нет в модуле.
+fn libc_function<'ctx>(module: &Module<'ctx>, name: &str, fn_ty: FunctionType<'ctx>) -> FunctionValue<'ctx> {
+    module.get_function(name).unwrap_or_else(|| module.add_function(name, fn_ty, None))
+}
+
+/// Выполняет `main` модуля через MCJIT и возвращает её код выхода. extern-функции JIT находит
+/// среди символов процесса — то есть в libc, с которой слинкован сам компилятор;
+/// `host_natives` — адреса функций хоста, имя -> `extern "C" fn`. exit() возвращает свой
+/// код, ошибка времени выполнения — Err с сообщением: процесс хоста продолжает работу.
+pub fn run_jit(module: &Module, host_natives: &HashMap<String, usize>) -> Result<i32, String> {
+    Target::initialize_native(&InitializationConfig::default())?;
+    module.verify().map_err(|e| e.to_string())?;
+    // точка входа с setjmp добавляется в копию: модуль остаётся пригодным для AOT
+    let module = module.clone();
+    add_jit_entry(&module)?;
+    let engine = module
+        .create_jit_execution_engine(OptimizationLevel::None)
+        .map_err(|e| e.to_string())?;
+    for (name, addr) in host_natives {
+        if let Some(f) = module.get_function(name) {
+            engine.add_global_mapping(&f, *addr);
+        }
+    }
+    let mut code = 0i32;
+    let mut message: *const c_char = std::ptr::null();
+    let status = unsafe {
+        let main = engine
+            .get_function::<unsafe extern "C" fn(*mut i32, *mut *const c_char) -> i32>("jit.main")
+            .map_err(|e| e.to_string())?;
+        let status = main.call(&mut code, &mut message);
+        fflush(std::ptr::null_mut());
+        status
+    };
+    match status {
+        JIT_FAILED => Err(unsafe { CStr::from_ptr(message) }.to_string_lossy().trim_end().to_string()),
+        _ => Ok(code),
+    }
+}
+
+// Что вернул jit.main: main завершилась сама, вызвала exit() или упала с ошибкой.
+const JIT_EXITED: i32 = 1;
+const JIT_FAILED: i32 = 2;
+
+// `i32 jit.main(i32* code, ptr* message)`: setjmp, затем main. exit и rt.error заменяются
+// на longjmp обратно в jit.main — вместо завершения процесса хоста.
+fn add_jit_entry<'ctx>(module: &Module<'ctx>) -> Result<(), String> {
+    let context = module.get_context();
+    let i32_t = context.i32_type();
+    let ptr_t = context.ptr_type(AddressSpace::default());
+    let void_t = context.void_type();
+    let main = module.get_function("main").ok_or("function main not found")?;
+    let global = |name: &str, ty: BasicTypeEnum<'ctx>| {
+        let g = module.add_global(ty, None, name);
+        g.set_linkage(Linkage::Internal);
+        g.set_initializer(&ty.const_zero());
+        g.as_pointer_value()
+    };
+    // jmp_buf glibc — 200 байт; берём с запасом
+    let jmp_buf = global("jit.jmp_buf", context.i64_type().array_type(64).into());
+    let exit_code = global("jit.code", i32_t.into());
+    let error = global("jit.message", ptr_t.into());
+
+    let setjmp = libc_function(module, "_setjmp", i32_t.fn_type(&[ptr_t.into()], false));
+    let returns_twice = context.create_enum_attribute(Attribute::get_named_enum_kind_id("returns_twice"), 0);
+    setjmp.add_attribute(AttributeLoc::Function, returns_twice);
+    let longjmp = libc_function(module, "longjmp", void_t.fn_type(&[ptr_t.into(), i32_t.into()], false));
+
+    let builder = context.create_builder();
+    // замена: сохранить аргумент в `slot` и прыгнуть в jit.main с `status`
+    let jump = |name: &str, replaced: &str, arg: BasicTypeEnum<'ctx>, slot: PointerValue<'ctx>, status: u64| {
+        let f = module.add_function(name, void_t.fn_type(&[arg.into()], false), Some(Linkage::Internal));
+        builder.position_at_end(context.append_basic_block(f, "entry"));
+        builder.build_store(slot, f.get_first_param().expect("argument")).expect("store");
+        builder
+            .build_call(longjmp, &[jmp_buf.into(), i32_t.const_int(status, false).into()], "")
+            .expect("call longjmp");
+        builder.build_unreachable().expect("unreachable");
+        if let Some(old) = module.get_function(replaced).filter(|old| old.get_type() == f.get_type()) {
+            old.replace_all_uses_with(f);
+        }
+    };
+    jump("jit.exit", "exit", i32_t.into(), exit_code, JIT_EXITED as u64);
+    jump("jit.error", "rt.error", ptr_t.into(), error, JIT_FAILED as u64);
+
+    let entry = module.add_function("jit.main", i32_t.fn_type(&[ptr_t.into(), ptr_t.into()], false), None);
+    let code_out = entry.get_nth_param(0).expect("code").into_pointer_value();
+    let message_out = entry.get_nth_param(1).expect("message").into_pointer_value();
+    let start = context.append_basic_block(entry, "entry");
+    let run = context.append_basic_block(entry, "run");
+    let jumped = context.append_basic_block(entry, "jumped");
+    builder.position_at_end(start);
+    let status = builder.build_call(setjmp, &[jmp_buf.into()], "status").expect("call setjmp");
+    let status = status.try_as_basic_value().left().expect("int").into_int_value();
+    let first = builder.build_int_compare(IntPredicate::EQ, status, i32_t.const_zero(), "first").expect("cmp");
+    builder.build_conditional_branch(first, run, jumped).expect("brcond");
+
+    builder.position_at_end(run);
+    let code = builder.build_call(main, &[], "code").expect("call main");
+    builder.build_store(code_out, code.try_as_basic_value().left().expect("main returns i32")).expect("store");
+    builder.build_return(Some(&i32_t.const_zero())).expect("ret");
+
+    builder.position_at_end(jumped);
+    let code = builder.build_load(i32_t, exit_code, "code").expect("load");
+    builder.build_store(code_out, code).expect("store");
+    let message = builder.build_load(ptr_t, error, "message").expect("load");
+    builder.build_store(message_out, message).expect("store");
+    builder.build_return(Some(&status)).expect("ret");
+    module.verify().map_err(|e| e.to_string())
+}
+
+/// AOT: объектный файл под текущую машину, исполняемый файл собирает `cc`
+/// (он же подключает libc, против которой разрешаются extern-функции).
+pub fn write_executable(module: &Module, out: &Path) -> Result<(), String> {
+    Target::initialize_native(&InitializationConfig::default())?;
+    module.verify().map_err(|e| e.to_string())?;
+    let triple = TargetMachine::get_default_triple();
+    let target = Target::from_triple(&triple).map_err(|e| e.to_string())?;
+    let machine = target
+        .create_target_machine(
+            &triple,
+            &TargetMachine::get_host_cpu_name().to_string(),
+            &TargetMachine::get_host_cpu_features().to_string(),
+            OptimizationLevel::Default,
+            RelocMode::PIC,
+            CodeModel::Default,
+        )
+        .ok_or("cannot create target machine for the host")?;
+    module.set_triple(&triple);
+    module.set_data_layout(&machine.get_target_data().get_data_layout());
+    let obj = out.with_extension("o");
+    machine
+        .write_to_file(module, FileType::Object, &obj)
+        .map_err(|e| e.to_string())?;
+    let status = Command::new("cc")
+        .arg(&obj)
+        .arg("-o")
+        .arg(out)
+        .status()
+        .map_err(|e| format!("failed to run cc: {}", e))?;
+    if !status.success() {
+        return Err(format!("cc exited with {}", status));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{compile_and_run, interpret_with_input, load};
+
+    // Один и тот же вывод и код выхода у Codegen и интерпретатора.
+    fn same_as_interpreter(src: &str) -> String {
+        same_with_input(src, "")
+    }
+
+    fn same_with_input(src: &str, input: &str) -> String {
+        let (expected, result) = interpret_with_input(src, input);
+        let run = compile_and_run(src, input);
+        assert_eq!(run.stdout, expected, "{}", src);
+        // код выхода процесса — младший байт результата main или аргумента exit
+        assert_eq!(Ok(run.code), result.map(|o| o.code().expect("int result") as u8 as i32), "{}", src);
+        run.stdout
+    }
+
+    // Обе стороны падают с одной и той же ошибкой после одного и того же вывода.
+    fn same_error(src: &str) -> String {
+        let (expected, result) = interpret_with_input(src, "");
+        let message = result.expect_err("interpreter should fail");
+        let run = compile_and_run(src, "");
+        assert_eq!(run.stdout, expected, "{}", src);
+        assert_eq!((run.code, run.stderr.trim_end()), (101, message.as_str()), "{}", src);
+        message
+    }
+
+    #[test]
+    fn literals() {
+        let src = r#"fn main() {
+            println(0xff + 0o17 + 0b101 + 1_000_000);
+            println(true);
+            println('ж');
+            print('a'); print('€'); println('\u{1F600}');
+            println("tab\there \"q\" \u{41}");
+            let s = "same";
+            print(s);
+            println(s);
+            7
+        }"#;
+        same_as_interpreter(src);
+    }
+
+    #[test]
+    fn read_int_reads_one_token() {
+        let src = r#"fn main() {
+            let a = read_int();
+            let b = read_int();
+            let c = read_int();
+            println(a);
+            println(b);
+            println(c);
+            c
+        }"#;
+        assert_eq!(same_with_input(src, "1 2\n  -3 tail"), "1\n2\n-3\n");
+        // нечисловой текст остаётся во входе: дальше тоже 0
+        assert_eq!(same_with_input(src, "7\nabc 5"), "7\n0\n0\n");
+        assert_eq!(same_with_input(src, ""), "0\n0\n0\n");
+        assert_eq!(same_with_input(src, "+4 99999999999999999999 1"), "4\n9223372036854775807\n1\n");
+    }
+
+    #[test]
+    fn exit_code_and_buffered_output() {
+        let src = r#"fn main() {
+            print("before ");
+            println(1);
+            exit(3);
+            println("never");
+            0
+        }"#;
+        assert_eq!(same_as_interpreter(src), "before 1\n");
+    }
+
+    #[test]
+    fn arrays() {
+        let src = r#"fn main() {
+            let a = [1, 2, 3];
+            let b = [0; 100];
+            a[1] = a[0] + a[2];
+            b[99] = len(a);
+            let i = 0;
+            let sum = 0;
+            while (i < len(b)) {
+                sum = sum + b[i];
+                i = i + 1;
+            }
+            println(a);
+            println(sum);
+            a[1]
+        }"#;
+        assert_eq!(same_as_interpreter(src), "[1, 4, 3]\n3\n");
+    }
+
+    #[test]
+    fn index_out_of_bounds() {
+        let src = "fn main() {\n    let a = [1, 2, 3];\n    let i = 3;\n    println(a[0]);\n    a[i]\n}";
+        assert_eq!(same_error(src), "runtime error at 5:5: index 3 out of bounds for length 3");
+        same_error("fn main() {\n    let a = [0; 4];\n    a[0 - 1] = 1;\n    0\n}");
+    }
+
+    #[test]
+    fn division_by_zero() {
+        let src = "fn main() {\n    let z = 0;\n    println(7 / 2);\n    let x = 1 / z;\n    x\n}";
+        assert_eq!(same_error(src), "runtime error at 4:5: division by zero");
+    }
+
+    #[test]
+    fn structs() {
+        let src = r#"struct Point { x: i64, y: i64 }
+        struct Tagged { tag: char, p: Point, items: [i64] }
+        fn sum(t: Tagged) -> i64 { t.p.x + t.p.y + len(t.items) }
+        fn main() {
+            let p = Point { y: 2, x: 1 };
+            p.x = p.x + 10;
+            let t = Tagged { tag: 'ы', p: p, items: [7, 8, 9] };
+            t.p.y = 5;
+            println(t.p);
+            println(t.tag);
+            println(t.items);
+            sum(t)
+        }"#;
+        assert_eq!(same_as_interpreter(src), "Point { x: 11, y: 5 }\nы\n[7, 8, 9]\n");
+    }
+
+    #[test]
+    fn assignments_take_the_place_type() {
+        let src = r#"struct Bag { items: [i64] }
+        fn main() {
+            let s: [i64] = [1, 2];
+            s = [3, 4, 5];
+            let b = Bag { items: [0] };
+            b.items = [7, 8];
+            let c = 'a';
+            c = 'ё';
+            println(s);
+            println(b.items);
+            println(c);
+            len(s) + len(b.items)
+        }"#;
+        assert_eq!(same_as_interpreter(src), "[3, 4, 5]\n[7, 8]\nё\n");
+    }
+
+    #[test]
+    fn pointers_and_heap() {
+        let src = r#"struct Node { value: i64, next: *Node }
+        fn push(head: *Node, v: i64) -> *Node { alloc(Node { value: v, next: head }) }
+        fn bump(p: *i64) -> i64 { *p = *p + 1; *p }
+        fn main() {
+            let head: *Node = null;
+            println(head);
+            for i in 1..=3 {
+                head = push(head, i * i);
+            }
+            println(head.value);
+            let sum = 0;
+            while (head != null) {
+                sum = sum + head.value;
+                let next = head.next;
+                free(head);
+                head = next;
+            }
+            let x = 40;
+            bump(&x);
+            println(x);
+            sum
+        }"#;
+        assert_eq!(same_as_interpreter(src), "null\n9\n41\n");
+        same_error("fn main() {\n    let p: *i64 = null;\n    println(1);\n    *p\n}");
+    }
+
+    // Текст LLVM IR программы (без sema).
+    fn ir(src: &str) -> String {
+        let (program, sources) = load(src);
+        let context = Context::create();
+        let mut codegen = Codegen::new(&context, "test", &sources);
+        codegen.compile_program(&program);
+        codegen.module().print_to_string().to_string()
+    }
+
+    #[test]
+    fn closures() {
+        let src = r#"fn make_adder(n: i64) -> fn(i64) -> i64 { |x| x + n }
+        fn fold(xs: [i64], init: i64, f: fn(i64, i64) -> i64) -> i64 {
+            let acc = init;
+            for i in 0..len(xs) {
+                acc = f(acc, xs[i]);
+            }
+            acc
+        }
+        fn twice(x: i64) -> i64 { x * 2 }
+        fn main() {
+            let add5 = make_adder(5);
+            let k = 10;
+            let scale = |x| x * k;
+            let total = 0;
+            for i in 0..3 {
+                let shift = |x| x + i;
+                total = total + shift(scale(add5(i)));
+            }
+            let apply: fn(i64) -> i64 = twice;
+            println(total);
+            println(fold([1, 2, 3], 0, |a, b| a + b * k));
+            apply(add5(1))
+        }"#;
+        assert_eq!(same_as_interpreter(src), "183\n60\n");
+    }
+
+    #[test]
+    fn called_only_closures_keep_env_on_the_stack() {
+        let local = ir("fn main() { let k = 3; let f = |x| x * k; f(2) + f(3) }");
+        assert!(!local.contains("@malloc"), "{}", local);
+        // возвращённое замыкание переживает функцию — окружение в куче
+        let escaping = ir("fn make(k: i64) -> fn(i64) -> i64 { let f = |x| x * k; f }\nfn main() { make(2)(3) }");
+        assert!(escaping.contains("@malloc"), "{}", escaping);
+        let captured = ir("fn main() { let k = 3; let f = |x| x * k; let g = |y| f(y) + 1; g(1) }");
+        assert_eq!(captured.matches("call ptr @malloc").count(), 1, "{}", captured);
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot use a value of type")]
+    fn mismatched_block_value_is_not_replaced_with_zero() {
+        ir("fn f() -> bool { \"text\" }\nfn main() { 0 }");
+    }
+
+    #[test]
+    fn for_loops_stop_before_overflow() {
+        let src = r#"fn main() {
+            let max = 9223372036854775807;
+            let min = 0 - max - 1;
+            let n = 0;
+            for i in max - 2..=max {
+                n = n + 1;
+                println(i);
+            }
+            for i in min + 5..=min step -3 {
+                n = n + 1;
+                println(i);
+            }
+            for i in max - 10..max step 4 {
+                n = n + 1;
+            }
+            for i in 10..0 {
+                n = n + 100;
+            }
+            for i in 0..=10 step 5 {
+                n = n + 1;
+                i = 100;
+            }
+            n
+        }"#;
+        assert_eq!(
+            same_as_interpreter(src),
+            "9223372036854775805\n9223372036854775806\n9223372036854775807\n\
+             -9223372036854775803\n-9223372036854775806\n"
+        );
+    }
+
+    #[test]
+    fn blocks_and_ifs_are_values() {
+        let src = r#"fn sign(x: i64) -> i64 { if x > 0 { 1 } else if x == 0 { 0 } else { 0 - 1 } }
+        fn main() {
+            let a = { let t = 4; t * t };
+            let b = { 42; };
+            let s = if (a > 10) { "big" } else { "small" };
+            let c = if (a > 100) { 7 };
+            let d = if (a > 10) { 5; } else { 6; };
+            let e = if (a > 10) { "s"; };
+            if (a > 10) { println(s) }
+            println(e);
+            println(sign(0 - 3) + sign(0) * 10 + sign(9) * 100);
+            a + b + c + d
+        }"#;
+        assert_eq!(same_as_interpreter(src), "big\n0\n99\n");
+    }
+
+    #[test]
+    fn match_on_integers() {
+        let src = r#"fn classify(x: i64) -> i64 {
+            match x {
+                0 => 100,
+                1 | 2 => 200,
+                3..=9 => { let y = x * 2; y },
+                -5..0 => 0 - 1,
+                _ => match x { 10..=99 => 1000, _ => 2000 }
+            }
+        }
+        fn main() {
+            for i in 0 - 7..12 {
+                print(classify(i));
+                print(" ");
+            }
+            println();
+            let c = 'b';
+            let k = match c { 'a' => 1, 'b'..='z' => 2, _ => 3 };
+            k
+        }"#;
+        assert_eq!(
+            same_as_interpreter(src),
+            "2000 2000 -1 -1 -1 -1 -1 100 200 200 6 8 10 12 14 16 18 1000 1000 \n"
+        );
+        // одиночные значения — через switch, диапазоны — сравнениями
+        assert!(ir(src).contains("switch i64"));
+    }
+
+    #[test]
+    fn prelude() {
+        let src = r#"fn main() {
+            let a = [3, 0 - 7, 12, 3, 5];
+            println(abs(0 - 4) + min(2, 9) * 10 + max(2, 9) * 100);
+            println(rem(0 - 7, 3));
+            println(pow(3, 4));
+            println(gcd(0 - 12, 18));
+            println(is_prime(97));
+            println(is_prime(91));
+            println(sum(a));
+            println(min_of(a));
+            println(max_of(a));
+            println(index_of(a, 12));
+            println(index_of(a, 99));
+            println(contains(a, 5));
+            println(count(a, 3));
+            0
+        }"#;
+        assert_eq!(
+            same_as_interpreter(src),
+            "924\n-1\n81\n6\ntrue\nfalse\n16\n-7\n12\n2\n-1\ntrue\n2\n"
+        );
+    }
+
+    #[test]
+    fn extern_functions_match_the_host_registry() {
+        // i32 обрезает аргумент и расширяет результат, void даёт 0
+        let src = r#"extern fn labs(x: i64) -> i64;
+        extern fn toupper(c: i32) -> i32;
+        extern fn strlen(s: str) -> i64;
+        extern fn atoi(s: str) -> i32;
+        fn main() {
+            println(labs(0 - 42));
+            println(toupper(97));
+            println(toupper(4294967393));
+            println(strlen("hello"));
+            println(atoi("  -17abc"));
+            atoi("300")
+        }"#;
+        assert_eq!(same_as_interpreter(src), "42\n65\n65\n5\n-17\n");
+    }
+
+    fn jit(src: &str) -> Result<i32, String> {
+        let (program, sources) = load(src);
+        let context = Context::create();
+        let mut codegen = Codegen::new(&context, "test", &sources);
+        codegen.compile_program(&program);
+        codegen.run_jit()
+    }
+
+    #[test]
+    fn jit_errors_and_exit_return_to_the_host() {
+        let src = "fn main() {\n    let z = 0;\n    let a = [1, 2];\n    a[5 / z]\n}";
+        assert_eq!(jit(src), Err("runtime error at 4:5: division by zero".to_string()));
+        assert_eq!(jit("fn f(n: i64) { exit(n); 1 }\nfn main() { f(7) + 1 }"), Ok(7));
+        // после longjmp хост продолжает работу, следующий запуск — с чистого листа
+        assert_eq!(jit("fn main() { 42 }"), Ok(42));
+        let src = "fn main() {\n    let a = [1, 2];\n    let i = 2;\n    a[i]\n}";
+        assert_eq!(jit(src), Err("runtime error at 4:5: index 2 out of bounds for length 2".to_string()));
+    }
+
+    extern "C" fn twice(n: i64) -> i64 {
+        n * 2
+    }
+
+    #[test]
+    fn jit_calls_host_natives() {
+        let host = HostFunction::new("twice", vec![ExternType::Value(Type::Int)], ExternType::Value(Type::Int), |_| {
+            Err("interpreter only".into())
+        });
+        let host = host.with_native(twice as *const () as usize);
+        let (program, sources) =
+            crate::modules::load_source(Path::new("test.src"), "fn main() { twice(21) }".into(), std::slice::from_ref(&host.sig))
+                .expect("load");
+        let context = Context::create();
+        let mut codegen = Codegen::new(&context, "test", &sources);
+        codegen.register_host(&host);
+        codegen.compile_program(&program);
+        assert_eq!(codegen.run_jit(), Ok(42));
+    }
+
+    #[test]
+    fn division_wraps_like_the_interpreter() {
+        let src = "fn main() { let m = 0 - 9223372036854775807 - 1; println(m / (0 - 1)); println(0 - 7 / 2); println(m * 2 - 1); 0 }";
+        assert_eq!(same_as_interpreter(src), "-9223372036854775808\n-3\n-1\n");
+    }
+}
@@ -0,0 +1,623 @@
+use std::cell::{Cell, RefCell};
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::ast::{Program, Stmt};
+use crate::debugger::{self, Resume, Stops};
+use crate::host::HostFunction;
+use crate::interpreter::{Hook, Interpreter, RuntimeError};
+use crate::json::{self, Json};
+use crate::lexer::Span;
+use crate::modules::{self, SourceMap};
+use crate::sema::{self, Severity};
+
+// `core dap` — Debug Adapter Protocol поверх stdio для VS Code и других клиентов.
+// Сообщения — JSON с заголовком Content-Length. Порядок: initialize -> launch
+// (программа грузится и проверяется sema) -> событие initialized -> setBreakpoints ->
+// configurationDone -> выполнение main под Stops из debugger.rs. Поток один (id 1);
+// запросы читаются только на остановках, так что pause не поддерживается. Вывод
+// программы идёт событиями output, read_int под DAP недоступен (stdin занят).
+
+const THREAD: i64 = 1;
+const QUIT: &str = "disconnected by the client";
+
+/// Связь с клиентом (stdin/stdout); seq общий с выводом программы (OutputEvents).
+#[derive(Clone)]
+struct Client {
+    seq: Rc<Cell<i64>>,
+    input: Rc<RefCell<Box<dyn BufRead>>>,
+    output: Rc<RefCell<Box<dyn Write>>>,
+}
+
+impl Client {
+    fn read(&self) -> Option<Json> {
+        json::read_message(&mut **self.input.borrow_mut())
+    }
+
+    fn send(&self, mut fields: Vec<(&str, Json)>) {
+        self.seq.set(self.seq.get() + 1);
+        fields.insert(0, ("seq", self.seq.get().into()));
+        json::write_message(&mut **self.output.borrow_mut(), &Json::object(fields));
+    }
+
+    // body: null клиенту не шлём — поле просто опускаем
+    fn respond(&self, req: &Json, body: Json) {
+        let mut fields = vec![
+            ("type", "response".into()),
+            ("request_seq", req.get("seq").cloned().unwrap_or(Json::Null)),
+            ("success", true.into()),
+            ("command", req.get("command").cloned().unwrap_or(Json::Null)),
+        ];
+        if body != Json::Null {
+            fields.push(("body", body));
+        }
+        self.send(fields);
+    }
+
+    fn fail(&self, req: &Json, message: &str) {
+        self.send(vec![
+            ("type", "response".into()),
+            ("request_seq", req.get("seq").cloned().unwrap_or(Json::Null)),
+            ("success", false.into()),
+            ("command", req.get("command").cloned().unwrap_or(Json::Null)),
+            ("message", message.into()),
+        ]);
+    }
+
+    fn event(&self, event: &str, body: Json) {
+        let mut fields = vec![("type", "event".into()), ("event", event.into())];
+        if body != Json::Null {
+            fields.push(("body", body));
+        }
+        self.send(fields);
+    }
+}
+
+// print/println программы — событиями output
+struct OutputEvents(Client);
+
+impl Write for OutputEvents {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = String::from_utf8_lossy(buf).to_string();
+        self.0.event("output", Json::object(vec![("category", "stdout".into()), ("output", text.into())]));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Что делать после запроса.
+enum Flow {
+    Wait,
+    Run,
+    Quit,
+}
+
+// Программа после launch.
+struct Launched {
+    program: Program,
+    sources: SourceMap,
+    stops: Stops,
+}
+
+struct Session {
+    client: Client,
+    launched: Option<Launched>,
+    stop_on_entry: bool,
+    // клиент может считать строки и столбцы с 0
+    line_base: usize,
+    column_base: usize,
+}
+
+/// Весь сеанс: конфигурация, выполнение, затем ожидание disconnect.
+pub fn run(hosts: &[HostFunction]) {
+    serve(hosts, Box::new(io::stdin().lock()), Box::new(io::stdout()));
+}
+
+fn serve(hosts: &[HostFunction], input: Box<dyn BufRead>, output: Box<dyn Write>) {
+    let client = Client {
+        seq: Rc::new(Cell::new(0)),
+        input: Rc::new(RefCell::new(input)),
+        output: Rc::new(RefCell::new(output)),
+    };
+    let mut session =
+        Session { client: client.clone(), launched: None, stop_on_entry: false, line_base: 1, column_base: 1 };
+    loop {
+        let Some(req) = client.read() else { return };
+        match session.configure(&req, hosts) {
+            Flow::Wait => {}
+            Flow::Run => break,
+            Flow::Quit => return,
+        }
+    }
+    let Some(Launched { program, sources, mut stops }) = session.launched.take() else { return };
+
+    let mut interp = Interpreter::new();
+    interp.set_trace(false);
+    interp.set_output(Box::new(OutputEvents(client.clone())));
+    // stdin — это запросы клиента: read_int из него читать нельзя, вход пустой
+    interp.set_input(Box::new(io::empty()));
+    for h in hosts {
+        interp.register_host(h);
+    }
+    stops.resume(if session.stop_on_entry { Resume::StepIn } else { Resume::Continue }, &interp);
+    let quit = Rc::new(Cell::new(false));
+    let entry = session.stop_on_entry;
+    interp.set_hook(Box::new(Adapter { session, sources: sources.clone(), stops, entry, quit: quit.clone() }));
+    let result = interp.run_program(&program);
+    if quit.get() {
+        return;
+    }
+    let code = match &result {
+        Ok(outcome) => outcome.code().unwrap_or(0),
+        Err(e) => {
+            let text = format!("runtime error at {}: {}\n", sources.location(e.span.start), e);
+            client.event("output", Json::object(vec![("category", "stderr".into()), ("output", text.into())]));
+            1
+        }
+    };
+    client.event("exited", Json::object(vec![("exitCode", code.into())]));
+    client.event("terminated", Json::object(vec![]));
+    // программа кончилась — отвечаем, пока клиент не отключится
+    while let Some(req) = client.read() {
+        match req.get("command").and_then(Json::as_str) {
+            Some("disconnect") => {
+                client.respond(&req, Json::Null);
+                return;
+            }
+            Some("threads") => client.respond(&req, threads()),
+            _ => client.fail(&req, "the program has finished"),
+        }
+    }
+}
+
+fn threads() -> Json {
+    let thread = Json::object(vec![("id", THREAD.into()), ("name", "main".into())]);
+    Json::object(vec![("threads", vec![thread].into())])
+}
+
+impl Session {
+    // До configurationDone: initialize, launch, setBreakpoints.
+    fn configure(&mut self, req: &Json, hosts: &[HostFunction]) -> Flow {
+        let args = req.get("arguments").cloned().unwrap_or(Json::Null);
+        match req.get("command").and_then(Json::as_str).unwrap_or("") {
+            "initialize" => {
+                if args.get("linesStartAt1").and_then(Json::as_bool) == Some(false) {
+                    self.line_base = 0;
+                }
+                if args.get("columnsStartAt1").and_then(Json::as_bool) == Some(false) {
+                    self.column_base = 0;
+                }
+                self.client.respond(
+                    req,
+                    Json::object(vec![
+                        ("supportsConfigurationDoneRequest", true.into()),
+                        ("supportsEvaluateForHovers", true.into()),
+                        ("supportsTerminateRequest", true.into()),
+                    ]),
+                );
+            }
+            "launch" => match self.launch(&args, hosts) {
+                Ok(launched) => {
+                    self.launched = Some(launched);
+                    self.client.respond(req, Json::Null);
+                    // точки останова имеет смысл ставить, только когда программа загружена
+                    self.client.event("initialized", Json::Null);
+                }
+                Err(e) => self.client.fail(req, &e),
+            },
+            "setBreakpoints" => match self.launched.take() {
+                Some(mut l) => {
+                    self.set_breakpoints(&mut l.stops, &l.sources, req, &args);
+                    self.launched = Some(l);
+                }
+                None => self.client.fail(req, "launch the program first"),
+            },
+            "threads" => self.client.respond(req, threads()),
+            "configurationDone" => {
+                self.client.respond(req, Json::Null);
+                if self.launched.is_some() {
+                    return Flow::Run;
+                }
+            }
+            "disconnect" | "terminate" => {
+                self.client.respond(req, Json::Null);
+                return Flow::Quit;
+            }
+            _ => self.client.fail(req, "not available before the program starts"),
+        }
+        Flow::Wait
+    }
+
+    fn launch(&mut self, args: &Json, hosts: &[HostFunction]) -> Result<Launched, String> {
+        let path = args.get("program").and_then(Json::as_str).ok_or("launch needs \"program\"")?;
+        self.stop_on_entry = args.get("stopOnEntry").and_then(Json::as_bool).unwrap_or(false);
+        let sigs: Vec<_> = hosts.iter().map(|h| h.sig.clone()).collect();
+        let (program, sources) = modules::load_file(Path::new(path), &sigs)?;
+        let errors: Vec<String> = sema::check_program(&program)
+            .into_iter()
+            .filter(|d| d.severity == Severity::Error)
+            .map(|d| format!("error at {}: {}", sources.location(d.span.start), d.message))
+            .collect();
+        if !errors.is_empty() {
+            return Err(errors.join("\n"));
+        }
+        let stops = Stops::new(&program, &sources, Resume::Continue);
+        Ok(Launched { program, sources, stops })
+    }
+
+    // Заменяет все точки файла; неподходящие строки возвращаются с verified: false.
+    fn set_breakpoints(&self, stops: &mut Stops, sources: &SourceMap, req: &Json, args: &Json) {
+        let path = args.get("source").and_then(|s| s.get("path")).and_then(Json::as_str).unwrap_or("");
+        let file = source_index(sources, path);
+        if let Some(file) = file {
+            stops.clear_file(file);
+        }
+        let requested = args.get("breakpoints").and_then(Json::as_array).unwrap_or(&[]);
+        let mut result = Vec::new();
+        for bp in requested {
+            // строки внутри — всегда с 1
+            let line = (bp.get("line").and_then(Json::as_i64).unwrap_or(0) as usize + 1).saturating_sub(self.line_base);
+            let placed = file.and_then(|f| stops.set_breakpoint(f, line));
+            result.push(match placed {
+                Some(at) => Json::object(vec![("verified", true.into()), ("line", self.line(at).into())]),
+                None => Json::object(vec![
+                    ("verified", false.into()),
+                    ("line", self.line(line).into()),
+                    ("message", "no statement on or after this line".into()),
+                ]),
+            });
+        }
+        self.client.respond(req, Json::object(vec![("breakpoints", result.into())]));
+    }
+
+    // строка с 1 -> строка клиента
+    fn line(&self, line: usize) -> usize {
+        line + self.line_base - 1
+    }
+}
+
+// Путь клиента сравниваем и как есть, и после canonicalize: VS Code присылает абсолютный.
+fn source_index(sources: &SourceMap, path: &str) -> Option<usize> {
+    let wanted = Path::new(path).canonicalize().ok();
+    sources
+        .files()
+        .iter()
+        .position(|f| wanted.is_some() && f.path.canonicalize().ok() == wanted)
+        .or_else(|| debugger::file_index(sources, Some(path)))
+}
+
+// Хук на время выполнения: на остановке отвечает на запросы до continue/next/step*.
+struct Adapter {
+    session: Session,
+    sources: SourceMap,
+    stops: Stops,
+    // первая остановка при stopOnEntry — с причиной entry
+    entry: bool,
+    // клиент отключился: run() не шлёт exited/terminated
+    quit: Rc<Cell<bool>>,
+}
+
+impl Hook for Adapter {
+    fn before_stmt(&mut self, interp: &mut Interpreter, stmt: &Stmt) -> Result<(), RuntimeError> {
+        let Some(reason) = self.stops.check(&self.sources, interp, stmt) else {
+            return Ok(());
+        };
+        let reason = if self.entry { "entry" } else { reason };
+        self.entry = false;
+        let client = self.session.client.clone();
+        client.event(
+            "stopped",
+            Json::object(vec![("reason", reason.into()), ("threadId", THREAD.into()), ("allThreadsStopped", true.into())]),
+        );
+        loop {
+            let Some(req) = client.read() else {
+                return Err(self.disconnect(interp));
+            };
+            let args = req.get("arguments").cloned().unwrap_or(Json::Null);
+            let resume = match req.get("command").and_then(Json::as_str).unwrap_or("") {
+                "continue" => Resume::Continue,
+                "next" => Resume::StepOver,
+                "stepIn" => Resume::StepIn,
+                "stepOut" => Resume::StepOut,
+                "disconnect" | "terminate" => {
+                    client.respond(&req, Json::Null);
+                    return Err(self.disconnect(interp));
+                }
+                "threads" => {
+                    client.respond(&req, threads());
+                    continue;
+                }
+                "stackTrace" => {
+                    client.respond(&req, self.stack_trace(interp));
+                    continue;
+                }
+                "scopes" => {
+                    let frame = args.get("frameId").and_then(Json::as_i64).unwrap_or(0);
+                    let scope = Json::object(vec![
+                        ("name", "Locals".into()),
+                        // 0 у DAP значит «нет потомков», поэтому кадр + 1
+                        ("variablesReference", (frame + 1).into()),
+                        ("expensive", false.into()),
+                    ]);
+                    client.respond(&req, Json::object(vec![("scopes", vec![scope].into())]));
+                    continue;
+                }
+                "variables" => {
+                    let frame = args.get("variablesReference").and_then(Json::as_i64).unwrap_or(1).max(1) as usize - 1;
+                    let vars = interp
+                        .locals(frame)
+                        .into_iter()
+                        .map(|(name, v)| {
+                            Json::object(vec![
+                                ("name", name.into()),
+                                ("value", v.to_string().into()),
+                                ("variablesReference", 0i64.into()),
+                            ])
+                        })
+                        .collect::<Vec<_>>();
+                    client.respond(&req, Json::object(vec![("variables", vars.into())]));
+                    continue;
+                }
+                "evaluate" => {
+                    let frame = args.get("frameId").and_then(Json::as_i64).unwrap_or(0);
+                    let expr = args.get("expression").and_then(Json::as_str).unwrap_or("");
+                    if frame != 0 {
+                        client.fail(&req, "expressions are evaluated in the top frame only");
+                        continue;
+                    }
+                    match debugger::evaluate(interp, expr) {
+                        Ok(v) => client.respond(&req, Json::object(vec![("result", v.into()), ("variablesReference", 0i64.into())])),
+                        Err(e) => client.fail(&req, &e),
+                    }
+                    continue;
+                }
+                "setBreakpoints" => {
+                    self.session.set_breakpoints(&mut self.stops, &self.sources, &req, &args);
+                    continue;
+                }
+                _ => {
+                    client.fail(&req, "unsupported request");
+                    continue;
+                }
+            };
+            self.stops.resume(resume, interp);
+            client.respond(&req, Json::object(vec![("allThreadsContinued", true.into())]));
+            return Ok(());
+        }
+    }
+}
+
+impl Adapter {
+    fn disconnect(&mut self, interp: &Interpreter) -> RuntimeError {
+        self.quit.set(true);
+        RuntimeError { message: QUIT.to_string(), span: interp.stack()[0].1.clone(), notes: Vec::new() }
+    }
+
+    fn stack_trace(&self, interp: &Interpreter) -> Json {
+        let frames = interp
+            .stack()
+            .iter()
+            .enumerate()
+            .map(|(id, (function, span))| {
+                let mut fields = vec![("id", id.into()), ("name", function.as_str().into())];
+                fields.extend(self.location(span));
+                Json::object(fields)
+            })
+            .collect::<Vec<_>>();
+        let total = frames.len();
+        Json::object(vec![("stackFrames", frames.into()), ("totalFrames", total.into())])
+    }
+
+    fn location(&self, span: &Span) -> Vec<(&'static str, Json)> {
+        let Some((file, line, col)) = self.sources.position(span.start) else {
+            return vec![("line", 0usize.into()), ("column", 0usize.into())];
+        };
+        let path = &self.sources.files()[file].path;
+        let name = path.file_name().map_or_else(|| path.display().to_string(), |n| n.to_string_lossy().to_string());
+        let source = Json::object(vec![("name", name.into()), ("path", path.display().to_string().into())]);
+        vec![
+            ("source", source),
+            ("line", self.session.line(line).into()),
+            ("column", (col + self.session.column_base - 1).into()),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::testutil::{scratch, Output};
+
+    const SRC: &str = "fn twice(x: i64) -> i64 {
+    let y = x * 2;
+    y
+}
+fn main() {
+    let x = 3;
+    println(x);
+    twice(x)
+}";
+
+    // Сеанс целиком: программа `src` в файле, запросы без seq (нумеруются здесь),
+    // вместо "<path>" в аргументах — путь к программе. Результат — все сообщения адаптера.
+    fn session(src: &str, requests: &[(&str, Json)]) -> Vec<Json> {
+        let path = scratch("dap.src");
+        fs::write(&path, src).expect("write program");
+        let mut input = Vec::new();
+        for (seq, (command, args)) in requests.iter().enumerate() {
+            let args = args.to_string().replace("<path>", &path.display().to_string());
+            let req = Json::object(vec![
+                ("seq", (seq + 1).into()),
+                ("type", "request".into()),
+                ("command", (*command).into()),
+                ("arguments", json::parse(&args).expect("arguments")),
+            ]);
+            json::write_message(&mut input, &req);
+        }
+        let output = Output::default();
+        serve(&[], Box::new(Cursor::new(input)), Box::new(output.clone()));
+        let _ = fs::remove_file(&path);
+        let text = output.text();
+        let mut reader = Cursor::new(text.as_bytes());
+        std::iter::from_fn(|| json::read_message(&mut reader)).collect()
+    }
+
+    fn args(text: &str) -> Json {
+        json::parse(text).expect("arguments")
+    }
+
+    fn launch(stop_on_entry: bool) -> (&'static str, Json) {
+        ("launch", Json::object(vec![("program", "<path>".into()), ("stopOnEntry", stop_on_entry.into())]))
+    }
+
+    fn breakpoints(lines: &[usize]) -> (&'static str, Json) {
+        let bps = lines.iter().map(|&l| Json::object(vec![("line", l.into())])).collect::<Vec<_>>();
+        ("setBreakpoints", Json::object(vec![("source", args(r#"{"path": "<path>"}"#)), ("breakpoints", bps.into())]))
+    }
+
+    fn responses<'a>(messages: &'a [Json], command: &str) -> Vec<&'a Json> {
+        messages
+            .iter()
+            .filter(|m| m.get("type") == Some(&"response".into()) && m.get("command") == Some(&command.into()))
+            .collect()
+    }
+
+    fn response<'a>(messages: &'a [Json], command: &str) -> &'a Json {
+        responses(messages, command).first().unwrap_or_else(|| panic!("no response to {}", command))
+    }
+
+    fn events<'a>(messages: &'a [Json], event: &str) -> Vec<&'a Json> {
+        messages.iter().filter(|m| m.get("event") == Some(&event.into())).collect()
+    }
+
+    fn field<'a>(message: &'a Json, path: &[&str]) -> &'a Json {
+        path.iter().fold(message, |m, key| m.get(key).unwrap_or_else(|| panic!("no {} in {}", key, message)))
+    }
+
+    #[test]
+    fn breakpoints_stack_and_variables() {
+        let messages = session(
+            SRC,
+            &[
+                ("initialize", args("{}")),
+                launch(false),
+                breakpoints(&[2, 5]),
+                ("configurationDone", args("{}")),
+                // main, строка 6: x ещё нет
+                ("evaluate", args(r#"{"expression": "x", "frameId": 0}"#)),
+                ("continue", args(r#"{"threadId": 1}"#)),
+                // twice, строка 2
+                ("stackTrace", args(r#"{"threadId": 1}"#)),
+                ("variables", args(r#"{"variablesReference": 1}"#)),
+                ("evaluate", args(r#"{"expression": "x + 1", "frameId": 0}"#)),
+                ("evaluate", args(r#"{"expression": "x", "frameId": 1}"#)),
+                ("continue", args(r#"{"threadId": 1}"#)),
+                ("threads", args("{}")),
+                ("disconnect", args("{}")),
+            ],
+        );
+        // на строке 5 оператора нет — точка переезжает на 6
+        let placed = field(response(&messages, "setBreakpoints"), &["body", "breakpoints"]);
+        assert_eq!(placed.to_string(), r#"[{"verified":true,"line":2},{"verified":true,"line":6}]"#);
+        let initialized = messages.iter().position(|m| m.get("event") == Some(&"initialized".into()));
+        let set = messages.iter().position(|m| m.get("command") == Some(&"setBreakpoints".into()));
+        assert!(initialized.expect("initialized") < set.expect("setBreakpoints"));
+
+        let stopped = events(&messages, "stopped");
+        assert_eq!(stopped.len(), 2);
+        assert!(stopped.iter().all(|e| field(e, &["body", "reason"]) == &"breakpoint".into()));
+
+        let evaluated = responses(&messages, "evaluate");
+        assert_eq!(evaluated[0].get("success"), Some(&false.into()));
+        assert_eq!(field(evaluated[1], &["body", "result"]), &"4".into());
+        assert_eq!(field(evaluated[2], &["message"]), &"expressions are evaluated in the top frame only".into());
+
+        let frames = field(response(&messages, "stackTrace"), &["body", "stackFrames"]).as_array().expect("frames");
+        let frames = frames.iter().map(|f| (field(f, &["name"]).to_string(), field(f, &["line"]).to_string()));
+        assert_eq!(frames.collect::<Vec<_>>(), [("\"twice\"".into(), "2".into()), ("\"main\"".into(), "8".into())]);
+        let variables = field(response(&messages, "variables"), &["body", "variables"]);
+        assert_eq!(variables.to_string(), r#"[{"name":"x","value":"3","variablesReference":0}]"#);
+
+        // вывод программы пришёл между остановками
+        assert_eq!(field(events(&messages, "output")[0], &["body", "output"]), &"3\n".into());
+        assert_eq!(field(events(&messages, "exited")[0], &["body", "exitCode"]), &6i64.into());
+        assert_eq!(events(&messages, "terminated").len(), 1);
+        assert_eq!(response(&messages, "threads").get("success"), Some(&true.into()));
+        assert_eq!(response(&messages, "disconnect").get("success"), Some(&true.into()));
+    }
+
+    #[test]
+    fn entry_zero_based_lines_and_disconnect() {
+        let messages = session(
+            SRC,
+            &[
+                ("initialize", args(r#"{"linesStartAt1": false}"#)),
+                ("setBreakpoints", args(r#"{"source": {"path": "<path>"}, "breakpoints": []}"#)),
+                launch(true),
+                breakpoints(&[1, 20]),
+                ("configurationDone", args("{}")),
+                ("stackTrace", args(r#"{"threadId": 1}"#)),
+                ("pause", args(r#"{"threadId": 1}"#)),
+                ("disconnect", args("{}")),
+            ],
+        );
+        assert_eq!(field(response(&messages, "setBreakpoints"), &["message"]), &"launch the program first".into());
+        let placed = field(responses(&messages, "setBreakpoints")[1], &["body", "breakpoints"]);
+        assert_eq!(
+            placed.to_string(),
+            r#"[{"verified":true,"line":1},{"verified":false,"line":20,"message":"no statement on or after this line"}]"#
+        );
+        let stopped = events(&messages, "stopped");
+        assert_eq!(stopped.len(), 1);
+        assert_eq!(field(stopped[0], &["body", "reason"]), &"entry".into());
+        let frame = &field(response(&messages, "stackTrace"), &["body", "stackFrames"]).as_array().expect("frames")[0];
+        assert_eq!(field(frame, &["line"]), &5usize.into());
+        assert_eq!(field(response(&messages, "pause"), &["message"]), &"unsupported request".into());
+        // клиент ушёл на остановке: программа не доработала, exited нет
+        assert!(events(&messages, "exited").is_empty());
+        assert!(events(&messages, "output").is_empty());
+    }
+
+    #[test]
+    fn launch_and_runtime_errors() {
+        let messages = session("fn main() { let s: str = 1 + 1; 0 }", &[("initialize", args("{}")), launch(false)]);
+        let failed = response(&messages, "launch");
+        assert_eq!(failed.get("success"), Some(&false.into()));
+        assert!(field(failed, &["message"]).as_str().expect("message").contains("`s` is declared as str, but the value is i64"));
+        assert!(events(&messages, "initialized").is_empty());
+
+        let messages = session(
+            "fn main() {\n    let z = 0;\n    1 / z\n}",
+            &[("initialize", args("{}")), launch(false), ("configurationDone", args("{}")), ("next", args("{}"))],
+        );
+        let error = field(events(&messages, "output")[0], &["body"]);
+        assert_eq!(field(error, &["category"]), &"stderr".into());
+        assert_eq!(field(error, &["output"]), &"runtime error at 3:5: division by zero\n".into());
+        assert_eq!(field(events(&messages, "exited")[0], &["body", "exitCode"]), &1i64.into());
+        assert_eq!(field(response(&messages, "next"), &["message"]), &"the program has finished".into());
+    }
+
+    #[test]
+    fn exit_ends_the_session_with_its_code() {
+        let src = "fn main() {\n    println(1);\n    exit(3);\n    println(2);\n    0\n}";
+        let messages = session(
+            src,
+            &[("initialize", args("{}")), launch(false), ("configurationDone", args("{}")), ("disconnect", args("{}"))],
+        );
+        assert_eq!(field(events(&messages, "output")[0], &["body", "output"]), &"1\n".into());
+        assert_eq!(events(&messages, "output").len(), 1);
+        let exited = messages.iter().position(|m| m.get("event") == Some(&"exited".into())).expect("exited");
+        let terminated = messages.iter().position(|m| m.get("event") == Some(&"terminated".into())).expect("terminated");
+        assert!(exited < terminated);
+        assert_eq!(field(&messages[exited], &["body", "exitCode"]), &3i64.into());
+        // адаптер пережил exit() и ответил на disconnect
+        assert_eq!(response(&messages, "disconnect").get("success"), Some(&true.into()));
+    }
+}
@@ -0,0 +1,246 @@
+use std::path::Path;
+
+use inkwell::basic_block::BasicBlock;
+use inkwell::context::Context;
+use inkwell::debug_info::{
+    debug_metadata_version, AsDIScope, DICompileUnit, DIFile, DIFlags, DIFlagsConstants, DILocation, DISubprogram,
+    DIType, DWARFEmissionKind, DWARFSourceLanguage, DebugInfoBuilder,
+};
+use inkwell::module::{FlagBehavior, Module};
+use inkwell::values::{FunctionValue, PointerValue};
+use inkwell::AddressSpace;
+
+use crate::ast::Type;
+use crate::lexer::Span;
+use crate::modules::SourceMap;
+
+// DWARF для `-g`: одна единица компиляции на программу, DIFile на каждый загруженный
+// файл (импорты и прелюдия — свои файлы), subprogram на функцию и лямбду, строка и
+// столбец — из спанов операторов. Переменные описываются для целых, bool, char, строк,
+// указателей и массивов; структуры, срезы и замыкания отладчик пока не увидит.
+
+// кодировки DW_ATE_*
+const DW_ATE_BOOLEAN: u32 = 0x02;
+const DW_ATE_SIGNED: u32 = 0x05;
+const DW_ATE_UNSIGNED_CHAR: u32 = 0x08;
+const DW_ATE_UTF: u32 = 0x10;
+
+pub struct DebugInfo<'ctx> {
+    builder: DebugInfoBuilder<'ctx>,
+    unit: DICompileUnit<'ctx>,
+    // индекс — номер файла в SourceMap::files
+    files: Vec<DIFile<'ctx>>,
+    sources: &'ctx SourceMap,
+    // subprogram функции, в которую сейчас идёт код; лямбда кладёт свою поверх
+    scopes: Vec<DISubprogram<'ctx>>,
+}
+
+fn split(path: &Path) -> (String, String) {
+    let name = path.file_name().map_or_else(|| path.display().to_string(), |n| n.to_string_lossy().to_string());
+    let dir = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p.display().to_string(),
+        _ => ".".to_string(),
+    };
+    (name, dir)
+}
+
+impl<'ctx> DebugInfo<'ctx> {
+    pub fn new(module: &Module<'ctx>, context: &'ctx Context, sources: &'ctx SourceMap) -> Self {
+        let version = context.i32_type().const_int(debug_metadata_version() as u64, false);
+        module.add_basic_value_flag("Debug Info Version", FlagBehavior::Warning, version);
+        let (name, dir) = split(&sources.root().path);
+        let (builder, unit) = module.create_debug_info_builder(
+            true,
+            // своего кода языка в DWARF нет; C — чтобы gdb/lldb не гадали
+            DWARFSourceLanguage::C,
+            &name,
+            &dir,
+            "simple-llvm",
+            false,
+            "",
+            0,
+            "",
+            DWARFEmissionKind::Full,
+            0,
+            false,
+            false,
+            "",
+            "",
+        );
+        let files = sources
+            .files()
+            .iter()
+            .map(|f| {
+                let (name, dir) = split(&f.path);
+                builder.create_file(&name, &dir)
+            })
+            .collect();
+        Self { builder, unit, files, sources, scopes: Vec::new() }
+    }
+
+    // файл, строка, столбец; без спана — начало главного файла
+    fn position(&self, span: Option<&Span>) -> (DIFile<'ctx>, u32, u32) {
+        match span.and_then(|s| self.sources.position(s.start)) {
+            Some((file, line, col)) => (self.files[file], line as u32, col as u32),
+            None => (self.unit.get_file(), 0, 0),
+        }
+    }
+
+    /// Subprogram для функции; возвращает место её начала — его надо сразу поставить
+    /// билдеру, чтобы инструкции пролога не остались с местом предыдущей функции.
+    pub fn enter_function(
+        &mut self,
+        context: &'ctx Context,
+        func: FunctionValue<'ctx>,
+        name: &str,
+        span: Option<&Span>,
+    ) -> DILocation<'ctx> {
+        let (file, line, col) = self.position(span);
+        let ty = self.builder.create_subroutine_type(file, None, &[], DIFlags::ZERO);
+        let sp = self.builder.create_function(
+            file.as_debug_info_scope(),
+            name,
+            None,
+            file,
+            line,
+            ty,
+            true,
+            true,
+            line,
+            DIFlags::ZERO,
+            false,
+        );
+        func.set_subprogram(sp);
+        self.scopes.push(sp);
+        self.builder.create_debug_location(context, line, col, sp.as_debug_info_scope(), None)
+    }
+
+    pub fn leave_function(&mut self) {
+        self.scopes.pop();
+    }
+
+    /// Место оператора в текущей функции.
+    pub fn location(&self, context: &'ctx Context, span: &Span) -> Option<DILocation<'ctx>> {
+        let sp = self.scopes.last()?;
+        let (_, line, col) = self.position(Some(span));
+        Some(self.builder.create_debug_location(context, line, col, sp.as_debug_info_scope(), None))
+    }
+
+    /// llvm.dbg.declare для слота переменной; `arg` — номер параметра (с 1).
+    #[allow(clippy::too_many_arguments)]
+    pub fn declare_variable(
+        &self,
+        context: &'ctx Context,
+        name: &str,
+        ty: &Type,
+        arg: Option<u32>,
+        ptr: PointerValue<'ctx>,
+        span: Option<&Span>,
+        block: BasicBlock<'ctx>,
+    ) {
+        let (Some(sp), Some(di_ty)) = (self.scopes.last(), self.di_type(ty)) else {
+            return;
+        };
+        let (file, line, col) = self.position(span);
+        let scope = sp.as_debug_info_scope();
+        let var = match arg {
+            Some(n) => self.builder.create_parameter_variable(scope, name, n, file, line, di_ty, true, DIFlags::ZERO),
+            None => self.builder.create_auto_variable(scope, name, file, line, di_ty, true, DIFlags::ZERO, 0),
+        };
+        let loc = self.builder.create_debug_location(context, line, col, scope, None);
+        self.builder.insert_declare_at_end(ptr, Some(var), None, loc, block);
+    }
+
+    fn di_type(&self, ty: &Type) -> Option<DIType<'ctx>> {
+        let basic = |name: &str, bits: u64, encoding: u32| {
+            self.builder.create_basic_type(name, bits, encoding, DIFlags::ZERO).ok().map(|t| t.as_type())
+        };
+        let pointer = |name: &str, pointee: DIType<'ctx>| {
+            self.builder.create_pointer_type(name, pointee, 64, 0, AddressSpace::default()).as_type()
+        };
+        match ty {
+            Type::Int => basic("i64", 64, DW_ATE_SIGNED),
+            Type::Bool => basic("bool", 8, DW_ATE_BOOLEAN),
+            Type::Char => basic("char", 32, DW_ATE_UTF),
+            Type::Str => Some(pointer("str", basic("u8", 8, DW_ATE_UNSIGNED_CHAR)?)),
+            Type::Ptr(inner) => Some(pointer("ptr", self.di_type(inner)?)),
+            Type::Array(elem, n) => {
+                let elem_ty = self.di_type(elem)?;
+                let bits = size_in_bits(elem)? * *n as u64;
+                Some(self.builder.create_array_type(elem_ty, bits, 0, std::slice::from_ref(&(0..*n as i64))).as_type())
+            }
+            Type::Slice(_) | Type::Struct(_) | Type::Fn(..) => None,
+        }
+    }
+
+    /// Дописывает отложенные узлы метаданных; вызывать после всех функций.
+    pub fn finalize(&self) {
+        self.builder.finalize();
+    }
+}
+
+fn size_in_bits(ty: &Type) -> Option<u64> {
+    Some(match ty {
+        Type::Int | Type::Str | Type::Ptr(_) => 64,
+        Type::Bool => 8,
+        Type::Char => 32,
+        Type::Array(elem, n) => size_in_bits(elem)? * *n as u64,
+        Type::Slice(_) | Type::Struct(_) | Type::Fn(..) => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen::Codegen;
+    use crate::testutil::load;
+
+    // Текст модуля, собранного с `-g`; модуль проходит verify.
+    fn debug_ir(src: &str) -> String {
+        let (program, sources) = load(src);
+        let context = Context::create();
+        let mut codegen = Codegen::new(&context, "test", &sources);
+        codegen.enable_debug_info();
+        codegen.compile_program(&program);
+        codegen.module().verify().expect("valid module");
+        codegen.module().print_to_string().to_string()
+    }
+
+    // Есть ли узел метаданных со всеми `parts`: номера узлов зависят от прелюдии,
+    // поэтому ищем по содержимому.
+    fn has(ir: &str, parts: &[&str]) -> bool {
+        ir.lines().any(|l| l.starts_with('!') && parts.iter().all(|p| l.contains(p)))
+    }
+
+    #[test]
+    fn functions_variables_and_lines() {
+        let src = "fn sq(n: i64) -> i64 { n * n }\nfn main() {\n    let x = sq(3);\n    let a = [1, 2, 3];\n    let f = |k: i64| k + x;\n    f(a[0])\n}";
+        let ir = debug_ir(src);
+        assert!(has(&ir, &["!DICompileUnit(", "producer: \"simple-llvm\""]), "{}", ir);
+        assert!(has(&ir, &["!DIFile(filename: \"test.src\"", "directory: \".\""]), "{}", ir);
+        assert!(has(&ir, &["!DISubprogram(name: \"sq\"", "line: 1,"]), "{}", ir);
+        assert!(has(&ir, &["!DISubprogram(name: \"main\"", "line: 3,"]), "{}", ir);
+        assert!(has(&ir, &["!DISubprogram(name: \"lambda.0\"", "line: 5,"]), "{}", ir);
+        assert!(has(&ir, &["!DILocalVariable(name: \"n\", arg: 1", "line: 1,"]), "{}", ir);
+        assert!(has(&ir, &["!DILocalVariable(name: \"k\", arg: 2", "line: 5,"]), "{}", ir);
+        // массив — DW_TAG_array_type с одним отрезком индексов
+        assert!(has(&ir, &["!DILocalVariable(name: \"a\", scope", "line: 4,"]), "{}", ir);
+        assert!(has(&ir, &["DW_TAG_array_type", "size: 192"]), "{}", ir);
+        assert!(ir.contains("!DISubrange(count: 3, lowerBound: 0)"), "{}", ir);
+        // каждый оператор main — своя строка
+        for line in 3..=6 {
+            assert!(ir.contains(&format!("!DILocation(line: {}, column: 5,", line)), "line {}", line);
+        }
+        // замыкание без имени в DWARF не описывается — отладчик его пока не видит
+        assert!(!ir.contains("!DILocalVariable(name: \"f\""), "{}", ir);
+    }
+
+    #[test]
+    fn imports_get_their_own_file() {
+        let (name, dir) = split(Path::new("lib/math.src"));
+        assert_eq!((name.as_str(), dir.as_str()), ("math.src", "lib"));
+        let ir = debug_ir("fn main() { 0 }");
+        // прелюдия — отдельный DIFile
+        assert!(ir.lines().filter(|l| l.contains("!DIFile(")).count() >= 2, "{}", ir);
+    }
+}
@@ -0,0 +1,735 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, BufRead, Write};
+
+use crate::ast::{Expr, Program, Stmt, StmtKind};
+use crate::host::HostFunction;
+use crate::interpreter::{Hook, Interpreter, Outcome, RuntimeError};
+use crate::json::{self, Json};
+use crate::lexer::{Lexer, Span};
+use crate::modules::SourceMap;
+use crate::parser::Parser;
+use crate::sema::{self, Severity};
+
+// Пошаговый отладчик поверх Interpreter::exec_stmt: перед каждым оператором решаем,
+// встать ли (точка останова или шаг), и на остановке читаем команды. Интерфейсов два,
+// команды у них одни: терминал (`core debug prog.src`) и JSON-строки для веб-GUI
+// (`core debug --json prog.src`) — команда в stdin, на каждую ровно одна строка-ответ
+// в stderr. stdout остаётся выводу программы.
+
+/// Сообщение ошибки, которой команда quit прерывает программу.
+const QUIT: &str = "stopped by the debugger";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Terminal,
+    Json,
+}
+
+/// Как продолжать после остановки.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    Continue,
+    // следующий оператор, хоть бы и в вызываемой функции
+    StepIn,
+    // следующий оператор этой функции (или вызвавшей, если она вернулась)
+    StepOver,
+    // первый оператор после возврата из этой функции
+    StepOut,
+}
+
+/// Общее для всех интерфейсов отладчика (терминал, JSON, DAP): где стоят точки
+/// останова и когда вставать перед оператором.
+pub struct Stops {
+    // (файл, строка) -> начало первого оператора на ней: точка останова встаёт на него
+    lines: BTreeMap<(usize, usize), usize>,
+    breakpoints: BTreeSet<(usize, usize)>,
+    resume: Resume,
+    // глубина стека на момент команды шага
+    depth: usize,
+}
+
+enum Command {
+    Break(Option<String>, usize),
+    Clear(Option<String>, usize),
+    Resume(Resume),
+    Print(String),
+    Watch(String),
+    Unwatch(usize),
+    Locals(usize),
+    Stack,
+    List,
+    Help,
+    Quit,
+}
+
+pub struct Debugger {
+    sources: SourceMap,
+    protocol: Protocol,
+    // до первой команды — шаг, чтобы встать на первом операторе main
+    stops: Stops,
+    watches: Vec<String>,
+    started: bool,
+    // прошлая команда терминала — пустая строка её повторяет
+    last: String,
+}
+
+/// `core debug`: проверка sema, затем main под отладчиком. Трассы нет, итог —
+/// событие exited (или terminated после quit).
+pub fn run(program: &Program, sources: &SourceMap, protocol: Protocol, hosts: &[HostFunction]) {
+    let errors: Vec<String> = sema::check_program(program)
+        .into_iter()
+        .filter(|d| d.severity == Severity::Error)
+        .map(|d| format!("error at {}: {}", sources.location(d.span.start), d.message))
+        .collect();
+    if !errors.is_empty() {
+        match protocol {
+            Protocol::Terminal => errors.iter().for_each(|e| eprintln!("{}", e)),
+            Protocol::Json => send(Json::object(vec![("event", "error".into()), ("message", errors.join("\n").into())])),
+        }
+        return;
+    }
+    let mut interp = Interpreter::new();
+    interp.set_trace(false);
+    for h in hosts {
+        interp.register_host(h);
+    }
+    interp.set_hook(Box::new(Debugger::new(program, sources.clone(), protocol)));
+    let result = interp.run_program(program);
+    io::stdout().flush().expect("flush stdout");
+    let terminated = matches!(&result, Err(e) if e.message == QUIT);
+    match (protocol, result) {
+        (_, Err(_)) if terminated => match protocol {
+            Protocol::Terminal => eprintln!("terminated"),
+            Protocol::Json => send(Json::object(vec![("event", "terminated".into())])),
+        },
+        (Protocol::Terminal, Ok(Outcome::Return(v))) => eprintln!("exited: main => {}", v),
+        (Protocol::Terminal, Ok(Outcome::Exit(code))) => eprintln!("exited: exit({})", code),
+        (Protocol::Terminal, Err(e)) => eprintln!("exited: runtime error at {}: {}", sources.location(e.span.start), e),
+        (Protocol::Json, Ok(Outcome::Return(v))) => {
+            send(Json::object(vec![("event", "exited".into()), ("value", v.to_string().into())]))
+        }
+        (Protocol::Json, Ok(Outcome::Exit(code))) => {
+            send(Json::object(vec![("event", "exited".into()), ("code", (code as i64).into())]))
+        }
+        (Protocol::Json, Err(e)) => {
+            let mut fields = vec![("event", "exited".into()), ("error", e.message.clone().into())];
+            fields.extend(position(sources, &e.span));
+            send(Json::object(fields));
+        }
+    }
+}
+
+fn send(event: Json) {
+    eprintln!("{}", event);
+}
+
+// file/line/column для JSON-событий
+fn position(sources: &SourceMap, span: &Span) -> Vec<(&'static str, Json)> {
+    match sources.position(span.start) {
+        Some((file, line, col)) => vec![
+            ("file", sources.files()[file].path.display().to_string().into()),
+            ("line", line.into()),
+            ("column", col.into()),
+        ],
+        None => Vec::new(),
+    }
+}
+
+/// Номер файла в SourceMap по имени (`math.src`) или пути; без имени — главный файл.
+pub fn file_index(sources: &SourceMap, name: Option<&str>) -> Option<usize> {
+    let files = sources.files();
+    match name {
+        None => files.iter().position(|f| f.path == sources.root().path),
+        Some(name) => files.iter().position(|f| f.path.ends_with(name) || f.path.display().to_string() == name),
+    }
+}
+
+impl Stops {
+    pub fn new(program: &Program, sources: &SourceMap, resume: Resume) -> Self {
+        let mut starts = Vec::new();
+        for f in &program.functions {
+            stmt_starts(&f.body, &mut starts);
+        }
+        let mut lines = BTreeMap::new();
+        for start in starts {
+            if let Some((file, line, _)) = sources.position(start) {
+                let first = lines.entry((file, line)).or_insert(start);
+                *first = (*first).min(start);
+            }
+        }
+        Self { lines, breakpoints: BTreeSet::new(), resume, depth: 0 }
+    }
+
+    /// Точка останова на строке; если на ней нет операторов — на ближайшей следующей,
+    /// как в gdb. Возвращает строку, где она встала.
+    pub fn set_breakpoint(&mut self, file: usize, line: usize) -> Option<usize> {
+        let (&at, _) = self.lines.range((file, line)..(file, usize::MAX)).next()?;
+        self.breakpoints.insert(at);
+        Some(at.1)
+    }
+
+    pub fn clear_breakpoint(&mut self, file: usize, line: usize) -> bool {
+        self.breakpoints.remove(&(file, line))
+    }
+
+    pub fn clear_file(&mut self, file: usize) {
+        self.breakpoints.retain(|(f, _)| *f != file);
+    }
+
+    pub fn has_breakpoint(&self, file: usize, line: usize) -> bool {
+        self.breakpoints.contains(&(file, line))
+    }
+
+    pub fn resume(&mut self, resume: Resume, interp: &Interpreter) {
+        self.resume = resume;
+        self.depth = interp.depth();
+    }
+
+    /// Причина остановки перед `stmt` ("step" или "breakpoint"), None — идём дальше.
+    pub fn check(&self, sources: &SourceMap, interp: &Interpreter, stmt: &Stmt) -> Option<&'static str> {
+        let pos = sources.position(stmt.span.start).map(|(file, line, _)| (file, line));
+        let at_breakpoint = pos.is_some_and(|p| self.breakpoints.contains(&p) && self.lines.get(&p) == Some(&stmt.span.start));
+        let depth = interp.depth();
+        let step = match self.resume {
+            Resume::Continue => false,
+            Resume::StepIn => true,
+            Resume::StepOver => depth <= self.depth,
+            Resume::StepOut => depth < self.depth,
+        };
+        if step {
+            Some("step")
+        } else if at_breakpoint {
+            Some("breakpoint")
+        } else {
+            None
+        }
+    }
+}
+
+impl Debugger {
+    pub fn new(program: &Program, sources: SourceMap, protocol: Protocol) -> Self {
+        Self {
+            stops: Stops::new(program, &sources, Resume::StepIn),
+            sources,
+            protocol,
+            watches: Vec::new(),
+            started: false,
+            last: String::new(),
+        }
+    }
+
+    fn set_breakpoint(&mut self, file: Option<&str>, line: usize) -> Result<(usize, usize), String> {
+        let file = file_index(&self.sources, file).ok_or("no such file")?;
+        let at = self.stops.set_breakpoint(file, line).ok_or_else(|| format!("no statement at or after line {}", line))?;
+        Ok((file, at))
+    }
+
+    fn clear_breakpoint(&mut self, file: Option<&str>, line: usize) -> Result<(usize, usize), String> {
+        let file = file_index(&self.sources, file).ok_or("no such file")?;
+        if self.stops.clear_breakpoint(file, line) {
+            Ok((file, line))
+        } else {
+            Err(format!("no breakpoint at line {}", line))
+        }
+    }
+
+    // Ждём команды до той, что продолжает выполнение. Конец ввода — как quit.
+    fn prompt(&mut self, interp: &mut Interpreter) -> Result<(), RuntimeError> {
+        let stdin = io::stdin();
+        loop {
+            if self.protocol == Protocol::Terminal {
+                eprint!("(debug) ");
+                io::stderr().flush().expect("flush stderr");
+            }
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line).expect("read stdin") == 0 {
+                return Err(quit(interp));
+            }
+            let command = match self.protocol {
+                Protocol::Terminal => {
+                    let line = line.trim();
+                    let line = if line.is_empty() { self.last.clone() } else { line.to_string() };
+                    self.last = line.clone();
+                    parse_terminal(&line)
+                }
+                Protocol::Json => parse_json(&line),
+            };
+            let command = match command {
+                Ok(c) => c,
+                Err(e) => {
+                    self.error(&e);
+                    continue;
+                }
+            };
+            match command {
+                Command::Resume(r) => {
+                    self.stops.resume(r, interp);
+                    return Ok(());
+                }
+                Command::Quit => return Err(quit(interp)),
+                Command::Break(file, line) => match self.set_breakpoint(file.as_deref(), line) {
+                    Ok(at) => self.breakpoint_event("breakpoint", at),
+                    Err(e) => self.error(&e),
+                },
+                Command::Clear(file, line) => match self.clear_breakpoint(file.as_deref(), line) {
+                    Ok(at) => self.breakpoint_event("cleared", at),
+                    Err(e) => self.error(&e),
+                },
+                Command::Print(src) => match evaluate(interp, &src) {
+                    Ok(v) => match self.protocol {
+                        Protocol::Terminal => eprintln!("{} = {}", src, v),
+                        Protocol::Json => send(Json::object(vec![
+                            ("event", "value".into()),
+                            ("expression", src.into()),
+                            ("value", v.into()),
+                        ])),
+                    },
+                    Err(e) => self.error(&e),
+                },
+                Command::Watch(src) => match parse_expression(&src) {
+                    Ok(_) => {
+                        self.watches.push(src);
+                        self.watches_event(interp);
+                    }
+                    Err(e) => self.error(&e),
+                },
+                Command::Unwatch(n) if n >= 1 && n <= self.watches.len() => {
+                    self.watches.remove(n - 1);
+                    self.watches_event(interp);
+                }
+                Command::Unwatch(n) => self.error(&format!("no watch {}", n)),
+                Command::Locals(frame) if frame < interp.stack().len() => self.locals_event(interp, frame),
+                Command::Locals(frame) => self.error(&format!("no frame {}", frame)),
+                Command::Stack => match self.protocol {
+                    Protocol::Terminal => self.print_stack(interp),
+                    Protocol::Json => send(Json::object(vec![("event", "stack".into()), ("frames", self.frames(interp))])),
+                },
+                Command::List => self.list(&interp.stack()[0].1),
+                Command::Help => eprint!("{}", HELP),
+            }
+        }
+    }
+
+    fn stopped(&mut self, interp: &mut Interpreter, reason: &str) {
+        let stack = interp.stack();
+        let (function, span) = &stack[0];
+        match self.protocol {
+            Protocol::Terminal => {
+                let place = if reason == "breakpoint" { "breakpoint at" } else { "at" };
+                eprintln!("{} {} in {}", place, self.sources.location(span.start), function);
+                self.source_line(span.start);
+                for (i, (src, value)) in self.watch_values(interp).into_iter().enumerate() {
+                    eprintln!("  watch {}: {} = {}", i + 1, src, value);
+                }
+            }
+            Protocol::Json => {
+                let mut fields = vec![("event", "stopped".into()), ("reason", reason.into()), ("function", function.as_str().into())];
+                fields.extend(position(&self.sources, span));
+                fields.push(("stack", self.frames(interp)));
+                fields.push(("locals", variables(interp, 0)));
+                fields.push(("watches", self.watches_json(interp)));
+                send(Json::object(fields));
+            }
+        }
+    }
+
+    fn error(&self, message: &str) {
+        match self.protocol {
+            Protocol::Terminal => eprintln!("error: {}", message),
+            Protocol::Json => send(Json::object(vec![("event", "error".into()), ("message", message.into())])),
+        }
+    }
+
+    fn breakpoint_event(&self, event: &str, (file, line): (usize, usize)) {
+        let path = self.sources.files()[file].path.display().to_string();
+        match self.protocol {
+            Protocol::Terminal if event == "cleared" => eprintln!("cleared breakpoint at {}:{}", path, line),
+            Protocol::Terminal => eprintln!("breakpoint at {}:{}", path, line),
+            Protocol::Json => send(Json::object(vec![
+                ("event", event.into()),
+                ("file", path.into()),
+                ("line", line.into()),
+            ])),
+        }
+    }
+
+    // Ошибку вычисления показываем вместо значения — watch не должен ронять остановку.
+    fn watch_values(&self, interp: &mut Interpreter) -> Vec<(String, String)> {
+        self.watches
+            .iter()
+            .map(|src| (src.clone(), evaluate(interp, src).unwrap_or_else(|e| format!("<{}>", e))))
+            .collect()
+    }
+
+    fn watches_json(&self, interp: &mut Interpreter) -> Json {
+        let items = self
+            .watch_values(interp)
+            .into_iter()
+            .map(|(src, value)| Json::object(vec![("expression", src.into()), ("value", value.into())]))
+            .collect::<Vec<_>>();
+        items.into()
+    }
+
+    fn watches_event(&self, interp: &mut Interpreter) {
+        match self.protocol {
+            Protocol::Terminal => {
+                for (i, (src, value)) in self.watch_values(interp).into_iter().enumerate() {
+                    eprintln!("  watch {}: {} = {}", i + 1, src, value);
+                }
+            }
+            Protocol::Json => send(Json::object(vec![("event", "watches".into()), ("watches", self.watches_json(interp))])),
+        }
+    }
+
+    fn locals_event(&self, interp: &Interpreter, frame: usize) {
+        match self.protocol {
+            Protocol::Terminal => {
+                let vars = interp.locals(frame);
+                if vars.is_empty() {
+                    eprintln!("  (no variables)");
+                }
+                for (name, value) in vars {
+                    eprintln!("  {} = {}", name, value);
+                }
+            }
+            Protocol::Json => send(Json::object(vec![
+                ("event", "locals".into()),
+                ("frame", frame.into()),
+                ("variables", variables(interp, frame)),
+            ])),
+        }
+    }
+
+    fn frames(&self, interp: &Interpreter) -> Json {
+        let frames = interp
+            .stack()
+            .iter()
+            .map(|(function, span)| {
+                let mut fields = vec![("function", function.as_str().into())];
+                fields.extend(position(&self.sources, span));
+                Json::object(fields)
+            })
+            .collect::<Vec<_>>();
+        frames.into()
+    }
+
+    fn print_stack(&self, interp: &Interpreter) {
+        for (i, (function, span)) in interp.stack().iter().enumerate() {
+            eprintln!("  #{} {} at {}", i, function, self.sources.location(span.start));
+        }
+    }
+
+    fn list(&self, span: &Span) {
+        let Some((file, line, _)) = self.sources.position(span.start) else { return };
+        let text = &self.sources.files()[file].text;
+        for (i, l) in text.lines().enumerate().skip(line.saturating_sub(4)).take(7) {
+            let mark = if i + 1 == line { "=>" } else { "  " };
+            let bp = if self.stops.has_breakpoint(file, i + 1) { "*" } else { " " };
+            eprintln!("{}{}{:>4} | {}", mark, bp, i + 1, l);
+        }
+    }
+
+    fn source_line(&self, offset: usize) {
+        let Some((file, line, _)) = self.sources.position(offset) else { return };
+        if let Some(text) = self.sources.files()[file].text.lines().nth(line - 1) {
+            eprintln!("=> {:>4} | {}", line, text);
+        }
+    }
+}
+
+impl Hook for Debugger {
+    fn before_stmt(&mut self, interp: &mut Interpreter, stmt: &Stmt) -> Result<(), RuntimeError> {
+        let Some(reason) = self.stops.check(&self.sources, interp, stmt) else {
+            return Ok(());
+        };
+        // первая остановка — вход в main, а не шаг
+        let reason = if self.started { reason } else { "entry" };
+        self.started = true;
+        self.stopped(interp, reason);
+        self.prompt(interp)
+    }
+}
+
+fn quit(interp: &Interpreter) -> RuntimeError {
+    RuntimeError { message: QUIT.to_string(), span: interp.stack()[0].1.clone(), notes: Vec::new() }
+}
+
+pub fn parse_expression(src: &str) -> Result<Expr, String> {
+    let tokens = Lexer::new(src).tokenize_spanned();
+    Parser::new(tokens).parse_expression().ok_or_else(|| format!("cannot parse expression `{}`", src))
+}
+
+/// Выражение из строки в верхнем кадре — для print/watch и evaluate в DAP.
+pub fn evaluate(interp: &mut Interpreter, src: &str) -> Result<String, String> {
+    let expr = parse_expression(src)?;
+    interp.evaluate(&expr).map(|v| v.to_string()).map_err(|e| e.message)
+}
+
+fn variables(interp: &Interpreter, frame: usize) -> Json {
+    let vars = interp
+        .locals(frame)
+        .into_iter()
+        .map(|(name, value)| Json::object(vec![("name", name.into()), ("value", value.to_string().into())]))
+        .collect::<Vec<_>>();
+    vars.into()
+}
+
+const HELP: &str = "\
+  b, break [FILE:]LINE   breakpoint on a line (of the main file by default)
+  d, delete [FILE:]LINE  remove a breakpoint
+  c, continue            run to the next breakpoint
+  s, step                next statement, entering calls
+  n, next                next statement in this function, stepping over calls
+  o, out                 run until the current function returns
+  p, print EXPR          evaluate an expression in the current frame
+  w, watch EXPR          show EXPR at every stop; unwatch N removes it
+  l, locals [N]          variables of frame N (0 — the current one)
+  bt, backtrace          call stack
+  list                   source around the current line
+  q, quit                stop the program
+  an empty line repeats the previous command
+";
+
+// `[FILE:]LINE`
+fn parse_location(arg: &str) -> Result<(Option<String>, usize), String> {
+    let (file, line) = match arg.rsplit_once(':') {
+        Some((file, line)) => (Some(file.to_string()), line),
+        None => (None, arg),
+    };
+    let line = line.trim().parse().map_err(|_| format!("expected a line number, got `{}`", arg))?;
+    Ok((file, line))
+}
+
+fn parse_terminal(line: &str) -> Result<Command, String> {
+    let (cmd, arg) = line.split_once(' ').map_or((line, ""), |(c, a)| (c, a.trim()));
+    Ok(match cmd {
+        "b" | "break" => {
+            let (file, line) = parse_location(arg)?;
+            Command::Break(file, line)
+        }
+        "d" | "delete" => {
+            let (file, line) = parse_location(arg)?;
+            Command::Clear(file, line)
+        }
+        "c" | "continue" => Command::Resume(Resume::Continue),
+        "s" | "step" => Command::Resume(Resume::StepIn),
+        "n" | "next" => Command::Resume(Resume::StepOver),
+        "o" | "out" | "finish" => Command::Resume(Resume::StepOut),
+        "p" | "print" if !arg.is_empty() => Command::Print(arg.to_string()),
+        "w" | "watch" if !arg.is_empty() => Command::Watch(arg.to_string()),
+        "unwatch" => Command::Unwatch(arg.parse().map_err(|_| "unwatch expects a watch number".to_string())?),
+        "l" | "locals" if arg.is_empty() => Command::Locals(0),
+        "l" | "locals" => Command::Locals(arg.parse().map_err(|_| "locals expects a frame number".to_string())?),
+        "bt" | "backtrace" => Command::Stack,
+        "list" => Command::List,
+        "h" | "help" => Command::Help,
+        "q" | "quit" => Command::Quit,
+        "" => return Err("no previous command to repeat".to_string()),
+        _ => return Err(format!("unknown command `{}`, try help", line)),
+    })
+}
+
+// {"command": "break", "line": 5, "file": "math.src"}, {"command": "print", "expression": "x + 1"}, ...
+fn parse_json(line: &str) -> Result<Command, String> {
+    let msg = json::parse(line)?;
+    let str_arg = |key: &str| msg.get(key).and_then(Json::as_str).map(str::to_string);
+    let int_arg = |key: &str| msg.get(key).and_then(Json::as_i64).filter(|n| *n >= 0).map(|n| n as usize);
+    let expression = || str_arg("expression").ok_or("missing \"expression\"".to_string());
+    Ok(match msg.get("command").and_then(Json::as_str).ok_or("missing \"command\"")? {
+        "break" => Command::Break(str_arg("file"), int_arg("line").ok_or("missing \"line\"")?),
+        "clear" => Command::Clear(str_arg("file"), int_arg("line").ok_or("missing \"line\"")?),
+        "continue" => Command::Resume(Resume::Continue),
+        "step" => Command::Resume(Resume::StepIn),
+        "next" => Command::Resume(Resume::StepOver),
+        "out" => Command::Resume(Resume::StepOut),
+        "print" => Command::Print(expression()?),
+        "watch" => Command::Watch(expression()?),
+        "unwatch" => Command::Unwatch(int_arg("index").ok_or("missing \"index\"")?),
+        "locals" => Command::Locals(int_arg("frame").unwrap_or(0)),
+        "stack" => Command::Stack,
+        "quit" => Command::Quit,
+        other => return Err(format!("unknown command {:?}", other)),
+    })
+}
+
+// Начала всех операторов, включая вложенные в if, блоки, match и лямбды.
+fn stmt_starts(body: &[Stmt], out: &mut Vec<usize>) {
+    for s in body {
+        out.push(s.span.start);
+        match &s.kind {
+            StmtKind::Let(_, _, e) | StmtKind::Expr(e) | StmtKind::Semi(e) => expr_starts(e, out),
+            StmtKind::Assign(target, e) => {
+                expr_starts(target, out);
+                expr_starts(e, out);
+            }
+            StmtKind::While { cond, body } => {
+                expr_starts(cond, out);
+                stmt_starts(body, out);
+            }
+            StmtKind::For { start, end, body, .. } => {
+                expr_starts(start, out);
+                expr_starts(end, out);
+                stmt_starts(body, out);
+            }
+        }
+    }
+}
+
+fn expr_starts(expr: &Expr, out: &mut Vec<usize>) {
+    match expr {
+        Expr::Number(_) | Expr::Bool(_) | Expr::Char(_) | Expr::Str(_) | Expr::Null | Expr::Ident(_) => {}
+        Expr::Call(_, args) => args.iter().for_each(|a| expr_starts(a, out)),
+        Expr::BinaryOp(l, _, r) => {
+            expr_starts(l, out);
+            expr_starts(r, out);
+        }
+        Expr::Array(elems) => elems.iter().for_each(|e| expr_starts(e, out)),
+        Expr::ArrayRepeat(e, _) | Expr::Field(e, _) | Expr::AddrOf(e) | Expr::Deref(e, _) => expr_starts(e, out),
+        Expr::Index { array, index, .. } => {
+            expr_starts(array, out);
+            expr_starts(index, out);
+        }
+        Expr::StructLit(_, fields) => fields.iter().for_each(|(_, e)| expr_starts(e, out)),
+        Expr::Lambda { body, .. } | Expr::Block(body) => stmt_starts(body, out),
+        Expr::Apply(callee, args) => {
+            expr_starts(callee, out);
+            args.iter().for_each(|a| expr_starts(a, out));
+        }
+        Expr::If { cond, then_body, else_body } => {
+            expr_starts(cond, out);
+            stmt_starts(then_body, out);
+            if let Some(b) = else_body {
+                stmt_starts(b, out);
+            }
+        }
+        Expr::Match { scrutinee, arms, .. } => {
+            expr_starts(scrutinee, out);
+            arms.iter().for_each(|arm| expr_starts(&arm.body, out));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::testutil::load;
+
+    // остановка: строка, причина, функция, значение x
+    type Stop = (usize, &'static str, String, String);
+
+    // Отладчик без терминала: на каждой остановке пишет Stop и продолжает следующей
+    // командой из сценария (когда он кончился — continue).
+    struct Script {
+        sources: SourceMap,
+        stops: Stops,
+        commands: Vec<Resume>,
+        log: Rc<RefCell<Vec<Stop>>>,
+    }
+
+    impl Hook for Script {
+        fn before_stmt(&mut self, interp: &mut Interpreter, stmt: &Stmt) -> Result<(), RuntimeError> {
+            let Some(reason) = self.stops.check(&self.sources, interp, stmt) else {
+                return Ok(());
+            };
+            let (_, line, _) = self.sources.position(stmt.span.start).expect("position");
+            let x = evaluate(interp, "x").unwrap_or_else(|e| e);
+            self.log.borrow_mut().push((line, reason, interp.function().to_string(), x));
+            let next = if self.commands.is_empty() { Resume::Continue } else { self.commands.remove(0) };
+            self.stops.resume(next, interp);
+            Ok(())
+        }
+    }
+
+    const SRC: &str = "fn twice(x: i64) -> i64 {
+    let y = x * 2;
+    y
+}
+fn main() {
+    let x = 1;
+    x = twice(x);
+    x = twice(x) + 1;
+    x
+}";
+
+    // Остановки при старте с `first`, точках останова на `lines` и командах `commands`.
+    fn stops(first: Resume, lines: &[usize], commands: &[Resume]) -> Vec<Stop> {
+        let (program, sources) = load(SRC);
+        let mut stops = Stops::new(&program, &sources, first);
+        let file = file_index(&sources, None).expect("main file");
+        for &line in lines {
+            stops.set_breakpoint(file, line).expect("statement on the line");
+        }
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut interp = Interpreter::new();
+        interp.set_trace(false);
+        interp.set_hook(Box::new(Script { sources, stops, commands: commands.to_vec(), log: log.clone() }));
+        assert_eq!(interp.run_program(&program).expect("run").code(), Some(5));
+        let log = log.borrow().clone();
+        log
+    }
+
+    fn at(line: usize, reason: &'static str, function: &str, x: &str) -> Stop {
+        (line, reason, function.to_string(), x.to_string())
+    }
+
+    #[test]
+    fn stepping() {
+        use Resume::*;
+        // step заходит в вызов, next — нет, out — до оператора после возврата
+        assert_eq!(
+            stops(StepIn, &[], &[StepOver, StepIn, StepIn, StepOut, StepOver, Continue]),
+            [
+                at(6, "step", "main", "no variable x in this frame"),
+                at(7, "step", "main", "1"),
+                at(2, "step", "twice", "1"),
+                at(3, "step", "twice", "1"),
+                at(8, "step", "main", "2"),
+                at(9, "step", "main", "5"),
+            ]
+        );
+    }
+
+    #[test]
+    fn breakpoints() {
+        // строка 4 без операторов — точка встаёт на следующей, где они есть (5 — fn, 6 — let)
+        let (program, sources) = load(SRC);
+        let mut s = Stops::new(&program, &sources, Resume::Continue);
+        let file = file_index(&sources, Some("test.src")).expect("main file");
+        assert_eq!(s.set_breakpoint(file, 4), Some(6));
+        assert!(s.has_breakpoint(file, 6) && s.clear_breakpoint(file, 6) && !s.has_breakpoint(file, 6));
+        assert_eq!(s.set_breakpoint(file, 100), None);
+
+        // в twice встаём при каждом вызове; значение x — из кадра twice
+        assert_eq!(
+            stops(Resume::Continue, &[2, 9], &[]),
+            [at(2, "breakpoint", "twice", "1"), at(2, "breakpoint", "twice", "2"), at(9, "breakpoint", "main", "5")]
+        );
+    }
+
+    #[test]
+    fn terminal_commands() {
+        assert!(matches!(parse_terminal("b 12"), Ok(Command::Break(None, 12))));
+        assert!(matches!(parse_terminal("break math.src:3"), Ok(Command::Break(Some(f), 3)) if f == "math.src"));
+        assert!(matches!(parse_terminal("d 4"), Ok(Command::Clear(None, 4))));
+        assert!(matches!(parse_terminal("n"), Ok(Command::Resume(Resume::StepOver))));
+        assert!(matches!(parse_terminal("finish"), Ok(Command::Resume(Resume::StepOut))));
+        assert!(matches!(parse_terminal("p x + 1"), Ok(Command::Print(e)) if e == "x + 1"));
+        assert!(matches!(parse_terminal("locals 2"), Ok(Command::Locals(2))));
+        assert_eq!(parse_terminal("b x").err(), Some("expected a line number, got `x`".to_string()));
+        assert_eq!(parse_terminal("p").err(), Some("unknown command `p`, try help".to_string()));
+        assert_eq!(parse_terminal("").err(), Some("no previous command to repeat".to_string()));
+    }
+
+    #[test]
+    fn json_commands() {
+        assert!(matches!(parse_json(r#"{"command": "break", "line": 5, "file": "a.src"}"#), Ok(Command::Break(Some(f), 5)) if f == "a.src"));
+        assert!(matches!(parse_json(r#"{"command": "watch", "expression": "n"}"#), Ok(Command::Watch(e)) if e == "n"));
+        assert!(matches!(parse_json(r#"{"command": "locals"}"#), Ok(Command::Locals(0))));
+        assert_eq!(parse_json(r#"{"command": "break"}"#).err(), Some("missing \"line\"".to_string()));
+        assert_eq!(parse_json(r#"{"command": "jump"}"#).err(), Some("unknown command \"jump\"".to_string()));
+        assert_eq!(parse_json(r#"{"line": 1}"#).err(), Some("missing \"command\"".to_string()));
+    }
+}
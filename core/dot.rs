@@ -0,0 +1,319 @@
+use inkwell::basic_block::BasicBlock;
+use inkwell::module::Module;
+use inkwell::values::{AnyValue, FunctionValue, InstructionOpcode};
+
+use crate::ast::{Expr, Pattern, Program, Stmt, StmtKind};
+
+// Graphviz DOT для картинок на занятиях: граф потока управления LLVM-функций
+// (блоки then/else/ifend/loop.cond/... — узлы, переходы — рёбра) и дерево AST.
+// Прелюдию не рисуем — она есть в каждой программе.
+
+/// CFG всех функций модуля Codegen: кластер на функцию, в узле — имя блока и его инструкции.
+pub fn llvm_cfg(module: &Module) -> String {
+    let mut out = String::from("digraph cfg {\n  node [shape=box, fontname=monospace];\n");
+    let functions = module.get_functions().filter(|f| f.count_basic_blocks() > 0);
+    for (fi, func) in functions.enumerate() {
+        let name = func.get_name().to_string_lossy().to_string();
+        // прелюдия и служебные функции Codegen (rt.error) — не код программы
+        if name.starts_with("prelude::") || name.starts_with("rt.") {
+            continue;
+        }
+        out.push_str(&format!("  subgraph cluster_{} {{\n    label=\"fn {}\";\n", fi, escape(&name)));
+        function_cfg(&mut out, fi, func);
+        out.push_str("  }\n");
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn function_cfg(out: &mut String, fi: usize, func: FunctionValue) {
+    let blocks = func.get_basic_blocks();
+    let id = |bb: BasicBlock| blocks.iter().position(|b| *b == bb).map(|i| format!("f{}_bb{}", fi, i));
+    for (i, bb) in blocks.iter().enumerate() {
+        // \l — выравнивание строк метки по левому краю
+        let mut label = format!("{}:\\l", escape(&bb.get_name().to_string_lossy()));
+        let mut inst = bb.get_first_instruction();
+        while let Some(i) = inst {
+            label.push_str(&format!("  {}\\l", escape(i.print_to_string().to_string().trim())));
+            inst = i.get_next_instruction();
+        }
+        out.push_str(&format!("    f{}_bb{} [label=\"{}\"];\n", fi, i, label));
+    }
+    for bb in &blocks {
+        let Some(term) = bb.get_terminator() else { continue };
+        let from = id(*bb).expect("block of this function");
+        let target = |n: u32| term.get_operand(n).and_then(|op| op.right()).and_then(id);
+        // операнды br с условием: cond, false, true; у switch: значение, default, (case, блок)...
+        let edges: Vec<(Option<String>, String)> = match term.get_opcode() {
+            InstructionOpcode::Br if term.get_num_operands() == 3 => {
+                vec![(target(2), "true".to_string()), (target(1), "false".to_string())]
+            }
+            InstructionOpcode::Br => vec![(target(0), String::new())],
+            InstructionOpcode::Switch => {
+                let mut edges = vec![(target(1), "default".to_string())];
+                for n in (2..term.get_num_operands()).step_by(2) {
+                    let case = term
+                        .get_operand(n)
+                        .and_then(|op| op.left())
+                        .and_then(|v| v.into_int_value().get_sign_extended_constant())
+                        .map_or_else(|| "case".to_string(), |c| c.to_string());
+                    edges.push((target(n + 1), case));
+                }
+                edges
+            }
+            _ => Vec::new(),
+        };
+        for (to, label) in edges {
+            match to {
+                Some(to) if label.is_empty() => out.push_str(&format!("    {} -> {};\n", from, to)),
+                Some(to) => out.push_str(&format!("    {} -> {} [label=\"{}\"];\n", from, to, label)),
+                None => {}
+            }
+        }
+    }
+}
+
+/// Дерево AST: функция -> операторы -> выражения; подписи рёбер — роль потомка (cond, then, ...).
+pub fn ast(program: &Program) -> String {
+    let mut g = Tree { out: String::from("digraph ast {\n  node [shape=box, fontname=monospace];\n"), next: 0 };
+    let root = g.node("program", None, "");
+    for func in program.functions.iter().filter(|f| !f.name.starts_with("prelude::")) {
+        let params: Vec<&str> = func.params.iter().map(|(p, _)| p.as_str()).collect();
+        let f = g.node(&format!("fn {}({})", func.name, params.join(", ")), Some(root), "");
+        g.stmts(&func.body, f, "");
+    }
+    g.out.push_str("}\n");
+    g.out
+}
+
+struct Tree {
+    out: String,
+    next: usize,
+}
+
+impl Tree {
+    fn node(&mut self, label: &str, parent: Option<usize>, edge: &str) -> usize {
+        let id = self.next;
+        self.next += 1;
+        self.out.push_str(&format!("  n{} [label=\"{}\"];\n", id, escape(label)));
+        match parent {
+            Some(p) if edge.is_empty() => self.out.push_str(&format!("  n{} -> n{};\n", p, id)),
+            Some(p) => self.out.push_str(&format!("  n{} -> n{} [label=\"{}\"];\n", p, id, escape(edge))),
+            None => {}
+        }
+        id
+    }
+
+    fn stmts(&mut self, body: &[Stmt], parent: usize, edge: &str) {
+        for s in body {
+            self.stmt(s, parent, edge);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt, parent: usize, edge: &str) {
+        match &stmt.kind {
+            StmtKind::Let(name, _, e) => {
+                let n = self.node(&format!("let {}", name), Some(parent), edge);
+                self.expr(e, n, "");
+            }
+            StmtKind::Assign(target, e) => {
+                let n = self.node("=", Some(parent), edge);
+                self.expr(target, n, "target");
+                self.expr(e, n, "value");
+            }
+            StmtKind::Expr(e) => self.expr(e, parent, edge),
+            StmtKind::Semi(e) => {
+                let n = self.node(";", Some(parent), edge);
+                self.expr(e, n, "expr");
+            }
+            StmtKind::While { cond, body } => {
+                let n = self.node("while", Some(parent), edge);
+                self.expr(cond, n, "cond");
+                self.stmts(body, n, "body");
+            }
+            StmtKind::For { var, start, end, inclusive, step, body } => {
+                let range = if *inclusive { "..=" } else { ".." };
+                let n = self.node(&format!("for {} in _{}_ step {}", var, range, step), Some(parent), edge);
+                self.expr(start, n, "start");
+                self.expr(end, n, "end");
+                self.stmts(body, n, "body");
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr, parent: usize, edge: &str) {
+        match expr {
+            Expr::Number(n) => {
+                self.node(&n.to_string(), Some(parent), edge);
+            }
+            Expr::Bool(b) => {
+                self.node(&b.to_string(), Some(parent), edge);
+            }
+            Expr::Char(c) => {
+                self.node(&format!("{:?}", c), Some(parent), edge);
+            }
+            Expr::Str(s) => {
+                self.node(&format!("{:?}", s), Some(parent), edge);
+            }
+            Expr::Ident(name) => {
+                self.node(name, Some(parent), edge);
+            }
+            Expr::Null => {
+                self.node("null", Some(parent), edge);
+            }
+            Expr::BinaryOp(l, op, r) => {
+                let n = self.node(op, Some(parent), edge);
+                self.expr(l, n, "");
+                self.expr(r, n, "");
+            }
+            Expr::Call(name, args) => {
+                let n = self.node(&format!("call {}", name), Some(parent), edge);
+                for a in args {
+                    self.expr(a, n, "");
+                }
+            }
+            Expr::Array(items) => {
+                let n = self.node("[ ]", Some(parent), edge);
+                for (i, e) in items.iter().enumerate() {
+                    self.expr(e, n, &i.to_string());
+                }
+            }
+            Expr::ArrayRepeat(e, len) => {
+                let n = self.node(&format!("[_; {}]", len), Some(parent), edge);
+                self.expr(e, n, "");
+            }
+            Expr::Index { array, index, .. } => {
+                let n = self.node("[]", Some(parent), edge);
+                self.expr(array, n, "array");
+                self.expr(index, n, "index");
+            }
+            Expr::StructLit(name, fields) => {
+                let n = self.node(&format!("{} {{ }}", name), Some(parent), edge);
+                for (f, e) in fields {
+                    self.expr(e, n, f);
+                }
+            }
+            Expr::Field(e, f) => {
+                let n = self.node(&format!(".{}", f), Some(parent), edge);
+                self.expr(e, n, "");
+            }
+            Expr::AddrOf(e) => {
+                let n = self.node("&", Some(parent), edge);
+                self.expr(e, n, "");
+            }
+            Expr::Deref(e, _) => {
+                let n = self.node("*", Some(parent), edge);
+                self.expr(e, n, "");
+            }
+            Expr::Lambda { params, body, .. } => {
+                let params: Vec<&str> = params.iter().map(|(p, _)| p.as_str()).collect();
+                let n = self.node(&format!("|{}|", params.join(", ")), Some(parent), edge);
+                self.stmts(body, n, "body");
+            }
+            Expr::Apply(f, args) => {
+                let n = self.node("apply", Some(parent), edge);
+                self.expr(f, n, "callee");
+                for a in args {
+                    self.expr(a, n, "");
+                }
+            }
+            Expr::If { cond, then_body, else_body } => {
+                let n = self.node("if", Some(parent), edge);
+                self.expr(cond, n, "cond");
+                self.stmts(then_body, n, "then");
+                if let Some(b) = else_body {
+                    self.stmts(b, n, "else");
+                }
+            }
+            Expr::Block(body) => {
+                let n = self.node("block", Some(parent), edge);
+                self.stmts(body, n, "");
+            }
+            Expr::Match { scrutinee, arms, .. } => {
+                let n = self.node("match", Some(parent), edge);
+                self.expr(scrutinee, n, "value");
+                for arm in arms {
+                    let pats: Vec<String> = arm
+                        .patterns
+                        .iter()
+                        .map(|(p, _)| match p {
+                            Pattern::Int(v) => v.to_string(),
+                            Pattern::Range(lo, hi) => format!("{}..={}", lo, hi),
+                            Pattern::Wildcard => "_".to_string(),
+                        })
+                        .collect();
+                    let a = self.node(&format!("{} =>", pats.join(" | ")), Some(n), "arm");
+                    self.expr(&arm.body, a, "");
+                }
+            }
+        }
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use inkwell::context::Context;
+
+    use super::*;
+    use crate::codegen::Codegen;
+    use crate::testutil::load;
+
+    fn cfg(src: &str) -> String {
+        let (program, sources) = load(src);
+        let context = Context::create();
+        let mut codegen = Codegen::new(&context, "test", &sources);
+        codegen.compile_program(&program);
+        llvm_cfg(codegen.module())
+    }
+
+    #[test]
+    fn ast_tree() {
+        let src = "fn main() {\n    let s = \"a\\\"b\";\n    if (s == \"x\") { 1 } else { match 2 { 1 | 2 => 3, 4..=5 => 4, _ => 5 } }\n}";
+        let dot = ast(&load(src).0);
+        assert!(dot.starts_with("digraph ast {\n  node [shape=box, fontname=monospace];\n  n0 [label=\"program\"];\n"), "{}", dot);
+        // прелюдии в дереве нет — только main
+        assert_eq!(dot.matches("[label=\"fn ").count(), 1, "{}", dot);
+        for part in [
+            "n2 [label=\"let s\"];\n  n1 -> n2;\n  n3 [label=\"\\\"a\\\\\\\"b\\\"\"];\n  n2 -> n3;",
+            "n4 -> n5 [label=\"cond\"];",
+            "n4 -> n8 [label=\"then\"];",
+            "n9 [label=\"match\"];\n  n4 -> n9 [label=\"else\"];",
+            "n11 [label=\"1 | 2 =>\"];\n  n9 -> n11 [label=\"arm\"];",
+            "n13 [label=\"4..=5 =>\"];",
+            "n15 [label=\"_ =>\"];",
+        ] {
+            assert!(dot.contains(part), "{}\nmissing: {}", dot, part);
+        }
+    }
+
+    #[test]
+    fn llvm_cfg_blocks_and_edges() {
+        let dot = cfg("fn main() {\n    let i = 0;\n    while (i < 3) { i = i + 1; }\n    match i { 3 => 1, _ => 0 }\n}");
+        assert_eq!(dot.matches("subgraph cluster_").count(), 1, "{}", dot);
+        assert!(dot.contains("label=\"fn main\";"), "{}", dot);
+        let block = |name: &str| {
+            let at = dot.find(&format!("[label=\"{}:\\l", name)).unwrap_or_else(|| panic!("no block {}\n{}", name, dot));
+            let line = dot[..at].rsplit('\n').next().expect("line");
+            line.trim().to_string()
+        };
+        let (cond, body, end) = (block("loop.cond"), block("loop.body"), block("loop.end"));
+        for edge in [
+            format!("{} -> {} [label=\"true\"];", cond, body),
+            format!("{} -> {} [label=\"false\"];", cond, end),
+            format!("{} -> {};", body, cond),
+            format!("{} -> {} [label=\"default\"];", end, block("match.default")),
+            format!("{} -> {} [label=\"3\"];", end, block("arm.0")),
+        ] {
+            assert!(dot.contains(&edge), "{}\nmissing: {}", dot, edge);
+        }
+    }
+
+    #[test]
+    fn labels_are_escaped() {
+        assert_eq!(escape("say \"hi\"\\n\n"), "say \\\"hi\\\"\\\\n\\n");
+    }
+}
@@ -0,0 +1,375 @@
+use crate::ast::{Expr, ExternFn, ExternType, Function, Import, Pattern, Stmt, StmtKind, StructDef};
+use crate::lexer::{Comment, Lexer, Span};
+use crate::parser::Parser;
+use crate::sema::type_name;
+
+// AST обратно в исходник. Без комментариев — для диффов оптимизатора (opt.rs), с ними —
+// форматтер (core lsp). Скобки вокруг операций — только где их требует приоритет, типы
+// параметров и результата пишутся явно. Комментарий встаёт отдельной строкой перед оператором, за которым шёл,
+// а стоявший внутри оператора или после него на той же строке — в конец его строки.
+// Пустые строки между операторами сохраняются (не больше одной подряд).
+
+/// Функция в виде исходника — для диффов «до/после». Комментарии и точное
+/// форматирование не сохраняются.
+pub fn show_function(func: &Function) -> String {
+    let mut out = String::new();
+    Printer::new("", &[]).function(func, &mut out);
+    out
+}
+
+/// `fn name(a: i64, b: bool) -> i64`
+pub fn show_header(func: &Function) -> String {
+    let params: Vec<String> = func.params.iter().map(|(n, t)| format!("{}: {}", n, type_name(t))).collect();
+    format!("fn {}({}) -> {}", func.name, params.join(", "), type_name(&func.ret))
+}
+
+/// `extern fn puts(s: str) -> i32;`
+pub fn show_extern(e: &ExternFn) -> String {
+    let ty = |t: &ExternType| match t {
+        ExternType::Void => "void".to_string(),
+        ExternType::I32 => "i32".to_string(),
+        ExternType::Value(t) => type_name(t),
+    };
+    let params: Vec<String> = e.params.iter().map(|(n, t)| format!("{}: {}", n, ty(t))).collect();
+    let ret = if e.ret == ExternType::Void { String::new() } else { format!(" -> {}", ty(&e.ret)) };
+    format!("extern fn {}({}){};", e.name, params.join(", "), ret)
+}
+
+/// `struct Point { x: i64, y: i64 }` — по полю на строку.
+pub fn show_struct(s: &StructDef) -> String {
+    if s.fields.is_empty() {
+        return format!("struct {} {{ }}", s.name);
+    }
+    let mut out = format!("struct {} {{\n", s.name);
+    for (name, ty) in &s.fields {
+        out.push_str(&format!("    {}: {},\n", name, type_name(ty)));
+    }
+    out.push('}');
+    out
+}
+
+/// Файл целиком в каноническом виде, с комментариями; None — файл не разбирается.
+pub fn format_source(src: &str) -> Option<String> {
+    let mut lexer = Lexer::new(src);
+    let program = Parser::new(lexer.tokenize_spanned()).parse_program()?;
+    // Program раскладывает объявления по видам — порядок файла восстанавливаем по span'ам
+    let mut items: Vec<(&Span, Item)> = Vec::new();
+    for i in &program.imports {
+        match i {
+            Import::File { span, .. } | Import::Use { span, .. } => items.push((span, Item::Import(i))),
+        }
+    }
+    items.extend(program.externs.iter().map(|e| (&e.span, Item::Extern(e))));
+    items.extend(program.structs.iter().map(|s| (&s.span, Item::Struct(s))));
+    items.extend(program.functions.iter().map(|f| (&f.span, Item::Function(f))));
+    items.sort_by_key(|(span, _)| span.start);
+
+    let mut p = Printer::new(src, lexer.comments());
+    let mut out = String::new();
+    for (span, item) in items {
+        p.comments_before(span.start, 0, &mut out);
+        p.gap(span.start, &mut out);
+        let text = match item {
+            Item::Import(Import::File { path, .. }) => format!("import {:?};", path),
+            Item::Import(Import::Use { module, item, .. }) => format!("use {}::{};", module, item),
+            Item::Extern(e) => show_extern(e),
+            Item::Struct(s) => show_struct(s),
+            Item::Function(f) => {
+                p.function(f, &mut out);
+                out.pop();
+                String::new()
+            }
+        };
+        out.push_str(&text);
+        out.push_str(&p.trailing(span.end));
+        out.push('\n');
+    }
+    p.comments_before(usize::MAX, 0, &mut out);
+    Some(out)
+}
+
+// приоритеты как в parser.rs: сравнения < `+ -` < `* /` < `&` и `*p`
+const UNARY: u8 = 4;
+
+fn precedence(op: &str) -> u8 {
+    match op {
+        "*" | "/" => 3,
+        "+" | "-" => 2,
+        _ => 1,
+    }
+}
+
+enum Item<'a> {
+    Import(&'a Import),
+    Extern(&'a ExternFn),
+    Struct(&'a StructDef),
+    Function(&'a Function),
+}
+
+struct Printer<'a> {
+    src: &'a str,
+    comments: &'a [Comment],
+    // первый ещё не напечатанный комментарий
+    next: usize,
+    // конец последнего напечатанного оператора или комментария в исходнике
+    last: usize,
+}
+
+impl<'a> Printer<'a> {
+    fn new(src: &'a str, comments: &'a [Comment]) -> Self {
+        Printer { src, comments, next: 0, last: 0 }
+    }
+
+    // Комментарии, начавшиеся до offset, — отдельными строками.
+    fn comments_before(&mut self, offset: usize, indent: usize, out: &mut String) {
+        while let Some(c) = self.comments.get(self.next).filter(|c| c.span.start < offset) {
+            self.gap(c.span.start, out);
+            out.push_str(&"    ".repeat(indent));
+            out.push_str(self.src[c.span.clone()].trim_end());
+            out.push('\n');
+            self.next += 1;
+            self.last = c.span.end;
+        }
+    }
+
+    // Пустая строка, если в исходнике перед offset она была.
+    fn gap(&self, offset: usize, out: &mut String) {
+        let blank = self.src.get(self.last..offset).is_some_and(|s| s.matches('\n').count() > 1);
+        if blank && !out.is_empty() && !out.ends_with("{\n") && !out.ends_with("\n\n") {
+            out.push('\n');
+        }
+    }
+
+    // Ещё не напечатанные комментарии внутри оператора (до end) и следующий за ним
+    // на той же строке — хвостом строки.
+    fn trailing(&mut self, end: usize) -> String {
+        let mut tail = String::new();
+        while let Some(c) = self.comments.get(self.next) {
+            let same_line = self.src.get(end..c.span.start).is_some_and(|s| !s.contains('\n'));
+            if c.span.start >= end && !same_line {
+                break;
+            }
+            tail.push(' ');
+            tail.push_str(self.src[c.span.clone()].trim_end());
+            self.next += 1;
+            self.last = c.span.end;
+        }
+        self.last = self.last.max(end);
+        tail
+    }
+
+    // Комментарии между последним оператором блока и его `}` — внутрь блока. Между ними
+    // могут быть только пробелы и `;`: иначе `}` не наша (тело лямбды без скобок).
+    fn before_close(&mut self, indent: usize, out: &mut String) {
+        while let Some(brace) = self.src.get(self.last..).and_then(|s| s.find('}')) {
+            let Some(c) = self.comments.get(self.next).filter(|c| c.span.start < self.last + brace) else { return };
+            let between = self.src.get(self.last..c.span.start);
+            if !between.is_some_and(|s| s.chars().all(|ch| ch.is_whitespace() || ch == ';')) {
+                return;
+            }
+            self.comments_before(c.span.end, indent, out);
+        }
+    }
+
+    fn function(&mut self, func: &Function, out: &mut String) {
+        out.push_str(&show_header(func));
+        out.push(' ');
+        out.push_str(&self.block(&func.body, 0));
+        out.push('\n');
+    }
+
+    fn body(&mut self, body: &[Stmt], indent: usize, out: &mut String) {
+        for (i, s) in body.iter().enumerate() {
+            self.comments_before(s.span.start, indent, out);
+            self.gap(s.span.start, out);
+            let line = match &s.kind {
+                StmtKind::Let(name, Some(t), e) => format!("let {}: {} = {};", name, type_name(t), self.expr(e, indent)),
+                StmtKind::Let(name, None, e) => format!("let {} = {};", name, self.expr(e, indent)),
+                StmtKind::Assign(t, e) => format!("{} = {};", self.expr(t, indent), self.expr(e, indent)),
+                // `;` меняет значение блока: у последнего выражения он как в исходнике. Выражение
+                // без `;` в середине бывает после оптимизаций (opt.rs) — ему `;` нужен для парсера
+                StmtKind::Expr(e)
+                    if i + 1 < body.len() && !matches!(e, Expr::If { .. } | Expr::Match { .. } | Expr::Block(_)) =>
+                {
+                    format!("{};", self.expr(e, indent))
+                }
+                StmtKind::Expr(e) => self.expr(e, indent),
+                StmtKind::Semi(e) => format!("{};", self.expr(e, indent)),
+                StmtKind::While { cond, body } => format!("while ({}) {}", self.expr(cond, indent), self.block(body, indent)),
+                StmtKind::For { var, start, end, inclusive, step, body } => {
+                    let range = if *inclusive { "..=" } else { ".." };
+                    let step = if *step == 1 { String::new() } else { format!(" step {}", step) };
+                    let (start, end) = (self.expr(start, indent), self.expr(end, indent));
+                    format!("for {} in {}{}{}{} {}", var, start, range, end, step, self.block(body, indent))
+                }
+            };
+            out.push_str(&"    ".repeat(indent));
+            out.push_str(&line);
+            out.push_str(&self.trailing(s.span.end));
+            out.push('\n');
+        }
+    }
+
+    fn block(&mut self, body: &[Stmt], indent: usize) -> String {
+        if body.is_empty() {
+            return "{ }".to_string();
+        }
+        let mut out = "{\n".to_string();
+        self.body(body, indent + 1, &mut out);
+        self.before_close(indent + 1, &mut out);
+        out.push_str(&"    ".repeat(indent));
+        out.push('}');
+        out
+    }
+
+    fn list(&mut self, items: &[Expr], indent: usize) -> String {
+        items.iter().map(|a| self.expr(a, indent)).collect::<Vec<_>>().join(", ")
+    }
+
+    // Операнд: операция слабее min — в скобках; if/match/блок/лямбда — всегда, чтобы
+    // не стать началом оператора.
+    fn operand(&mut self, e: &Expr, min: u8, indent: usize) -> String {
+        match e {
+            Expr::BinaryOp(_, op, _) if precedence(op) < min => format!("({})", self.expr(e, indent)),
+            Expr::If { .. } | Expr::Match { .. } | Expr::Block(_) | Expr::Lambda { .. } => {
+                format!("({})", self.expr(e, indent))
+            }
+            _ => self.expr(e, indent),
+        }
+    }
+
+    // База `a[i]` и `a.f`: ещё и `&`/`*` в скобках — `(*p).x` не то же, что `*p.x`.
+    fn postfix_base(&mut self, e: &Expr, indent: usize) -> String {
+        match e {
+            Expr::AddrOf(_) | Expr::Deref(..) => format!("({})", self.expr(e, indent)),
+            _ => self.operand(e, UNARY, indent),
+        }
+    }
+
+    fn expr(&mut self, e: &Expr, indent: usize) -> String {
+        match e {
+            Expr::Number(n) => n.to_string(),
+            Expr::Bool(b) => b.to_string(),
+            Expr::Char(c) => format!("{:?}", c),
+            Expr::Str(s) => format!("{:?}", s),
+            Expr::Ident(name) => name.clone(),
+            Expr::Null => "null".to_string(),
+            Expr::BinaryOp(l, op, r) => {
+                // все операции левоассоциативны: `a - (b - c)` справа нужны скобки
+                let l = self.operand(l, precedence(op), indent);
+                format!("{} {} {}", l, op, self.operand(r, precedence(op) + 1, indent))
+            }
+            Expr::Call(name, args) => format!("{}({})", name, self.list(args, indent)),
+            Expr::Array(elems) => format!("[{}]", self.list(elems, indent)),
+            Expr::ArrayRepeat(e, n) => format!("[{}; {}]", self.expr(e, indent), n),
+            Expr::Index { array, index, .. } => {
+                let array = self.postfix_base(array, indent);
+                format!("{}[{}]", array, self.expr(index, indent))
+            }
+            Expr::StructLit(name, fields) => {
+                let fields: Vec<String> = fields.iter().map(|(f, e)| format!("{}: {}", f, self.expr(e, indent))).collect();
+                format!("{} {{ {} }}", name, fields.join(", "))
+            }
+            Expr::Field(base, field) => format!("{}.{}", self.postfix_base(base, indent), field),
+            Expr::AddrOf(e) => format!("&{}", self.operand(e, UNARY, indent)),
+            Expr::Deref(e, _) => format!("*{}", self.operand(e, UNARY, indent)),
+            Expr::Lambda { params, ret, body } => {
+                let params: Vec<String> = params.iter().map(|(n, t)| format!("{}: {}", n, type_name(t))).collect();
+                format!("|{}| -> {} {}", params.join(", "), type_name(ret), self.block(body, indent))
+            }
+            Expr::Apply(callee, args) => {
+                let callee = self.expr(callee, indent);
+                format!("({})({})", callee, self.list(args, indent))
+            }
+            Expr::If { cond, then_body, else_body } => {
+                let cond = self.expr(cond, indent);
+                let mut s = format!("if {} {}", cond, self.block(then_body, indent));
+                match else_body.as_deref() {
+                    // `else if` парсер хранит как else-блок из одного if
+                    Some([Stmt { kind: StmtKind::Expr(nested @ Expr::If { .. }), .. }]) => {
+                        s.push_str(&format!(" else {}", self.expr(nested, indent)));
+                    }
+                    Some(b) => s.push_str(&format!(" else {}", self.block(b, indent))),
+                    None => {}
+                }
+                s
+            }
+            Expr::Block(body) => self.block(body, indent),
+            Expr::Match { scrutinee, arms, .. } => {
+                let pad = "    ".repeat(indent + 1);
+                let mut s = format!("match {} {{\n", self.expr(scrutinee, indent));
+                for arm in arms {
+                    let pats: Vec<String> = arm
+                        .patterns
+                        .iter()
+                        .map(|(p, _)| match p {
+                            Pattern::Int(n) => n.to_string(),
+                            Pattern::Range(lo, hi) => format!("{}..={}", lo, hi),
+                            Pattern::Wildcard => "_".to_string(),
+                        })
+                        .collect();
+                    let body = self.expr(&arm.body, indent + 1);
+                    s.push_str(&format!("{}{} => {},\n", pad, pats.join(" | "), body));
+                }
+                s.push_str(&"    ".repeat(indent));
+                s.push('}');
+                s
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{interpret, load};
+
+    // Формат: повторный прогон ничего не меняет, программа работает как раньше.
+    fn format(src: &str) -> String {
+        let out = format_source(src).expect("source parses");
+        assert_eq!(format_source(&out).as_deref(), Some(out.as_str()), "formatting is stable");
+        assert_eq!(interpret(&out), interpret(src));
+        out
+    }
+
+    #[test]
+    fn canonical_layout() {
+        assert_eq!(
+            format(
+                "struct P{x:i64}\nextern fn puts(s:str)->i32;\n\
+                 fn main(){let p=P{x:(1+2)*3};if p.x>2{puts(\"big\");}else{puts(\"small\");}\np.x-(1-2)}"
+            ),
+            "struct P {\n    x: i64,\n}\nextern fn puts(s: str) -> i32;\nfn main() -> i64 {\n    \
+             let p = P { x: (1 + 2) * 3 };\n    if p.x > 2 {\n        puts(\"big\");\n    } else {\n        \
+             puts(\"small\");\n    }\n    p.x - (1 - 2)\n}\n"
+        );
+    }
+
+    #[test]
+    fn comments_and_blank_lines() {
+        // пустые строки подряд схлопываются в одну, перенос внутри выражения убирается
+        let src = "// header\nfn main() {\n  // before\n  let x = 1; // after x\n\n\n\n  \
+                   let y = x\n    + 2;\n  y /* end */\n}\n// tail\n";
+        assert_eq!(
+            format(src),
+            "// header\nfn main() -> i64 {\n    // before\n    let x = 1; // after x\n\n    \
+             let y = x + 2;\n    y /* end */\n}\n// tail\n"
+        );
+    }
+
+    #[test]
+    fn broken_source_is_left_alone() {
+        assert_eq!(format_source("fn main() { let = 1 }"), None);
+    }
+
+    #[test]
+    fn headers() {
+        let (program, _) = load("extern fn abort();\nstruct E {}\nfn f(a: i64, b: bool) -> bool { b }\nfn main() { 0 }");
+        let f = program.functions.iter().find(|f| f.name == "f").expect("f");
+        assert_eq!(show_header(f), "fn f(a: i64, b: bool) -> bool");
+        let abort = program.externs.iter().find(|e| e.name == "abort").expect("abort");
+        assert_eq!(show_extern(abort), "extern fn abort();");
+        assert_eq!(show_struct(&program.structs[0]), "struct E { }");
+        assert_eq!(show_function(f), "fn f(a: i64, b: bool) -> bool {\n    b\n}\n");
+    }
+}
@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::rc::Rc;
+
+use crate::ast::{ExternFn, ExternType};
+use crate::interpreter::Value;
+
+/// Реализация для интерпретатора: аргументы уже приведены к типам сигнатуры.
+/// Ошибка — текст для RuntimeError.
+pub type HostFn = Rc<dyn Fn(&[Value]) -> Result<Value, String>>;
+
+/// Функция встраивающего Rust-приложения, которую программа вызывает как встроенную,
+/// без `extern fn`. Сигнатура нужна sema (проверка вызовов) и Codegen (объявление),
+/// `call` — интерпретатору, `native` — адрес `extern "C" fn` с той же сигнатурой для JIT.
+#[derive(Clone)]
+pub struct HostFunction {
+    pub sig: ExternFn,
+    pub call: HostFn,
+    pub native: Option<usize>,
+}
+
+impl HostFunction {
+    pub fn new(
+        name: &str,
+        params: Vec<ExternType>,
+        ret: ExternType,
+        call: impl Fn(&[Value]) -> Result<Value, String> + 'static,
+    ) -> Self {
+        let params = params.into_iter().enumerate().map(|(i, t)| (format!("arg{}", i), t)).collect();
+        Self {
+            sig: ExternFn { name: name.to_string(), params, ret, span: 0..0 },
+            call: Rc::new(call),
+            native: None,
+        }
+    }
+
+    /// Без адреса функция есть только в интерпретаторе: её вызов в скомпилированной
+    /// программе — ошибка sema::check_compiled.
+    pub fn with_native(mut self, addr: usize) -> Self {
+        self.native = Some(addr);
+        self
+    }
+}
+
+/// Вызов функции хоста по сигнатуре: аргументы и результат приводятся к C-типам
+/// (i32 обрезается, void -> 0) — так же, как при вызове из Codegen.
+pub fn call(ext: &ExternFn, f: &HostFn, args: Vec<Value>) -> Result<Value, String> {
+    let mut c_args = Vec::new();
+    for ((_, ty), v) in ext.params.iter().zip(args) {
+        c_args.push(match (ty, v.as_int()) {
+            (ExternType::I32, Some(n)) => Value::Int(n as i32 as i64),
+            (ExternType::I32, None) => return Err(format!("{}: expected integer, got {}", ext.name, v)),
+            _ => v,
+        });
+    }
+    let result = f(&c_args).map_err(|e| format!("{}: {}", ext.name, e))?;
+    Ok(match (&ext.ret, result) {
+        (ExternType::Void, _) => Value::Int(0),
+        (ExternType::I32, Value::Int(n)) => Value::Int(n as i32 as i64),
+        (_, v) => v,
+    })
+}
+
+// Реализации части libc на Rust: программа с `extern fn puts(s: str) -> i32;` работает
+// и в интерпретаторе (и VM), и в скомпилированном виде.
+pub fn libc() -> HashMap<String, HostFn> {
+    let mut host: HashMap<String, HostFn> = HashMap::new();
+    host.insert("puts".into(), Rc::new(|args| {
+        println!("{}", str_arg(args, 0)?);
+        Ok(Value::Int(0))
+    }));
+    host.insert("putchar".into(), Rc::new(|args| {
+        let c = int_arg(args, 0)?;
+        io::stdout().write_all(&[c as u8]).map_err(|e| e.to_string())?;
+        Ok(Value::Int(c as u8 as i64))
+    }));
+    host.insert("getchar".into(), Rc::new(|_| {
+        io::stdout().flush().map_err(|e| e.to_string())?;
+        let mut b = [0u8];
+        let n = io::stdin().read(&mut b).map_err(|e| e.to_string())?;
+        Ok(Value::Int(if n == 0 { -1 } else { b[0] as i64 }))
+    }));
+    host.insert("strlen".into(), Rc::new(|args| Ok(Value::Int(str_arg(args, 0)?.len() as i64))));
+    // как atoi: пробелы, знак, цифры до первой не-цифры
+    host.insert("atoi".into(), Rc::new(|args| {
+        let s = str_arg(args, 0)?.trim_start();
+        let (sign, digits) = match s.as_bytes().first() {
+            Some(b'-') => (-1, &s[1..]),
+            Some(b'+') => (1, &s[1..]),
+            _ => (1, s),
+        };
+        let n = digits
+            .bytes()
+            .take_while(u8::is_ascii_digit)
+            .fold(0i64, |n, d| n.wrapping_mul(10).wrapping_add((d - b'0') as i64));
+        Ok(Value::Int(sign * n))
+    }));
+    for name in ["abs", "labs"] {
+        host.insert(name.into(), Rc::new(|args| Ok(Value::Int(int_arg(args, 0)?.wrapping_abs()))));
+    }
+    host.insert("toupper".into(), Rc::new(|args| Ok(Value::Int((int_arg(args, 0)? as u8).to_ascii_uppercase() as i64))));
+    host.insert("tolower".into(), Rc::new(|args| Ok(Value::Int((int_arg(args, 0)? as u8).to_ascii_lowercase() as i64))));
+    host
+}
+
+fn int_arg(args: &[Value], i: usize) -> Result<i64, String> {
+    args.get(i).and_then(Value::as_int).ok_or_else(|| format!("argument {} must be an integer", i + 1))
+}
+
+fn str_arg(args: &[Value], i: usize) -> Result<&str, String> {
+    match args.get(i) {
+        Some(Value::Str(s)) => Ok(s),
+        _ => Err(format!("argument {} must be a string", i + 1)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Type;
+
+    fn sig(params: Vec<ExternType>, ret: ExternType) -> ExternFn {
+        HostFunction::new("f", params, ret, |_| Ok(Value::Int(0))).sig
+    }
+
+    #[test]
+    fn call_converts_to_c_types() {
+        let echo: HostFn = Rc::new(|args| Ok(args[0].clone()));
+        let i32_fn = sig(vec![ExternType::I32], ExternType::I32);
+        let r = call(&i32_fn, &echo, vec![Value::Int(0x1_0000_0005)]).expect("call");
+        assert_eq!(r.as_int(), Some(5));
+        let r = call(&i32_fn, &echo, vec![Value::Int(-1)]).expect("call");
+        assert_eq!(r.as_int(), Some(-1));
+        let void_fn = sig(vec![ExternType::Value(Type::Int)], ExternType::Void);
+        assert_eq!(call(&void_fn, &echo, vec![Value::Int(9)]).expect("call").as_int(), Some(0));
+        let err = call(&i32_fn, &echo, vec![Value::Str("x".into())]).expect_err("not an integer");
+        assert_eq!(err, "f: expected integer, got x");
+    }
+
+    #[test]
+    fn host_errors_name_the_function() {
+        let failing: HostFn = Rc::new(|_| Err("boom".into()));
+        let f = sig(vec![], ExternType::Value(Type::Int));
+        assert_eq!(call(&f, &failing, vec![]).expect_err("fails"), "f: boom");
+    }
+
+    #[test]
+    fn libc_semantics() {
+        let libc = libc();
+        let int = |name: &str, arg: Value| libc[name](&[arg]).expect(name).as_int().expect("int");
+        assert_eq!(int("atoi", Value::Str(" +12x3".into())), 12);
+        assert_eq!(int("atoi", Value::Str("-7".into())), -7);
+        assert_eq!(int("atoi", Value::Str("abc".into())), 0);
+        assert_eq!(int("labs", Value::Int(i64::MIN)), i64::MIN);
+        assert_eq!(int("tolower", Value::Int('Q' as i64)), 'q' as i64);
+        assert_eq!(int("strlen", Value::Str("жж".into())), 4);
+        assert!(libc["strlen"](&[Value::Int(1)]).is_err());
+    }
+}
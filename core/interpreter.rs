@@ -1,77 +1,1055 @@
-use crate::ast::{Expr, Stmt, Function};
+use crate::ast::{referenced_names, Expr, ExternFn, Stmt, StmtKind, Function, Program, StructDef};
+use crate::host::{self, HostFn, HostFunction};
+use crate::lexer::Span;
 use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Bool(bool),
+    Char(char),
+    Str(String),
+    // массивы и структуры копируются по значению — как alloca в Codegen
+    Array(Vec<Value>),
+    // имя структуры и поля в порядке объявления
+    Struct(String, Vec<(String, Value)>),
+    Null,
+    Ptr(Pointer),
+    // лямбда или функция, взятая как значение
+    Closure(Rc<Closure>),
+}
+
+/// Функция плюс захваченные по значению переменные (как env-структура в Codegen).
+#[derive(Debug)]
+pub struct Closure {
+    pub(crate) func: Rc<Function>,
+    pub(crate) captured: Vec<(String, Value)>,
+}
+
+// Замыкания равны, только если это одно и то же значение.
+impl PartialEq for Closure {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// Указатель — не адрес, а путь: корень (переменная кадра или блок кучи)
+/// плюс индексы/поля внутри него. Так ловятся висячие указатели и use-after-free.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pointer {
+    base: Place,
+    path: Vec<PathSeg>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Place {
+    // id кадра и имя переменной
+    Local(usize, String),
+    Heap(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum PathSeg {
+    Index(usize),
+    Field(String),
+}
+
+// Блок из alloc(); после free значение None, но запись остаётся — для сообщений об ошибках.
+struct HeapBlock {
+    value: Option<Value>,
+    alloc_span: Span,
+    free_span: Option<Span>,
+}
+
+struct Frame {
+    id: usize,
+    vars: HashMap<String, Value>,
+    // для стека вызовов в отладчике: чья это функция и откуда её вызвали
+    function: String,
+    call_span: Span,
+}
+
+impl Value {
+    // Для арифметики и условий: bool -> 0/1, char -> код символа.
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Value::Int(n) => Some(*n),
+            Value::Bool(b) => Some(*b as i64),
+            Value::Char(c) => Some(*c as i64),
+            Value::Str(_) | Value::Array(_) | Value::Struct(..) | Value::Null | Value::Ptr(_) | Value::Closure(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Char(c) => write!(f, "{}", c),
+            Value::Str(s) => write!(f, "{}", s),
+            Value::Array(items) => {
+                write!(f, "[")?;
+                for (i, v) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", v)?;
+                }
+                write!(f, "]")
+            }
+            Value::Struct(name, fields) => {
+                write!(f, "{} {{ ", name)?;
+                for (i, (field, v)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", field, v)?;
+                }
+                write!(f, " }}")
+            }
+            Value::Null => write!(f, "null"),
+            Value::Ptr(_) => write!(f, "<ptr>"),
+            Value::Closure(_) => write!(f, "<fn>"),
+        }
+    }
+}
+
+/// Чем кончилась программа: `main` вернула значение или программа вызвала `exit(n)`.
+/// Процесс хоста exit() не завершает — код выхода решает вызывающий.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Return(Value),
+    Exit(i32),
+}
+
+impl Outcome {
+    /// Код выхода, как у скомпилированной программы: результат main или аргумент exit.
+    pub fn code(&self) -> Option<i64> {
+        match self {
+            Outcome::Return(v) => v.as_int(),
+            Outcome::Exit(code) => Some(*code as i64),
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Return(v) => write!(f, "{}", v),
+            Outcome::Exit(code) => write!(f, "exit({})", code),
+        }
+    }
+}
+
+/// Ошибка времени выполнения с местом в исходнике.
+/// `notes` — связанные места, например где блок был выделен и где освобождён.
+#[derive(Debug, Clone)]
+pub struct RuntimeError {
+    pub message: String,
+    pub span: Span,
+    pub notes: Vec<(String, Span)>,
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Вызывается перед каждым оператором — на этом построен отладчик (debugger.rs).
+/// Ошибка прерывает программу так же, как ошибка времени выполнения.
+/// Остальные точки нужны трассировке и профилю (trace.rs).
+pub trait Hook {
+    fn before_stmt(&mut self, interp: &mut Interpreter, stmt: &Stmt) -> Result<(), RuntimeError>;
+    /// Оператор выполнен — успешно или с ошибкой; кадр тот же, что в before_stmt.
+    fn after_stmt(&mut self, _interp: &Interpreter, _stmt: &Stmt) {}
+    /// Кадр функции уже создан: interp.function() — вызванная функция.
+    fn enter_call(&mut self, _interp: &Interpreter) {}
+    /// Кадр функции ещё не снят.
+    fn leave_call(&mut self, _interp: &Interpreter) {}
+}
+
+/// Имена встроенных функций (call_builtin).
+pub const BUILTINS: &[&str] = &["print", "println", "read_int", "exit", "len", "alloc", "free"];
 
 pub struct Interpreter {
-    vars: HashMap<String, i64>,
+    functions: HashMap<String, Function>,
+    externs: HashMap<String, ExternFn>,
+    // реализации extern-функций по имени; по умолчанию — часть libc
+    host: HashMap<String, HostFn>,
+    structs: HashMap<String, StructDef>,
+    // по кадру переменных на каждый активный вызов; id кадров не повторяются
+    frames: Vec<Frame>,
+    next_frame: usize,
+    heap: Vec<HeapBlock>,
+    // span выполняемого оператора — для ошибок без более точного места
+    span: Span,
+    // печатать ли трассу (let/expr/присваивания) в stderr
+    trace: bool,
+    hook: Option<Box<dyn Hook>>,
+    // куда печатают print/println; None — stdout
+    output: Option<Box<dyn Write>>,
+    // откуда читает read_int; None — stdin
+    input: Option<Box<dyn BufRead>>,
+    // код exit(): вызов раскручивается как ошибка, run_program превращает её в Outcome::Exit
+    exit: Option<i32>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Interpreter {
     pub fn new() -> Self {
-        Self { vars: HashMap::new() }
+        Self {
+            functions: HashMap::new(),
+            externs: HashMap::new(),
+            host: host::libc(),
+            structs: HashMap::new(),
+            frames: vec![Frame { id: 0, vars: HashMap::new(), function: String::new(), call_span: 0..0 }],
+            next_frame: 1,
+            heap: Vec::new(),
+            span: 0..0,
+            trace: true,
+            hook: None,
+            output: None,
+            input: None,
+            exit: None,
+        }
     }
 
-    pub fn run_function(func: &Function) {
-        let mut interp = Interpreter::new();
-        for stmt in &func.body {
-            interp.exec_stmt(stmt);
+    /// Запускает `main` программы и возвращает её результат или код exit().
+    pub fn run_program(&mut self, program: &Program) -> Result<Outcome, RuntimeError> {
+        for s in &program.structs {
+            self.structs.insert(s.name.clone(), s.clone());
+        }
+        for f in &program.functions {
+            self.functions.insert(f.name.clone(), f.clone());
+        }
+        for e in &program.externs {
+            self.externs.insert(e.name.clone(), e.clone());
+        }
+        let result = match self.call("main", Vec::new()) {
+            Ok(v) => Outcome::Return(v),
+            Err(_) if self.exit.is_some() => Outcome::Exit(self.exit.take().expect("exit code")),
+            Err(e) => return Err(e),
+        };
+        if self.trace {
+            eprintln!("main => {}", result);
+        }
+        Ok(result)
+    }
+
+    /// Без трассы интерпретатор заметно быстрее — так его и меряет `--bench`.
+    pub fn set_trace(&mut self, on: bool) {
+        self.trace = on;
+    }
+
+    /// Делает функцию хоста доступной программе; имя должно совпадать с её сигнатурой
+    /// в программе (modules::load_file добавляет её туда сам).
+    pub fn register_host(&mut self, f: &HostFunction) {
+        self.externs.insert(f.sig.name.clone(), f.sig.clone());
+        self.host.insert(f.sig.name.clone(), f.call.clone());
+    }
+
+    pub fn set_hook(&mut self, hook: Box<dyn Hook>) {
+        self.hook = Some(hook);
+    }
+
+    /// Вывод программы вместо stdout — в DAP stdout занят протоколом.
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = Some(output);
+    }
+
+    /// Вход read_int вместо stdin.
+    pub fn set_input(&mut self, input: Box<dyn BufRead>) {
+        self.input = Some(input);
+    }
+
+    fn write_output(&mut self, text: &str) {
+        match &mut self.output {
+            Some(out) => {
+                out.write_all(text.as_bytes()).expect("write program output");
+                out.flush().expect("flush program output");
+            }
+            None => {
+                print!("{}", text);
+                io::stdout().flush().expect("flush stdout");
+            }
+        }
+    }
+
+    /// Глубина вызовов: 1 — выполняется main.
+    pub fn depth(&self) -> usize {
+        self.frames.len() - 1
+    }
+
+    /// Функция верхнего кадра.
+    pub fn function(&self) -> &str {
+        &self.frames.last().expect("no active frame").function
+    }
+
+    /// Стек вызовов сверху вниз: функция и место, где она сейчас стоит.
+    pub fn stack(&self) -> Vec<(String, Span)> {
+        let mut out = Vec::new();
+        let mut span = self.span.clone();
+        for f in self.frames[1..].iter().rev() {
+            out.push((f.function.clone(), span));
+            span = f.call_span.clone();
+        }
+        out
+    }
+
+    /// Переменные кадра по номеру в stack() (0 — верхний), по алфавиту.
+    pub fn locals(&self, frame: usize) -> Vec<(String, Value)> {
+        let Some(f) = self.frames.len().checked_sub(frame + 1).filter(|&i| i > 0).map(|i| &self.frames[i]) else {
+            return Vec::new();
+        };
+        let mut vars: Vec<(String, Value)> = f.vars.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        vars.sort_by(|a, b| a.0.cmp(&b.0));
+        vars
+    }
+
+    /// Выражение в верхнем кадре, без трассы — для print/watch отладчика.
+    /// В отличие от программы, неизвестное имя здесь — ошибка, а не 0.
+    pub fn evaluate(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        let stmt = Stmt { kind: StmtKind::Expr(expr.clone()), span: self.span.clone() };
+        for name in referenced_names(&[stmt]) {
+            let known = self.vars().contains_key(&name)
+                || self.functions.contains_key(&name)
+                || self.externs.contains_key(&name)
+                || BUILTINS.contains(&name.as_str());
+            if !known {
+                return Err(self.error(format!("no variable {} in this frame", name)));
+            }
+        }
+        let (span, trace) = (self.span.clone(), self.trace);
+        self.trace = false;
+        let result = self.eval_expr(expr);
+        self.span = span;
+        self.trace = trace;
+        // exit() из отладчика программу не завершает
+        if self.exit.take().is_some() {
+            return Err(self.error("exit() cannot be evaluated here".to_string()));
+        }
+        result
+    }
+
+    /// Места alloc() для блоков, которые так и не освободили.
+    pub fn leaks(&self) -> Vec<Span> {
+        self.heap
+            .iter()
+            .filter(|b| b.value.is_some())
+            .map(|b| b.alloc_span.clone())
+            .collect()
+    }
+
+    fn error(&self, message: String) -> RuntimeError {
+        RuntimeError { message, span: self.span.clone(), notes: Vec::new() }
+    }
+
+    fn int(&self, v: &Value) -> Result<i64, RuntimeError> {
+        v.as_int().ok_or_else(|| self.error(format!("expected integer, got {}", v)))
+    }
+
+    fn vars(&mut self) -> &mut HashMap<String, Value> {
+        &mut self.frames.last_mut().expect("no active frame").vars
+    }
+
+    // Как и в Codegen: значение тела — значение последнего оператора-выражения.
+    fn exec_body(&mut self, body: &[Stmt]) -> Result<Option<Value>, RuntimeError> {
+        let mut last = None;
+        for s in body {
+            last = self.exec_stmt(s)?;
+        }
+        Ok(last)
+    }
+
+    fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Value, RuntimeError> {
+        if let Some(v) = self.call_builtin(name, &args)? {
+            return Ok(v);
+        }
+        // локальная переменная-замыкание перекрывает функцию с тем же именем
+        if let Some(Value::Closure(c)) = self.vars().get(name) {
+            let c = c.clone();
+            return self.invoke(&c.func, c.captured.clone(), args);
+        }
+        let func = match self.functions.get(name) {
+            Some(f) => f.clone(),
+            None => match self.externs.get(name) {
+                Some(e) => return self.call_extern(&e.clone(), args),
+                None => return Err(self.error(format!("call to undefined function {}", name))),
+            },
+        };
+        self.invoke(&func, Vec::new(), args)
+    }
+
+    fn call_extern(&mut self, ext: &ExternFn, args: Vec<Value>) -> Result<Value, RuntimeError> {
+        if ext.params.len() != args.len() {
+            return Err(self.error(format!(
+                "{} expects {} arguments, got {}", ext.name, ext.params.len(), args.len()
+            )));
+        }
+        let host = match self.host.get(&ext.name) {
+            Some(h) => h.clone(),
+            None => {
+                return Err(self.error(format!(
+                    "extern fn {} has no host implementation in the interpreter", ext.name
+                )))
+            }
+        };
+        host::call(ext, &host, args).map_err(|e| self.error(e))
+    }
+
+    // Новый кадр: захваченные переменные, поверх них — параметры.
+    fn invoke(&mut self, func: &Function, captured: Vec<(String, Value)>, args: Vec<Value>) -> Result<Value, RuntimeError> {
+        if func.params.len() != args.len() {
+            return Err(self.error(format!(
+                "{} expects {} arguments, got {}", func.name, func.params.len(), args.len()
+            )));
+        }
+        let caller_span = self.span.clone();
+        let vars = captured.into_iter().chain(func.params.iter().map(|(p, _)| p.clone()).zip(args)).collect();
+        self.frames.push(Frame { id: self.next_frame, vars, function: func.name.clone(), call_span: caller_span.clone() });
+        self.next_frame += 1;
+        self.notify(|hook, interp| hook.enter_call(interp));
+        let result = self.exec_body(&func.body);
+        self.notify(|hook, interp| hook.leave_call(interp));
+        self.frames.pop();
+        self.span = caller_span;
+        Ok(result?.unwrap_or(Value::Int(0)))
+    }
+
+    // Встроенные функции; None — такой встроенной нет, ищем пользовательскую.
+    // Список имён — в BUILTINS.
+    fn call_builtin(&mut self, name: &str, args: &[Value]) -> Result<Option<Value>, RuntimeError> {
+        match (name, args) {
+            ("print", [v]) => self.write_output(&v.to_string()),
+            ("println", []) => self.write_output("\n"),
+            ("println", [v]) => self.write_output(&format!("{}\n", v)),
+            ("read_int", []) => {
+                let n = match &mut self.input {
+                    Some(input) => read_int(input),
+                    None => read_int(&mut io::stdin().lock()),
+                };
+                return Ok(Some(Value::Int(n)));
+            }
+            ("exit", [code]) => {
+                self.exit = Some(self.int(code)? as i32);
+                return Err(self.error("exit".to_string()));
+            }
+            ("len", [Value::Array(items)]) => return Ok(Some(Value::Int(items.len() as i64))),
+            ("len", [other]) => return Err(self.error(format!("len() expects an array, got {}", other))),
+            ("alloc", [v]) => {
+                self.heap.push(HeapBlock { value: Some(v.clone()), alloc_span: self.span.clone(), free_span: None });
+                let base = Place::Heap(self.heap.len() - 1);
+                return Ok(Some(Value::Ptr(Pointer { base, path: Vec::new() })));
+            }
+            // free(null) ничего не делает, как в C
+            ("free", [Value::Null]) => {}
+            ("free", [Value::Ptr(Pointer { base: Place::Heap(block), path })]) if path.is_empty() => {
+                let block = *block;
+                if let Some(freed) = self.heap[block].free_span.clone() {
+                    return Err(self.block_error("double free".to_string(), block, Some(freed)));
+                }
+                self.heap[block].value = None;
+                self.heap[block].free_span = Some(self.span.clone());
+            }
+            ("free", [other]) => {
+                return Err(self.error(format!("free() expects a pointer returned by alloc(), got {}", other)));
+            }
+            ("print" | "println" | "read_int" | "exit" | "len" | "alloc" | "free", _) => {
+                return Err(self.error(format!("wrong number of arguments to {}", name)));
+            }
+            _ => return Ok(None),
         }
+        Ok(Some(Value::Int(0)))
     }
 
-    fn exec_stmt(&mut self, stmt: &Stmt) {
-        match stmt {
-            Stmt::Let(name, expr) => {
-                let val = self.eval_expr(expr);
-                self.vars.insert(name.clone(), val);
-                println!("let {} = {}", name, val);
+    fn exec_stmt(&mut self, stmt: &Stmt) -> Result<Option<Value>, RuntimeError> {
+        self.span = stmt.span.clone();
+        // хук на время вызова вынимаем: выражения, которые он вычисляет, его не зовут
+        if let Some(mut hook) = self.hook.take() {
+            let result = hook.before_stmt(self, stmt);
+            self.hook = Some(hook);
+            result?;
+        }
+        let result = self.run_stmt(stmt);
+        self.notify(|hook, interp| hook.after_stmt(interp, stmt));
+        result
+    }
+
+    // Точки хука, которые не могут прервать программу.
+    fn notify(&mut self, f: impl FnOnce(&mut dyn Hook, &Interpreter)) {
+        if let Some(mut hook) = self.hook.take() {
+            f(hook.as_mut(), self);
+            self.hook = Some(hook);
+        }
+    }
+
+    fn run_stmt(&mut self, stmt: &Stmt) -> Result<Option<Value>, RuntimeError> {
+        match &stmt.kind {
+            StmtKind::Let(name, _, expr) => {
+                let val = self.eval_expr(expr)?;
+                if self.trace {
+                    eprintln!("let {} = {}", name, val);
+                }
+                self.vars().insert(name.clone(), val);
             }
-            Stmt::Assign(name,expr) => {
-                let val = self.eval_expr(expr);
-                if self.vars.contains_key(name) {
-                    self.vars.insert(name.clone(), val);
-                } else {
-                    panic!("assgin to undefined var {}", name);
+            StmtKind::Assign(target, expr) => {
+                let val = self.eval_expr(expr)?;
+                self.assign(target, val)?;
+            }
+            StmtKind::Expr(expr) | StmtKind::Semi(expr) => {
+                let val = self.eval_expr(expr)?;
+                // if/блок-операторы не трассируем: их тело уже оттрассировано
+                if self.trace && !matches!(expr, Expr::If { .. } | Expr::Block(_) | Expr::Match { .. }) {
+                    eprintln!("expr => {}", val);
+                }
+                if matches!(stmt.kind, StmtKind::Expr(_)) {
+                    return Ok(Some(val));
                 }
-                println!("{} = {}", name, val);
             }
-            Stmt::Expr(expr) => {
-                let val = self.eval_expr(expr);
-                println!("expr => {}", val);
+            StmtKind::While { cond, body } => loop {
+                let c = self.eval_expr(cond)?;
+                if self.int(&c)? == 0 {
+                    break;
+                }
+                self.exec_body(body)?;
+            },
+            StmtKind::For { var, start, end, inclusive, step, body } => {
+                let s = self.eval_expr(start)?;
+                let e = self.eval_expr(end)?;
+                let (mut i, end) = (self.int(&s)?, self.int(&e)?);
+                // переменная цикла перекрывает внешнюю только на время цикла
+                let shadowed = self.vars().remove(var);
+                let in_range = |i: i64| match (*step > 0, *inclusive) {
+                    (true, false) => i < end,
+                    (true, true) => i <= end,
+                    (false, false) => i > end,
+                    (false, true) => i >= end,
+                };
+                let mut result = Ok(());
+                while in_range(i) {
+                    self.vars().insert(var.clone(), Value::Int(i));
+                    if let Err(e) = self.exec_body(body) {
+                        result = Err(e);
+                        break;
+                    }
+                    // `for i in 0..=i64::MAX` кончается на MAX: следующего значения нет
+                    match i.checked_add(*step) {
+                        Some(next) => i = next,
+                        None => break,
+                    }
+                }
+                self.vars().remove(var);
+                if let Some(v) = shadowed {
+                    self.vars().insert(var.clone(), v);
+                }
+                result?;
             }
-            Stmt::If { cond, then_body, else_body } => {
-                let c = self.eval_expr(cond) != 0;
-                if c {
-                    for s in then_body {self.exec_stmt(s);}
-                } else if let Some(eb) = else_body {
-                    for s in eb {self.exec_stmt(s);}
+        }
+        Ok(None)
+    }
+
+    // `a[i] = v`, `p.x = v`, `*p = v` — вычисляем место и пишем через store.
+    fn assign(&mut self, target: &Expr, val: Value) -> Result<(), RuntimeError> {
+        match target {
+            Expr::Ident(name) => {
+                if self.trace {
+                    eprintln!("{} = {}", name, val);
+                }
+                if !self.vars().contains_key(name) {
+                    return Err(self.error(format!("assign to undefined var {}", name)));
                 }
+                self.vars().insert(name.clone(), val);
+                Ok(())
+            }
+            _ => {
+                let ptr = self.place(target)?;
+                self.store(&ptr, val)
             }
-            &Stmt::While { .. } => {
-    todo!("While loop not implemented yet")
-}
         }
     }
 
-    fn eval_expr(&mut self, expr: &Expr) -> i64 {
+    // Место в памяти для `&expr` и присваивания. Через указатель `p.x` — это поле `*p`.
+    fn place(&mut self, expr: &Expr) -> Result<Pointer, RuntimeError> {
         match expr {
-            Expr::Number(n) => *n,
-            Expr::Ident(name) => *self.vars.get(name).unwrap_or(&0),
+            Expr::Ident(name) => {
+                if !self.vars().contains_key(name) {
+                    return Err(self.error(format!("use of undefined variable {}", name)));
+                }
+                let id = self.frames.last().expect("no active frame").id;
+                Ok(Pointer { base: Place::Local(id, name.clone()), path: Vec::new() })
+            }
+            Expr::Index { array, index, span } => {
+                let mut ptr = self.place(array)?;
+                let i = self.eval_expr(index)?;
+                let i = self.int(&i)?;
+                // проверка границ — на текущем значении массива
+                let mut arr = self.load(&ptr)?;
+                self.element(&mut arr, i, span)?;
+                ptr.path.push(PathSeg::Index(i as usize));
+                Ok(ptr)
+            }
+            Expr::Field(base, field) => {
+                let mut ptr = match base.as_ref() {
+                    Expr::Ident(_) | Expr::Index { .. } | Expr::Field(..) | Expr::Deref(..) => {
+                        let ptr = self.place(base)?;
+                        match self.load(&ptr)? {
+                            Value::Ptr(p) => p,
+                            Value::Null => return Err(self.error("null pointer dereference".to_string())),
+                            _ => ptr,
+                        }
+                    }
+                    _ => self.deref(base, &self.span.clone())?,
+                };
+                // проверяем, что поле существует
+                let mut obj = self.load(&ptr)?;
+                self.field(&mut obj, field)?;
+                ptr.path.push(PathSeg::Field(field.clone()));
+                Ok(ptr)
+            }
+            Expr::Deref(inner, span) => self.deref(inner, span),
+            _ => Err(self.error("expression is not a place in memory".to_string())),
+        }
+    }
+
+    fn deref(&mut self, expr: &Expr, span: &Span) -> Result<Pointer, RuntimeError> {
+        match self.eval_expr(expr)? {
+            Value::Ptr(p) => Ok(p),
+            Value::Null => Err(RuntimeError {
+                message: "null pointer dereference".to_string(),
+                span: span.clone(),
+                notes: Vec::new(),
+            }),
+            other => Err(self.error(format!("cannot dereference {}", other))),
+        }
+    }
+
+    // Ошибка, связанная с блоком кучи: к ней приложены места alloc и free.
+    fn block_error(&self, message: String, block: usize, freed: Option<Span>) -> RuntimeError {
+        let mut err = self.error(message);
+        err.notes.push(("block allocated here".to_string(), self.heap[block].alloc_span.clone()));
+        if let Some(span) = freed {
+            err.notes.push(("block freed here".to_string(), span));
+        }
+        err
+    }
+
+    fn root(&mut self, base: &Place) -> Result<&mut Value, RuntimeError> {
+        match base {
+            Place::Local(id, name) => {
+                let err = self.error(format!(
+                    "dangling pointer to `{}`: the function that owned it has returned", name
+                ));
+                self.frames
+                    .iter_mut()
+                    .find(|f| f.id == *id)
+                    .and_then(|f| f.vars.get_mut(name))
+                    .ok_or(err)
+            }
+            Place::Heap(block) => {
+                if let Some(freed) = self.heap[*block].free_span.clone() {
+                    return Err(self.block_error("use after free".to_string(), *block, Some(freed)));
+                }
+                Ok(self.heap[*block].value.as_mut().expect("live block"))
+            }
+        }
+    }
+
+    fn load(&mut self, ptr: &Pointer) -> Result<Value, RuntimeError> {
+        let mut v = self.root(&ptr.base)?.clone();
+        for seg in &ptr.path {
+            v = match seg {
+                PathSeg::Index(i) => self.element(&mut v, *i as i64, &self.span.clone())?.clone(),
+                PathSeg::Field(f) => self.field(&mut v, f)?.clone(),
+            };
+        }
+        Ok(v)
+    }
+
+    // Как и раньше, копия корня меняется по пути и записывается обратно целиком.
+    fn store(&mut self, ptr: &Pointer, val: Value) -> Result<(), RuntimeError> {
+        let mut root = self.root(&ptr.base)?.clone();
+        let span = self.span.clone();
+        let mut slot = &mut root;
+        for seg in &ptr.path {
+            slot = match seg {
+                PathSeg::Index(i) => self.element(slot, *i as i64, &span)?,
+                PathSeg::Field(f) => self.field(slot, f)?,
+            };
+        }
+        *slot = val;
+        *self.root(&ptr.base)? = root;
+        Ok(())
+    }
+
+    fn element<'v>(&self, arr: &'v mut Value, i: i64, span: &Span) -> Result<&'v mut Value, RuntimeError> {
+        let items = match arr {
+            Value::Array(items) => items,
+            other => return Err(self.error(format!("cannot index into {}", other))),
+        };
+        let len = items.len();
+        usize::try_from(i)
+            .ok()
+            .and_then(|i| items.get_mut(i))
+            .ok_or_else(|| RuntimeError {
+                message: format!("index {} out of bounds for length {}", i, len),
+                span: span.clone(),
+                notes: Vec::new(),
+            })
+    }
+
+    fn field<'v>(&self, obj: &'v mut Value, field: &str) -> Result<&'v mut Value, RuntimeError> {
+        let (name, fields) = match obj {
+            Value::Struct(name, fields) => (name.clone(), fields),
+            other => return Err(self.error(format!("cannot access field {} of {}", field, other))),
+        };
+        fields
+            .iter_mut()
+            .find(|(f, _)| f == field)
+            .map(|(_, v)| v)
+            .ok_or_else(|| self.error(format!("struct {} has no field {}", name, field)))
+    }
+
+    // Поля раскладываем в порядке объявления; лишние и пропущенные — ошибка.
+    fn struct_lit(&mut self, name: &str, inits: &[(String, Expr)]) -> Result<Value, RuntimeError> {
+        let def = match self.structs.get(name) {
+            Some(d) => d.clone(),
+            None => return Err(self.error(format!("unknown struct {}", name))),
+        };
+        if let Some((extra, _)) = inits.iter().find(|(f, _)| !def.fields.iter().any(|(d, _)| d == f)) {
+            return Err(self.error(format!("struct {} has no field {}", name, extra)));
+        }
+        let mut fields = Vec::new();
+        for (field, _) in &def.fields {
+            let init = match inits.iter().find(|(f, _)| f == field) {
+                Some((_, e)) => e,
+                None => return Err(self.error(format!("missing field {} in {} literal", field, name))),
+            };
+            fields.push((field.clone(), self.eval_expr(init)?));
+        }
+        Ok(Value::Struct(name.to_string(), fields))
+    }
+
+    fn eval_expr(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        Ok(match expr {
+            Expr::Number(n) => Value::Int(*n),
+            Expr::Bool(b) => Value::Bool(*b),
+            Expr::Char(c) => Value::Char(*c),
+            Expr::Str(s) => Value::Str(s.clone()),
+            Expr::Null => Value::Null,
+            Expr::Ident(name) => match self.vars().get(name) {
+                Some(v) => v.clone(),
+                // имя функции как значение — замыкание без захвата
+                None => match self.functions.get(name) {
+                    Some(f) => Value::Closure(Rc::new(Closure { func: Rc::new(f.clone()), captured: Vec::new() })),
+                    None => Value::Int(0),
+                },
+            },
             Expr::BinaryOp(left, op, right) => {
-                let a = self.eval_expr(left);
-                let b = self.eval_expr(right);
+                let a = self.eval_expr(left)?;
+                let b = self.eval_expr(right)?;
+                // строки сравниваем целиком, указатели — по месту, всё остальное — как числа
+                if matches!((&a, &b), (Value::Str(_), Value::Str(_)))
+                    || matches!(a, Value::Null | Value::Ptr(_))
+                    || matches!(b, Value::Null | Value::Ptr(_))
+                {
+                    return match op.as_str() {
+                        "==" => Ok(Value::Bool(a == b)),
+                        "!=" => Ok(Value::Bool(a != b)),
+                        _ => Err(self.error(format!("operator {} is not defined for {} and {}", op, a, b))),
+                    };
+                }
+                let (a, b) = (self.int(&a)?, self.int(&b)?);
                 match op.as_str() {
-                    "+" => a + b,
-                    "-" => a - b,
-                    "*" => a * b,
-                    "/" => a / b,
-                    "==" => (a == b) as i64,
-                    "!=" => (a != b) as i64,
-                    "<" => (a < b) as i64,
-                    "<=" => (a <= b) as i64,
-                    ">" => (a > b) as i64,
-                    ">=" => (a >= b) as i64,
-                    _ => panic!("Unknown operator {}", op),
+                    // переполнение — по модулю 2^64, как в скомпилированном коде (и i64::MIN / -1)
+                    "+" => Value::Int(a.wrapping_add(b)),
+                    "-" => Value::Int(a.wrapping_sub(b)),
+                    "*" => Value::Int(a.wrapping_mul(b)),
+                    "/" if b == 0 => return Err(self.error("division by zero".to_string())),
+                    "/" => Value::Int(a.wrapping_div(b)),
+                    "==" => Value::Bool(a == b),
+                    "!=" => Value::Bool(a != b),
+                    "<" => Value::Bool(a < b),
+                    "<=" => Value::Bool(a <= b),
+                    ">" => Value::Bool(a > b),
+                    ">=" => Value::Bool(a >= b),
+                    _ => return Err(self.error(format!("Unknown operator {}", op))),
                 }
             }
-        }
+            Expr::Call(name, args) => {
+                let mut vals = Vec::new();
+                for a in args {
+                    vals.push(self.eval_expr(a)?);
+                }
+                self.call(name, vals)?
+            }
+            Expr::Array(elems) => {
+                let mut items = Vec::new();
+                for e in elems {
+                    items.push(self.eval_expr(e)?);
+                }
+                Value::Array(items)
+            }
+            Expr::ArrayRepeat(elem, n) => Value::Array(vec![self.eval_expr(elem)?; *n]),
+            Expr::Index { array, index, span } => {
+                let mut arr = self.eval_expr(array)?;
+                let i = self.eval_expr(index)?;
+                let i = self.int(&i)?;
+                self.element(&mut arr, i, span)?.clone()
+            }
+            Expr::StructLit(name, inits) => self.struct_lit(name, inits)?,
+            Expr::Field(base, field) => match self.eval_expr(base)? {
+                // автоматическое разыменование: p.x == (*p).x
+                Value::Ptr(p) => {
+                    let mut obj = self.load(&p)?;
+                    self.field(&mut obj, field)?.clone()
+                }
+                Value::Null => return Err(self.error("null pointer dereference".to_string())),
+                mut obj => self.field(&mut obj, field)?.clone(),
+            },
+            Expr::AddrOf(target) => Value::Ptr(self.place(target)?),
+            // захват по значению: дальнейшие изменения переменных лямбда не видит
+            Expr::Lambda { params, ret, body } => {
+                let mut captured = Vec::new();
+                for name in referenced_names(body) {
+                    if params.iter().any(|(p, _)| *p == name) {
+                        continue;
+                    }
+                    if let Some(v) = self.vars().get(&name) {
+                        captured.push((name, v.clone()));
+                    }
+                }
+                let func = Rc::new(Function {
+                    name: "<lambda>".to_string(),
+                    doc: None,
+                    params: params.clone(),
+                    ret: ret.clone(),
+                    body: body.clone(),
+                    span: self.span.clone(),
+                });
+                Value::Closure(Rc::new(Closure { func, captured }))
+            }
+            Expr::If { cond, then_body, else_body } => {
+                let c = self.eval_expr(cond)?;
+                let body = if self.int(&c)? != 0 { Some(then_body) } else { else_body.as_ref() };
+                match body {
+                    Some(b) => self.exec_body(b)?.unwrap_or(Value::Int(0)),
+                    None => Value::Int(0),
+                }
+            }
+            Expr::Block(body) => self.exec_body(body)?.unwrap_or(Value::Int(0)),
+            // первая ветка, у которой подошёл хоть один образец
+            Expr::Match { scrutinee, arms, span } => {
+                let v = self.eval_expr(scrutinee)?;
+                let n = self.int(&v)?;
+                match arms.iter().find(|arm| arm.patterns.iter().any(|(p, _)| p.matches(n))) {
+                    Some(arm) => self.eval_expr(&arm.body)?,
+                    None => {
+                        return Err(RuntimeError {
+                            message: format!("no match arm for value {}", n),
+                            span: span.clone(),
+                            notes: Vec::new(),
+                        })
+                    }
+                }
+            }
+            Expr::Apply(callee, args) => {
+                let c = match self.eval_expr(callee)? {
+                    Value::Closure(c) => c,
+                    other => return Err(self.error(format!("cannot call {}", other))),
+                };
+                let mut vals = Vec::new();
+                for a in args {
+                    vals.push(self.eval_expr(a)?);
+                }
+                self.invoke(&c.func, c.captured.clone(), vals)?
+            }
+            Expr::Deref(inner, span) => {
+                let ptr = self.deref(inner, span)?;
+                self.load(&ptr)?
+            }
+        })
+    }
+}
+
+
+/// read_int как scanf("%lld") в Codegen: пропускает пробелы и читает одно число, остаток
+/// строки не трогает. Если числа нет — 0, а мешающий текст остаётся во входе, так что
+/// и следующие read_int вернут 0. Переполнение насыщается, как у strtoll.
+pub fn read_int(input: &mut dyn BufRead) -> i64 {
+    fn peek(input: &mut dyn BufRead) -> Option<u8> {
+        input.fill_buf().expect("read input").first().copied()
+    }
+    while peek(input).is_some_and(|b| b.is_ascii_whitespace()) {
+        input.consume(1);
+    }
+    let mut negative = false;
+    if let Some(sign @ (b'-' | b'+')) = peek(input) {
+        negative = sign == b'-';
+        input.consume(1);
+    }
+    let mut n: i64 = 0;
+    while let Some(d) = peek(input).filter(u8::is_ascii_digit) {
+        input.consume(1);
+        let d = (d - b'0') as i64;
+        n = n.saturating_mul(10).saturating_add(if negative { -d } else { d });
+    }
+    n
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::SourceMap;
+    use crate::testutil::{interpret, load};
+
+    fn output(src: &str) -> String {
+        let (out, result) = interpret(src);
+        result.expect("run");
+        out
+    }
+
+    #[test]
+    fn literals() {
+        let src = r#"fn main() {
+            println(0xff + 0o17 + 0b101 + 1_000_000);
+            println(true);
+            println('ж');
+            print('a'); print('€'); println('\u{1F600}');
+            println("tab\there \"q\" \u{41}");
+            0
+        }"#;
+        assert_eq!(output(src), "1000275\ntrue\nж\na€\u{1F600}\ntab\there \"q\" A\n");
+    }
+
+    #[test]
+    fn read_int_is_token_based() {
+        let read = |text: &str| {
+            let mut input = std::io::Cursor::new(text.as_bytes().to_vec());
+            let values: Vec<i64> = (0..3).map(|_| super::read_int(&mut input)).collect();
+            values
+        };
+        assert_eq!(read("1 2 3"), [1, 2, 3]);
+        assert_eq!(read("\n 10\n\t-20\n"), [10, -20, 0]);
+        assert_eq!(read("5abc 6"), [5, 0, 0]);
+        assert_eq!(read("-99999999999999999999"), [i64::MIN, 0, 0]);
+    }
+
+    // Запуск с доступом к интерпретатору после программы (утечки) и к полной ошибке.
+    fn run(src: &str) -> (Interpreter, Result<Outcome, RuntimeError>, SourceMap) {
+        let (program, sources) = load(src);
+        let mut interp = Interpreter::new();
+        interp.set_trace(false);
+        interp.set_output(Box::new(io::sink()));
+        let result = interp.run_program(&program);
+        (interp, result, sources)
+    }
+
+    // Сообщение и места заметок как "строка:столбец".
+    fn error_at(src: &str) -> (String, String, Vec<(String, String)>) {
+        let (_, result, sources) = run(src);
+        let e = result.expect_err("program should fail");
+        let notes = e.notes.iter().map(|(n, span)| (n.clone(), sources.location(span.start))).collect();
+        (e.message, sources.location(e.span.start), notes)
+    }
+
+    #[test]
+    fn exit_returns_to_the_host() {
+        let src = "fn f(n: i64) -> i64 { exit(n); 1 }\nfn main() { print(\"before \"); let x = f(3); println(x); x }";
+        let (out, result) = interpret(src);
+        assert_eq!((out.as_str(), result), ("before ", Ok(Outcome::Exit(3))));
+        // хост жив: следующий запуск — обычный
+        assert_eq!(interpret("fn main() { exit(0 - 1); 0 }").1, Ok(Outcome::Exit(-1)));
+        assert_eq!(interpret("fn main() { println(1); 2 }"), ("1\n".to_string(), Ok(Outcome::Return(Value::Int(2)))));
+    }
+
+    #[test]
+    fn linked_list_on_the_heap() {
+        let src = r#"struct Node { value: i64, next: *Node }
+        fn main() {
+            let head: *Node = null;
+            for i in 1..=4 {
+                head = alloc(Node { value: i, next: head });
+            }
+            let sum = 0;
+            let p = head;
+            while (p != null) {
+                sum = sum + p.value;
+                let next = p.next;
+                free(p);
+                p = next;
+            }
+            let x = 5;
+            let r = &x;
+            *r = *r + 1;
+            sum * 10 + x
+        }"#;
+        let (interp, result, _) = run(src);
+        assert_eq!(result.expect("run"), Outcome::Return(Value::Int(106)));
+        assert!(interp.leaks().is_empty());
+    }
+
+    #[test]
+    fn memory_errors_point_at_alloc_and_free() {
+        let src = "fn main() {\n    let p = alloc(1);\n    free(p);\n    *p\n}";
+        assert_eq!(
+            error_at(src),
+            (
+                "use after free".to_string(),
+                "4:5".to_string(),
+                vec![
+                    ("block allocated here".to_string(), "2:5".to_string()),
+                    ("block freed here".to_string(), "3:5".to_string()),
+                ]
+            )
+        );
+        let src = "fn main() {\n    let p = alloc(1);\n    free(p);\n    free(p);\n    0\n}";
+        assert_eq!(error_at(src).0, "double free");
+        let src = "fn main() {\n    let p: *i64 = null;\n    *p\n}";
+        assert_eq!(error_at(src).0, "null pointer dereference");
+        let src = "fn f() -> *i64 {\n    let x = 1;\n    &x\n}\nfn main() {\n    let p = f();\n    *p\n}";
+        assert_eq!(error_at(src).0, "dangling pointer to `x`: the function that owned it has returned");
+    }
+
+    #[test]
+    fn leaks_are_reported_by_alloc_site() {
+        let src = "fn main() {\n    let a = alloc(1);\n    let b = alloc(2);\n    free(a);\n    0\n}";
+        let (interp, result, sources) = run(src);
+        result.expect("run");
+        let leaks: Vec<String> = interp.leaks().iter().map(|s| sources.location(s.start)).collect();
+        assert_eq!(leaks, ["3:5"]);
+    }
+
+    #[test]
+    fn semicolon_drops_the_block_value() {
+        let (_, result) = interpret("fn main() { let a = { 1; }; let b = { 2 }; a * 10 + b }");
+        assert_eq!(result, Ok(Outcome::Return(Value::Int(2))));
+        let (_, result) = interpret("fn f() -> i64 { 5; }\nfn main() { f() + if (true) { 3 } else { 4 } }");
+        assert_eq!(result, Ok(Outcome::Return(Value::Int(3))));
+    }
+
+    #[test]
+    fn integer_overflow_wraps() {
+        let src = r#"fn main() {
+            let max = 9223372036854775807;
+            let min = 0 - max - 1;
+            println(max + 1);
+            println(min - 1);
+            println(max * 3);
+            println(min / (0 - 1));
+            min / 0
+        }"#;
+        let (out, result) = crate::testutil::interpret(src);
+        assert_eq!(out, "-9223372036854775808\n9223372036854775807\n9223372036854775805\n-9223372036854775808\n");
+        assert_eq!(result.expect_err("division by zero"), "runtime error at 8:13: division by zero");
     }
 }
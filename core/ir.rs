@@ -0,0 +1,1400 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::ast::{Expr, ExternFn, ExternType, Function as AstFunction, MatchArm, Pattern, Program, Stmt, StmtKind, Type};
+use crate::lexer::Span;
+use crate::modules::SourceMap;
+
+// Своё промежуточное представление между AST и LLVM: функции из базовых блоков,
+// каждое значение определяется ровно одной инструкцией (SSA), у значения есть тип.
+// Переменные строятся одним из двух способов (SsaMode): через alloca/load/store — как
+// у Codegen до mem2reg, или сразу в SSA с phi по алгоритму Braun et al.
+// Поддерживаются целые, bool, char, строки, управление и вызовы; массивы, структуры,
+// указатели и замыкания — ещё нет (ошибка построения).
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrType {
+    I1,
+    // char
+    I32,
+    I64,
+    Str,
+    // адрес слота alloca
+    Ptr,
+    Void,
+}
+
+impl IrType {
+    pub fn is_int(self) -> bool {
+        matches!(self, IrType::I1 | IrType::I32 | IrType::I64)
+    }
+
+    fn bits(self) -> u32 {
+        match self {
+            IrType::I1 => 1,
+            IrType::I32 => 32,
+            _ => 64,
+        }
+    }
+}
+
+impl fmt::Display for IrType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            IrType::I1 => "i1",
+            IrType::I32 => "i32",
+            IrType::I64 => "i64",
+            IrType::Str => "str",
+            IrType::Ptr => "ptr",
+            IrType::Void => "void",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ValueId(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub usize);
+
+impl fmt::Display for ValueId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl fmt::Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl BinOp {
+    fn parse(op: &str) -> Option<BinOp> {
+        Some(match op {
+            "+" => BinOp::Add,
+            "-" => BinOp::Sub,
+            "*" => BinOp::Mul,
+            "/" => BinOp::Div,
+            "==" => BinOp::Eq,
+            "!=" => BinOp::Ne,
+            "<" => BinOp::Lt,
+            "<=" => BinOp::Le,
+            ">" => BinOp::Gt,
+            ">=" => BinOp::Ge,
+            _ => return None,
+        })
+    }
+
+    pub fn is_cmp(self) -> bool {
+        !matches!(self, BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div)
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            BinOp::Add => "add",
+            BinOp::Sub => "sub",
+            BinOp::Mul => "mul",
+            BinOp::Div => "sdiv",
+            BinOp::Eq => "eq",
+            BinOp::Ne => "ne",
+            BinOp::Lt => "slt",
+            BinOp::Le => "sle",
+            BinOp::Gt => "sgt",
+            BinOp::Ge => "sge",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CastOp {
+    Zext,
+    Sext,
+    Trunc,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Builtin {
+    Print,
+    Println,
+    ReadInt,
+    Exit,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Callee {
+    Function(String),
+    Extern(String),
+    Builtin(Builtin),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inst {
+    // целая константа, тип — у результата
+    Const(i64),
+    Str(String),
+    Param(usize),
+    Binary(BinOp, ValueId, ValueId),
+    // к типу результата
+    Cast(CastOp, ValueId),
+    // результат — ptr на слот данного типа
+    Alloca(IrType),
+    Load(ValueId),
+    // ptr, значение
+    Store(ValueId, ValueId),
+    Call(Callee, Vec<ValueId>),
+    Phi(Vec<(BlockId, ValueId)>),
+    // ошибка времени выполнения: printf-формат в stderr и exit(101); дальше — unreachable
+    Fail(String, Vec<ValueId>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Instr {
+    pub dest: Option<ValueId>,
+    pub inst: Inst,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator {
+    Br(BlockId),
+    CondBr(ValueId, BlockId, BlockId),
+    Ret(ValueId),
+    Unreachable,
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Br(b) => vec![*b],
+            Terminator::CondBr(_, t, e) => vec![*t, *e],
+            Terminator::Ret(_) | Terminator::Unreachable => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub name: String,
+    pub insts: Vec<Instr>,
+    // None только пока функция строится
+    pub term: Option<Terminator>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<(String, IrType)>,
+    pub ret: IrType,
+    // bb0 — вход
+    pub blocks: Vec<Block>,
+    // тип каждого значения, индекс — ValueId
+    pub types: Vec<IrType>,
+    // имена переменных у параметров и alloca — только для дампа
+    pub names: HashMap<ValueId, String>,
+}
+
+pub struct Module {
+    pub functions: Vec<Function>,
+    pub externs: Vec<ExternFn>,
+}
+
+impl Function {
+    pub fn ty(&self, v: ValueId) -> IrType {
+        self.types[v.0]
+    }
+
+    pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+        let mut preds = vec![Vec::new(); self.blocks.len()];
+        for (i, b) in self.blocks.iter().enumerate() {
+            for s in b.term.iter().flat_map(Terminator::successors) {
+                if s.0 < preds.len() && !preds[s.0].contains(&BlockId(i)) {
+                    preds[s.0].push(BlockId(i));
+                }
+            }
+        }
+        preds
+    }
+
+    /// Достижимые из входа блоки в обратном постпорядке: доминатор идёт раньше
+    /// доминируемого — в этом порядке блоки и переводятся в LLVM.
+    pub fn reverse_postorder(&self) -> Vec<BlockId> {
+        let mut seen = vec![false; self.blocks.len()];
+        let mut order = Vec::new();
+        // (блок, уже обошли потомков)
+        let mut stack = vec![(BlockId(0), false)];
+        while let Some((b, done)) = stack.pop() {
+            if done {
+                order.push(b);
+                continue;
+            }
+            if seen[b.0] {
+                continue;
+            }
+            seen[b.0] = true;
+            stack.push((b, true));
+            let succs = self.blocks[b.0].term.iter().flat_map(Terminator::successors);
+            for s in succs.collect::<Vec<_>>().into_iter().rev() {
+                if s.0 < seen.len() && !seen[s.0] {
+                    stack.push((s, false));
+                }
+            }
+        }
+        order.reverse();
+        order
+    }
+
+    /// Непосредственные доминаторы достижимых блоков (Cooper, Harvey, Kennedy).
+    /// У входа — он сам, у недостижимых — None.
+    pub fn dominators(&self) -> Vec<Option<BlockId>> {
+        let rpo = self.reverse_postorder();
+        let mut index = vec![usize::MAX; self.blocks.len()];
+        for (i, b) in rpo.iter().enumerate() {
+            index[b.0] = i;
+        }
+        let preds = self.predecessors();
+        let mut idom: Vec<Option<BlockId>> = vec![None; self.blocks.len()];
+        idom[0] = Some(BlockId(0));
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &b in rpo.iter().skip(1) {
+                let mut new: Option<BlockId> = None;
+                for &p in &preds[b.0] {
+                    if idom[p.0].is_none() {
+                        continue;
+                    }
+                    new = Some(match new {
+                        None => p,
+                        Some(mut a) => {
+                            // пересечение: поднимаемся по дереву, пока не встретимся
+                            let mut c = p;
+                            while a != c {
+                                while index[a.0] > index[c.0] {
+                                    a = idom[a.0].expect("processed");
+                                }
+                                while index[c.0] > index[a.0] {
+                                    c = idom[c.0].expect("processed");
+                                }
+                            }
+                            a
+                        }
+                    });
+                }
+                if new.is_some() && idom[b.0] != new {
+                    idom[b.0] = new;
+                    changed = true;
+                }
+            }
+        }
+        idom
+    }
+}
+
+pub fn dominates(idom: &[Option<BlockId>], a: BlockId, mut b: BlockId) -> bool {
+    loop {
+        if a == b {
+            return true;
+        }
+        match idom[b.0] {
+            Some(p) if p != b => b = p,
+            _ => return false,
+        }
+    }
+}
+
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for ext in &self.externs {
+            let params: Vec<String> = ext.params.iter().map(|(_, t)| extern_type(t).to_string()).collect();
+            writeln!(f, "extern fn {}({}) -> {}", ext.name, params.join(", "), extern_type(&ext.ret))?;
+        }
+        for func in &self.functions {
+            writeln!(f)?;
+            write!(f, "{}", func)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let params: Vec<String> = self.params.iter().map(|(n, t)| format!("{}: {}", n, t)).collect();
+        writeln!(f, "fn {}({}) -> {} {{", self.name, params.join(", "), self.ret)?;
+        for (i, b) in self.blocks.iter().enumerate() {
+            writeln!(f, "{}: ; {}", BlockId(i), b.name)?;
+            for instr in &b.insts {
+                write!(f, "  ")?;
+                if let Some(d) = instr.dest {
+                    write!(f, "{}: {} = ", d, self.ty(d))?;
+                }
+                write!(f, "{}", self.show(&instr.inst))?;
+                match instr.dest.and_then(|d| self.names.get(&d)) {
+                    Some(name) => writeln!(f, " ; {}", name)?,
+                    None => writeln!(f)?,
+                }
+            }
+            match &b.term {
+                Some(Terminator::Br(t)) => writeln!(f, "  br {}", t)?,
+                Some(Terminator::CondBr(c, t, e)) => writeln!(f, "  condbr {}, {}, {}", c, t, e)?,
+                Some(Terminator::Ret(v)) => writeln!(f, "  ret {}", v)?,
+                Some(Terminator::Unreachable) => writeln!(f, "  unreachable")?,
+                None => writeln!(f, "  <no terminator>")?,
+            }
+        }
+        writeln!(f, "}}")
+    }
+}
+
+impl Function {
+    fn show(&self, inst: &Inst) -> String {
+        let list = |vs: &[ValueId]| vs.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(", ");
+        match inst {
+            Inst::Const(n) => format!("const {}", n),
+            Inst::Str(s) => format!("str {:?}", s),
+            Inst::Param(i) => format!("param {}", i),
+            Inst::Binary(op, a, b) => format!("{} {}, {}", op.name(), a, b),
+            Inst::Cast(op, v) => format!("{} {}", format!("{:?}", op).to_lowercase(), v),
+            Inst::Alloca(t) => format!("alloca {}", t),
+            Inst::Load(p) => format!("load {}", p),
+            Inst::Store(p, v) => format!("store {}, {}", p, v),
+            Inst::Call(Callee::Function(name), args) => format!("call {}({})", name, list(args)),
+            Inst::Call(Callee::Extern(name), args) => format!("call extern {}({})", name, list(args)),
+            Inst::Call(Callee::Builtin(b), args) => format!("call {}({})", format!("{:?}", b).to_lowercase(), list(args)),
+            Inst::Phi(incoming) => {
+                let items: Vec<String> = incoming.iter().map(|(b, v)| format!("[{}, {}]", b, v)).collect();
+                format!("phi {}", items.join(", "))
+            }
+            Inst::Fail(fmt, args) if args.is_empty() => format!("fail {:?}", fmt),
+            Inst::Fail(fmt, args) => format!("fail {:?}, {}", fmt, list(args)),
+        }
+    }
+}
+
+pub fn extern_type(t: &ExternType) -> IrType {
+    match t {
+        ExternType::Void => IrType::Void,
+        ExternType::I32 => IrType::I32,
+        ExternType::Value(t) => lower_type(t).unwrap_or(IrType::Void),
+    }
+}
+
+fn lower_type(t: &Type) -> Option<IrType> {
+    Some(match t {
+        Type::Int => IrType::I64,
+        Type::Bool => IrType::I1,
+        Type::Char => IrType::I32,
+        Type::Str => IrType::Str,
+        _ => return None,
+    })
+}
+
+/// Как строятся переменные.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SsaMode {
+    /// слот alloca на переменную, чтение — load, запись — store
+    #[default]
+    Memory,
+    /// значения переменных — сразу SSA-значения, на слияниях — phi
+    /// (Braun et al., "Simple and Efficient Construction of SSA Form")
+    Direct,
+}
+
+/// AST -> IR. Строятся только функции, достижимые из main: прелюдия со срезами
+/// не мешает программе, которая их не вызывает.
+pub fn build(program: &Program, sources: &SourceMap, mode: SsaMode) -> Result<Module, String> {
+    let mut b = Builder {
+        sources,
+        mode,
+        functions: program.functions.iter().map(|f| (f.name.clone(), f)).collect(),
+        externs: program.externs.iter().map(|e| (e.name.clone(), e)).collect(),
+        queue: vec!["main".to_string()],
+        queued: HashSet::from(["main".to_string()]),
+        called_externs: HashSet::new(),
+        f: None,
+        cur: BlockId(0),
+        locals: HashMap::new(),
+        slots: HashMap::new(),
+        defs: HashMap::new(),
+        sealed: HashSet::new(),
+        incomplete: HashMap::new(),
+        pending: HashSet::new(),
+        span: 0..0,
+    };
+    if !b.functions.contains_key("main") {
+        return Err("program has no main function".to_string());
+    }
+    let mut functions = Vec::new();
+    while let Some(name) = b.queue.pop() {
+        let func = b.functions[&name];
+        functions.push(b.function(func)?);
+    }
+    functions.sort_by_key(|f| program.functions.iter().position(|g| g.name == f.name));
+    let externs = program.externs.iter().filter(|e| b.called_externs.contains(&e.name)).cloned().collect();
+    Ok(Module { functions, externs })
+}
+
+struct Builder<'a> {
+    sources: &'a SourceMap,
+    mode: SsaMode,
+    functions: HashMap<String, &'a AstFunction>,
+    externs: HashMap<String, &'a ExternFn>,
+    // функции, на которые уже есть вызов, но тело ещё не построено
+    queue: Vec<String>,
+    queued: HashSet<String>,
+    // в модуль попадают только вызванные extern — у остальных могут быть типы, которых IR не знает
+    called_externs: HashSet<String>,
+    f: Option<Function>,
+    cur: BlockId,
+    // видимые переменные и их типы
+    locals: HashMap<String, IrType>,
+    // Memory: имя -> слот alloca
+    slots: HashMap<String, ValueId>,
+    // Direct: значение переменной на выходе из блока (currentDef у Braun et al.)
+    defs: HashMap<(String, BlockId), ValueId>,
+    // блоки, у которых уже известны все предшественники
+    sealed: HashSet<BlockId>,
+    // phi незапечатанных блоков: входы добавятся при запечатывании
+    incomplete: HashMap<BlockId, Vec<(String, ValueId)>>,
+    // phi, у которых ещё не все входы, — их нельзя считать тривиальными
+    pending: HashSet<ValueId>,
+    span: Span,
+}
+
+impl<'a> Builder<'a> {
+    fn error(&self, message: &str) -> String {
+        format!("{}: {}", self.sources.location(self.span.start), message)
+    }
+
+    fn unsupported(&self, what: &str) -> String {
+        self.error(&format!("{} are not supported by the IR yet", what))
+    }
+
+    fn func(&mut self) -> &mut Function {
+        self.f.as_mut().expect("building a function")
+    }
+
+    fn signature(&self, func: &AstFunction) -> Result<(Vec<(String, IrType)>, IrType), String> {
+        let mut params = Vec::new();
+        for (name, t) in &func.params {
+            match lower_type(t) {
+                Some(t) => params.push((name.clone(), t)),
+                None => return Err(format!("fn {}: parameter {}: {}", func.name, name, self.unsupported("arrays, structs, pointers and closures"))),
+            }
+        }
+        match lower_type(&func.ret) {
+            Some(ret) => Ok((params, ret)),
+            None => Err(format!("fn {}: {}", func.name, self.unsupported("non-scalar results"))),
+        }
+    }
+
+    fn function(&mut self, func: &AstFunction) -> Result<Function, String> {
+        self.span = func.body.first().map_or(0..0, |s| s.span.clone());
+        let (params, ret) = self.signature(func)?;
+        self.f = Some(Function {
+            name: func.name.clone(),
+            params: params.clone(),
+            ret,
+            blocks: Vec::new(),
+            types: Vec::new(),
+            names: HashMap::new(),
+        });
+        self.locals.clear();
+        self.slots.clear();
+        self.defs.clear();
+        self.sealed.clear();
+        self.incomplete.clear();
+        self.pending.clear();
+        self.cur = self.new_block("entry");
+        self.seal(self.cur);
+        for (i, (name, ty)) in params.iter().enumerate() {
+            let v = self.push(Inst::Param(i), *ty);
+            self.func().names.insert(v, name.clone());
+            self.declare(name, *ty, v);
+        }
+        let v = self.body(&func.body)?;
+        let v = self.coerce(v, ret)?;
+        self.terminate(Terminator::Ret(v));
+        Ok(self.f.take().expect("building a function"))
+    }
+
+    fn new_block(&mut self, name: &str) -> BlockId {
+        let f = self.func();
+        f.blocks.push(Block { name: name.to_string(), insts: Vec::new(), term: None });
+        BlockId(f.blocks.len() - 1)
+    }
+
+    fn push(&mut self, inst: Inst, ty: IrType) -> ValueId {
+        let cur = self.cur;
+        let f = self.func();
+        let v = ValueId(f.types.len());
+        f.types.push(ty);
+        f.blocks[cur.0].insts.push(Instr { dest: Some(v), inst });
+        v
+    }
+
+    fn push_void(&mut self, inst: Inst) {
+        let cur = self.cur;
+        self.func().blocks[cur.0].insts.push(Instr { dest: None, inst });
+    }
+
+    // Терминатор ставится только в открытый блок (после fail блок уже закрыт).
+    fn terminate(&mut self, t: Terminator) {
+        let cur = self.cur;
+        let block = &mut self.func().blocks[cur.0];
+        if block.term.is_none() {
+            block.term = Some(t);
+        }
+    }
+
+    // alloca — всегда во входном блоке, после параметров и других alloca,
+    // чтобы `let` в цикле не растил стек (как Codegen::entry_alloca)
+    fn alloca(&mut self, ty: IrType, name: &str) -> ValueId {
+        let f = self.func();
+        let v = ValueId(f.types.len());
+        f.types.push(IrType::Ptr);
+        f.names.insert(v, name.to_string());
+        let entry = &mut f.blocks[0].insts;
+        let at = entry
+            .iter()
+            .position(|i| !matches!(i.inst, Inst::Param(_) | Inst::Alloca(_)))
+            .unwrap_or(entry.len());
+        entry.insert(at, Instr { dest: Some(v), inst: Inst::Alloca(ty) });
+        v
+    }
+
+    // Новая переменная (let, параметр, переменная цикла); v уже нужного типа.
+    fn declare(&mut self, name: &str, ty: IrType, v: ValueId) {
+        self.locals.insert(name.to_string(), ty);
+        match self.mode {
+            SsaMode::Memory => {
+                let slot = self.alloca(ty, name);
+                self.push_void(Inst::Store(slot, v));
+                self.slots.insert(name.to_string(), slot);
+            }
+            SsaMode::Direct => self.write_variable(name, self.cur, v),
+        }
+    }
+
+    fn assign(&mut self, name: &str, v: ValueId) {
+        match self.mode {
+            SsaMode::Memory => {
+                let slot = self.slots[name];
+                self.push_void(Inst::Store(slot, v));
+            }
+            SsaMode::Direct => self.write_variable(name, self.cur, v),
+        }
+    }
+
+    fn read(&mut self, name: &str) -> ValueId {
+        match self.mode {
+            SsaMode::Memory => {
+                let slot = self.slots[name];
+                let ty = self.locals[name];
+                self.push(Inst::Load(slot), ty)
+            }
+            SsaMode::Direct => self.read_variable(name, self.cur),
+        }
+    }
+
+    // Привязка имени перед циклом, который его затеняет: слот или текущее значение.
+    fn binding(&mut self, name: &str) -> Option<(IrType, ValueId)> {
+        let ty = *self.locals.get(name)?;
+        Some(match self.mode {
+            SsaMode::Memory => (ty, self.slots[name]),
+            SsaMode::Direct => (ty, self.read_variable(name, self.cur)),
+        })
+    }
+
+    fn restore(&mut self, name: &str, saved: Option<(IrType, ValueId)>) {
+        let Some((ty, v)) = saved else {
+            self.locals.remove(name);
+            self.slots.remove(name);
+            return;
+        };
+        self.locals.insert(name.to_string(), ty);
+        match self.mode {
+            SsaMode::Memory => {
+                self.slots.insert(name.to_string(), v);
+            }
+            SsaMode::Direct => self.write_variable(name, self.cur, v),
+        }
+    }
+
+    fn write_variable(&mut self, name: &str, block: BlockId, v: ValueId) {
+        self.func().names.entry(v).or_insert_with(|| name.to_string());
+        self.defs.insert((name.to_string(), block), v);
+    }
+
+    // Значение переменной в конце блока: своё определение, иначе — из предшественников.
+    // В незапечатанном блоке ставим пустую phi и дозаполняем её в seal.
+    fn read_variable(&mut self, name: &str, block: BlockId) -> ValueId {
+        if let Some(&v) = self.defs.get(&(name.to_string(), block)) {
+            return v;
+        }
+        let v = if !self.sealed.contains(&block) {
+            let phi = self.new_phi(name, block);
+            self.pending.insert(phi);
+            self.incomplete.entry(block).or_default().push((name.to_string(), phi));
+            phi
+        } else {
+            let preds = self.func().predecessors()[block.0].clone();
+            if preds.len() == 1 {
+                self.read_variable(name, preds[0])
+            } else {
+                // phi записываем до обхода предшественников — так обрываются циклы
+                let phi = self.new_phi(name, block);
+                self.pending.insert(phi);
+                self.write_variable(name, block, phi);
+                self.add_phi_operands(name, block, phi)
+            }
+        };
+        self.write_variable(name, block, v);
+        v
+    }
+
+    fn add_phi_operands(&mut self, name: &str, block: BlockId, phi: ValueId) -> ValueId {
+        let preds = self.func().predecessors()[block.0].clone();
+        for p in preds {
+            let v = self.read_variable(name, p);
+            if let Some(Inst::Phi(incoming)) = self.find_phi(phi).map(|(b, i)| &mut self.func().blocks[b].insts[i].inst) {
+                incoming.push((p, v));
+            }
+        }
+        self.pending.remove(&phi);
+        self.try_remove_trivial_phi(phi)
+    }
+
+    // phi, все входы которой — одно значение (или она сама), заменяется этим значением.
+    // После замены тривиальными могут стать phi, которые её использовали.
+    fn try_remove_trivial_phi(&mut self, phi: ValueId) -> ValueId {
+        let Some((b, i)) = self.find_phi(phi) else {
+            return phi;
+        };
+        let Inst::Phi(incoming) = &self.func().blocks[b].insts[i].inst else {
+            return phi;
+        };
+        let mut same = None;
+        for &(_, v) in incoming {
+            if Some(v) == same || v == phi {
+                continue;
+            }
+            if same.is_some() {
+                return phi;
+            }
+            same = Some(v);
+        }
+        let same = match same {
+            Some(v) => v,
+            // ни одного входа: переменная не определена ни на одном пути
+            None => {
+                let ty = self.ty(phi);
+                self.undefined(ty)
+            }
+        };
+        let users: Vec<ValueId> = self
+            .func()
+            .blocks
+            .iter()
+            .flat_map(|b| &b.insts)
+            .filter(|instr| matches!(&instr.inst, Inst::Phi(inc) if inc.iter().any(|&(_, v)| v == phi)))
+            .filter_map(|instr| instr.dest)
+            .filter(|&u| u != phi)
+            .collect();
+        if let Some((b, i)) = self.find_phi(phi) {
+            self.func().blocks[b].insts.remove(i);
+        }
+        self.replace_uses(phi, same);
+        for u in users {
+            if !self.pending.contains(&u) {
+                self.try_remove_trivial_phi(u);
+            }
+        }
+        same
+    }
+
+    fn find_phi(&mut self, phi: ValueId) -> Option<(usize, usize)> {
+        self.func().blocks.iter().enumerate().find_map(|(b, block)| {
+            block.insts.iter().position(|instr| instr.dest == Some(phi) && matches!(instr.inst, Inst::Phi(_))).map(|i| (b, i))
+        })
+    }
+
+    // Пустая phi в начале блока, после других phi.
+    fn new_phi(&mut self, name: &str, block: BlockId) -> ValueId {
+        let ty = self.locals[name];
+        let f = self.func();
+        let v = ValueId(f.types.len());
+        f.types.push(ty);
+        f.names.insert(v, name.to_string());
+        let insts = &mut f.blocks[block.0].insts;
+        let at = insts.iter().position(|i| !matches!(i.inst, Inst::Phi(_))).unwrap_or(insts.len());
+        insts.insert(at, Instr { dest: Some(v), inst: Inst::Phi(Vec::new()) });
+        v
+    }
+
+    // Значение неинициализированной переменной — ноль во входном блоке (в Memory
+    // это был бы load из слота без store).
+    fn undefined(&mut self, ty: IrType) -> ValueId {
+        let f = self.func();
+        let v = ValueId(f.types.len());
+        f.types.push(ty);
+        let inst = if ty == IrType::Str { Inst::Str(String::new()) } else { Inst::Const(0) };
+        let entry = &mut f.blocks[0].insts;
+        let at = entry.iter().position(|i| !matches!(i.inst, Inst::Param(_))).unwrap_or(entry.len());
+        entry.insert(at, Instr { dest: Some(v), inst });
+        v
+    }
+
+    fn replace_uses(&mut self, old: ValueId, new: ValueId) {
+        let swap = |v: &mut ValueId| {
+            if *v == old {
+                *v = new;
+            }
+        };
+        for block in &mut self.func().blocks {
+            for instr in &mut block.insts {
+                match &mut instr.inst {
+                    Inst::Binary(_, a, b) | Inst::Store(a, b) => {
+                        swap(a);
+                        swap(b);
+                    }
+                    Inst::Cast(_, v) | Inst::Load(v) => swap(v),
+                    Inst::Call(_, args) | Inst::Fail(_, args) => args.iter_mut().for_each(swap),
+                    Inst::Phi(incoming) => incoming.iter_mut().for_each(|(_, v)| swap(v)),
+                    Inst::Const(_) | Inst::Str(_) | Inst::Param(_) | Inst::Alloca(_) => {}
+                }
+            }
+            match &mut block.term {
+                Some(Terminator::CondBr(c, ..)) => swap(c),
+                Some(Terminator::Ret(v)) => swap(v),
+                _ => {}
+            }
+        }
+        self.defs.values_mut().for_each(swap);
+    }
+
+    // Все предшественники блока уже известны: дозаполняем его отложенные phi.
+    fn seal(&mut self, block: BlockId) {
+        for (name, phi) in self.incomplete.remove(&block).unwrap_or_default() {
+            self.add_phi_operands(&name, block, phi);
+        }
+        self.sealed.insert(block);
+    }
+
+    fn constant(&mut self, ty: IrType, n: i64) -> ValueId {
+        self.push(Inst::Const(n), ty)
+    }
+
+    fn ty(&mut self, v: ValueId) -> IrType {
+        self.func().ty(v)
+    }
+
+    // bool и char расширяем до i64, как Codegen::int_operand
+    fn widen(&mut self, v: ValueId) -> Result<ValueId, String> {
+        match self.ty(v) {
+            IrType::I64 => Ok(v),
+            IrType::I1 | IrType::I32 => Ok(self.push(Inst::Cast(CastOp::Zext, v), IrType::I64)),
+            other => Err(self.error(&format!("expected integer operand, got {}", other))),
+        }
+    }
+
+    // Значение нужного типа; нет значения — ноль, как Codegen::coerce.
+    fn coerce(&mut self, v: Option<ValueId>, ty: IrType) -> Result<ValueId, String> {
+        let Some(v) = v else {
+            return Ok(self.constant(ty, 0));
+        };
+        let from = self.ty(v);
+        if from == ty {
+            return Ok(v);
+        }
+        if from.is_int() && ty.is_int() {
+            let op = if from.bits() < ty.bits() { CastOp::Zext } else { CastOp::Trunc };
+            return Ok(self.push(Inst::Cast(op, v), ty));
+        }
+        if ty == IrType::Str || from == IrType::Str {
+            return Err(self.error(&format!("expected {}, got {}", ty, from)));
+        }
+        Ok(self.constant(ty, 0))
+    }
+
+    fn condition(&mut self, v: ValueId) -> Result<ValueId, String> {
+        match self.ty(v) {
+            IrType::I1 => Ok(v),
+            t if t.is_int() => {
+                let zero = self.constant(t, 0);
+                Ok(self.push(Inst::Binary(BinOp::Ne, v, zero), IrType::I1))
+            }
+            other => Err(self.error(&format!("condition must be an integer or bool, got {}", other))),
+        }
+    }
+
+    // Значение блока — значение последнего оператора-выражения (как Codegen::compile_block).
+    fn body(&mut self, body: &[Stmt]) -> Result<Option<ValueId>, String> {
+        let mut last = None;
+        for s in body {
+            last = self.stmt(s)?;
+        }
+        Ok(last)
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Result<Option<ValueId>, String> {
+        self.span = stmt.span.clone();
+        match &stmt.kind {
+            StmtKind::Let(name, ty, e) => {
+                let v = self.expr(e)?;
+                let ty = match ty {
+                    Some(t) => lower_type(t).ok_or_else(|| self.unsupported("arrays, structs, pointers and closures"))?,
+                    None => self.ty(v),
+                };
+                let v = self.coerce(Some(v), ty)?;
+                self.declare(name, ty, v);
+            }
+            StmtKind::Assign(Expr::Ident(name), e) => {
+                let v = self.expr(e)?;
+                let Some(&ty) = self.locals.get(name) else {
+                    return Err(self.error(&format!("assign to undefined var {}", name)));
+                };
+                let v = self.coerce(Some(v), ty)?;
+                self.assign(name, v);
+            }
+            StmtKind::Assign(..) => return Err(self.unsupported("assignments to elements, fields and pointers")),
+            StmtKind::Expr(e) => return self.expr(e).map(Some),
+            StmtKind::Semi(e) => {
+                self.expr(e)?;
+            }
+            StmtKind::While { cond, body } => {
+                let cond_bb = self.new_block("loop.cond");
+                let body_bb = self.new_block("loop.body");
+                let end_bb = self.new_block("loop.end");
+                self.terminate(Terminator::Br(cond_bb));
+
+                self.cur = cond_bb;
+                let c = self.expr(cond)?;
+                let c = self.condition(c)?;
+                self.terminate(Terminator::CondBr(c, body_bb, end_bb));
+                self.seal(body_bb);
+                self.seal(end_bb);
+
+                self.cur = body_bb;
+                self.body(body)?;
+                self.terminate(Terminator::Br(cond_bb));
+                // обратная дуга есть — у заголовка больше не появится предшественников
+                self.seal(cond_bb);
+
+                self.cur = end_bb;
+            }
+            // счётчик — скрытая переменная; переменная цикла — копия на каждой итерации,
+            // так что присваивание ей в теле не меняет ход цикла (как в Codegen)
+            StmtKind::For { var, start, end, inclusive, step, body } => {
+                let start = self.expr(start)?;
+                let start = self.widen(start)?;
+                let end = self.expr(end)?;
+                let end = self.widen(end)?;
+
+                let cond_bb = self.new_block("for.cond");
+                let body_bb = self.new_block("for.body");
+                let latch_bb = self.new_block("for.latch");
+                let end_bb = self.new_block("for.end");
+                // номер блока в имени — чтобы вложенный цикл с той же переменной не путал счётчики
+                let counter = format!("{}.counter.{}", var, cond_bb.0);
+                self.declare(&counter, IrType::I64, start);
+                // внешняя переменная с тем же именем — до цикла, пока тело её не затенило
+                let shadowed = self.binding(var);
+                self.terminate(Terminator::Br(cond_bb));
+
+                self.cur = cond_bb;
+                let i = self.read(&counter);
+                let op = match (*step > 0, *inclusive) {
+                    (true, false) => BinOp::Lt,
+                    (true, true) => BinOp::Le,
+                    (false, false) => BinOp::Gt,
+                    (false, true) => BinOp::Ge,
+                };
+                let c = self.push(Inst::Binary(op, i, end), IrType::I1);
+                self.terminate(Terminator::CondBr(c, body_bb, end_bb));
+                self.seal(body_bb);
+
+                self.cur = body_bb;
+                self.declare(var, IrType::I64, i);
+                self.body(body)?;
+                self.terminate(Terminator::Br(latch_bb));
+                self.seal(latch_bb);
+
+                // следующее значение переполнило бы i64 — выход из цикла, как в Codegen
+                self.cur = latch_bb;
+                let i = self.read(&counter);
+                let (limit, fits) = if *step > 0 { (i64::MAX - step, BinOp::Le) } else { (i64::MIN - step, BinOp::Ge) };
+                let limit = self.constant(IrType::I64, limit);
+                let c = self.push(Inst::Binary(fits, i, limit), IrType::I1);
+                let next_bb = self.new_block("for.next");
+                self.terminate(Terminator::CondBr(c, next_bb, end_bb));
+                self.seal(next_bb);
+                // в for.end приходят из заголовка и из latch
+                self.seal(end_bb);
+
+                self.cur = next_bb;
+                let step = self.constant(IrType::I64, *step);
+                let next = self.push(Inst::Binary(BinOp::Add, i, step), IrType::I64);
+                self.assign(&counter, next);
+                self.terminate(Terminator::Br(cond_bb));
+                self.seal(cond_bb);
+
+                self.cur = end_bb;
+                self.restore(var, shadowed);
+                self.restore(&counter, None);
+            }
+        }
+        Ok(None)
+    }
+
+    // b == 0 -> fail с местом оператора, как Codegen::checked_div; i64::MIN / -1 — забота
+    // перевода в LLVM (sdiv там UB).
+    fn div_check(&mut self, b: ValueId) {
+        let zero = self.constant(IrType::I64, 0);
+        let is_zero = self.push(Inst::Binary(BinOp::Eq, b, zero), IrType::I1);
+        let fail_bb = self.new_block("div.fail");
+        let ok_bb = self.new_block("div.ok");
+        self.terminate(Terminator::CondBr(is_zero, fail_bb, ok_bb));
+        self.seal(fail_bb);
+        self.seal(ok_bb);
+        self.cur = fail_bb;
+        let msg = format!("runtime error at {}: division by zero\n", self.sources.location(self.span.start).replace('%', "%%"));
+        self.push_void(Inst::Fail(msg, vec![]));
+        self.terminate(Terminator::Unreachable);
+        self.cur = ok_bb;
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<ValueId, String> {
+        match expr {
+            Expr::Number(n) => Ok(self.constant(IrType::I64, *n)),
+            Expr::Bool(b) => Ok(self.constant(IrType::I1, *b as i64)),
+            Expr::Char(c) => Ok(self.constant(IrType::I32, *c as i64)),
+            Expr::Str(s) => Ok(self.push(Inst::Str(s.clone()), IrType::Str)),
+            Expr::Ident(name) => match self.locals.get(name) {
+                Some(_) => Ok(self.read(name)),
+                None if self.functions.contains_key(name) => Err(self.unsupported("functions as values")),
+                None => Err(self.error(&format!("use of undefined variable {}", name))),
+            },
+            Expr::BinaryOp(l, op, r) => {
+                let Some(op) = BinOp::parse(op) else {
+                    return Err(self.error(&format!("unsupported op {}", op)));
+                };
+                let a = self.expr(l)?;
+                let b = self.expr(r)?;
+                // строки сравниваются по содержимому (strcmp при переводе в LLVM)
+                if self.ty(a) == IrType::Str && self.ty(b) == IrType::Str {
+                    if !matches!(op, BinOp::Eq | BinOp::Ne) {
+                        return Err(self.error(&format!("operator {} is not defined for strings", op.name())));
+                    }
+                    return Ok(self.push(Inst::Binary(op, a, b), IrType::I1));
+                }
+                let a = self.widen(a)?;
+                let b = self.widen(b)?;
+                if op == BinOp::Div {
+                    self.div_check(b);
+                }
+                let ty = if op.is_cmp() { IrType::I1 } else { IrType::I64 };
+                Ok(self.push(Inst::Binary(op, a, b), ty))
+            }
+            Expr::Call(name, args) => self.call(name, args),
+            Expr::If { cond, then_body, else_body } => {
+                let c = self.expr(cond)?;
+                let c = self.condition(c)?;
+                let then_bb = self.new_block("then");
+                let else_bb = self.new_block("else");
+                let cont_bb = self.new_block("ifend");
+                self.terminate(Terminator::CondBr(c, then_bb, else_bb));
+                self.seal(then_bb);
+                self.seal(else_bb);
+
+                self.cur = then_bb;
+                let tv = self.body(then_body)?;
+                let then_end = self.cur;
+                self.cur = else_bb;
+                let ev = match else_body {
+                    Some(b) => self.body(b)?,
+                    None => None,
+                };
+                let else_end = self.cur;
+                self.merge(&[(then_end, tv), (else_end, ev)], cont_bb)
+            }
+            Expr::Block(body) => {
+                let v = self.body(body)?;
+                match v {
+                    Some(v) => Ok(v),
+                    None => Ok(self.constant(IrType::I64, 0)),
+                }
+            }
+            Expr::Match { scrutinee, arms, span } => self.match_expr(scrutinee, arms, span),
+            Expr::Null | Expr::AddrOf(_) | Expr::Deref(..) => Err(self.unsupported("pointers")),
+            Expr::Array(_) | Expr::ArrayRepeat(..) | Expr::Index { .. } => Err(self.unsupported("arrays")),
+            Expr::StructLit(..) | Expr::Field(..) => Err(self.unsupported("structs")),
+            Expr::Lambda { .. } | Expr::Apply(..) => Err(self.unsupported("closures")),
+        }
+    }
+
+    // Сведение веток в cont: общий тип (разные целые -> i64, нет значения -> 0),
+    // приведение — в конце каждой ветки, значение — phi.
+    fn merge(&mut self, arms: &[(BlockId, Option<ValueId>)], cont: BlockId) -> Result<ValueId, String> {
+        let f = self.f.as_ref().expect("building a function");
+        let open: Vec<(BlockId, Option<ValueId>)> =
+            arms.iter().filter(|(b, _)| f.blocks[b.0].term.is_none()).cloned().collect();
+        let mut types: Vec<IrType> = Vec::new();
+        for (_, v) in &open {
+            types.push(match v {
+                Some(v) => self.ty(*v),
+                None => IrType::I64,
+            });
+        }
+        let ty = match types.first() {
+            Some(&t) if types.iter().all(|&u| u == t) => t,
+            _ if types.iter().all(|t| t.is_int()) => IrType::I64,
+            _ => return Err(self.error("branches have different types")),
+        };
+        let mut incoming = Vec::new();
+        for (b, v) in open {
+            self.cur = b;
+            let v = self.coerce(v, ty)?;
+            incoming.push((b, v));
+            self.terminate(Terminator::Br(cont));
+        }
+        self.cur = cont;
+        self.seal(cont);
+        if incoming.is_empty() {
+            self.terminate(Terminator::Unreachable);
+            return Ok(self.constant(ty, 0));
+        }
+        Ok(self.push(Inst::Phi(incoming), ty))
+    }
+
+    // Цепочка сравнений в порядке веток (switch — забота LLVM); не подошла ни одна — fail.
+    fn match_expr(&mut self, scrutinee: &Expr, arms: &[MatchArm], span: &Span) -> Result<ValueId, String> {
+        let v = self.expr(scrutinee)?;
+        let v = self.widen(v)?;
+        let arm_bbs: Vec<BlockId> = (0..arms.len()).map(|i| self.new_block(&format!("arm.{}", i))).collect();
+        let cont = self.new_block("match.end");
+        let mut exhaustive = false;
+        'arms: for (arm, &arm_bb) in arms.iter().zip(&arm_bbs) {
+            for (pat, _) in &arm.patterns {
+                let (lo, hi) = match pat {
+                    Pattern::Wildcard => {
+                        self.terminate(Terminator::Br(arm_bb));
+                        exhaustive = true;
+                        break 'arms;
+                    }
+                    Pattern::Int(n) => (*n, *n),
+                    Pattern::Range(lo, hi) => (*lo, *hi),
+                };
+                let c = if lo == hi {
+                    let n = self.constant(IrType::I64, lo);
+                    self.push(Inst::Binary(BinOp::Eq, v, n), IrType::I1)
+                } else {
+                    let lo_v = self.constant(IrType::I64, lo);
+                    let ge = self.push(Inst::Binary(BinOp::Ge, v, lo_v), IrType::I1);
+                    let hi_bb = self.new_block("range.hi");
+                    let next_bb = self.new_block("match.next");
+                    self.terminate(Terminator::CondBr(ge, hi_bb, next_bb));
+                    self.seal(hi_bb);
+                    self.cur = hi_bb;
+                    let hi_v = self.constant(IrType::I64, hi);
+                    let le = self.push(Inst::Binary(BinOp::Le, v, hi_v), IrType::I1);
+                    self.terminate(Terminator::CondBr(le, arm_bb, next_bb));
+                    self.seal(next_bb);
+                    self.cur = next_bb;
+                    continue;
+                };
+                let next_bb = self.new_block("match.next");
+                self.terminate(Terminator::CondBr(c, arm_bb, next_bb));
+                self.seal(next_bb);
+                self.cur = next_bb;
+            }
+        }
+        if !exhaustive {
+            let msg = format!("runtime error at {}: no match arm for value %lld\n", self.sources.location(span.start).replace('%', "%%"));
+            self.push_void(Inst::Fail(msg, vec![v]));
+            self.terminate(Terminator::Unreachable);
+        }
+        // в ветку можно прийти из нескольких сравнений — запечатываем, когда цепочка готова
+        for &bb in &arm_bbs {
+            self.seal(bb);
+        }
+        let mut results = Vec::new();
+        for (arm, bb) in arms.iter().zip(arm_bbs) {
+            self.cur = bb;
+            let r = self.expr(&arm.body)?;
+            results.push((self.cur, Some(r)));
+        }
+        self.merge(&results, cont)
+    }
+
+    // Порядок поиска — как в Codegen: встроенные, extern/хост, функции программы.
+    fn call(&mut self, name: &str, args: &[Expr]) -> Result<ValueId, String> {
+        let builtin = match (name, args.len()) {
+            ("print", 1) => Some((Builtin::Print, IrType::Void)),
+            ("println", 0 | 1) => Some((Builtin::Println, IrType::Void)),
+            ("read_int", 0) => Some((Builtin::ReadInt, IrType::I64)),
+            ("exit", 1) => Some((Builtin::Exit, IrType::Void)),
+            ("len" | "alloc" | "free", _) => return Err(self.unsupported("arrays and pointers")),
+            ("print" | "println" | "read_int" | "exit", _) => {
+                return Err(self.error(&format!("wrong number of arguments to {}", name)));
+            }
+            _ => None,
+        };
+        if let Some((b, ret)) = builtin {
+            let mut argv = Vec::new();
+            for a in args {
+                argv.push(self.expr(a)?);
+            }
+            if b == Builtin::Exit {
+                argv[0] = self.widen(argv[0])?;
+            }
+            return Ok(self.call_value(Callee::Builtin(b), argv, ret));
+        }
+        if self.locals.contains_key(name) {
+            return Err(self.unsupported("closures"));
+        }
+        let (callee, params, ret) = if let Some(ext) = self.externs.get(name) {
+            let params: Vec<IrType> = ext.params.iter().map(|(_, t)| extern_type(t)).collect();
+            if params.contains(&IrType::Void) || (extern_type(&ext.ret) == IrType::Void && ext.ret != ExternType::Void) {
+                return Err(self.unsupported("extern functions with non-scalar types"));
+            }
+            self.called_externs.insert(name.to_string());
+            (Callee::Extern(name.to_string()), params, extern_type(&ext.ret))
+        } else if let Some(func) = self.functions.get(name).copied() {
+            let (params, ret) = self.signature(func)?;
+            if self.queued.insert(name.to_string()) {
+                self.queue.push(name.to_string());
+            }
+            (Callee::Function(name.to_string()), params.into_iter().map(|(_, t)| t).collect(), ret)
+        } else {
+            return Err(self.error(&format!("call to undefined function {}", name)));
+        };
+        if params.len() != args.len() {
+            return Err(self.error(&format!("{} expects {} arguments, got {}", name, params.len(), args.len())));
+        }
+        let mut argv = Vec::new();
+        for (a, ty) in args.iter().zip(params) {
+            let v = self.expr(a)?;
+            argv.push(self.coerce(Some(v), ty)?);
+        }
+        let v = self.call_value(callee, argv, ret);
+        // i32 из C расширяем со знаком, как Codegen::call_extern
+        if self.ty(v) == IrType::I32 && matches!(self.externs.get(name), Some(e) if e.ret == ExternType::I32) {
+            return Ok(self.push(Inst::Cast(CastOp::Sext, v), IrType::I64));
+        }
+        Ok(v)
+    }
+
+    // void-вызов значения не даёт — выражение равно 0
+    fn call_value(&mut self, callee: Callee, args: Vec<ValueId>, ret: IrType) -> ValueId {
+        if ret == IrType::Void {
+            self.push_void(Inst::Call(callee, args));
+            return self.constant(IrType::I64, 0);
+        }
+        self.push(Inst::Call(callee, args), ret)
+    }
+}
+
+/// Проверки корректности: у каждого блока есть терминатор, значение определено один раз
+/// и его определение доминирует над использованиями, phi — в начале блока и по одному
+/// входу на предшественника, типы операндов сходятся. Пустой список — IR корректен.
+pub fn verify(module: &Module) -> Vec<String> {
+    let mut errors = Vec::new();
+    let sigs: HashMap<&str, (Vec<IrType>, IrType)> = module
+        .functions
+        .iter()
+        .map(|f| (f.name.as_str(), (f.params.iter().map(|(_, t)| *t).collect(), f.ret)))
+        .chain(
+            module
+                .externs
+                .iter()
+                .map(|e| (e.name.as_str(), (e.params.iter().map(|(_, t)| extern_type(t)).collect(), extern_type(&e.ret)))),
+        )
+        .collect();
+    for f in &module.functions {
+        let mut err = |at: String, msg: String| errors.push(format!("fn {}, {}: {}", f.name, at, msg));
+        let n = f.blocks.len();
+        if n == 0 {
+            err("entry".to_string(), "function has no blocks".to_string());
+            continue;
+        }
+        // определения: значение -> (блок, позиция)
+        let mut defs: HashMap<ValueId, (BlockId, usize)> = HashMap::new();
+        for (bi, b) in f.blocks.iter().enumerate() {
+            for (ii, instr) in b.insts.iter().enumerate() {
+                let Some(d) = instr.dest else { continue };
+                if d.0 >= f.types.len() {
+                    err(BlockId(bi).to_string(), format!("{} has no type", d));
+                } else if defs.insert(d, (BlockId(bi), ii)).is_some() {
+                    err(BlockId(bi).to_string(), format!("{} is defined more than once", d));
+                }
+            }
+        }
+        let mut broken = false;
+        for (bi, b) in f.blocks.iter().enumerate() {
+            match &b.term {
+                None => {
+                    err(BlockId(bi).to_string(), "block has no terminator".to_string());
+                    broken = true;
+                }
+                Some(t) => {
+                    for s in t.successors() {
+                        if s.0 >= n {
+                            err(BlockId(bi).to_string(), format!("branch to missing block {}", s));
+                            broken = true;
+                        } else if s.0 == 0 {
+                            err(BlockId(bi).to_string(), "branch to the entry block".to_string());
+                        }
+                    }
+                }
+            }
+        }
+        if broken {
+            continue;
+        }
+        let preds = f.predecessors();
+        let idom = f.dominators();
+        // def доминирует над местом использования (блок, позиция)
+        let available = |v: ValueId, block: BlockId, pos: usize| match defs.get(&v) {
+            None => false,
+            Some(&(db, di)) if db == block => di < pos,
+            Some(&(db, _)) => dominates(&idom, db, block),
+        };
+        let alloca_type = |p: ValueId| {
+            defs.get(&p).and_then(|&(b, i)| match f.blocks[b.0].insts[i].inst {
+                Inst::Alloca(t) => Some(t),
+                _ => None,
+            })
+        };
+        for (bi, b) in f.blocks.iter().enumerate() {
+            let here = BlockId(bi);
+            if idom[bi].is_none() {
+                // недостижимый блок: значения в нём нигде не доминируют, проверять нечего
+                continue;
+            }
+            let mut err = |msg: String| errors.push(format!("fn {}, {}: {}", f.name, here, msg));
+            let mut phis_done = false;
+            for (ii, instr) in b.insts.iter().enumerate() {
+                let ty = instr.dest.map(|d| f.ty(d));
+                let uses: Vec<ValueId> = match &instr.inst {
+                    Inst::Binary(_, a, c) | Inst::Store(a, c) => vec![*a, *c],
+                    Inst::Cast(_, v) | Inst::Load(v) => vec![*v],
+                    Inst::Call(_, args) | Inst::Fail(_, args) => args.clone(),
+                    _ => Vec::new(),
+                };
+                for u in &uses {
+                    if u.0 >= f.types.len() || !available(*u, here, ii) {
+                        err(format!("{} is used before its definition dominates this point", u));
+                    }
+                }
+                if uses.iter().any(|u| u.0 >= f.types.len()) {
+                    continue;
+                }
+                match &instr.inst {
+                    Inst::Phi(incoming) => {
+                        if phis_done {
+                            err("phi after a non-phi instruction".to_string());
+                        }
+                        let mut from: Vec<BlockId> = incoming.iter().map(|(p, _)| *p).collect();
+                        from.sort();
+                        let mut expected = preds[bi].clone();
+                        expected.sort();
+                        if from != expected {
+                            err(format!("phi incoming blocks {:?} do not match predecessors {:?}", from, expected));
+                        }
+                        // вход из недостижимого предшественника не выполняется никогда
+                        for (p, v) in incoming.iter().filter(|(p, _)| idom.get(p.0).is_some_and(Option::is_some)) {
+                            let end = f.blocks.get(p.0).map_or(0, |b| b.insts.len());
+                            if v.0 >= f.types.len() || !available(*v, *p, end) {
+                                err(format!("phi operand {} is not available at the end of {}", v, p));
+                            } else if Some(f.ty(*v)) != ty {
+                                err(format!("phi operand {} has type {}, expected {}", v, f.ty(*v), ty.unwrap_or(IrType::Void)));
+                            }
+                        }
+                        continue;
+                    }
+                    _ => phis_done = true,
+                }
+                let check = |ok: bool, msg: &str| if ok { None } else { Some(msg.to_string()) };
+                let problem = match &instr.inst {
+                    Inst::Const(_) => check(ty.is_some_and(IrType::is_int), "const must have an integer type"),
+                    Inst::Str(_) => check(ty == Some(IrType::Str), "str must have type str"),
+                    Inst::Param(i) => check(f.params.get(*i).map(|p| p.1) == ty, "param type does not match the signature"),
+                    Inst::Binary(op, a, c) => {
+                        let (ta, tc) = (f.ty(*a), f.ty(*c));
+                        if op.is_cmp() {
+                            let str_cmp = ta == IrType::Str && matches!(op, BinOp::Eq | BinOp::Ne);
+                            check(ta == tc && (ta.is_int() || str_cmp) && ty == Some(IrType::I1), "comparison of mismatched types")
+                        } else {
+                            check(ta == IrType::I64 && tc == IrType::I64 && ty == Some(IrType::I64), "arithmetic expects i64 operands")
+                        }
+                    }
+                    Inst::Cast(op, v) => {
+                        let (from, to) = (f.ty(*v), ty.unwrap_or(IrType::Void));
+                        let widening = from.bits() < to.bits();
+                        let ok = from.is_int()
+                            && to.is_int()
+                            && match op {
+                                CastOp::Zext | CastOp::Sext => widening,
+                                CastOp::Trunc => to.bits() < from.bits(),
+                            };
+                        check(ok, "invalid cast")
+                    }
+                    Inst::Alloca(t) => check(ty == Some(IrType::Ptr) && *t != IrType::Void && bi == 0, "alloca must be in the entry block and return ptr"),
+                    Inst::Load(p) => check(alloca_type(*p).is_some() && alloca_type(*p) == ty, "load type does not match the alloca"),
+                    Inst::Store(p, v) => check(alloca_type(*p) == Some(f.ty(*v)), "store type does not match the alloca"),
+                    Inst::Call(Callee::Builtin(b), args) => {
+                        let ok = match b {
+                            Builtin::Print => args.len() == 1 && ty.is_none(),
+                            Builtin::Println => args.len() <= 1 && ty.is_none(),
+                            Builtin::ReadInt => args.is_empty() && ty == Some(IrType::I64),
+                            Builtin::Exit => args.len() == 1 && f.ty(args[0]) == IrType::I64 && ty.is_none(),
+                        };
+                        check(ok, "invalid builtin call")
+                    }
+                    Inst::Call(Callee::Function(name) | Callee::Extern(name), args) => match sigs.get(name.as_str()) {
+                        None => Some(format!("call to unknown function {}", name)),
+                        Some((params, ret)) => {
+                            let arg_types: Vec<IrType> = args.iter().map(|a| f.ty(*a)).collect();
+                            let ret = if *ret == IrType::Void { None } else { Some(*ret) };
+                            check(&arg_types == params && ret == ty, &format!("call to {} does not match its signature", name))
+                        }
+                    },
+                    Inst::Fail(..) => check(matches!(b.term, Some(Terminator::Unreachable)), "fail must be followed by unreachable"),
+                    Inst::Phi(_) => None,
+                };
+                if let Some(p) = problem {
+                    err(format!("{}: {}", f.show(&instr.inst), p));
+                }
+            }
+            match &b.term {
+                Some(Terminator::CondBr(c, ..)) if c.0 >= f.types.len() || !available(*c, here, b.insts.len()) => {
+                    err(format!("branch condition {} is not available", c));
+                }
+                Some(Terminator::CondBr(c, ..)) if f.ty(*c) != IrType::I1 => err(format!("branch condition {} must be i1", c)),
+                Some(Terminator::Ret(v)) if v.0 >= f.types.len() || !available(*v, here, b.insts.len()) => {
+                    err(format!("returned value {} is not available", v));
+                }
+                Some(Terminator::Ret(v)) if f.ty(*v) != f.ret => {
+                    err(format!("returns {} but the function returns {}", f.ty(*v), f.ret));
+                }
+                _ => {}
+            }
+        }
+    }
+    errors
+}
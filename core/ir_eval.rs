@@ -0,0 +1,266 @@
+use std::collections::HashMap;
+
+use crate::ast::ExternFn;
+use crate::host::{self, HostFn};
+use crate::interpreter::Value;
+use crate::ir::{BinOp, BlockId, Builtin, Callee, CastOp, Function, Inst, IrType, Module, Terminator, ValueId};
+
+// Исполнитель IR без LLVM — чтобы сравнивать способы построения SSA (SsaMode)
+// на одной программе. Вывод print/println копится в строку, а не идёт в stdout;
+// extern-функции вызываются через хост (host.rs), как в интерпретаторе.
+
+/// Итог запуска: вывод программы и код — результат main, аргумент exit или 101 после fail.
+#[derive(Debug, PartialEq)]
+pub struct Outcome {
+    pub output: String,
+    pub code: i64,
+}
+
+#[derive(Debug, Clone)]
+enum Val {
+    // i1 — 0/1, i32 — без знака, как лежит в регистре
+    Int(i64),
+    Str(String),
+    // номер слота alloca в кадре
+    Ptr(usize),
+}
+
+enum Stop {
+    Exit(i64),
+    Error(String),
+}
+
+pub fn run(module: &Module, hosts: &HashMap<String, HostFn>) -> Result<Outcome, String> {
+    let mut eval = Eval {
+        functions: module.functions.iter().map(|f| (f.name.as_str(), f)).collect(),
+        externs: module.externs.iter().map(|e| (e.name.as_str(), e)).collect(),
+        hosts,
+        output: String::new(),
+    };
+    let main = *eval.functions.get("main").ok_or("module has no main function")?;
+    let code = match eval.call(main, Vec::new()) {
+        Ok(Val::Int(n)) => n,
+        Ok(_) => 0,
+        Err(Stop::Exit(code)) => code,
+        Err(Stop::Error(e)) => return Err(e),
+    };
+    Ok(Outcome { output: eval.output, code })
+}
+
+struct Eval<'a> {
+    functions: HashMap<&'a str, &'a Function>,
+    externs: HashMap<&'a str, &'a ExternFn>,
+    hosts: &'a HashMap<String, HostFn>,
+    output: String,
+}
+
+fn normalize(ty: IrType, n: i64) -> i64 {
+    match ty {
+        IrType::I1 => n & 1,
+        IrType::I32 => n as u32 as i64,
+        _ => n,
+    }
+}
+
+// знаковое значение для сравнений, как icmp s*
+fn signed(ty: IrType, n: i64) -> i64 {
+    match ty {
+        IrType::I32 => n as i32 as i64,
+        _ => n,
+    }
+}
+
+impl<'a> Eval<'a> {
+    fn call(&mut self, f: &Function, args: Vec<Val>) -> Result<Val, Stop> {
+        let mut values: Vec<Option<Val>> = vec![None; f.types.len()];
+        let mut memory: Vec<Val> = Vec::new();
+        let mut prev: Option<BlockId> = None;
+        let mut cur = BlockId(0);
+        loop {
+            let block = &f.blocks[cur.0];
+            // phi блока читаются одновременно — по значениям на выходе из prev
+            let mut phis = Vec::new();
+            for instr in &block.insts {
+                if let (Inst::Phi(incoming), Some(d)) = (&instr.inst, instr.dest) {
+                    let v = incoming
+                        .iter()
+                        .find(|(b, _)| Some(*b) == prev)
+                        .ok_or_else(|| Stop::Error(format!("fn {}: phi {} has no input from {:?}", f.name, d, prev)))?;
+                    phis.push((d, get(&values, v.1)?));
+                }
+            }
+            for (d, v) in phis {
+                values[d.0] = Some(v);
+            }
+            for instr in block.insts.iter().filter(|i| !matches!(i.inst, Inst::Phi(_))) {
+                let ty = instr.dest.map_or(IrType::Void, |d| f.ty(d));
+                let result = match &instr.inst {
+                    Inst::Const(n) => Some(Val::Int(normalize(ty, *n))),
+                    Inst::Str(s) => Some(Val::Str(s.clone())),
+                    Inst::Param(i) => Some(args[*i].clone()),
+                    Inst::Binary(op, a, b) => Some(self.binary(f, *op, *a, *b, &values)?),
+                    Inst::Cast(op, v) => {
+                        let n = int(&values, *v)?;
+                        let n = match op {
+                            CastOp::Zext => n,
+                            CastOp::Sext if f.ty(*v) == IrType::I1 => -n,
+                            CastOp::Sext => signed(f.ty(*v), n),
+                            CastOp::Trunc => n,
+                        };
+                        Some(Val::Int(normalize(ty, n)))
+                    }
+                    Inst::Alloca(_) => {
+                        memory.push(Val::Int(0));
+                        Some(Val::Ptr(memory.len() - 1))
+                    }
+                    Inst::Load(p) => Some(memory[ptr(&values, *p)?].clone()),
+                    Inst::Store(p, v) => {
+                        let slot = ptr(&values, *p)?;
+                        memory[slot] = get(&values, *v)?;
+                        None
+                    }
+                    Inst::Call(callee, argv) => {
+                        let mut args = Vec::new();
+                        for a in argv {
+                            args.push(get(&values, *a)?);
+                        }
+                        self.call_callee(f, callee, argv, args)?
+                    }
+                    Inst::Fail(fmt, argv) => {
+                        let mut msg = fmt.replace("%%", "%");
+                        for a in argv {
+                            msg = msg.replacen("%lld", &int(&values, *a)?.to_string(), 1);
+                        }
+                        self.output.push_str(&msg);
+                        return Err(Stop::Exit(101));
+                    }
+                    Inst::Phi(_) => unreachable!("phis are evaluated at block entry"),
+                };
+                if let (Some(d), Some(v)) = (instr.dest, result) {
+                    values[d.0] = Some(v);
+                }
+            }
+            prev = Some(cur);
+            cur = match &block.term {
+                Some(Terminator::Br(b)) => *b,
+                Some(Terminator::CondBr(c, t, e)) => {
+                    if int(&values, *c)? != 0 {
+                        *t
+                    } else {
+                        *e
+                    }
+                }
+                Some(Terminator::Ret(v)) => return get(&values, *v),
+                Some(Terminator::Unreachable) | None => {
+                    return Err(Stop::Error(format!("fn {}: reached unreachable in {}", f.name, cur)));
+                }
+            };
+        }
+    }
+
+    fn binary(&self, f: &Function, op: BinOp, a: ValueId, b: ValueId, values: &[Option<Val>]) -> Result<Val, Stop> {
+        if let (Val::Str(x), Val::Str(y)) = (get(values, a)?, get(values, b)?) {
+            return Ok(Val::Int(match op {
+                BinOp::Eq => (x == y) as i64,
+                _ => (x != y) as i64,
+            }));
+        }
+        let ty = f.ty(a);
+        let (x, y) = (signed(ty, int(values, a)?), signed(ty, int(values, b)?));
+        Ok(Val::Int(match op {
+            BinOp::Add => x.wrapping_add(y),
+            BinOp::Sub => x.wrapping_sub(y),
+            BinOp::Mul => x.wrapping_mul(y),
+            BinOp::Div if y == 0 => return Err(Stop::Error(format!("fn {}: division by zero", f.name))),
+            BinOp::Div => x.wrapping_div(y),
+            BinOp::Eq => (x == y) as i64,
+            BinOp::Ne => (x != y) as i64,
+            BinOp::Lt => (x < y) as i64,
+            BinOp::Le => (x <= y) as i64,
+            BinOp::Gt => (x > y) as i64,
+            BinOp::Ge => (x >= y) as i64,
+        }))
+    }
+
+    fn call_callee(&mut self, f: &Function, callee: &Callee, argv: &[ValueId], args: Vec<Val>) -> Result<Option<Val>, Stop> {
+        match callee {
+            Callee::Builtin(b) => self.builtin(f, *b, argv, args),
+            Callee::Function(name) => {
+                let g = *self.functions.get(name.as_str()).ok_or_else(|| Stop::Error(format!("call to unknown function {}", name)))?;
+                Ok(Some(self.call(g, args)?))
+            }
+            Callee::Extern(name) => {
+                let ext = *self.externs.get(name.as_str()).ok_or_else(|| Stop::Error(format!("call to unknown extern {}", name)))?;
+                let Some(host_fn) = self.hosts.get(name) else {
+                    return Err(Stop::Error(format!("extern {} has no host implementation", name)));
+                };
+                let args = argv
+                    .iter()
+                    .zip(args)
+                    .map(|(a, v)| match (f.ty(*a), v) {
+                        (IrType::I1, Val::Int(n)) => Value::Bool(n != 0),
+                        (_, Val::Int(n)) => Value::Int(n),
+                        (_, Val::Str(s)) => Value::Str(s),
+                        (_, Val::Ptr(_)) => Value::Null,
+                    })
+                    .collect();
+                let ret = crate::ir::extern_type(&ext.ret);
+                let result = host::call(ext, host_fn, args).map_err(Stop::Error)?;
+                Ok(match (ret, result) {
+                    (IrType::Void, _) => None,
+                    (_, Value::Str(s)) => Some(Val::Str(s)),
+                    (ty, v) => Some(Val::Int(normalize(ty, v.as_int().unwrap_or(0)))),
+                })
+            }
+        }
+    }
+
+    // формат — как printf в ir_llvm::lower_builtin
+    fn builtin(&mut self, f: &Function, b: Builtin, argv: &[ValueId], args: Vec<Val>) -> Result<Option<Val>, Stop> {
+        match b {
+            Builtin::Print | Builtin::Println => {
+                if let (Some(&a), Some(v)) = (argv.first(), args.into_iter().next()) {
+                    let text = match (f.ty(a), v) {
+                        (IrType::I1, Val::Int(n)) => (if n != 0 { "true" } else { "false" }).to_string(),
+                        (IrType::I32, Val::Int(n)) => char::from_u32(n as u32).unwrap_or('?').to_string(),
+                        (_, Val::Int(n)) => n.to_string(),
+                        (_, Val::Str(s)) => s,
+                        (_, Val::Ptr(p)) => format!("<slot {}>", p),
+                    };
+                    self.output.push_str(&text);
+                }
+                if b == Builtin::Println {
+                    self.output.push('\n');
+                }
+                Ok(None)
+            }
+            Builtin::ReadInt => Err(Stop::Error("read_int is not supported by the IR evaluator".to_string())),
+            Builtin::Exit => match args.first() {
+                Some(Val::Int(n)) => Err(Stop::Exit(*n as i32 as i64)),
+                _ => Err(Stop::Exit(0)),
+            },
+        }
+    }
+}
+
+fn get(values: &[Option<Val>], v: ValueId) -> Result<Val, Stop> {
+    values
+        .get(v.0)
+        .cloned()
+        .flatten()
+        .ok_or_else(|| Stop::Error(format!("{} is used before it is computed", v)))
+}
+
+fn int(values: &[Option<Val>], v: ValueId) -> Result<i64, Stop> {
+    match get(values, v)? {
+        Val::Int(n) => Ok(n),
+        _ => Err(Stop::Error(format!("{} is not an integer", v))),
+    }
+}
+
+fn ptr(values: &[Option<Val>], v: ValueId) -> Result<usize, Stop> {
+    match get(values, v)? {
+        Val::Ptr(p) => Ok(p),
+        _ => Err(Stop::Error(format!("{} is not a pointer", v))),
+    }
+}
@@ -0,0 +1,426 @@
+use std::collections::HashMap;
+
+use inkwell::basic_block::BasicBlock;
+use inkwell::builder::Builder;
+use inkwell::context::Context;
+use inkwell::module::{Linkage, Module};
+use inkwell::types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum, FunctionType};
+use inkwell::values::{BasicMetadataValueEnum, BasicValue, BasicValueEnum, FunctionValue, PhiValue};
+use inkwell::{AddressSpace, IntPredicate};
+
+use crate::codegen;
+use crate::ir::{self, BinOp, BlockId, Builtin, Callee, CastOp, Inst, IrType, Terminator, ValueId};
+
+// Перевод нашего IR в LLVM: инструкция в инструкцию, блок в блок. Вся работа
+// по построению программы уже сделана в ir::build — здесь только выбор вызовов inkwell.
+
+/// Строит LLVM-модуль из проверенного IR (ir::verify без ошибок).
+pub fn lower<'ctx>(program: &ir::Module, context: &'ctx Context, name: &str) -> Module<'ctx> {
+    let lowering = Lowering {
+        context,
+        module: context.create_module(name),
+        builder: context.create_builder(),
+        values: HashMap::new(),
+        blocks: HashMap::new(),
+    };
+    lowering.lower_module(program)
+}
+
+struct Lowering<'ctx> {
+    context: &'ctx Context,
+    module: Module<'ctx>,
+    builder: Builder<'ctx>,
+    // значения и блоки текущей функции
+    values: HashMap<ValueId, BasicValueEnum<'ctx>>,
+    blocks: HashMap<BlockId, BasicBlock<'ctx>>,
+}
+
+impl<'ctx> Lowering<'ctx> {
+    fn lower_module(mut self, program: &ir::Module) -> Module<'ctx> {
+        for ext in &program.externs {
+            let params: Vec<BasicMetadataTypeEnum> = ext.params.iter().map(|(_, t)| self.lower_type(ir::extern_type(t)).into()).collect();
+            let fn_ty = self.fn_type(ir::extern_type(&ext.ret), &params);
+            self.module.add_function(&ext.name, fn_ty, Some(Linkage::External));
+        }
+        // сначала все объявления — чтобы работали вызовы «вперёд»
+        for f in &program.functions {
+            let params: Vec<BasicMetadataTypeEnum> = f.params.iter().map(|(_, t)| self.lower_type(*t).into()).collect();
+            // main возвращает i32 — код выхода, как в Codegen
+            let ret = if f.name == "main" { IrType::I32 } else { f.ret };
+            let fn_ty = self.fn_type(ret, &params);
+            self.module.add_function(&f.name, fn_ty, None);
+        }
+        for f in &program.functions {
+            self.lower_function(f);
+        }
+        self.module
+    }
+
+    fn lower_type(&self, ty: IrType) -> BasicTypeEnum<'ctx> {
+        match ty {
+            IrType::I1 => self.context.bool_type().into(),
+            IrType::I32 => self.context.i32_type().into(),
+            IrType::I64 => self.context.i64_type().into(),
+            IrType::Str | IrType::Ptr => self.context.ptr_type(AddressSpace::default()).into(),
+            IrType::Void => panic!("void is not a value type"),
+        }
+    }
+
+    fn fn_type(&self, ret: IrType, params: &[BasicMetadataTypeEnum<'ctx>]) -> FunctionType<'ctx> {
+        match ret {
+            IrType::Void => self.context.void_type().fn_type(params, false),
+            t => self.lower_type(t).fn_type(params, false),
+        }
+    }
+
+    fn value(&self, v: ValueId) -> BasicValueEnum<'ctx> {
+        *self.values.get(&v).unwrap_or_else(|| panic!("{} is not lowered yet", v))
+    }
+
+    // Блоки — в порядке обратного постпорядка: определение значения всегда переведено
+    // раньше использования. Входы phi дописываются в конце, когда готовы все блоки.
+    fn lower_function(&mut self, f: &ir::Function) {
+        let func = self.module.get_function(&f.name).expect("declared function");
+        self.values.clear();
+        self.blocks.clear();
+        let order = f.reverse_postorder();
+        let mut reachable: Vec<BlockId> = order.clone();
+        reachable.sort();
+        for b in reachable {
+            let bb = self.context.append_basic_block(func, &f.blocks[b.0].name);
+            self.blocks.insert(b, bb);
+        }
+        let mut phis: Vec<(PhiValue<'ctx>, &Vec<(BlockId, ValueId)>)> = Vec::new();
+        for b in order {
+            self.builder.position_at_end(self.blocks[&b]);
+            let block = &f.blocks[b.0];
+            for instr in &block.insts {
+                if let Inst::Phi(incoming) = &instr.inst {
+                    let d = instr.dest.expect("phi has a result");
+                    let phi = self.builder.build_phi(self.lower_type(f.ty(d)), &d.0.to_string()).expect("phi");
+                    self.values.insert(d, phi.as_basic_value());
+                    phis.push((phi, incoming));
+                    continue;
+                }
+                if let Some(v) = self.lower_inst(f, func, &instr.inst, instr.dest) {
+                    self.values.insert(instr.dest.expect("instruction with a result"), v);
+                }
+            }
+            self.lower_term(f, block.term.as_ref().expect("verified block"));
+        }
+        for (phi, incoming) in phis {
+            for (b, v) in incoming {
+                // вход из недостижимого блока: такого блока в LLVM нет
+                if let Some(bb) = self.blocks.get(b) {
+                    let v = self.value(*v);
+                    phi.add_incoming(&[(&v as &dyn BasicValue, *bb)]);
+                }
+            }
+        }
+    }
+
+    fn lower_inst(
+        &mut self,
+        f: &ir::Function,
+        func: FunctionValue<'ctx>,
+        inst: &Inst,
+        dest: Option<ValueId>,
+    ) -> Option<BasicValueEnum<'ctx>> {
+        let result_ty = dest.map(|d| f.ty(d));
+        let name = dest.map_or(String::new(), |d| d.0.to_string());
+        Some(match inst {
+            Inst::Const(n) => self.lower_type(result_ty.expect("const type")).into_int_type().const_int(*n as u64, true).into(),
+            Inst::Str(s) => self.global_str(s),
+            Inst::Param(i) => func.get_nth_param(*i as u32).expect("param"),
+            Inst::Binary(op, a, b) if f.ty(*a) == IrType::Str => {
+                let (a, b) = (self.value(*a), self.value(*b));
+                let i32_t = self.context.i32_type();
+                let ptr_t = self.context.ptr_type(AddressSpace::default());
+                let strcmp = self.libc_function("strcmp", i32_t.fn_type(&[ptr_t.into(), ptr_t.into()], false));
+                let res = self
+                    .builder
+                    .build_call(strcmp, &[a.into(), b.into()], "strcmp")
+                    .expect("call strcmp")
+                    .try_as_basic_value()
+                    .left()
+                    .expect("strcmp returns i32")
+                    .into_int_value();
+                let pred = if *op == BinOp::Eq { IntPredicate::EQ } else { IntPredicate::NE };
+                self.builder.build_int_compare(pred, res, i32_t.const_zero(), &name).expect("cmp").into()
+            }
+            Inst::Binary(op, a, b) => {
+                let (a, b) = (self.value(*a).into_int_value(), self.value(*b).into_int_value());
+                let cmp = |pred| self.builder.build_int_compare(pred, a, b, &name).expect("cmp");
+                match op {
+                    BinOp::Add => self.builder.build_int_add(a, b, &name).expect("add"),
+                    BinOp::Sub => self.builder.build_int_sub(a, b, &name).expect("sub"),
+                    BinOp::Mul => self.builder.build_int_mul(a, b, &name).expect("mul"),
+                    // на ноль ir::build уже проверил; i64::MIN / -1 у sdiv — UB, поэтому на -1
+                    // делим как 0 - a (с переполнением, как wrapping_div в ir_eval)
+                    BinOp::Div => {
+                        let int_t = b.get_type();
+                        let is_neg = self.builder.build_int_compare(IntPredicate::EQ, b, int_t.const_all_ones(), "divneg").expect("cmp");
+                        let safe = self.builder.build_select(is_neg, int_t.const_int(1, false), b, "divisor").expect("select");
+                        let q = self.builder.build_int_signed_div(a, safe.into_int_value(), "div").expect("div");
+                        let neg = self.builder.build_int_sub(int_t.const_zero(), a, "neg").expect("sub");
+                        self.builder.build_select(is_neg, neg, q, &name).expect("select").into_int_value()
+                    }
+                    BinOp::Eq => cmp(IntPredicate::EQ),
+                    BinOp::Ne => cmp(IntPredicate::NE),
+                    BinOp::Lt => cmp(IntPredicate::SLT),
+                    BinOp::Le => cmp(IntPredicate::SLE),
+                    BinOp::Gt => cmp(IntPredicate::SGT),
+                    BinOp::Ge => cmp(IntPredicate::SGE),
+                }
+                .into()
+            }
+            Inst::Cast(op, v) => {
+                let v = self.value(*v).into_int_value();
+                let to = self.lower_type(result_ty.expect("cast type")).into_int_type();
+                match op {
+                    CastOp::Zext => self.builder.build_int_z_extend(v, to, &name).expect("zext"),
+                    CastOp::Sext => self.builder.build_int_s_extend(v, to, &name).expect("sext"),
+                    CastOp::Trunc => self.builder.build_int_truncate(v, to, &name).expect("trunc"),
+                }
+                .into()
+            }
+            Inst::Alloca(t) => {
+                let var = dest.and_then(|d| f.names.get(&d)).cloned().unwrap_or(name);
+                self.builder.build_alloca(self.lower_type(*t), &var).expect("alloca").into()
+            }
+            Inst::Load(p) => {
+                let ptr = self.value(*p).into_pointer_value();
+                self.builder.build_load(self.lower_type(result_ty.expect("load type")), ptr, &name).expect("load failed")
+            }
+            Inst::Store(p, v) => {
+                let ptr = self.value(*p).into_pointer_value();
+                self.builder.build_store(ptr, self.value(*v)).expect("store failed");
+                return None;
+            }
+            Inst::Call(Callee::Builtin(b), args) => return self.lower_builtin(f, func, *b, args),
+            Inst::Call(Callee::Function(callee) | Callee::Extern(callee), args) => {
+                let callee = self.module.get_function(callee).expect("declared function");
+                let argv: Vec<BasicMetadataValueEnum> = args.iter().map(|a| self.value(*a).into()).collect();
+                let call = self.builder.build_call(callee, &argv, &name).expect("call");
+                return dest.map(|_| call.try_as_basic_value().left().expect("function returns a value"));
+            }
+            Inst::Phi(_) => unreachable!("phi is lowered by lower_function"),
+            // сообщение + exit(101), как Codegen::runtime_error; unreachable — терминатор блока
+            Inst::Fail(fmt, args) => {
+                let fmt = self.global_str(fmt).into_pointer_value();
+                let args: Vec<BasicMetadataValueEnum> = args.iter().map(|a| self.value(*a).into()).collect();
+                codegen::build_runtime_error(self.context, &self.module, &self.builder, fmt, &args);
+                return None;
+            }
+        })
+    }
+
+    fn lower_term(&mut self, f: &ir::Function, term: &Terminator) {
+        match term {
+            Terminator::Br(b) => {
+                self.builder.build_unconditional_branch(self.blocks[b]).expect("br");
+            }
+            Terminator::CondBr(c, t, e) => {
+                let c = self.value(*c).into_int_value();
+                self.builder.build_conditional_branch(c, self.blocks[t], self.blocks[e]).expect("brcond");
+            }
+            Terminator::Ret(v) if f.name == "main" => {
+                let i32_t = self.context.i32_type();
+                let code = match f.ret {
+                    IrType::I64 => self.builder.build_int_truncate(self.value(*v).into_int_value(), i32_t, "code").expect("trunc"),
+                    IrType::I1 => self.builder.build_int_z_extend(self.value(*v).into_int_value(), i32_t, "code").expect("zext"),
+                    IrType::I32 => self.value(*v).into_int_value(),
+                    _ => i32_t.const_zero(),
+                };
+                self.builder.build_return(Some(&code)).expect("ret");
+            }
+            Terminator::Ret(v) => {
+                self.builder.build_return(Some(&self.value(*v))).expect("ret");
+            }
+            Terminator::Unreachable => {
+                self.builder.build_unreachable().expect("unreachable");
+            }
+        }
+    }
+
+    // Формат печати — как у Codegen::print_value для скалярных типов.
+    fn lower_builtin(
+        &mut self,
+        f: &ir::Function,
+        func: FunctionValue<'ctx>,
+        b: Builtin,
+        args: &[ValueId],
+    ) -> Option<BasicValueEnum<'ctx>> {
+        let i32_t = self.context.i32_type();
+        match b {
+            Builtin::Print | Builtin::Println => {
+                if let Some(&a) = args.first() {
+                    let v = self.value(a);
+                    let (fmt, v): (&str, BasicMetadataValueEnum) = match f.ty(a) {
+                        IrType::I64 => ("%lld", v.into()),
+                        IrType::I32 => ("%c", v.into()),
+                        IrType::I1 => {
+                            let t = self.global_str("true").into_pointer_value();
+                            let e = self.global_str("false").into_pointer_value();
+                            ("%s", self.builder.build_select(v.into_int_value(), t, e, "sel").expect("select").into())
+                        }
+                        _ => ("%s", v.into()),
+                    };
+                    let fmt = self.global_str(fmt);
+                    self.printf(&[fmt.into(), v]);
+                }
+                if b == Builtin::Println {
+                    let nl = self.global_str("\n");
+                    self.printf(&[nl.into()]);
+                }
+            }
+            Builtin::ReadInt => {
+                let int_t = self.context.i64_type();
+                let ptr_t = self.context.ptr_type(AddressSpace::default());
+                let scanf = self.libc_function("scanf", i32_t.fn_type(&[ptr_t.into()], true));
+                // слот — во входном блоке, чтобы read_int в цикле не растил стек
+                let entry = func.get_first_basic_block().expect("function has no entry");
+                let tmp = self.context.create_builder();
+                match entry.get_first_instruction() {
+                    Some(inst) => tmp.position_before(&inst),
+                    None => tmp.position_at_end(entry),
+                }
+                let slot = tmp.build_alloca(int_t, "read_int").expect("alloca failed");
+                self.builder.build_store(slot, int_t.const_zero()).expect("store");
+                let fmt = self.global_str("%lld");
+                self.builder.build_call(scanf, &[fmt.into(), slot.into()], "scanf").expect("call scanf");
+                return Some(self.builder.build_load(int_t, slot, "read_int").expect("load"));
+            }
+            Builtin::Exit => {
+                let code = self.builder.build_int_truncate(self.value(args[0]).into_int_value(), i32_t, "code").expect("trunc");
+                let exit = self.libc_function("exit", self.context.void_type().fn_type(&[i32_t.into()], false));
+                self.builder.build_call(exit, &[code.into()], "").expect("call exit");
+            }
+        }
+        None
+    }
+
+    fn global_str(&self, s: &str) -> BasicValueEnum<'ctx> {
+        self.builder
+            .build_global_string_ptr(s, "str")
+            .expect("global string")
+            .as_pointer_value()
+            .into()
+    }
+
+    fn libc_function(&self, name: &str, fn_ty: FunctionType<'ctx>) -> FunctionValue<'ctx> {
+        self.module
+            .get_function(name)
+            .unwrap_or_else(|| self.module.add_function(name, fn_ty, None))
+    }
+
+    fn printf(&self, args: &[BasicMetadataValueEnum<'ctx>]) {
+        let ptr_t = self.context.ptr_type(AddressSpace::default());
+        let printf = self.libc_function("printf", self.context.i32_type().fn_type(&[ptr_t.into()], true));
+        self.builder.build_call(printf, args, "printf").expect("call printf");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use inkwell::context::Context;
+
+    use super::*;
+    use crate::ir::SsaMode;
+    use crate::ir_eval;
+    use crate::testutil::load;
+
+    // Результат main после перевода в LLVM (JIT), одинаковый в обоих способах построения SSA.
+    fn jit(src: &str) -> Result<i32, String> {
+        let (program, sources) = load(src);
+        let results: Vec<Result<i32, String>> = [SsaMode::Memory, SsaMode::Direct]
+            .into_iter()
+            .map(|mode| {
+                let module = ir::build(&program, &sources, mode).expect("build ir");
+                assert_eq!(ir::verify(&module), Vec::<String>::new(), "{:?}", mode);
+                let context = Context::create();
+                let llvm = lower(&module, &context, "test");
+                codegen::run_jit(&llvm, &HashMap::new())
+            })
+            .collect();
+        assert_eq!(results[0], results[1], "{}", src);
+        results[0].clone()
+    }
+
+    #[test]
+    fn division_is_checked() {
+        let src = "fn main() {\n    let z = 0;\n    7 / z\n}";
+        assert_eq!(jit(src), Err("runtime error at 3:5: division by zero".to_string()));
+        let (program, sources) = load(src);
+        let module = ir::build(&program, &sources, SsaMode::Direct).expect("build ir");
+        let outcome = ir_eval::run(&module, &HashMap::new()).expect("run");
+        assert_eq!((outcome.output.as_str(), outcome.code), ("runtime error at 3:5: division by zero\n", 101));
+
+        let src = "fn main() { let m = 0 - 9223372036854775807 - 1; let d = 0 - 1; if (m / d == m) { 0 - 7 / 2 } else { 0 } }";
+        assert_eq!(jit(src), Ok(-3));
+    }
+
+    #[test]
+    fn for_loops_stop_before_overflow() {
+        let src = r#"fn main() {
+            let max = 9223372036854775807;
+            let min = 0 - max - 1;
+            let n = 0;
+            for i in max - 2..=max { n = n + 1; }
+            for i in min + 5..=min step -3 { n = n + 10; }
+            for i in max - 10..max step 4 { n = n + 100; }
+            n
+        }"#;
+        assert_eq!(jit(src), Ok(323));
+    }
+
+    fn count(src: &str, mode: SsaMode, pred: fn(&Inst) -> bool) -> usize {
+        let (program, sources) = load(src);
+        let module = ir::build(&program, &sources, mode).expect("build ir");
+        module.functions.iter().flat_map(|f| &f.blocks).flat_map(|b| &b.insts).filter(|i| pred(&i.inst)).count()
+    }
+
+    #[test]
+    fn ssa_modes_agree_after_lowering() {
+        let programs = [
+            // if/else присваивают одной переменной — phi на слиянии
+            "fn main() { let x = 1; let y = 0; if (x > 0) { y = 10; } else { y = 20; } if (y == 10) { x = x + y; } x }",
+            // вложенные while
+            r#"fn main() {
+                let i = 0; let total = 0;
+                while (i < 10) {
+                    let j = 0;
+                    while (j < i) { total = total + i * j; j = j + 1; }
+                    i = i + 1;
+                }
+                total
+            }"#,
+            // вложенные for с одним именем переменной и шагом
+            r#"fn main() {
+                let s = 0;
+                for i in 0..5 { for i in 10..0 step -3 { s = s + i; } s = s + i; }
+                for k in 1..=4 { s = s * 2 + k; }
+                s
+            }"#,
+            // match в цикле и значение ветки
+            r#"fn main() {
+                let acc = 0;
+                for n in 0..20 {
+                    acc = acc + match n { 0 => 100, 1..=5 => n, 6 | 7 => 0 - n, _ => 1 };
+                }
+                acc
+            }"#,
+            // рекурсия и значение блока
+            "fn fib(n: i64) -> i64 { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }\nfn main() { let r = { let a = fib(15); a - 10 }; r }",
+        ];
+        for src in programs {
+            let expected = crate::testutil::interpret(src).1.expect("interpreter result").code().expect("int");
+            assert_eq!(jit(src), Ok(expected as i32), "{}", src);
+            // напрямую в SSA — без слотов для переменных, значения сливаются в phi
+            let allocas = |mode| count(src, mode, |i| matches!(i, Inst::Alloca(_)));
+            assert!(allocas(SsaMode::Memory) > 0, "{}", src);
+            assert_eq!(allocas(SsaMode::Direct), 0, "{}", src);
+            assert!(count(src, SsaMode::Direct, |i| matches!(i, Inst::Phi(_))) > 0, "{}", src);
+        }
+    }
+}
@@ -1,187 +1,405 @@
-#[derive(Debug, Clone, PartialEq)]
+use logos::Logos;
+
+pub type Span = std::ops::Range<usize>;
+
+/// Байтовое смещение -> (строка, колонка), обе с единицы — для сообщений об ошибках.
+pub fn line_col(src: &str, offset: usize) -> (usize, usize) {
+    let before = &src[..offset.min(src.len())];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |nl| nl + 1);
+    let col = before[line_start..].chars().count() + 1;
+    (line, col)
+}
+
+#[derive(Logos, Debug, Clone, PartialEq)]
+#[logos(skip r"[ \t\r\n\f]+")]
 pub enum Token {
+    #[regex("[0-9][0-9_]*", |lex| parse_int(lex.slice(), 10))]
+    #[regex("0x[0-9a-fA-F_]+", |lex| parse_int(&lex.slice()[2..], 16))]
+    #[regex("0o[0-7_]+", |lex| parse_int(&lex.slice()[2..], 8))]
+    #[regex("0b[01_]+", |lex| parse_int(&lex.slice()[2..], 2))]
     Number(i64),
+    #[token("true", |_| true)]
+    #[token("false", |_| false)]
+    Bool(bool),
+    #[token("'", char_literal)]
+    Char(char),
+    #[regex(r#""([^"\\]|\\[^\n]|\\u\{[0-9a-fA-F]+\})*""#, |lex| unescape(&lex.slice()[1..lex.slice().len() - 1]))]
+    Str(String),
+    #[regex(r"[\p{Alphabetic}_][\p{Alphabetic}\p{N}_]*", |lex| lex.slice().to_string())]
     Ident(String),
 
-   Fn,
-   Let,
-   If,
-   Else,
-   While,
+    #[token("fn")]
+    Fn,
+    #[token("let")]
+    Let,
+    #[token("if")]
+    If,
+    #[token("else")]
+    Else,
+    #[token("while")]
+    While,
+    #[token("for")]
+    For,
+    #[token("in")]
+    In,
+    #[token("match")]
+    Match,
+    #[token("import")]
+    Import,
+    #[token("use")]
+    Use,
+    #[token("extern")]
+    Extern,
+    #[token("struct")]
+    Struct,
+    #[token("null")]
+    Null,
 
+    #[token("{")]
     LBrace,
+    #[token("}")]
     RBrace,
+    #[token("(")]
     LParen,
+    #[token(")")]
     RParen,
+    #[token("[")]
+    LBracket,
+    #[token("]")]
+    RBracket,
+    #[token(";")]
     Semicolon,
+    #[token(",")]
+    Comma,
+    #[token(".")]
+    Dot,
+    #[token("..")]
+    DotDot,
+    #[token("..=")]
+    DotDotEq,
+    #[token(":")]
+    Colon,
+    #[token("::")]
+    ColonColon,
+    #[token("=")]
     Eq,
+    #[token("|")]
+    Pipe,
+    #[token("->")]
+    Arrow,
+    #[token("=>")]
+    FatArrow,
 
+    #[token("+")]
     Plus,
+    #[token("-")]
     Minus,
+    #[token("*")]
     Star,
+    #[token("/")]
     Slash,
+    #[token("&")]
+    Amp,
 
+    #[token("==")]
     EqEq,
+    #[token("!=")]
     NotEq,
+    #[token("<")]
     Lt,
+    #[token("<=")]
     Le,
+    #[token(">")]
     Gt,
+    #[token(">=")]
     Ge,
 
+    // `/// ...` — текст без префикса, парсер цепляет его к следующей fn.
+    // `////` — уже обычный комментарий, как в Rust.
+    #[regex(r"///([^/\n][^\n]*)?", |lex| doc_text(lex.slice()), priority = 10)]
+    DocComment(String),
+
+    // Эти два варианта наружу не выходят: Lexer::tokenize складывает их в trivia.
+    #[regex(r"//[^\n]*", |lex| lex.slice()[2..].to_string())]
+    LineComment(String),
+    #[token("/*", block_comment)]
+    BlockComment(String),
+
     Error,
 }
 
-pub struct Lexer<'a> {
-    input: &'a str,
-    pos: usize,
+// Имя целиком как один токен: ключевое слово или то же Ident.
+fn keyword(name: String) -> Token {
+    let mut lex = Token::lexer(&name);
+    match (lex.next(), lex.next()) {
+        (Some(Ok(tok)), None) => tok,
+        _ => Token::Ident(name),
+    }
 }
 
-impl<'a> Lexer<'a> {
-    pub fn new(input: &'a str) -> Self {
-        Self{ input, pos: 0}
+// `1_000`, `0xff`, `0b1010` — подчёркивания игнорируются, но хотя бы одна цифра нужна.
+fn parse_int(digits: &str, radix: u32) -> Option<i64> {
+    let digits: String = digits.chars().filter(|c| *c != '_').collect();
+    if digits.is_empty() {
+        return None;
     }
+    i64::from_str_radix(&digits, radix).ok()
+}
 
-    fn next_char(&mut self) -> Option<char> {
-        let ch = self.input[self.pos..].chars().next()?;
-        self.pos += ch.len_utf8();
-        Some(ch)
+// Разворачивает escape-последовательности: \n \t \r \0 \\ \' \" и \u{XXXX}.
+fn unescape(body: &str) -> Option<String> {
+    let mut out = String::new();
+    let mut chars = body.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => out.push('\n'),
+            't' => out.push('\t'),
+            'r' => out.push('\r'),
+            '0' => out.push('\0'),
+            '\\' => out.push('\\'),
+            '\'' => out.push('\''),
+            '"' => out.push('"'),
+            'u' => {
+                if chars.next()? != '{' {
+                    return None;
+                }
+                let hex: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                out.push(char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?);
+            }
+            _ => return None,
+        }
     }
+    Some(out)
+}
 
+// `'x'`, `'\n'`, `'\u{41}'`. Не регуляркой: с ней logos не принимал не-ASCII символ
+// между кавычками (`'ж'`). Без закрывающей кавычки ошибка — одна `'`.
+fn char_literal(lex: &mut logos::Lexer<Token>) -> Option<char> {
+    let rest = lex.remainder();
+    let first = rest.chars().next()?;
+    let hex = rest.strip_prefix("\\u{").map(|r| r.find(|c: char| !c.is_ascii_hexdigit()).unwrap_or(r.len()));
+    let len = match (first, hex) {
+        ('\'' | '\n', _) => return None,
+        ('\\', Some(n)) if n > 0 && rest[3 + n..].starts_with('}') => 3 + n + 1,
+        ('\\', _) => match rest[1..].chars().next()? {
+            '\n' => return None,
+            c => 1 + c.len_utf8(),
+        },
+        (c, _) => c.len_utf8(),
+    };
+    if !rest[len..].starts_with('\'') {
+        return None;
+    }
+    lex.bump(len + 1);
+    let s = unescape(&rest[..len])?;
+    let mut chars = s.chars();
+    let c = chars.next()?;
+    if chars.next().is_some() { None } else { Some(c) }
+}
+
+fn doc_text(slice: &str) -> String {
+    let raw = &slice[3..];
+    raw.strip_prefix(' ').unwrap_or(raw).to_string()
+}
+
+// `/* ... */` с вложенностью. None — если комментарий не закрыт (станет Token::Error).
+fn block_comment(lex: &mut logos::Lexer<Token>) -> Option<String> {
+    let rest = lex.remainder();
+    let mut depth = 1;
+    let mut i = 0;
+    while depth > 0 {
+        if rest[i..].starts_with("/*") {
+            i += 2;
+            depth += 1;
+        } else if rest[i..].starts_with("*/") {
+            i += 2;
+            depth -= 1;
+        } else if let Some(ch) = rest[i..].chars().next() {
+            i += ch.len_utf8();
+        } else {
+            lex.bump(i);
+            return None;
+        }
+    }
+    lex.bump(i);
+    Some(rest[..i - 2].to_string())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommentKind {
+    Line,
+    Block,
+    Doc,
+}
 
-    fn peek_char(&self) -> Option<char> {
-        self.input[self.pos..].chars().next()
- 
+/// Комментарий как trivia: не попадает в поток токенов (кроме `///`),
+/// но сохраняется с позицией — пригодится форматтеру и генератору доков.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment {
+    pub kind: CommentKind,
+    pub text: String,
+    pub span: Span,
+}
+
+pub struct Lexer<'a> {
+    input: &'a str,
+    comments: Vec<Comment>,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Self { input, comments: Vec::new() }
     }
 
-    fn starts_with(&self, s: &str) -> bool {
-        self.input[self.pos..].starts_with(s)
+    pub fn comments(&self) -> &[Comment] {
+        &self.comments
     }
 
     pub fn tokenize(&mut self) -> Vec<Token> {
+        self.tokenize_spanned().into_iter().map(|(tok, _)| tok).collect()
+    }
+
+    /// Токены вместе с байтовыми диапазонами в исходнике.
+    pub fn tokenize_spanned(&mut self) -> Vec<(Token, Span)> {
         let mut tokens = Vec::new();
+        self.comments.clear();
 
-        while let Some(ch) = self.peek_char() {
-            match ch
-                 {
-                ' ' | '\n' | '\t' | '\r' => {
-                    self.next_char();
-                }
-                '+' => {
-                    self.next_char();
-                    tokens.push(Token::Plus);
-                }
-                '-' => {
-                    self.next_char();
-                    tokens.push(Token::Minus);
-                }
-                '(' => {
-                    self.next_char();
-                    tokens.push(Token::LParen);
-                }
-                ')' => {
-                    self.next_char();
-                    tokens.push(Token::RParen);
-                }
-                '{' => {
-                    self.next_char();
-                    tokens.push(Token::LBrace);
-                }
-                '}' => {
-                    self.next_char();
-                    tokens.push(Token::RBrace);
-                }
-                ';' => {
-                    self.next_char();
-                    tokens.push(Token::Semicolon);
+        for (res, span) in Token::lexer(self.input).spanned() {
+            match res {
+                Ok(Token::LineComment(text)) => {
+                    self.comments.push(Comment { kind: CommentKind::Line, text, span });
                 }
-                '=' => {
-                    if self.starts_with("==") {
-                        self.pos += 2;
-                        tokens.push(Token::EqEq);
-                    } else {
-                        self.next_char();
-                    tokens.push(Token::Eq);
-                    }
-                }    
-                '!' => {
-                    if self.starts_with("!=") {
-                        self.pos += 2;
-                        tokens.push(Token::NotEq);
-                    } else {
-                        self.next_char();
-                    tokens.push(Token::Error);
-                    }
-                }    
-                '<' => {
-                    if self.starts_with("<=") {
-                        self.pos += 2;
-                        tokens.push(Token::Le);
-                    } else {
-                        self.next_char();
-                        tokens.push(Token::Lt);
-                    }
-                }        
-                '>' => {
-                    if self.starts_with(">=") {
-                        self.pos += 2;
-                        tokens.push(Token::Ge);
-                    } else {
-                        self.next_char();
-                        tokens.push(Token::Error);
-                    }
-                }       
-                '*' => {
-                    self.next_char();
-                    tokens.push(Token::Star);
+                Ok(Token::BlockComment(text)) => {
+                    self.comments.push(Comment { kind: CommentKind::Block, text, span });
                 }
-                '/' => {
-                    self.next_char();
-                    tokens.push(Token::Slash);
+                Ok(Token::DocComment(text)) => {
+                    self.comments.push(Comment { kind: CommentKind::Doc, text: text.clone(), span: span.clone() });
+                    tokens.push((Token::DocComment(text), span));
                 }
-                
-                
-                
-                c if c.is_ascii_digit() => {
-                    let mut num = String::new();
-                    while let Some(d) = self.peek_char() {
-                        if d.is_ascii_digit() {
-                            num.push(d);
-                            self.next_char();
-                        } else {
-                            break;
-                        }
-                    }
-                    tokens.push(Token::Number(num.parse().unwrap()));
-                }
-                c if c.is_alphabetic() || c == '_' => {
-                    let mut ident = String::new();
-                    ident.push(c);
-                    self.next_char();
-                    while let Some(nc) = self.peek_char() {
-                        if nc.is_alphanumeric() || nc == '_' {
-                            ident.push(nc);
-                            self.next_char();
-
-                        } else {
-                            break;
-                        }
-                    }
-                    match ident.as_str() {
-                        "fn" => tokens.push(Token::Fn),
-                        "let" =>tokens.push(Token::Let),
-                        "if" => tokens.push(Token::If),
-                        "else" => tokens.push(Token::Else),
-                        "while" => tokens.push(Token::While),
-                        _ => tokens.push(Token::Ident(ident)),
-                    }
-                }
-                _ => {
-                    self.next_char();
-                    tokens.push(Token::Error);
+                // за ключевым словом не-ASCII символ, который не продолжает имя (`let\u{a0}`):
+                // автомат logos откатывается к Ident, а не к ключевому слову — переразбираем
+                Ok(Token::Ident(name)) if self.input[span.end..].chars().next().is_some_and(|c| !c.is_ascii()) => {
+                    tokens.push((keyword(name), span));
                 }
+                Ok(tok) => tokens.push((tok, span)),
+                Err(()) => tokens.push((Token::Error, span)),
             }
         }
 
         tokens
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer_hand;
+
+    // Программы и куски, которые прежний лексер разбирал правильно: без `>` и без чисел
+    // больше i64 (на них он ошибался, см. fixed_bugs).
+    const CORPUS: &[&str] = &[
+        "fn main() {\n    let x = 0;\n    while (x < 5) {\n        x = x + 1;\n    }\n    \
+         if (x == 5) {\n        42;\n    } else {\n        0;\n    }\n}",
+        "fn f() { let a=1;let b=a*2-3/4; if (a != b) { a } else { b } a<=b; a>=b; a==b; !a }",
+        "let_x _a a_1 é ünï x² fn1 iff while_ whilé 007 9223372036854775807",
+        "// line\n/// doc\n///\n//// not doc\n/* a /* nested */ b */ x /* open",
+        "@ # $ ~ \u{a0} \r\n\t",
+    ];
+
+    fn assert_same(src: &str) {
+        let (mut a, mut b) = (Lexer::new(src), lexer_hand::Lexer::new(src));
+        assert_eq!(a.tokenize(), b.tokenize(), "{:?}", src);
+        assert_eq!(a.comments(), b.comments(), "{:?}", src);
+    }
+
+    #[test]
+    fn matches_hand_lexer_on_corpus() {
+        for src in CORPUS {
+            assert_same(src);
+        }
+    }
+
+    // Случайные склейки кусков: границы токенов, ключевые слова, не-ASCII, комментарии.
+    #[test]
+    fn matches_hand_lexer_on_random_input() {
+        let pieces = [
+            "a", "é", "z9", "²", "0", "7", "fn", "let", "if", "else", "while", "=", "!", "<", "-", "+", "*", "/",
+            "//", "///", "/*", "*/", "(", ")", "{", "}", ";", "\n", " ", "\t", "\u{a0}", "@",
+        ];
+        let mut seed: u64 = 0x2545f4914f6cdd1d;
+        let mut next = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed as usize
+        };
+        for _ in 0..20_000 {
+            let n = next() % 8 + 1;
+            let src: String = (0..n).map(|_| pieces[next() % pieces.len()]).collect();
+            assert_same(&src);
+        }
+    }
+
+    #[test]
+    fn comments_are_trivia() {
+        let mut lexer = Lexer::new("a // x\n/* b /* c */ d */ b");
+        assert_eq!(lexer.tokenize_spanned(), vec![(Token::Ident("a".into()), 0..1), (Token::Ident("b".into()), 25..26)]);
+        let comments: Vec<_> = lexer.comments().iter().map(|c| (c.kind, c.text.as_str(), c.span.clone())).collect();
+        assert_eq!(comments, vec![(CommentKind::Line, " x", 2..6), (CommentKind::Block, " b /* c */ d ", 7..24)]);
+    }
+
+    #[test]
+    fn doc_comments() {
+        let mut lexer = Lexer::new("/// one\n////two\n///\nfn");
+        let docs = vec![Token::DocComment("one".into()), Token::DocComment(String::new()), Token::Fn];
+        assert_eq!(lexer.tokenize(), docs);
+        let kinds: Vec<_> = lexer.comments().iter().map(|c| c.kind).collect();
+        assert_eq!(kinds, vec![CommentKind::Doc, CommentKind::Line, CommentKind::Doc]);
+    }
+
+    #[test]
+    fn unterminated_block_comment() {
+        assert_eq!(Lexer::new("x /* a /* b */").tokenize_spanned(), vec![(Token::Ident("x".into()), 0..1), (Token::Error, 2..14)]);
+    }
+
+    // Где logos-лексер намеренно расходится с прежним.
+    #[test]
+    fn fixed_bugs() {
+        assert_eq!(lexer_hand::Lexer::new("a > b").tokenize()[1], Token::Error);
+        let ident = |s: &str| Token::Ident(s.to_string());
+        assert_eq!(
+            Lexer::new("a > b >= c != d").tokenize(),
+            vec![ident("a"), Token::Gt, ident("b"), Token::Ge, ident("c"), Token::NotEq, ident("d")]
+        );
+        // прежний паниковал на parse().unwrap()
+        assert_eq!(Lexer::new("99999999999999999999").tokenize_spanned(), vec![(Token::Error, 0..20)]);
+        assert_eq!(Lexer::new("9223372036854775807").tokenize(), vec![Token::Number(i64::MAX)]);
+    }
+
+    #[test]
+    fn keyword_before_non_ascii() {
+        assert_eq!(Lexer::new("fn\u{a0}").tokenize(), vec![Token::Fn, Token::Error]);
+        assert_eq!(Lexer::new("while²").tokenize(), vec![Token::Ident("while²".to_string())]);
+    }
+
+    #[test]
+    fn spans() {
+        let tokens = Lexer::new("let x = 10; // c\nx").tokenize_spanned();
+        let spans: Vec<Span> = tokens.into_iter().map(|(_, s)| s).collect();
+        assert_eq!(spans, [0..3, 4..5, 6..7, 8..10, 10..11, 17..18]);
+    }
+
+    #[test]
+    fn char_literals() {
+        let chars = Lexer::new("'ж' '😀' '\\n' '\\u{41}'").tokenize();
+        assert_eq!(chars, vec![Token::Char('ж'), Token::Char('😀'), Token::Char('\n'), Token::Char('A')]);
+        // без закрывающей кавычки и с двумя символами — ошибка на одной `'`
+        assert_eq!(Lexer::new("'a").tokenize_spanned(), vec![(Token::Error, 0..1), (Token::Ident("a".into()), 1..2)]);
+        assert_eq!(Lexer::new("'ab'").tokenize().first(), Some(&Token::Error));
+    }
+}
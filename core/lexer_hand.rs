@@ -0,0 +1,253 @@
+use std::time::Instant;
+
+use crate::lexer::{self, Comment, CommentKind, Token};
+
+// Прежний посимвольный лексер — эталон для lexer.rs на logos: тесты сверяют потоки
+// токенов, `core --bench-lexer` сравнивает скорость. Код оставлен как был, только
+// токены и комментарии теперь типов из lexer.rs. Его известные ошибки: `>` даёт Error,
+// слишком длинное число — панику; в сверке их обходят.
+
+pub struct Lexer<'a> {
+    input: &'a str,
+    pos: usize,
+    comments: Vec<Comment>,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Self{ input, pos: 0, comments: Vec::new() }
+    }
+
+    pub fn comments(&self) -> &[Comment] {
+        &self.comments
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        let ch = self.input[self.pos..].chars().next()?;
+        self.pos += ch.len_utf8();
+        Some(ch)
+    }
+
+
+    fn peek_char(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+ 
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        self.input[self.pos..].starts_with(s)
+    }
+
+    // Съедает `//...` до конца строки, возвращает текст после префикса.
+    fn line_comment(&mut self, prefix: usize) -> String {
+        self.pos += prefix;
+        let rest = &self.input[self.pos..];
+        let len = rest.find('\n').unwrap_or(rest.len());
+        let text = rest[..len].to_string();
+        self.pos += len;
+        text
+    }
+
+    // `/* ... */` с вложенностью. None — если комментарий не закрыт.
+    fn block_comment(&mut self) -> Option<String> {
+        self.pos += 2;
+        let body_start = self.pos;
+        let mut depth = 1;
+        while depth > 0 {
+            if self.starts_with("/*") {
+                self.pos += 2;
+                depth += 1;
+            } else if self.starts_with("*/") {
+                self.pos += 2;
+                depth -= 1;
+            } else if self.next_char().is_none() {
+                return None;
+            }
+        }
+        Some(self.input[body_start..self.pos - 2].to_string())
+    }
+
+    // ветки `!` и `>` — как в оригинале, с их ошибками
+    #[allow(clippy::collapsible_match)]
+    pub fn tokenize(&mut self) -> Vec<Token> {
+        let mut tokens = Vec::new();
+
+        while let Some(ch) = self.peek_char() {
+            match ch
+                 {
+                ' ' | '\n' | '\t' | '\r' => {
+                    self.next_char();
+                }
+                '+' => {
+                    self.next_char();
+                    tokens.push(Token::Plus);
+                }
+                '-' => {
+                    self.next_char();
+                    tokens.push(Token::Minus);
+                }
+                '(' => {
+                    self.next_char();
+                    tokens.push(Token::LParen);
+                }
+                ')' => {
+                    self.next_char();
+                    tokens.push(Token::RParen);
+                }
+                '{' => {
+                    self.next_char();
+                    tokens.push(Token::LBrace);
+                }
+                '}' => {
+                    self.next_char();
+                    tokens.push(Token::RBrace);
+                }
+                ';' => {
+                    self.next_char();
+                    tokens.push(Token::Semicolon);
+                }
+                '=' => {
+                    if self.starts_with("==") {
+                        self.pos += 2;
+                        tokens.push(Token::EqEq);
+                    } else {
+                        self.next_char();
+                    tokens.push(Token::Eq);
+                    }
+                }    
+                '!' => {
+                    if self.starts_with("!=") {
+                        self.pos += 2;
+                        tokens.push(Token::NotEq);
+                    } else {
+                        self.next_char();
+                    tokens.push(Token::Error);
+                    }
+                }    
+                '<' => {
+                    if self.starts_with("<=") {
+                        self.pos += 2;
+                        tokens.push(Token::Le);
+                    } else {
+                        self.next_char();
+                        tokens.push(Token::Lt);
+                    }
+                }        
+                '>' => {
+                    if self.starts_with(">=") {
+                        self.pos += 2;
+                        tokens.push(Token::Ge);
+                    } else {
+                        self.next_char();
+                        tokens.push(Token::Error);
+                    }
+                }       
+                '*' => {
+                    self.next_char();
+                    tokens.push(Token::Star);
+                }
+                '/' => {
+                    let start = self.pos;
+                    if self.starts_with("///") && !self.starts_with("////") {
+                        let raw = self.line_comment(3);
+                        let text = raw.strip_prefix(' ').unwrap_or(&raw).to_string();
+                        self.comments.push(Comment { kind: CommentKind::Doc, text: text.clone(), span: start..self.pos });
+                        tokens.push(Token::DocComment(text));
+                    } else if self.starts_with("//") {
+                        let text = self.line_comment(2);
+                        self.comments.push(Comment { kind: CommentKind::Line, text, span: start..self.pos });
+                    } else if self.starts_with("/*") {
+                        match self.block_comment() {
+                            Some(text) => {
+                                self.comments.push(Comment { kind: CommentKind::Block, text, span: start..self.pos });
+                            }
+                            None => tokens.push(Token::Error),
+                        }
+                    } else {
+                        self.next_char();
+                        tokens.push(Token::Slash);
+                    }
+                }
+                
+                
+                
+                c if c.is_ascii_digit() => {
+                    let mut num = String::new();
+                    while let Some(d) = self.peek_char() {
+                        if d.is_ascii_digit() {
+                            num.push(d);
+                            self.next_char();
+                        } else {
+                            break;
+                        }
+                    }
+                    tokens.push(Token::Number(num.parse().unwrap()));
+                }
+                c if c.is_alphabetic() || c == '_' => {
+                    let mut ident = String::new();
+                    ident.push(c);
+                    self.next_char();
+                    while let Some(nc) = self.peek_char() {
+                        if nc.is_alphanumeric() || nc == '_' {
+                            ident.push(nc);
+                            self.next_char();
+
+                        } else {
+                            break;
+                        }
+                    }
+                    match ident.as_str() {
+                        "fn" => tokens.push(Token::Fn),
+                        "let" =>tokens.push(Token::Let),
+                        "if" => tokens.push(Token::If),
+                        "else" => tokens.push(Token::Else),
+                        "while" => tokens.push(Token::While),
+                        _ => tokens.push(Token::Ident(ident)),
+                    }
+                }
+                _ => {
+                    self.next_char();
+                    tokens.push(Token::Error);
+                }
+            }
+        }
+
+        tokens
+    }
+}
+
+/// `core --bench-lexer`: текст повторяется до 8 МБ, время — лучшее из пяти прогонов
+/// каждого лексера. Заодно сообщает, где потоки токенов разошлись.
+pub fn bench(sample: &str) {
+    let mut input = String::new();
+    while input.len() < 8 << 20 {
+        input.push_str(sample);
+        input.push('\n');
+    }
+    let time = |lex: &dyn Fn() -> Vec<Token>| {
+        let mut best = f64::MAX;
+        let mut tokens = Vec::new();
+        for _ in 0..5 {
+            let start = Instant::now();
+            tokens = lex();
+            best = best.min(start.elapsed().as_secs_f64() * 1000.0);
+        }
+        (best, tokens)
+    };
+    let (logos_ms, logos) = time(&|| lexer::Lexer::new(&input).tokenize());
+    let (hand_ms, hand) = time(&|| Lexer::new(&input).tokenize());
+    let mb = input.len() as f64 / (1 << 20) as f64;
+    println!("=== Lexer bench: {:.1} MB, {} tokens ===", mb, logos.len());
+    for (name, ms) in [("logos", logos_ms), ("hand", hand_ms)] {
+        println!("{:<8} {:>10.2} ms {:>10.1} MB/s", name, ms, mb / ms * 1000.0);
+    }
+    if let Some(i) = (0..logos.len().max(hand.len())).find(|&i| logos.get(i) != hand.get(i)) {
+        println!("token streams differ at #{}: logos {:?}, hand {:?}", i, logos.get(i), hand.get(i));
+    }
+    let (mut a, mut b) = (lexer::Lexer::new(&input), Lexer::new(&input));
+    a.tokenize();
+    b.tokenize();
+    if a.comments() != b.comments() {
+        println!("comments differ: logos {}, hand {}", a.comments().len(), b.comments().len());
+    }
+}
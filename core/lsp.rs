@@ -0,0 +1,701 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use crate::ast::{ExternFn, Function, Import, Program, StructDef};
+use crate::format;
+use crate::host::HostFunction;
+use crate::interpreter::BUILTINS;
+use crate::json::{self, Json};
+use crate::lexer::{Lexer, Span, Token};
+use crate::modules::{self, SourceMap};
+use crate::parser::Parser;
+use crate::sema::{self, type_name, Binding, Severity};
+
+// `core lsp` — Language Server Protocol поверх stdio (JSON-RPC с заголовком Content-Length).
+// Документ приходит целиком (didOpen/didChange). После каждого изменения: лексер и парсер
+// самого файла, затем загрузка с импортами (modules.rs) и sema — ошибки и предупреждения
+// уходят клиенту в publishDiagnostics. Последний удачный разбор хранится: hover и definition
+// работают, только пока он совпадает с текстом, а documentSymbol и completion берут его и
+// отставшим — пока правка не разбирается. Позиции LSP — строка с 0 и столбец в UTF-16.
+
+const KEYWORDS: &[&str] = &[
+    "fn", "let", "if", "else", "while", "for", "in", "step", "match", "import", "use", "extern", "struct", "true",
+    "false", "null",
+];
+
+// коды ошибок JSON-RPC
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const REQUEST_FAILED: i64 = -32803;
+
+// SymbolKind и CompletionItemKind из спецификации
+const SYMBOL_FUNCTION: i64 = 12;
+const SYMBOL_STRUCT: i64 = 23;
+const ITEM_FUNCTION: i64 = 3;
+const ITEM_VARIABLE: i64 = 6;
+const ITEM_KEYWORD: i64 = 14;
+const ITEM_STRUCT: i64 = 22;
+
+struct Document {
+    text: String,
+    tokens: Vec<(Token, Span)>,
+    analysis: Option<Analysis>,
+}
+
+// Программа со всеми импортами; смещения главного файла в ней сдвинуты на base.
+struct Analysis {
+    text: String,
+    program: Program,
+    sources: SourceMap,
+    base: usize,
+}
+
+// На что указывает имя под курсором.
+enum Symbol<'a> {
+    Local(Binding),
+    Function(&'a Function),
+    Extern(&'a ExternFn),
+    Struct(&'a StructDef),
+}
+
+struct Server {
+    hosts: Vec<ExternFn>,
+    documents: HashMap<String, Document>,
+    output: Box<dyn Write>,
+}
+
+/// Обслуживает клиента до уведомления exit или закрытия stdin.
+pub fn run(hosts: &[HostFunction]) {
+    serve(hosts, &mut io::stdin().lock(), Box::new(io::stdout()));
+}
+
+fn serve(hosts: &[HostFunction], input: &mut dyn BufRead, output: Box<dyn Write>) {
+    let mut server =
+        Server { hosts: hosts.iter().map(|h| h.sig.clone()).collect(), documents: HashMap::new(), output };
+    while let Some(msg) = json::read_message(input) {
+        // ответы клиента на наши запросы не нужны: мы их не шлём
+        let Some(method) = msg.get("method").and_then(Json::as_str) else { continue };
+        let params = msg.get("params").cloned().unwrap_or(Json::Null);
+        match msg.get("id") {
+            Some(id) => {
+                let reply = match server.request(method, &params) {
+                    Ok(result) => ("result", result),
+                    Err((code, message)) => {
+                        ("error", Json::object(vec![("code", code.into()), ("message", message.into())]))
+                    }
+                };
+                server.send(vec![("id", id.clone()), reply]);
+            }
+            None if method == "exit" => return,
+            None => server.notify(method, &params),
+        }
+    }
+}
+
+impl Server {
+    fn send(&mut self, fields: Vec<(&str, Json)>) {
+        let mut message = vec![("jsonrpc", Json::from("2.0"))];
+        message.extend(fields);
+        json::write_message(&mut *self.output, &Json::object(message));
+    }
+
+    fn publish(&mut self, uri: &str, diagnostics: Vec<Json>) {
+        let params = Json::object(vec![("uri", uri.into()), ("diagnostics", diagnostics.into())]);
+        self.send(vec![("method", "textDocument/publishDiagnostics".into()), ("params", params)]);
+    }
+
+    fn request(&mut self, method: &str, params: &Json) -> Result<Json, (i64, String)> {
+        match method {
+            "initialize" => {
+                let capabilities = Json::object(vec![
+                    // 1 — документ присылается целиком
+                    ("textDocumentSync", 1i64.into()),
+                    ("hoverProvider", true.into()),
+                    ("definitionProvider", true.into()),
+                    ("documentSymbolProvider", true.into()),
+                    ("completionProvider", Json::object(vec![("triggerCharacters", vec![Json::from(":")].into())])),
+                    ("documentFormattingProvider", true.into()),
+                ]);
+                let info = Json::object(vec![("name", "core lsp".into())]);
+                Ok(Json::object(vec![("capabilities", capabilities), ("serverInfo", info)]))
+            }
+            "shutdown" => Ok(Json::Null),
+            "textDocument/hover" => Ok(self.hover(params).unwrap_or(Json::Null)),
+            "textDocument/definition" => Ok(self.definition(params).unwrap_or(Json::Null)),
+            "textDocument/documentSymbol" => Ok(self.symbols(params).unwrap_or(Json::Null)),
+            "textDocument/completion" => Ok(self.completion(params).unwrap_or(Json::Null)),
+            "textDocument/formatting" => self.formatting(params),
+            _ => Err((METHOD_NOT_FOUND, format!("unsupported request {}", method))),
+        }
+    }
+
+    fn notify(&mut self, method: &str, params: &Json) {
+        let doc = params.get("textDocument");
+        let Some(uri) = doc.and_then(|d| d.get("uri")).and_then(Json::as_str) else { return };
+        match method {
+            "textDocument/didOpen" => {
+                if let Some(text) = doc.and_then(|d| d.get("text")).and_then(Json::as_str) {
+                    self.update(uri, text.to_string());
+                }
+            }
+            // при полной синхронизации последнее изменение — весь текст
+            "textDocument/didChange" => {
+                let changes = params.get("contentChanges").and_then(Json::as_array).unwrap_or(&[]);
+                if let Some(text) = changes.last().and_then(|c| c.get("text")).and_then(Json::as_str) {
+                    self.update(uri, text.to_string());
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                self.publish(uri, Vec::new());
+            }
+            _ => {}
+        }
+    }
+
+    // Новый текст документа: разбор, проверки и диагностика.
+    fn update(&mut self, uri: &str, text: String) {
+        let tokens = Lexer::new(&text).tokenize_spanned();
+        let mut diagnostics = Vec::new();
+        for (_, span) in tokens.iter().filter(|(t, _)| *t == Token::Error) {
+            let bad = &text[span.clone()];
+            let message = if bad.starts_with("/*") {
+                "unterminated block comment".to_string()
+            } else {
+                format!("invalid token `{}`", bad)
+            };
+            diagnostics.push(diagnostic(&text, span, Severity::Error, &message));
+        }
+
+        let mut parser = Parser::new(tokens.clone());
+        let mut analysis = None;
+        if parser.parse_program().is_none() {
+            // на плохом токене парсер и встаёт — об этом уже сказал лексер
+            let (span, message) = parser.error();
+            if !tokens.iter().any(|(t, s)| *t == Token::Error && *s == span) {
+                diagnostics.push(diagnostic(&text, &span, Severity::Error, &format!("parse error: {}", message)));
+            }
+        } else {
+            match modules::load_source(&uri_path(uri), text.clone(), &self.hosts) {
+                Ok((program, sources)) => {
+                    let base = sources.root().base;
+                    for d in sema::check_program(&program) {
+                        // ошибки импортированных модулей показываются в их собственных файлах
+                        if d.span.start >= base && d.span.end <= base + text.len() {
+                            let span = d.span.start - base..d.span.end - base;
+                            diagnostics.push(diagnostic(&text, &span, d.severity, &d.message));
+                        }
+                    }
+                    analysis = Some(Analysis { text: text.clone(), program, sources, base });
+                }
+                Err(e) => diagnostics.push(diagnostic(&text, &(0..0), Severity::Error, &e)),
+            }
+        }
+        self.publish(uri, diagnostics);
+
+        let previous = self.documents.remove(uri).and_then(|d| d.analysis);
+        self.documents.insert(uri.to_string(), Document { text, tokens, analysis: analysis.or(previous) });
+    }
+
+    // Документ и смещение курсора в нём.
+    fn locate<'a>(&'a self, params: &'a Json) -> Option<(&'a str, &'a Document, usize)> {
+        let uri = params.get("textDocument")?.get("uri")?.as_str()?;
+        let doc = self.documents.get(uri)?;
+        Some((uri, doc, offset(&doc.text, params.get("position")?)))
+    }
+
+    fn hover(&self, params: &Json) -> Option<Json> {
+        let (_, doc, at) = self.locate(params)?;
+        let analysis = doc.analysis.as_ref().filter(|a| a.text == doc.text)?;
+        let (name, span) = word_at(&doc.tokens, at)?;
+        let (code, docs) = match analysis.resolve(&name, span.start)? {
+            Symbol::Local(b) => match &b.ty {
+                Some(ty) => (format!("{}: {}", b.name, type_name(ty)), None),
+                None => (b.name.clone(), None),
+            },
+            Symbol::Function(f) => (format::show_header(f), f.doc.clone()),
+            Symbol::Extern(e) => (format::show_extern(e), None),
+            Symbol::Struct(s) => (format::show_struct(s), s.doc.clone()),
+        };
+        let mut value = format!("```\n{}\n```", code);
+        if let Some(docs) = docs {
+            value.push_str("\n\n");
+            value.push_str(&docs);
+        }
+        let contents = Json::object(vec![("kind", "markdown".into()), ("value", value.into())]);
+        Some(Json::object(vec![("contents", contents), ("range", range(&doc.text, &span))]))
+    }
+
+    fn definition(&self, params: &Json) -> Option<Json> {
+        let (uri, doc, at) = self.locate(params)?;
+        let analysis = doc.analysis.as_ref().filter(|a| a.text == doc.text)?;
+        let (name, span) = word_at(&doc.tokens, at)?;
+        let (def, short) = match analysis.resolve(&name, span.start)? {
+            // переменная — её имя в операторе, который её ввёл
+            Symbol::Local(b) => {
+                let from = b.at - analysis.base;
+                let (_, span) = doc.tokens.iter().find(|(t, s)| s.start >= from && *t == Token::Ident(b.name.clone()))?;
+                return Some(location(uri, &doc.text, span));
+            }
+            Symbol::Function(f) => (f.span.clone(), f.name.clone()),
+            Symbol::Extern(e) => (e.span.clone(), e.name.clone()),
+            Symbol::Struct(s) => (s.span.clone(), s.name.clone()),
+        };
+        // у функций хоста места в исходнике нет
+        if def.is_empty() {
+            return None;
+        }
+        let short = short.rsplit("::").next().unwrap_or(&short).to_string();
+        let (idx, _, _) = analysis.sources.position(def.start)?;
+        let file = &analysis.sources.files()[idx];
+        let rel = def.start - file.base..def.end - file.base;
+        let start = rel.start + file.text[rel.clone()].find(&short).unwrap_or(0);
+        let span = start..start + short.len();
+        if def.start >= analysis.base && def.end <= analysis.base + analysis.text.len() {
+            Some(location(uri, &doc.text, &span))
+        } else if file.path.to_string_lossy().starts_with('<') {
+            // прелюдия встроена в бинарь
+            None
+        } else {
+            Some(location(&path_uri(&file.path), &file.text, &span))
+        }
+    }
+
+    fn symbols(&self, params: &Json) -> Option<Json> {
+        let uri = params.get("textDocument")?.get("uri")?.as_str()?;
+        let analysis = self.documents.get(uri)?.analysis.as_ref()?;
+        let mut items: Vec<(usize, Json)> = Vec::new();
+        let mut add = |name: &str, kind: i64, detail: String, span: &Span| {
+            let Some(rel) = analysis.local(span) else { return };
+            let start = rel.start + analysis.text[rel.clone()].find(name).unwrap_or(0);
+            items.push((
+                rel.start,
+                Json::object(vec![
+                    ("name", name.into()),
+                    ("detail", detail.into()),
+                    ("kind", kind.into()),
+                    ("range", range(&analysis.text, &rel)),
+                    ("selectionRange", range(&analysis.text, &(start..start + name.len()))),
+                ]),
+            ));
+        };
+        for f in &analysis.program.functions {
+            add(&f.name, SYMBOL_FUNCTION, format::show_header(f), &f.span);
+        }
+        for e in &analysis.program.externs {
+            add(&e.name, SYMBOL_FUNCTION, format::show_extern(e), &e.span);
+        }
+        for s in &analysis.program.structs {
+            add(&s.name, SYMBOL_STRUCT, String::new(), &s.span);
+        }
+        items.sort_by_key(|(start, _)| *start);
+        Some(items.into_iter().map(|(_, item)| item).collect::<Vec<_>>().into())
+    }
+
+    // Ключевые слова, встроенные функции и имена, видимые в позиции курсора.
+    fn completion(&self, params: &Json) -> Option<Json> {
+        let (_, doc, at) = self.locate(params)?;
+        // после `.` нужны поля, а их типы мы знаем не всегда — лучше ничего;
+        // недописанное имя под курсором пропускаем
+        let mut before = doc.tokens.iter().rev().filter(|(_, s)| s.end <= at);
+        let prev = match before.next() {
+            Some((Token::Ident(_), s)) if s.end == at => before.next(),
+            prev => prev,
+        };
+        if matches!(prev, Some((Token::Dot, _))) {
+            return Some(Json::Array(Vec::new()));
+        }
+        let mut items: Vec<(String, i64, String)> = Vec::new();
+        items.extend(KEYWORDS.iter().map(|k| (k.to_string(), ITEM_KEYWORD, String::new())));
+        items.extend(BUILTINS.iter().map(|b| (b.to_string(), ITEM_FUNCTION, "builtin".to_string())));
+        if let Some(a) = &doc.analysis {
+            // переменные — объявленные выше курсора; у повторного let тип последнего
+            if let Some(func) = a.enclosing(at) {
+                for b in sema::bindings(&a.program, func).into_iter().filter(|b| b.at <= at + a.base) {
+                    let detail = b.ty.as_ref().map(type_name).unwrap_or_default();
+                    items.retain(|(label, kind, _)| !(*label == b.name && *kind == ITEM_VARIABLE));
+                    items.push((b.name, ITEM_VARIABLE, detail));
+                }
+            }
+            // `use` даёт короткое имя и перекрывает прелюдию, а она видна без `prelude::`
+            for import in &a.program.imports {
+                if let Import::Use { module, item, .. } = import {
+                    let full = format!("{}::{}", module, item);
+                    if let Some(f) = a.program.functions.iter().find(|f| f.name == full) {
+                        items.push((item.clone(), ITEM_FUNCTION, format::show_header(f)));
+                    }
+                }
+            }
+            for f in &a.program.functions {
+                let name = f.name.strip_prefix("prelude::").unwrap_or(&f.name);
+                items.push((name.to_string(), ITEM_FUNCTION, format::show_header(f)));
+            }
+            items.extend(a.program.externs.iter().map(|e| (e.name.clone(), ITEM_FUNCTION, format::show_extern(e))));
+            items.extend(a.program.structs.iter().map(|s| (s.name.clone(), ITEM_STRUCT, String::new())));
+        }
+        let mut seen = Vec::new();
+        let mut list = Vec::new();
+        for (label, kind, detail) in items {
+            if seen.contains(&label) {
+                continue;
+            }
+            seen.push(label.clone());
+            list.push(Json::object(vec![("label", label.into()), ("kind", kind.into()), ("detail", detail.into())]));
+        }
+        Some(list.into())
+    }
+
+    // Весь документ одной правкой; без изменений — пустой список.
+    fn formatting(&self, params: &Json) -> Result<Json, (i64, String)> {
+        let uri = params.get("textDocument").and_then(|d| d.get("uri")).and_then(Json::as_str);
+        let doc = uri.and_then(|u| self.documents.get(u)).ok_or((INVALID_PARAMS, "unknown document".to_string()))?;
+        let formatted = format::format_source(&doc.text)
+            .ok_or((REQUEST_FAILED, "the document has syntax errors".to_string()))?;
+        if formatted == doc.text {
+            return Ok(Json::Array(Vec::new()));
+        }
+        let edit = Json::object(vec![("range", range(&doc.text, &(0..doc.text.len()))), ("newText", formatted.into())]);
+        Ok(vec![edit].into())
+    }
+}
+
+impl Analysis {
+    // Span главного файла в смещениях документа; None — объявление из другого файла.
+    fn local(&self, span: &Span) -> Option<Span> {
+        let end = self.base + self.text.len();
+        (span.start >= self.base && span.end <= end && !span.is_empty())
+            .then(|| span.start - self.base..span.end - self.base)
+    }
+
+    // Функция главного файла, в которой курсор: последняя, начавшаяся до него
+    // (разбор может отставать от текста, так что конец не проверяем).
+    fn enclosing(&self, at: usize) -> Option<&Function> {
+        self.program
+            .functions
+            .iter()
+            .filter(|f| self.local(&f.span).is_some_and(|s| s.start <= at))
+            .max_by_key(|f| f.span.start)
+    }
+
+    // Как имя разрешают бэкенды: сначала переменная функции, потом функция, `use`,
+    // прелюдия, extern и структура.
+    fn resolve(&self, name: &str, at: usize) -> Option<Symbol<'_>> {
+        if !name.contains("::") {
+            if let Some(func) = self.enclosing(at) {
+                let same: Vec<Binding> =
+                    sema::bindings(&self.program, func).into_iter().filter(|b| b.name == name).collect();
+                let before = same.iter().rev().find(|b| b.at <= at + self.base);
+                if let Some(b) = before.or(same.first()) {
+                    return Some(Symbol::Local(b.clone()));
+                }
+            }
+        }
+        let mut names = vec![name.to_string()];
+        for import in &self.program.imports {
+            if let Import::Use { module, item, .. } = import {
+                if item == name {
+                    names.push(format!("{}::{}", module, item));
+                }
+            }
+        }
+        names.push(format!("prelude::{}", name));
+        let p = &self.program;
+        names.iter().find_map(|n| {
+            p.functions
+                .iter()
+                .find(|f| f.name == *n)
+                .map(Symbol::Function)
+                .or_else(|| p.externs.iter().find(|e| e.name == *n).map(Symbol::Extern))
+                .or_else(|| p.structs.iter().find(|s| s.name == *n).map(Symbol::Struct))
+        })
+    }
+}
+
+// Имя под курсором (курсор может стоять и сразу за ним) вместе с путём `math::abs`;
+// поле `p.x` не считается.
+fn word_at(tokens: &[(Token, Span)], at: usize) -> Option<(String, Span)> {
+    let is_ident = |i: usize| matches!(tokens.get(i), Some((Token::Ident(_), _)));
+    let is_sep = |i: usize| matches!(tokens.get(i), Some((Token::ColonColon, _)));
+    let i = tokens.iter().position(|(t, s)| matches!(t, Token::Ident(_)) && s.start <= at && at <= s.end)?;
+    let mut first = i;
+    while first >= 2 && is_sep(first - 1) && is_ident(first - 2) {
+        first -= 2;
+    }
+    let mut last = i;
+    while is_sep(last + 1) && is_ident(last + 2) {
+        last += 2;
+    }
+    if first > 0 && matches!(tokens[first - 1].0, Token::Dot) {
+        return None;
+    }
+    let name: Vec<&str> = tokens[first..=last]
+        .iter()
+        .filter_map(|(t, _)| if let Token::Ident(n) = t { Some(n.as_str()) } else { None })
+        .collect();
+    Some((name.join("::"), tokens[first].1.start..tokens[last].1.end))
+}
+
+fn diagnostic(text: &str, span: &Span, severity: Severity, message: &str) -> Json {
+    let severity: i64 = if severity == Severity::Error { 1 } else { 2 };
+    Json::object(vec![
+        ("range", range(text, span)),
+        ("severity", severity.into()),
+        ("source", "core".into()),
+        ("message", message.into()),
+    ])
+}
+
+fn location(uri: &str, text: &str, span: &Span) -> Json {
+    Json::object(vec![("uri", uri.into()), ("range", range(text, span))])
+}
+
+fn range(text: &str, span: &Span) -> Json {
+    Json::object(vec![("start", position(text, span.start)), ("end", position(text, span.end))])
+}
+
+// Байтовое смещение -> позиция LSP.
+fn position(text: &str, offset: usize) -> Json {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count();
+    let line_start = before.rfind('\n').map_or(0, |nl| nl + 1);
+    let character: usize = before[line_start..].chars().map(char::len_utf16).sum();
+    Json::object(vec![("line", line.into()), ("character", character.into())])
+}
+
+// Позиция LSP -> байтовое смещение; за концом строки — её конец.
+fn offset(text: &str, pos: &Json) -> usize {
+    let line = pos.get("line").and_then(Json::as_i64).unwrap_or(0).max(0) as usize;
+    let character = pos.get("character").and_then(Json::as_i64).unwrap_or(0).max(0) as usize;
+    let line_start: usize = text.split_inclusive('\n').take(line).map(str::len).sum();
+    let mut units = 0;
+    for (i, c) in text[line_start..].char_indices() {
+        if units >= character || c == '\n' {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+// `file:///home/a%20b/x.src` -> путь; импорты ищутся рядом с ним.
+fn uri_path(uri: &str) -> PathBuf {
+    let raw = uri.strip_prefix("file://").unwrap_or(uri).as_bytes();
+    let mut bytes = Vec::new();
+    let mut i = 0;
+    while i < raw.len() {
+        let hex = raw.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok());
+        match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+            Some(b) if raw[i] == b'%' => {
+                bytes.push(b);
+                i += 3;
+            }
+            _ => {
+                bytes.push(raw[i]);
+                i += 1;
+            }
+        }
+    }
+    PathBuf::from(String::from_utf8_lossy(&bytes).to_string())
+}
+
+fn path_uri(path: &Path) -> String {
+    let mut uri = "file://".to_string();
+    for b in path.to_string_lossy().bytes() {
+        if b.is_ascii_alphanumeric() || b"/-._~".contains(&b) {
+            uri.push(b as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", b));
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::testutil::Output;
+
+    const URI: &str = "file:///tmp/core-lsp-test/main.src";
+
+    const SRC: &str = "struct P { x: i64 }
+/// Удвоение.
+fn twice(n: i64) -> i64 { n * 2 }
+fn main() {
+    let p = P { x: 1 };
+    twice(p.x)
+}
+";
+
+    // Сообщения клиента: (метод, params); запросы — с id по порядку, уведомления — без.
+    // Результат — всё, что прислал сервер.
+    fn session(messages: &[(&str, Json)]) -> Vec<Json> {
+        let mut input = Vec::new();
+        for (id, (method, params)) in messages.iter().enumerate() {
+            let mut fields = vec![("jsonrpc", "2.0".into()), ("method", (*method).into()), ("params", params.clone())];
+            if !method.starts_with("textDocument/did") && *method != "exit" {
+                fields.push(("id", id.into()));
+            }
+            json::write_message(&mut input, &Json::object(fields));
+        }
+        let output = Output::default();
+        serve(&[], &mut Cursor::new(input), Box::new(output.clone()));
+        let text = output.text();
+        let mut reader = Cursor::new(text.as_bytes());
+        std::iter::from_fn(|| json::read_message(&mut reader)).collect()
+    }
+
+    fn open(text: &str) -> (&'static str, Json) {
+        let doc = Json::object(vec![("uri", URI.into()), ("text", text.into())]);
+        ("textDocument/didOpen", Json::object(vec![("textDocument", doc)]))
+    }
+
+    fn change(text: &str) -> (&'static str, Json) {
+        let changes = vec![Json::object(vec![("text", text.into())])];
+        (
+            "textDocument/didChange",
+            Json::object(vec![("textDocument", uri()), ("contentChanges", changes.into())]),
+        )
+    }
+
+    fn uri() -> Json {
+        Json::object(vec![("uri", URI.into())])
+    }
+
+    fn at(method: &str, line: usize, character: usize) -> (&str, Json) {
+        let position = Json::object(vec![("line", line.into()), ("character", character.into())]);
+        (method, Json::object(vec![("textDocument", uri()), ("position", position)]))
+    }
+
+    fn reply(messages: &[Json], id: usize) -> &Json {
+        let id = Json::from(id);
+        let msg = messages.iter().find(|m| m.get("id") == Some(&id)).expect("reply");
+        msg.get("result").or(msg.get("error")).expect("result or error")
+    }
+
+    // (строка, сообщение) каждой публикации диагностики по порядку
+    fn diagnostics(messages: &[Json]) -> Vec<Vec<(i64, String)>> {
+        messages
+            .iter()
+            .filter(|m| m.get("method") == Some(&"textDocument/publishDiagnostics".into()))
+            .map(|m| {
+                let list = m.get("params").and_then(|p| p.get("diagnostics")).and_then(Json::as_array).unwrap_or(&[]);
+                list.iter()
+                    .map(|d| {
+                        let line = d.get("range").and_then(|r| r.get("start")).and_then(|s| s.get("line"));
+                        let message = d.get("message").and_then(Json::as_str).unwrap_or("");
+                        (line.and_then(Json::as_i64).unwrap_or(-1), message.to_string())
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn diagnostics_follow_the_document() {
+        let messages = session(&[
+            open("fn main() {\n    let x = 1 $ 2;\n    x\n}"),
+            change("fn main() {\n    let x = ;\n}"),
+            change("fn main() {\n    let s: str = 1;\n    0\n}"),
+            change("fn main() { 0 }"),
+            ("textDocument/didClose", Json::object(vec![("textDocument", uri())])),
+            ("exit", Json::Null),
+            // после exit сервер уже не читает
+            ("shutdown", Json::Null),
+        ]);
+        assert_eq!(
+            diagnostics(&messages),
+            [
+                vec![(1, "invalid token `$`".to_string())],
+                vec![(1, "parse error: unexpected token Semicolon".to_string())],
+                vec![(1, "`s` is declared as str, but the value is i64".to_string())],
+                vec![],
+                vec![],
+            ]
+        );
+        assert_eq!(messages.len(), 5);
+    }
+
+    #[test]
+    fn navigation_and_completion() {
+        let messages = session(&[
+            ("initialize", Json::object(vec![])),
+            open(SRC),
+            at("textDocument/hover", 5, 5),
+            at("textDocument/hover", 5, 10),
+            at("textDocument/definition", 5, 6),
+            at("textDocument/definition", 5, 10),
+            ("textDocument/documentSymbol", Json::object(vec![("textDocument", uri())])),
+            at("textDocument/completion", 5, 4),
+            at("textDocument/completion", 5, 12),
+            ("textDocument/formatting", Json::object(vec![("textDocument", uri())])),
+            ("textDocument/rename", Json::object(vec![])),
+            ("shutdown", Json::Null),
+        ]);
+        let capabilities = reply(&messages, 0).get("capabilities").expect("capabilities");
+        assert_eq!(capabilities.get("hoverProvider"), Some(&true.into()));
+
+        let hover = |id| reply(&messages, id).get("contents").and_then(|c| c.get("value")).cloned();
+        assert_eq!(hover(2), Some("```\nfn twice(n: i64) -> i64\n```\n\nУдвоение.".into()));
+        assert_eq!(hover(3), Some("```\np: P\n```".into()));
+
+        // имя в объявлении функции и в let
+        let name = |before: &str, word: &str| {
+            let start = SRC.find(before).expect("declaration") + before.len();
+            location(URI, SRC, &(start..start + word.len()))
+        };
+        assert_eq!(reply(&messages, 4), &name("fn ", "twice"));
+        assert_eq!(reply(&messages, 5), &name("let ", "p"));
+
+        let names: Vec<String> = reply(&messages, 6)
+            .as_array()
+            .expect("symbols")
+            .iter()
+            .map(|s| s.get("name").and_then(Json::as_str).unwrap_or("").to_string())
+            .collect();
+        assert_eq!(names, ["P", "twice", "main"]);
+
+        let labels = |id| -> Vec<String> {
+            let items = reply(&messages, id).as_array().expect("items");
+            items.iter().map(|i| i.get("label").and_then(Json::as_str).unwrap_or("").to_string()).collect()
+        };
+        let items = labels(7);
+        for label in ["p", "twice", "abs", "while", "println", "P"] {
+            assert!(items.iter().any(|i| i == label), "no {} in {:?}", label, items);
+        }
+        assert!(labels(8).is_empty());
+
+        let edits = reply(&messages, 9).as_array().expect("edits");
+        assert_eq!(edits.len(), 1);
+        assert_eq!(reply(&messages, 10).get("code"), Some(&METHOD_NOT_FOUND.into()));
+        assert_eq!(reply(&messages, 11), &Json::Null);
+    }
+
+    #[test]
+    fn positions_and_uris() {
+        // «ё» — одна единица UTF-16 и два байта, 😀 — две единицы и четыре байта
+        let text = "ab\nё😀x\n";
+        let pos = |line: usize, character: usize| {
+            Json::object(vec![("line", line.into()), ("character", character.into())])
+        };
+        assert_eq!(offset(text, &pos(1, 3)), 9);
+        assert_eq!(position(text, 9), pos(1, 3));
+        assert_eq!(offset(text, &pos(0, 10)), 2);
+        assert_eq!(offset(text, &pos(5, 0)), text.len());
+
+        let path = uri_path("file:///home/a%20b/%D1%91.src");
+        assert_eq!(path, PathBuf::from("/home/a b/ё.src"));
+        assert_eq!(path_uri(&path), "file:///home/a%20b/%D1%91.src");
+    }
+
+    #[test]
+    fn words_under_the_cursor() {
+        let tokens = Lexer::new("math::abs(p.x) + y").tokenize_spanned();
+        assert_eq!(word_at(&tokens, 7), Some(("math::abs".to_string(), 0..9)));
+        assert_eq!(word_at(&tokens, 0), Some(("math::abs".to_string(), 0..9)));
+        assert_eq!(word_at(&tokens, 12), None);
+        assert_eq!(word_at(&tokens, 18), Some(("y".to_string(), 17..18)));
+    }
+}
@@ -1,70 +1,578 @@
 mod lexer;
+mod lexer_hand;
 mod parser;
 mod ast;
 mod interpreter;
 mod codegen;
+mod sema;
+mod modules;
+mod host;
+mod vm;
+mod ir;
+mod ir_llvm;
+mod ir_eval;
+mod opt;
+mod format;
+mod dot;
+mod debug_info;
+mod json;
+mod debugger;
+mod dap;
+mod lsp;
+mod trace;
+#[cfg(test)]
+mod testutil;
 
 use inkwell::context::Context;
 
+use std::collections::HashMap;
 use std::env;
-use std::fs;
+use std::fs::File;
+use std::io::{self, LineWriter, Write};
+use std::path::Path;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use crate::lexer::Lexer;
-use crate::parser::Parser;
 use crate::interpreter::Interpreter;
 use crate::codegen::Codegen;
+use crate::sema::Severity;
+use crate::ast::{ExternFn, ExternType, Program, Type};
+use crate::host::HostFunction;
+use crate::interpreter::{Outcome, RuntimeError, Value};
+use crate::modules::SourceMap;
 
-/// Если запускаешь без аргументов — используется demo-программа.
-/// Если передаёшь путь до файла — выполняем его.
-fn main() {
-    let args: Vec<String> = env::args().collect();
+/// `core [--backend interp|vm] [--bench] [--bench-lexer] [--opt passes] [--ir] [--ssa] [--ssa-check] [--dot ast|cfg] [-g] [--jit] [-o out]
+///      [--trace jsonl|chrome] [--trace-out file] [--profile] [file]`
+/// `core debug [--json] [file]`, `core dap`, `core lsp`
+#[derive(Default)]
+struct Options {
+    path: Option<String>,
+    backend: Backend,
+    // вместо обычного запуска — замерить время main во всех бэкендах
+    bench: bool,
+    // сравнить скорость logos-лексера и прежнего посимвольного (lexer_hand.rs)
+    bench_lexer: bool,
+    // после интерпретатора ещё и выполнить скомпилированный main в JIT
+    jit: bool,
+    // собрать исполняемый файл (AOT)
+    output: Option<String>,
+    // DWARF в модуле Codegen — для gdb/lldb на собранном файле
+    debug: bool,
+    // LLVM-модуль строить через свой IR (ir.rs -> ir_llvm.rs), а не прямо из AST
+    ir: bool,
+    // как ir.rs строит переменные: alloca/load/store или сразу SSA (`--ssa`)
+    ssa: ir::SsaMode,
+    // построить IR обоими способами и сравнить результаты выполнения
+    ssa_check: bool,
+    // проходы opt.rs перед любым бэкендом: `--opt all` или `--opt fold,dce`
+    passes: Vec<opt::Pass>,
+    // вместо запуска — только граф в DOT на stdout (для GUI и картинок)
+    dot: Option<DotKind>,
+    // `core debug`: выполнить под пошаговым отладчиком (терминал или JSON для GUI)
+    debugger: Option<debugger::Protocol>,
+    // `core dap`: Debug Adapter Protocol на stdio, программу называет клиент в launch
+    dap: bool,
+    // `core lsp`: Language Server Protocol на stdio для редакторов
+    lsp: bool,
+    // трасса интерпретатора вместо строк `let x = ...`: формат и файл (по умолчанию stderr)
+    trace: Option<trace::Format>,
+    trace_out: Option<String>,
+    // счётчики по строкам и функциям, отчёт после запуска
+    profile: bool,
+}
 
-    let input = if args.len() >= 2 {
-        let path = &args[1];
-        match fs::read_to_string(path) {
-            Ok(s) => s,
-            Err(e) => {
-                eprintln!("failed to read {}: {}", path, e);
-                return;
+enum DotKind {
+    // дерево AST
+    Ast,
+    // граф потока управления функций после Codegen
+    Cfg,
+}
+
+#[derive(Default)]
+enum Backend {
+    // обход AST (Interpreter)
+    #[default]
+    Interp,
+    // байткод и стековая VM (vm.rs)
+    Vm,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut opts = Options::default();
+    let mut args = env::args().skip(1).peekable();
+    match args.peek().map(String::as_str) {
+        Some("debug") => opts.debugger = Some(debugger::Protocol::Terminal),
+        Some("dap") => opts.dap = true,
+        Some("lsp") => opts.lsp = true,
+        _ => {}
+    }
+    if opts.debugger.is_some() || opts.dap || opts.lsp {
+        args.next();
+    }
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" if opts.debugger.is_some() => opts.debugger = Some(debugger::Protocol::Json),
+            "--jit" => opts.jit = true,
+            "--bench" => opts.bench = true,
+            "--bench-lexer" => opts.bench_lexer = true,
+            "--ir" => opts.ir = true,
+            "--ssa" => {
+                opts.ir = true;
+                opts.ssa = ir::SsaMode::Direct;
+            }
+            "--ssa-check" => opts.ssa_check = true,
+            "--dot" => {
+                opts.dot = match args.next().as_deref() {
+                    Some("ast") => Some(DotKind::Ast),
+                    Some("cfg") => Some(DotKind::Cfg),
+                    _ => return Err("--dot expects ast or cfg".to_string()),
+                }
+            }
+            "--opt" => opts.passes = opt::Pass::parse_list(&args.next().ok_or("--opt expects a list of passes")?)?,
+            "--backend" => {
+                opts.backend = match args.next().as_deref() {
+                    Some("interp") => Backend::Interp,
+                    Some("vm") => Backend::Vm,
+                    _ => return Err("--backend expects interp or vm".to_string()),
+                }
             }
+            "--trace" => {
+                let format = args.next().ok_or("--trace expects jsonl or chrome")?;
+                opts.trace = Some(trace::Format::parse(&format).ok_or("--trace expects jsonl or chrome")?);
+            }
+            "--trace-out" => opts.trace_out = Some(args.next().ok_or("--trace-out expects a file name")?),
+            "--profile" => opts.profile = true,
+            "-g" => opts.debug = true,
+            "-o" => opts.output = Some(args.next().ok_or("-o expects a file name")?),
+            flag if flag.starts_with('-') => return Err(format!("unknown flag {}", flag)),
+            _ => opts.path = Some(arg),
+        }
+    }
+    if (opts.trace.is_some() || opts.profile) && matches!(opts.backend, Backend::Vm) {
+        return Err("--trace and --profile need the interp backend".to_string());
+    }
+    Ok(opts)
+}
+
+/// Если запускаешь без аргументов — используется demo-программа.
+/// Если передаёшь путь до файла — выполняем его (вместе с импортами).
+fn main() {
+    let opts = match parse_args() {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
         }
+    };
+
+    let hosts = host_functions();
+    if opts.dap {
+        dap::run(&hosts);
+        return;
+    }
+    if opts.lsp {
+        lsp::run(&hosts);
+        return;
+    }
+    let sigs: Vec<ExternFn> = hosts.iter().map(|h| h.sig.clone()).collect();
+    let loaded = if let Some(path) = &opts.path {
+        modules::load_file(Path::new(path), &sigs)
     } else {
         // demo program
-        r#"
+        let demo = r#"
         fn main() {
             let x = 0;
             while (x < 5) {
                 x = x + 1;
             }
+            print("x = ");
+            println(x);
             if (x == 5) {
-                42;
+                42
             } else {
-                0;
+                0
             }
         }
-        "#.to_string()
+        "#.to_string();
+        modules::load_source(Path::new("demo.src"), demo, &sigs)
     };
 
-    // LEXER
-    let mut lexer = Lexer::new(&input);
-    let tokens = lexer.tokenize();
-    println!("tokens {:?}", tokens);
-
-    // PARSER
-    let mut parser = Parser::new(tokens);
-    if let Some(func) = parser.parse_function() {
-        println!("AST: {:#?}", func);
-
-        // INTERPRETER
-        println!("\n=== Interpreter ===");
-        Interpreter::run_function(&func);
-
-        // CODEGEN -> LLVM IR (печатаем IR)
-        println!("\n=== LLVM IR (generated) ===");
-        let context = Context::create();
-        let mut codegen = Codegen::new(&context, "simple_module");
-        codegen.compile_function(&func);
-        codegen.dump_ir();
+    // LEXER + PARSER (импорты грузятся и переименовываются в modules.rs)
+    match loaded {
+        Ok((program, sources)) if opts.dot.is_some() => export_dot(&program, &sources, &opts, &hosts),
+        Ok((program, sources)) if opts.debugger.is_some() => start_debugger(&program, &sources, &opts, &hosts),
+        Ok((program, sources)) => {
+            if opts.bench_lexer {
+                lexer_hand::bench(&sources.root().text);
+                return;
+            }
+            let mut lexer = Lexer::new(&sources.root().text);
+            let tokens = lexer.tokenize();
+            println!("tokens {:?}", tokens);
+            println!("comments {:?}", lexer.comments());
+            println!("AST: {:#?}", program);
+            run(&program, &sources, &opts, &hosts);
+        }
+        Err(e) => println!("{}", e),
+    }
+}
+
+fn run(program: &Program, sources: &SourceMap, opts: &Options, hosts: &[HostFunction]) {
+    // SEMA: предупреждения печатаем, при ошибках дальше не идём
+    let diagnostics = sema::check_program(program);
+    for d in &diagnostics {
+        let kind = if d.severity == Severity::Error { "error" } else { "warning" };
+        eprintln!("{} at {}: {}", kind, sources.location(d.span.start), d.message);
+    }
+    if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+        return;
+    }
+
+    // OPT: что изменил каждый проход — диффом в stderr (прелюдию не показываем)
+    let optimized;
+    let program = if opts.passes.is_empty() {
+        program
     } else {
-        println!("Parser error");
+        let mut p = program.clone();
+        eprintln!("\n=== Optimizations ===");
+        for c in opt::optimize(&mut p, &opts.passes) {
+            if !c.function.starts_with("prelude::") {
+                eprintln!("--- {}: fn {}", c.pass.name(), c.function);
+                eprint!("{}", opt::diff(&c.before, &c.after));
+            }
+        }
+        optimized = p;
+        &optimized
+    };
+    if opts.bench {
+        bench(program, sources, hosts);
+        return;
+    }
+    if opts.ssa_check {
+        // расхождение — ненулевой код выхода, чтобы проверку можно было запускать в CI
+        if !ssa_check(program, sources, hosts) {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let exit = match opts.backend {
+        Backend::Interp => run_interpreter(program, sources, opts, hosts),
+        Backend::Vm => run_vm(program, sources, hosts),
+    };
+    // exit(n) в программе — код выхода процесса; до Codegen дело не доходит
+    if let Some(code) = exit {
+        io::stdout().flush().expect("flush stdout");
+        std::process::exit(code);
+    }
+    if !check_compiled(program, sources, hosts) {
+        return;
     }
+    if opts.ir {
+        run_ir(program, sources, opts, hosts);
+        return;
+    }
+
+    // CODEGEN -> LLVM IR (один модуль на всю программу со всеми импортами)
+    println!("\n=== LLVM IR (generated) ===");
+    let context = Context::create();
+    let mut codegen = Codegen::new(&context, "simple_module", sources);
+    for h in hosts {
+        codegen.register_host(h);
+    }
+    if opts.debug {
+        codegen.enable_debug_info();
+    }
+    codegen.compile_program(program);
+    codegen.dump_ir();
+
+    if let Some(out) = &opts.output {
+        match codegen.write_executable(Path::new(out)) {
+            Ok(()) => eprintln!("wrote {}", out),
+            Err(e) => eprintln!("aot error: {}", e),
+        }
+    }
+    // JIT последним: движок забирает модуль себе
+    if opts.jit {
+        println!("\n=== JIT ===");
+        io::stdout().flush().expect("flush stdout");
+        match codegen.run_jit() {
+            Ok(code) => eprintln!("main => {}", code),
+            Err(e) => eprintln!("jit error: {}", e),
+        }
+    }
+}
+
+// В stdout — только DOT, чтобы вывод можно было сразу отдать в `dot -Tsvg`.
+fn export_dot(program: &Program, sources: &SourceMap, opts: &Options, hosts: &[HostFunction]) {
+    match opts.dot {
+        Some(DotKind::Ast) => print!("{}", dot::ast(program)),
+        Some(DotKind::Cfg) => {
+            if !check_compiled(program, sources, hosts) {
+                return;
+            }
+            let context = Context::create();
+            let mut codegen = Codegen::new(&context, "simple_module", sources);
+            for h in hosts {
+                codegen.register_host(h);
+            }
+            codegen.compile_program(program);
+            print!("{}", dot::llvm_cfg(codegen.module()));
+        }
+        None => {}
+    }
+}
+
+// Ошибки sema для скомпилированного кода (check_program + функции хоста без адреса);
+// false — компилировать нельзя.
+fn check_compiled(program: &Program, sources: &SourceMap, hosts: &[HostFunction]) -> bool {
+    let errors: Vec<_> =
+        sema::check_compiled(program, hosts).into_iter().filter(|d| d.severity == Severity::Error).collect();
+    for d in &errors {
+        eprintln!("error at {}: {}", sources.location(d.span.start), d.message);
+    }
+    errors.is_empty()
+}
+
+// Под отладчиком — без дампов токенов/AST и трассы: stdout и stderr нужны сессии.
+fn start_debugger(program: &Program, sources: &SourceMap, opts: &Options, hosts: &[HostFunction]) {
+    if let Some(protocol) = opts.debugger {
+        debugger::run(program, sources, protocol, hosts);
+    }
+}
+
+// Some(n) — программа вызвала exit(n).
+fn run_interpreter(program: &Program, sources: &SourceMap, opts: &Options, hosts: &[HostFunction]) -> Option<i32> {
+    // INTERPRETER (трасса — в stderr, в stdout только вывод самой программы)
+    println!("\n=== Interpreter ===");
+    let mut interp = Interpreter::new();
+    for h in hosts {
+        interp.register_host(h);
+    }
+    // построчно: трассу видно, пока программа ещё работает
+    let out: Box<dyn Write> = match (&opts.trace, &opts.trace_out) {
+        (Some(_), Some(path)) => match File::create(path) {
+            Ok(f) => Box::new(LineWriter::new(f)),
+            Err(e) => {
+                eprintln!("failed to create {}: {}", path, e);
+                return None;
+            }
+        },
+        _ => Box::new(io::stderr()),
+    };
+    let recording = (opts.trace.is_some() || opts.profile).then(|| {
+        interp.set_trace(false);
+        trace::attach(&mut interp, sources, opts.trace.map(|f| (f, out)), opts.profile)
+    });
+    let exit = match interp.run_program(program) {
+        Ok(outcome) => {
+            for span in interp.leaks() {
+                eprintln!("leak: block allocated at {} was never freed", sources.location(span.start));
+            }
+            match outcome {
+                Outcome::Exit(code) => Some(code),
+                Outcome::Return(_) => None,
+            }
+        }
+        Err(e) => {
+            report(&e, sources);
+            None
+        }
+    };
+    if let Some(profile) = recording.and_then(trace::Recording::finish) {
+        eprint!("\n{}", profile);
+    }
+    exit
+}
+
+fn run_vm(program: &Program, sources: &SourceMap, hosts: &[HostFunction]) -> Option<i32> {
+    let bytecode = match vm::compile(program) {
+        Ok(bc) => bc,
+        Err(e) => {
+            eprintln!("vm error at {}: {}", sources.location(e.span.start), e);
+            return None;
+        }
+    };
+    eprintln!("\n=== Bytecode ===");
+    eprint!("{}", bytecode.disassemble(sources));
+    println!("\n=== VM ===");
+    let mut machine = vm::Vm::new(&bytecode);
+    for h in hosts {
+        machine.register_host(h);
+    }
+    match machine.run_main() {
+        Ok(Outcome::Exit(code)) => Some(code),
+        Ok(v) => {
+            eprintln!("main => {}", v);
+            None
+        }
+        Err(e) => {
+            report(&e, sources);
+            None
+        }
+    }
+}
+
+// AST -> свой IR -> проверка -> LLVM. Печатает оба дампа; дальше — как у Codegen.
+fn run_ir(program: &Program, sources: &SourceMap, opts: &Options, hosts: &[HostFunction]) {
+    let module = match ir::build(program, sources, opts.ssa) {
+        Ok(m) => m,
+        Err(e) => {
+            eprintln!("ir error at {}", e);
+            return;
+        }
+    };
+    println!("\n=== IR ===");
+    eprint!("{}", module);
+    let errors = ir::verify(&module);
+    for e in &errors {
+        eprintln!("ir verify: {}", e);
+    }
+    if !errors.is_empty() {
+        return;
+    }
+
+    println!("\n=== LLVM IR (from IR) ===");
+    let context = Context::create();
+    let llvm = ir_llvm::lower(&module, &context, "ir_module");
+    llvm.print_to_stderr();
+
+    if let Some(out) = &opts.output {
+        match codegen::write_executable(&llvm, Path::new(out)) {
+            Ok(()) => eprintln!("wrote {}", out),
+            Err(e) => eprintln!("aot error: {}", e),
+        }
+    }
+    if opts.jit {
+        println!("\n=== JIT ===");
+        io::stdout().flush().expect("flush stdout");
+        let natives: HashMap<String, usize> =
+            hosts.iter().filter_map(|h| h.native.map(|addr| (h.sig.name.clone(), addr))).collect();
+        match codegen::run_jit(&llvm, &natives) {
+            Ok(code) => eprintln!("main => {}", code),
+            Err(e) => eprintln!("jit error: {}", e),
+        }
+    }
+}
+
+// Оба способа построения SSA на одной программе: IR каждого проверяется verify
+// и выполняется ir_eval; вывод и код возврата должны совпасть. false — не совпали
+// (или IR не построился).
+fn ssa_check(program: &Program, sources: &SourceMap, hosts: &[HostFunction]) -> bool {
+    let mut natives = host::libc();
+    for h in hosts {
+        natives.insert(h.sig.name.clone(), h.call.clone());
+    }
+    let mut outcomes = Vec::new();
+    for mode in [ir::SsaMode::Memory, ir::SsaMode::Direct] {
+        let module = match ir::build(program, sources, mode) {
+            Ok(m) => m,
+            Err(e) => {
+                eprintln!("ir error at {}", e);
+                return false;
+            }
+        };
+        let errors = ir::verify(&module);
+        for e in &errors {
+            eprintln!("ir verify ({:?}): {}", mode, e);
+        }
+        if !errors.is_empty() {
+            return false;
+        }
+        let insts: usize = module.functions.iter().flat_map(|f| &f.blocks).map(|b| b.insts.len()).sum();
+        match ir_eval::run(&module, &natives) {
+            Ok(outcome) => {
+                eprintln!("{:?}: {} instructions, main => {}", mode, insts, outcome.code);
+                outcomes.push(outcome);
+            }
+            Err(e) => {
+                eprintln!("ir eval ({:?}): {}", mode, e);
+                return false;
+            }
+        }
+    }
+    if outcomes[0] == outcomes[1] {
+        eprintln!("ssa check: ok, {} bytes of identical output", outcomes[0].output.len());
+        true
+    } else {
+        eprintln!("ssa check: FAILED");
+        eprintln!("--- Memory (code {}):\n{}", outcomes[0].code, outcomes[0].output);
+        eprintln!("--- Direct (code {}):\n{}", outcomes[1].code, outcomes[1].output);
+        false
+    }
+}
+
+fn report(e: &RuntimeError, sources: &SourceMap) {
+    eprintln!("runtime error at {}: {}", sources.location(e.span.start), e);
+    for (note, span) in &e.notes {
+        eprintln!("  note: {} at {}", note, sources.location(span.start));
+    }
+}
+
+// Время выполнения main в каждом бэкенде. Вывод программы идёт как обычно (трижды),
+// таблица — в stderr. Время VM — без компиляции в байткод, время JIT — вместе с
+// компиляцией модуля в машинный код: run_jit их не разделяет.
+fn bench(program: &Program, sources: &SourceMap, hosts: &[HostFunction]) {
+    let mut rows: Vec<(&str, Result<f64, String>)> = Vec::new();
+
+    let mut interp = Interpreter::new();
+    interp.set_trace(false);
+    for h in hosts {
+        interp.register_host(h);
+    }
+    let start = Instant::now();
+    let result = interp.run_program(program).map(|_| start.elapsed().as_secs_f64() * 1000.0);
+    rows.push(("interp", result.map_err(|e| e.to_string())));
+
+    // компиляция в байткод — вне замера, как разбор у интерпретатора
+    let result = vm::compile(program).and_then(|bc| {
+        let mut machine = vm::Vm::new(&bc);
+        for h in hosts {
+            machine.register_host(h);
+        }
+        let start = Instant::now();
+        machine.run_main().map(|_| start.elapsed().as_secs_f64() * 1000.0)
+    });
+    rows.push(("vm", result.map_err(|e| e.to_string())));
+
+    let context = Context::create();
+    let mut codegen = Codegen::new(&context, "bench_module", sources);
+    for h in hosts {
+        codegen.register_host(h);
+    }
+    let errors: Vec<String> = sema::check_compiled(program, hosts)
+        .into_iter()
+        .filter(|d| d.severity == Severity::Error)
+        .map(|d| d.message)
+        .collect();
+    if errors.is_empty() {
+        codegen.compile_program(program);
+        let start = Instant::now();
+        let result = codegen.run_jit().map(|_| start.elapsed().as_secs_f64() * 1000.0);
+        rows.push(("jit", result));
+    } else {
+        rows.push(("jit", Err(errors.join("; "))));
+    }
+
+    io::stdout().flush().expect("flush stdout");
+    eprintln!("\n=== Bench ===");
+    for (name, result) in rows {
+        match result {
+            Ok(ms) => eprintln!("{:<8} {:>10.2} ms", name, ms),
+            Err(e) => eprintln!("{:<8} {:>10}    ({})", name, "-", e),
+        }
+    }
+}
+
+// Функции хоста, доступные любой программе без `extern fn` — заодно пример встраивания.
+fn host_functions() -> Vec<HostFunction> {
+    vec![HostFunction::new("time_ms", vec![], ExternType::Value(Type::Int), |_| Ok(Value::Int(now_ms())))
+        .with_native(time_ms as *const () as usize)]
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as i64)
+}
+
+extern "C" fn time_ms() -> i64 {
+    now_ms()
 }
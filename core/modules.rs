@@ -0,0 +1,522 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::ast::{Expr, ExternFn, ExternType, Function, Import, Program, Stmt, StmtKind, StructDef, Type};
+use crate::lexer::{line_col, Lexer};
+use crate::parser::Parser;
+
+/// Расширение файлов модулей: `use math::abs;` ищет `math.src` рядом с импортирующим файлом.
+const EXT: &str = "src";
+
+/// Прелюдия — модуль `prelude`, его имена видны в каждом файле без import.
+const PRELUDE: &str = include_str!("prelude.src");
+
+#[derive(Clone)]
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub base: usize,
+    pub text: String,
+}
+
+/// Все загруженные файлы. Спаны в AST — смещения в общей «склейке»: у каждого файла
+/// своё начало `base`, так что по смещению всегда понятно, из какого он файла.
+#[derive(Default, Clone)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+    // главный файл (до него загружается прелюдия)
+    root: usize,
+}
+
+impl SourceMap {
+    fn add(&mut self, path: PathBuf, text: String) -> usize {
+        // +1, чтобы конец одного файла не совпадал с началом следующего
+        let base = self.files.last().map_or(0, |f| f.base + f.text.len() + 1);
+        self.files.push(SourceFile { path, base, text });
+        base
+    }
+
+    pub fn root(&self) -> &SourceFile {
+        &self.files[self.root]
+    }
+
+    pub fn files(&self) -> &[SourceFile] {
+        &self.files
+    }
+
+    /// Номер файла в files(), строка и столбец (с 1) — для отладочной информации.
+    pub fn position(&self, offset: usize) -> Option<(usize, usize, usize)> {
+        let idx = self.files.iter().rposition(|f| f.base <= offset)?;
+        let (line, col) = line_col(&self.files[idx].text, offset - self.files[idx].base);
+        Some((idx, line, col))
+    }
+
+    /// `L:C` для главного файла, `path:L:C` для импортированных.
+    pub fn location(&self, offset: usize) -> String {
+        let Some(idx) = self.files.iter().rposition(|f| f.base <= offset) else {
+            return "?".to_string();
+        };
+        let file = &self.files[idx];
+        let (line, col) = line_col(&file.text, offset - file.base);
+        if idx == self.root {
+            format!("{}:{}", line, col)
+        } else {
+            format!("{}:{}:{}", file.path.display(), line, col)
+        }
+    }
+}
+
+/// Загружает файл со всеми импортами и склеивает их в одну программу.
+/// `hosts` — сигнатуры функций хоста: они попадают в program.externs и видны во всех
+/// модулях под своим именем (сильнее прелюдии).
+pub fn load_file(path: &Path, hosts: &[ExternFn]) -> Result<(Program, SourceMap), String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    load_source(path, text, hosts)
+}
+
+/// То же для уже прочитанного текста; импорты ищутся относительно `path`.
+pub fn load_source(path: &Path, text: String, hosts: &[ExternFn]) -> Result<(Program, SourceMap), String> {
+    let mut loader = Loader {
+        externs: hosts.to_vec(),
+        hosts: hosts.iter().map(|h| h.name.clone()).collect(),
+        ..Loader::default()
+    };
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    loader.load(PathBuf::from("<prelude>"), PRELUDE.to_string(), Some("prelude"))?;
+    loader.load(path, text, None)?;
+    let program = Program {
+        imports: loader.root_imports,
+        externs: loader.externs,
+        structs: loader.structs,
+        functions: loader.functions,
+    };
+    Ok((program, loader.sources))
+}
+
+#[derive(Default)]
+struct Loader {
+    sources: SourceMap,
+    // стек загружаемых сейчас файлов — по нему ищем циклы
+    stack: Vec<PathBuf>,
+    // имя модуля -> (файл, имена его функций и структур)
+    modules: HashMap<String, (PathBuf, HashSet<String>)>,
+    root_imports: Vec<Import>,
+    // extern-функции общие для всех модулей (это символы C) и не переименовываются
+    externs: Vec<ExternFn>,
+    // имена функций хоста
+    hosts: HashSet<String>,
+    structs: Vec<StructDef>,
+    functions: Vec<Function>,
+}
+
+impl Loader {
+    // `module` — имя модуля для импортированных файлов, None — главный файл (его имена не трогаем).
+    fn load(&mut self, path: PathBuf, text: String, module: Option<&str>) -> Result<(), String> {
+        let base = self.sources.add(path.clone(), text.clone());
+        if module.is_none() {
+            self.sources.root = self.sources.files.len() - 1;
+        }
+        let tokens = Lexer::new(&text)
+            .tokenize_spanned()
+            .into_iter()
+            .map(|(t, s)| (t, s.start + base..s.end + base))
+            .collect();
+        let mut parser = Parser::new(tokens);
+        let Some(mut program) = parser.parse_program() else {
+            let (span, message) = parser.error();
+            return Err(format!("parse error at {}: {}", self.sources.location(span.start), message));
+        };
+
+        let items: HashSet<String> = program
+            .structs
+            .iter()
+            .map(|s| s.name.clone())
+            .chain(program.functions.iter().map(|f| f.name.clone()))
+            .collect();
+        match module {
+            Some(m) => {
+                self.modules.insert(m.to_string(), (path.clone(), items.clone()));
+            }
+            // имена главного файла не переименовываются и столкнулись бы с символом хоста
+            None => {
+                if let Some(clash) = items.iter().find(|i| self.hosts.contains(*i)) {
+                    return Err(format!("{}: `{}` is already defined by the host", path.display(), clash));
+                }
+            }
+        }
+
+        // Сначала грузим зависимости: к переименованию все модули должны быть известны.
+        self.stack.push(path.clone());
+        let mut visible = HashSet::new();
+        let mut aliases = HashMap::new();
+        // имена прелюдии — самые слабые: их перекрывают свои функции и `use`
+        if let Some((_, prelude)) = self.modules.get("prelude").filter(|_| module != Some("prelude")) {
+            for item in prelude {
+                aliases.insert(item.clone(), format!("prelude::{}", item));
+            }
+            visible.insert("prelude".to_string());
+        }
+        for import in &program.imports {
+            let (file, span) = match import {
+                Import::File { path, span } => (PathBuf::from(path), span),
+                Import::Use { module, span, .. } => (PathBuf::from(format!("{}.{}", module, EXT)), span),
+            };
+            let name = self.import(&path, &file, span.start)?;
+            if let Import::Use { item, span, .. } = import {
+                if !self.modules[&name].1.contains(item) {
+                    return Err(format!("{}: module `{}` has no item `{}`", self.sources.location(span.start), name, item));
+                }
+                aliases.insert(item.clone(), format!("{}::{}", name, item));
+            }
+            visible.insert(name);
+        }
+        self.stack.pop();
+
+        let externs: HashSet<String> =
+            program.externs.iter().map(|e| e.name.clone()).chain(self.hosts.iter().cloned()).collect();
+        let mut renamer = Renamer {
+            prefix: module,
+            own: &items,
+            externs: &externs,
+            aliases: &aliases,
+            visible: &visible,
+            modules: &self.modules,
+            locals: HashSet::new(),
+            at: base,
+            error: None,
+        };
+        for s in &mut program.structs {
+            s.name = renamer.item(&s.name);
+            for (_, t) in &mut s.fields {
+                renamer.ty(t);
+            }
+        }
+        for e in &mut program.externs {
+            for (_, t) in &mut e.params {
+                renamer.extern_ty(t);
+            }
+            renamer.extern_ty(&mut e.ret);
+        }
+        for f in &mut program.functions {
+            renamer.function(f);
+        }
+        if let Some(e) = renamer.error {
+            return Err(format!("{}: {}", self.sources.location(e.1), e.0));
+        }
+
+        if module.is_none() {
+            self.root_imports = program.imports;
+        }
+        for e in program.externs {
+            match self.externs.iter().find(|prev| prev.name == e.name) {
+                Some(prev) if (prev.params != e.params || prev.ret != e.ret) && self.hosts.contains(&e.name) => {
+                    return Err(format!(
+                        "{}: extern fn `{}` does not match the host function's signature",
+                        self.sources.location(e.span.start),
+                        e.name
+                    ));
+                }
+                Some(prev) if prev.params != e.params || prev.ret != e.ret => {
+                    return Err(format!(
+                        "{}: conflicting declarations of extern fn `{}` (first at {})",
+                        self.sources.location(e.span.start),
+                        e.name,
+                        self.sources.location(prev.span.start)
+                    ));
+                }
+                Some(_) => {}
+                None => self.externs.push(e),
+            }
+        }
+        self.structs.extend(program.structs);
+        self.functions.extend(program.functions);
+        Ok(())
+    }
+
+    // Загружает импорт (если ещё не загружен) и возвращает имя модуля.
+    fn import(&mut self, importer: &Path, file: &Path, at: usize) -> Result<String, String> {
+        let dir = importer.parent().unwrap_or(Path::new("."));
+        let full = dir.join(file);
+        let full = fs::canonicalize(&full)
+            .map_err(|e| format!("{}: cannot import {}: {}", self.sources.location(at), full.display(), e))?;
+        let name = full.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+
+        if let Some(pos) = self.stack.iter().position(|p| p == &full) {
+            let chain: Vec<String> = self.stack[pos..]
+                .iter()
+                .chain([&full])
+                .map(|p| p.file_name().map_or_else(String::new, |n| n.to_string_lossy().into_owned()))
+                .collect();
+            return Err(format!("{}: import cycle: {}", self.sources.location(at), chain.join(" -> ")));
+        }
+        match self.modules.get(&name) {
+            Some((loaded, _)) if loaded == &full => {}
+            Some((loaded, _)) => {
+                return Err(format!(
+                    "{}: module name `{}` is used by both {} and {}",
+                    self.sources.location(at),
+                    name,
+                    loaded.display(),
+                    full.display()
+                ))
+            }
+            None => {
+                let text = fs::read_to_string(&full)
+                    .map_err(|e| format!("{}: cannot import {}: {}", self.sources.location(at), full.display(), e))?;
+                self.load(full, text, Some(&name))?;
+            }
+        }
+        Ok(name)
+    }
+}
+
+// Переводит имена модуля в полные: свои `abs` -> `math::abs`, `use`-имена -> их модуль,
+// `math::abs` проверяется на существование. Локальные переменные не трогаем.
+struct Renamer<'a> {
+    prefix: Option<&'a str>,
+    own: &'a HashSet<String>,
+    externs: &'a HashSet<String>,
+    aliases: &'a HashMap<String, String>,
+    visible: &'a HashSet<String>,
+    modules: &'a HashMap<String, (PathBuf, HashSet<String>)>,
+    locals: HashSet<String>,
+    // начало текущего оператора — туда указывает ошибка
+    at: usize,
+    error: Option<(String, usize)>,
+}
+
+impl Renamer<'_> {
+    fn item(&self, name: &str) -> String {
+        match self.prefix {
+            Some(m) => format!("{}::{}", m, name),
+            None => name.to_string(),
+        }
+    }
+
+    fn name(&mut self, name: &str) -> String {
+        if let Some((module, item)) = name.split_once("::") {
+            let known = self.modules.get(module).filter(|_| self.visible.contains(module));
+            match known {
+                None => self.fail(format!("unknown module `{}` (missing import?)", module)),
+                Some((_, items)) if !items.contains(item) => {
+                    self.fail(format!("module `{}` has no item `{}`", module, item))
+                }
+                Some(_) => {}
+            }
+            name.to_string()
+        } else if self.locals.contains(name) {
+            name.to_string()
+        } else if self.own.contains(name) {
+            self.item(name)
+        } else if self.externs.contains(name) {
+            name.to_string()
+        } else if let Some(full) = self.aliases.get(name) {
+            full.clone()
+        } else {
+            name.to_string()
+        }
+    }
+
+    fn fail(&mut self, message: String) {
+        if self.error.is_none() {
+            self.error = Some((message, self.at));
+        }
+    }
+
+    fn function(&mut self, f: &mut Function) {
+        f.name = self.item(&f.name);
+        self.locals = f.params.iter().map(|(n, _)| n.clone()).collect();
+        for (_, t) in &mut f.params {
+            self.ty(t);
+        }
+        self.ty(&mut f.ret);
+        self.body(&mut f.body);
+    }
+
+    fn ty(&mut self, t: &mut Type) {
+        match t {
+            Type::Struct(name) => *name = self.name(name),
+            Type::Array(elem, _) | Type::Slice(elem) | Type::Ptr(elem) => self.ty(elem),
+            Type::Fn(params, ret) => {
+                params.iter_mut().for_each(|p| self.ty(p));
+                self.ty(ret);
+            }
+            Type::Int | Type::Bool | Type::Char | Type::Str => {}
+        }
+    }
+
+    fn extern_ty(&mut self, t: &mut ExternType) {
+        if let ExternType::Value(t) = t {
+            self.ty(t);
+        }
+    }
+
+    fn body(&mut self, body: &mut [Stmt]) {
+        for s in body {
+            self.stmt(s);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &mut Stmt) {
+        self.at = stmt.span.start;
+        match &mut stmt.kind {
+            StmtKind::Let(name, ty, e) => {
+                self.expr(e);
+                if let Some(t) = ty {
+                    self.ty(t);
+                }
+                self.locals.insert(name.clone());
+            }
+            StmtKind::Expr(e) | StmtKind::Semi(e) => self.expr(e),
+            StmtKind::Assign(target, e) => {
+                self.expr(target);
+                self.expr(e);
+            }
+            StmtKind::While { cond, body } => {
+                self.expr(cond);
+                self.body(body);
+            }
+            StmtKind::For { var, start, end, body, .. } => {
+                self.expr(start);
+                self.expr(end);
+                self.locals.insert(var.clone());
+                self.body(body);
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &mut Expr) {
+        match expr {
+            Expr::Number(_) | Expr::Bool(_) | Expr::Char(_) | Expr::Str(_) | Expr::Null => {}
+            Expr::Ident(name) => *name = self.name(name),
+            Expr::BinaryOp(l, _, r) => {
+                self.expr(l);
+                self.expr(r);
+            }
+            Expr::Call(name, args) => {
+                *name = self.name(name);
+                args.iter_mut().for_each(|a| self.expr(a));
+            }
+            Expr::Apply(callee, args) => {
+                self.expr(callee);
+                args.iter_mut().for_each(|a| self.expr(a));
+            }
+            Expr::Array(elems) => elems.iter_mut().for_each(|e| self.expr(e)),
+            Expr::ArrayRepeat(e, _) | Expr::Field(e, _) | Expr::AddrOf(e) | Expr::Deref(e, _) => self.expr(e),
+            Expr::Index { array, index, .. } => {
+                self.expr(array);
+                self.expr(index);
+            }
+            Expr::StructLit(name, fields) => {
+                *name = self.name(name);
+                fields.iter_mut().for_each(|(_, e)| self.expr(e));
+            }
+            Expr::Lambda { params, ret, body } => {
+                for (n, t) in params.iter_mut() {
+                    self.ty(t);
+                    self.locals.insert(n.clone());
+                }
+                self.ty(ret);
+                self.body(body);
+            }
+            Expr::Block(body) => self.body(body),
+            Expr::If { cond, then_body, else_body } => {
+                self.expr(cond);
+                self.body(then_body);
+                if let Some(eb) = else_body {
+                    self.body(eb);
+                }
+            }
+            Expr::Match { scrutinee, arms, .. } => {
+                self.expr(scrutinee);
+                arms.iter_mut().for_each(|arm| self.expr(&mut arm.body));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen::Codegen;
+    use crate::interpreter::{Interpreter, Outcome, Value};
+    use crate::testutil::scratch;
+    use inkwell::context::Context;
+
+    // Каталог с файлами модулей; путь к первому — главный файл.
+    fn project(files: &[(&str, &str)]) -> PathBuf {
+        let dir = scratch("modules");
+        fs::create_dir_all(&dir).expect("create project dir");
+        for (name, text) in files {
+            fs::write(dir.join(name), text).expect("write module");
+        }
+        dir.join(files[0].0)
+    }
+
+    fn load(files: &[(&str, &str)]) -> Result<Program, String> {
+        let main = project(files);
+        let result = load_file(&main, &[]).map(|(program, _)| program);
+        let _ = fs::remove_dir_all(main.parent().expect("project dir"));
+        result
+    }
+
+    #[test]
+    fn imports_are_namespaced() {
+        let program = load(&[
+            (
+                "main.src",
+                "import \"math.src\";\nuse util::twice;\nfn abs(x) { 0 - 1 }\n\
+                 fn main() { let p = math::Pair { a: 2 }; math::abs(0 - 5) * 100 + twice(p.a) + abs(1) }",
+            ),
+            ("math.src", "struct Pair { a: i64 }\nfn abs(x) { if x < 0 { 0 - x } else { x } }"),
+            ("util.src", "fn twice(x) { helper(x) * 2 }\nfn helper(x) { x }"),
+        ])
+        .expect("load");
+        let names: Vec<&str> = program.functions.iter().map(|f| f.name.as_str()).collect();
+        for name in ["math::abs", "util::twice", "util::helper", "prelude::abs", "abs", "main"] {
+            assert!(names.contains(&name), "{} not in {:?}", name, names);
+        }
+        let mut interp = Interpreter::new();
+        interp.set_trace(false);
+        assert_eq!(interp.run_program(&program).expect("run"), Outcome::Return(Value::Int(503)));
+        // всё в одном LLVM-модуле, под полными именами
+        let context = Context::create();
+        let sources = SourceMap::default();
+        let mut codegen = Codegen::new(&context, "test", &sources);
+        codegen.compile_program(&program);
+        assert!(codegen.module().get_function("math::abs").is_some());
+        assert!(codegen.module().verify().is_ok());
+    }
+
+    #[test]
+    fn import_errors() {
+        let cycle = load(&[
+            ("a.src", "import \"b.src\";\nfn main() { 0 }"),
+            ("b.src", "import \"c.src\";"),
+            ("c.src", "\nimport \"b.src\";"),
+        ])
+        .expect_err("cycle");
+        assert!(cycle.ends_with("c.src:2:1: import cycle: b.src -> c.src -> b.src"), "{}", cycle);
+
+        let missing = load(&[("main.src", "use math::nope;\nfn main() { 0 }"), ("math.src", "fn abs(x) { x }")])
+            .expect_err("missing item");
+        assert_eq!(missing, "1:1: module `math` has no item `nope`");
+
+        let unknown = load(&[("main.src", "fn main() { math::abs(1) }")]).expect_err("unknown module");
+        assert_eq!(unknown, "1:13: unknown module `math` (missing import?)");
+
+        let parse = load(&[("main.src", "import \"bad.src\";\nfn main() { 0 }"), ("bad.src", "fn f() {\n    1 +\n}")])
+            .expect_err("parse error");
+        assert!(parse.starts_with("parse error at ") && parse.contains("bad.src:3:1"), "{}", parse);
+    }
+
+    #[test]
+    fn own_functions_shadow_the_prelude() {
+        let program = load(&[("main.src", "fn min(a, b) { 42 }\nfn main() { min(1, 2) + gcd(4, 6) }")]).expect("load");
+        let mut interp = Interpreter::new();
+        interp.set_trace(false);
+        assert_eq!(interp.run_program(&program).expect("run"), Outcome::Return(Value::Int(44)));
+        // прелюдия загружается и для программы без импортов
+        assert!(program.functions.iter().any(|f| f.name == "prelude::gcd"));
+    }
+}
@@ -0,0 +1,612 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::{Expr, Function, Program, Stmt, StmtKind, Type};
+use crate::format::show_function;
+
+// Оптимизации на AST — до любого бэкенда, так что выигрывают и Interpreter, и VM, и Codegen.
+// Переменные в языке — одна область на вызов функции (блоки и ветки новую не создают),
+// поэтому подставлять и переносить можно только имена, которые в функции связываются
+// ровно один раз.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pass {
+    // `2 * 3` -> `6`, `1 < 2` -> `true`
+    Fold,
+    // `let x = 6; ... x ...` -> `... 6 ...`
+    Propagate,
+    // `if true { a } else { b }` -> `a`, `while (false) { }` -> ничего
+    Dce,
+    // `let t = a * b;` из тела цикла — перед циклом, если a и b в цикле не меняются
+    Licm,
+}
+
+impl Pass {
+    /// Порядок, в котором проходы выполняются: свёртка открывает подстановку,
+    /// подстановка — новые свёртки и постоянные условия.
+    pub const ALL: [Pass; 4] = [Pass::Fold, Pass::Propagate, Pass::Dce, Pass::Licm];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Pass::Fold => "fold",
+            Pass::Propagate => "propagate",
+            Pass::Dce => "dce",
+            Pass::Licm => "licm",
+        }
+    }
+
+    /// `all` или список через запятую: `fold,dce`.
+    pub fn parse_list(s: &str) -> Result<Vec<Pass>, String> {
+        if s == "all" {
+            return Ok(Pass::ALL.to_vec());
+        }
+        let mut passes = Vec::new();
+        for name in s.split(',') {
+            match Pass::ALL.iter().find(|p| p.name() == name) {
+                Some(p) => passes.push(*p),
+                None => return Err(format!("unknown pass {} (expected fold, propagate, dce, licm or all)", name)),
+            }
+        }
+        Ok(passes)
+    }
+}
+
+/// Изменение одной функции одним проходом — исходник до и после.
+pub struct Change {
+    pub pass: Pass,
+    pub function: String,
+    pub before: String,
+    pub after: String,
+}
+
+// раундов немного: каждый проход сам доводит функцию до неподвижной точки
+const MAX_ROUNDS: usize = 4;
+
+/// Прогоняет выбранные проходы (в порядке Pass::ALL) по всем функциям, пока что-то меняется.
+pub fn optimize(program: &mut Program, passes: &[Pass]) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for _ in 0..MAX_ROUNDS {
+        let mut changed = false;
+        for pass in Pass::ALL.iter().filter(|p| passes.contains(p)) {
+            for func in &mut program.functions {
+                let before = show_function(func);
+                match pass {
+                    Pass::Fold => exprs_mut(&mut func.body, &mut fold),
+                    Pass::Propagate => propagate(func),
+                    Pass::Dce => dce(&mut func.body),
+                    Pass::Licm => licm(func),
+                }
+                let after = show_function(func);
+                if before != after {
+                    changed = true;
+                    changes.push(Change { pass: *pass, function: func.name.clone(), before, after });
+                }
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    changes
+}
+
+// Свёртка бинарной операции над числами. Деление на ноль и переполнение не сворачиваем:
+// ошибка (или поведение) должна остаться той же, что без оптимизации.
+fn fold(expr: &mut Expr) {
+    let Expr::BinaryOp(l, op, r) = expr else { return };
+    let (Expr::Number(a), Expr::Number(b)) = (l.as_ref(), r.as_ref()) else { return };
+    let (a, b) = (*a, *b);
+    let folded = match op.as_str() {
+        "+" => a.checked_add(b).map(Expr::Number),
+        "-" => a.checked_sub(b).map(Expr::Number),
+        "*" => a.checked_mul(b).map(Expr::Number),
+        "/" if b != 0 => a.checked_div(b).map(Expr::Number),
+        "==" => Some(Expr::Bool(a == b)),
+        "!=" => Some(Expr::Bool(a != b)),
+        "<" => Some(Expr::Bool(a < b)),
+        "<=" => Some(Expr::Bool(a <= b)),
+        ">" => Some(Expr::Bool(a > b)),
+        ">=" => Some(Expr::Bool(a >= b)),
+        _ => None,
+    };
+    if let Some(f) = folded {
+        *expr = f;
+    }
+}
+
+// Как связываются имена в функции — общее для подстановки и выноса из цикла.
+#[derive(Default)]
+struct Bindings {
+    // let, параметры (функции и лямбд), переменные for
+    binds: HashMap<String, usize>,
+    // корни целей присваивания: `x = ...`, `x[i] = ...`, `x.f = ...`
+    assigned: HashSet<String>,
+    // `&x`: через указатель x может поменяться где угодно
+    addr_taken: HashSet<String>,
+}
+
+impl Bindings {
+    fn of(func: &Function) -> Self {
+        let mut b = Bindings::default();
+        for (p, _) in &func.params {
+            *b.binds.entry(p.clone()).or_default() += 1;
+        }
+        stmts(&func.body, &mut |s| match &s.kind {
+            StmtKind::Let(name, ..) => *b.binds.entry(name.clone()).or_default() += 1,
+            StmtKind::For { var, .. } => *b.binds.entry(var.clone()).or_default() += 1,
+            StmtKind::Assign(target, _) => {
+                if let Some(root) = place_root(target) {
+                    b.assigned.insert(root);
+                }
+            }
+            _ => {}
+        });
+        exprs(&func.body, &mut |e| match e {
+            Expr::Lambda { params, .. } => {
+                for (p, _) in params {
+                    *b.binds.entry(p.clone()).or_default() += 1;
+                }
+            }
+            Expr::AddrOf(target) => {
+                if let Some(root) = place_root(target) {
+                    b.addr_taken.insert(root);
+                }
+            }
+            _ => {}
+        });
+        b
+    }
+
+    // имя связано один раз и больше не меняется
+    fn constant(&self, name: &str) -> bool {
+        self.binds.get(name) == Some(&1) && !self.assigned.contains(name) && !self.addr_taken.contains(name)
+    }
+}
+
+fn place_root(e: &Expr) -> Option<String> {
+    match e {
+        Expr::Ident(name) => Some(name.clone()),
+        Expr::Index { array, .. } => place_root(array),
+        Expr::Field(base, _) => place_root(base),
+        _ => None,
+    }
+}
+
+// Подстановка литерала из `let x = литерал;` верхнего уровня тела во все последующие
+// использования; сам let, оставшийся без использований, удаляется.
+fn propagate(func: &mut Function) {
+    let bindings = Bindings::of(func);
+    let mut i = 0;
+    while i < func.body.len() {
+        let StmtKind::Let(name, ty, init) = &func.body[i].kind else {
+            i += 1;
+            continue;
+        };
+        let literal = match (init, ty) {
+            (Expr::Number(_), None | Some(Type::Int)) | (Expr::Bool(_), None | Some(Type::Bool)) | (Expr::Char(_), None | Some(Type::Char)) => init.clone(),
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        let name = name.clone();
+        if !bindings.constant(&name) {
+            i += 1;
+            continue;
+        }
+        exprs_mut(&mut func.body[i + 1..], &mut |e| {
+            if matches!(e, Expr::Ident(n) if *n == name) {
+                *e = literal.clone();
+            }
+        });
+        // последний оператор — значение функции, его не трогаем
+        if i + 1 < func.body.len() && refs(&func.body, &name) == 0 {
+            func.body.remove(i);
+        } else {
+            i += 1;
+        }
+    }
+}
+
+// Ветки с постоянным условием и циклы, которые не выполнятся ни разу.
+// Последний оператор тела — его значение, поэтому он только заменяется, но не исчезает.
+fn dce(body: &mut Vec<Stmt>) {
+    exprs_mut(body, &mut |e| {
+        let Expr::If { cond, then_body, else_body } = e else { return };
+        let taken = match cond.as_ref() {
+            Expr::Bool(b) => *b,
+            Expr::Number(n) => *n != 0,
+            _ => return,
+        };
+        *e = if taken {
+            Expr::Block(std::mem::take(then_body))
+        } else {
+            Expr::Block(else_body.take().unwrap_or_default())
+        };
+    });
+    dce_blocks(body);
+}
+
+// Убирает `while (false)` и разворачивает блоки-операторы: `{ a; b };` -> `a; b;`
+// (блок не создаёт новую область, так что это ничего не меняет).
+fn dce_blocks(body: &mut Vec<Stmt>) {
+    let last = body.len().saturating_sub(1);
+    let mut out = Vec::new();
+    for (i, mut s) in std::mem::take(body).into_iter().enumerate() {
+        match &mut s.kind {
+            StmtKind::While { cond: Expr::Bool(false) | Expr::Number(0), .. } if i != last => continue,
+            StmtKind::Expr(Expr::Block(inner)) | StmtKind::Semi(Expr::Block(inner)) if i != last => {
+                dce_blocks(inner);
+                out.append(inner);
+                continue;
+            }
+            StmtKind::While { body, .. } | StmtKind::For { body, .. } => dce_blocks(body),
+            _ => {}
+        }
+        out.push(s);
+    }
+    *body = out;
+}
+
+// Вынос инвариантов из циклов: `let t = e;` на верхнем уровне тела переезжает перед циклом,
+// если e не может упасть (нет вызовов, индексов, деления на не-константу), ничего из e
+// в цикле не меняется, а t связан один раз и нигде кроме этого тела не используется.
+fn licm(func: &mut Function) {
+    let bindings = Bindings::of(func);
+    let total: HashMap<String, usize> = bindings.binds.keys().map(|n| (n.clone(), refs(&func.body, n))).collect();
+    licm_body(&mut func.body, &bindings, &total);
+}
+
+fn licm_body(body: &mut Vec<Stmt>, bindings: &Bindings, total: &HashMap<String, usize>) {
+    // сначала вложенные циклы — их инварианты окажутся в нашем теле и могут поехать дальше
+    for s in body.iter_mut() {
+        match &mut s.kind {
+            StmtKind::While { body, .. } | StmtKind::For { body, .. } => licm_body(body, bindings, total),
+            _ => {}
+        }
+    }
+    let mut i = 0;
+    while i < body.len() {
+        let hoisted = match &mut body[i].kind {
+            StmtKind::While { cond, body: inner } => hoist(inner, std::slice::from_ref(&*cond), None, bindings, total),
+            StmtKind::For { var, body: inner, .. } => hoist(inner, &[], Some(var.as_str()), bindings, total),
+            _ => Vec::new(),
+        };
+        let n = hoisted.len();
+        for (k, s) in hoisted.into_iter().enumerate() {
+            body.insert(i + k, s);
+        }
+        i += n + 1;
+    }
+}
+
+fn hoist(
+    inner: &mut Vec<Stmt>,
+    cond: &[Expr],
+    loop_var: Option<&str>,
+    bindings: &Bindings,
+    total: &HashMap<String, usize>,
+) -> Vec<Stmt> {
+    let mut hoisted = Vec::new();
+    let mut j = 0;
+    while j < inner.len() {
+        let invariant = match &inner[j].kind {
+            StmtKind::Let(t, _, e) if is_hoistable(e) && bindings.constant(t) => {
+                // всё, что в цикле переприсваивается или связывается заново
+                let mut changing: HashSet<String> = loop_var.iter().map(|v| v.to_string()).collect();
+                stmts(inner, &mut |s| match &s.kind {
+                    StmtKind::Let(n, ..) | StmtKind::For { var: n, .. } => {
+                        changing.insert(n.clone());
+                    }
+                    StmtKind::Assign(target, _) => changing.extend(place_root(target)),
+                    _ => {}
+                });
+                let mut used = Vec::new();
+                exprs_of(e, &mut |x| {
+                    if let Expr::Ident(n) = x {
+                        used.push(n.clone());
+                    }
+                });
+                let stable = used.iter().all(|n| !changing.contains(n) && !bindings.addr_taken.contains(n));
+                // t не встречается ни до let в теле, ни в условии, ни вне цикла
+                let before = refs(&inner[..j], t) + cond.iter().map(|c| refs_expr(c, t)).sum::<usize>();
+                let local = total.get(t) == Some(&refs(inner, t));
+                stable && before == 0 && local && loop_var != Some(t.as_str())
+            }
+            _ => false,
+        };
+        if invariant {
+            hoisted.push(inner.remove(j));
+        } else {
+            j += 1;
+        }
+    }
+    hoisted
+}
+
+// Есть что вычислять, и вычисление не может упасть — его можно сделать раньше.
+fn is_hoistable(e: &Expr) -> bool {
+    fn safe(e: &Expr) -> bool {
+        match e {
+            Expr::Number(_) | Expr::Bool(_) | Expr::Char(_) | Expr::Str(_) | Expr::Ident(_) => true,
+            Expr::BinaryOp(_, op, r) if op == "/" && !matches!(r.as_ref(), Expr::Number(n) if *n != 0) => false,
+            Expr::BinaryOp(l, _, r) => safe(l) && safe(r),
+            Expr::Field(base, _) => safe(base),
+            _ => false,
+        }
+    }
+    matches!(e, Expr::BinaryOp(..) | Expr::Field(..)) && safe(e)
+}
+
+fn refs(body: &[Stmt], name: &str) -> usize {
+    let mut n = 0;
+    exprs(body, &mut |e| {
+        if matches!(e, Expr::Ident(x) if x == name) {
+            n += 1;
+        }
+    });
+    n
+}
+
+fn refs_expr(e: &Expr, name: &str) -> usize {
+    let mut n = 0;
+    exprs_of(e, &mut |x| {
+        if matches!(x, Expr::Ident(v) if v == name) {
+            n += 1;
+        }
+    });
+    n
+}
+
+// Обходы: все операторы (и внутри выражений), все выражения (сверху вниз),
+// все выражения с изменением (снизу вверх — сначала дети, потом родитель).
+
+fn stmts(body: &[Stmt], f: &mut dyn FnMut(&Stmt)) {
+    // операторы списка и вложенных циклов; тела внутри выражений — через exprs,
+    // который доходит до каждого выражения ровно один раз
+    fn list(body: &[Stmt], f: &mut dyn FnMut(&Stmt)) {
+        for s in body {
+            f(s);
+            if let StmtKind::While { body, .. } | StmtKind::For { body, .. } = &s.kind {
+                list(body, f);
+            }
+        }
+    }
+    list(body, f);
+    exprs(body, &mut |e| match e {
+        Expr::Lambda { body, .. } | Expr::Block(body) => list(body, f),
+        Expr::If { then_body, else_body, .. } => {
+            list(then_body, f);
+            if let Some(b) = else_body {
+                list(b, f);
+            }
+        }
+        _ => {}
+    });
+}
+
+fn exprs(body: &[Stmt], f: &mut dyn FnMut(&Expr)) {
+    for s in body {
+        match &s.kind {
+            StmtKind::Let(_, _, e) | StmtKind::Expr(e) | StmtKind::Semi(e) => exprs_of(e, f),
+            StmtKind::Assign(t, e) => {
+                exprs_of(t, f);
+                exprs_of(e, f);
+            }
+            StmtKind::While { cond, body } => {
+                exprs_of(cond, f);
+                exprs(body, f);
+            }
+            StmtKind::For { start, end, body, .. } => {
+                exprs_of(start, f);
+                exprs_of(end, f);
+                exprs(body, f);
+            }
+        }
+    }
+}
+
+fn exprs_of(e: &Expr, f: &mut dyn FnMut(&Expr)) {
+    f(e);
+    match e {
+        Expr::Number(_) | Expr::Bool(_) | Expr::Char(_) | Expr::Str(_) | Expr::Ident(_) | Expr::Null => {}
+        Expr::BinaryOp(l, _, r) => {
+            exprs_of(l, f);
+            exprs_of(r, f);
+        }
+        Expr::Call(_, args) | Expr::Array(args) => args.iter().for_each(|a| exprs_of(a, f)),
+        Expr::ArrayRepeat(e, _) | Expr::Field(e, _) | Expr::AddrOf(e) | Expr::Deref(e, _) => exprs_of(e, f),
+        Expr::Index { array, index, .. } => {
+            exprs_of(array, f);
+            exprs_of(index, f);
+        }
+        Expr::StructLit(_, fields) => fields.iter().for_each(|(_, e)| exprs_of(e, f)),
+        Expr::Lambda { body, .. } | Expr::Block(body) => exprs(body, f),
+        Expr::Apply(callee, args) => {
+            exprs_of(callee, f);
+            args.iter().for_each(|a| exprs_of(a, f));
+        }
+        Expr::If { cond, then_body, else_body } => {
+            exprs_of(cond, f);
+            exprs(then_body, f);
+            if let Some(b) = else_body {
+                exprs(b, f);
+            }
+        }
+        Expr::Match { scrutinee, arms, .. } => {
+            exprs_of(scrutinee, f);
+            arms.iter().for_each(|a| exprs_of(&a.body, f));
+        }
+    }
+}
+
+fn exprs_mut(body: &mut [Stmt], f: &mut dyn FnMut(&mut Expr)) {
+    for s in body {
+        match &mut s.kind {
+            StmtKind::Let(_, _, e) | StmtKind::Expr(e) | StmtKind::Semi(e) => expr_mut(e, f),
+            StmtKind::Assign(t, e) => {
+                expr_mut(t, f);
+                expr_mut(e, f);
+            }
+            StmtKind::While { cond, body } => {
+                expr_mut(cond, f);
+                exprs_mut(body, f);
+            }
+            StmtKind::For { start, end, body, .. } => {
+                expr_mut(start, f);
+                expr_mut(end, f);
+                exprs_mut(body, f);
+            }
+        }
+    }
+}
+
+fn expr_mut(e: &mut Expr, f: &mut dyn FnMut(&mut Expr)) {
+    match e {
+        Expr::Number(_) | Expr::Bool(_) | Expr::Char(_) | Expr::Str(_) | Expr::Ident(_) | Expr::Null => {}
+        Expr::BinaryOp(l, _, r) => {
+            expr_mut(l, f);
+            expr_mut(r, f);
+        }
+        Expr::Call(_, args) | Expr::Array(args) => args.iter_mut().for_each(|a| expr_mut(a, f)),
+        Expr::ArrayRepeat(e, _) | Expr::Field(e, _) | Expr::AddrOf(e) | Expr::Deref(e, _) => expr_mut(e, f),
+        Expr::Index { array, index, .. } => {
+            expr_mut(array, f);
+            expr_mut(index, f);
+        }
+        Expr::StructLit(_, fields) => fields.iter_mut().for_each(|(_, e)| expr_mut(e, f)),
+        Expr::Lambda { body, .. } | Expr::Block(body) => exprs_mut(body, f),
+        Expr::Apply(callee, args) => {
+            expr_mut(callee, f);
+            args.iter_mut().for_each(|a| expr_mut(a, f));
+        }
+        Expr::If { cond, then_body, else_body } => {
+            expr_mut(cond, f);
+            exprs_mut(then_body, f);
+            if let Some(b) = else_body {
+                exprs_mut(b, f);
+            }
+        }
+        Expr::Match { scrutinee, arms, .. } => {
+            expr_mut(scrutinee, f);
+            arms.iter_mut().for_each(|a| expr_mut(&mut a.body, f));
+        }
+    }
+    f(e);
+}
+
+/// Построчный дифф (наибольшая общая подпоследовательность): `- ` удалено, `+ ` добавлено.
+pub fn diff(before: &str, after: &str) -> String {
+    let a: Vec<&str> = before.lines().collect();
+    let b: Vec<&str> = after.lines().collect();
+    // lcs[i][j] — длина общей подпоследовательности a[i..] и b[j..]
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut out = String::new();
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            out.push_str(&format!("  {}\n", a[i]));
+            i += 1;
+            j += 1;
+        } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            out.push_str(&format!("- {}\n", a[i]));
+            i += 1;
+        } else {
+            out.push_str(&format!("+ {}\n", b[j]));
+            j += 1;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{interpret_program, load};
+
+    // main после проходов и сделанные изменения (проход, функция) без прелюдии. Вывод
+    // и результат интерпретатора до и после оптимизации совпадают.
+    fn optimized(src: &str, passes: &[Pass]) -> (String, Vec<(&'static str, String)>) {
+        let (mut program, sources) = load(src);
+        let expected = interpret_program(&program, &sources, "");
+        let changes = optimize(&mut program, passes);
+        assert_eq!(interpret_program(&program, &sources, ""), expected, "{}", src);
+        let main = program.functions.iter().find(|f| f.name == "main").expect("main");
+        let changes = changes
+            .into_iter()
+            .filter(|c| !c.function.starts_with("prelude::"))
+            .map(|c| (c.pass.name(), c.function))
+            .collect();
+        (show_function(main), changes)
+    }
+
+    #[test]
+    fn fold_keeps_errors_and_overflow() {
+        let src = "fn main() {\n    let x = 2 * 3 + 1;\n    println(9223372036854775807 + 1);\n    println(x < 10);\n    1 / 0\n}";
+        let (main, changes) = optimized(src, &[Pass::Fold]);
+        assert_eq!(
+            main,
+            "fn main() -> i64 {\n    let x = 7;\n    println(9223372036854775807 + 1);\n    println(x < 10);\n    1 / 0\n}\n"
+        );
+        assert_eq!(changes, [("fold", "main".to_string())]);
+    }
+
+    #[test]
+    fn propagate_and_dce() {
+        let src = r#"fn main() {
+            let debug = false;
+            let n = 3;
+            if (debug) { println("debug"); }
+            while (debug) { println(n); }
+            let total = 0;
+            if (n > 2) { total = n * 10; } else { total = 1; }
+            total
+        }"#;
+        let (main, changes) = optimized(src, &Pass::ALL);
+        assert_eq!(main, "fn main() -> i64 {\n    let total = 0;\n    total = 30;\n    total\n}\n");
+        // второй раунд: подстановка открыла свёртку `3 > 2` и `3 * 10`, та — ещё один dce
+        let passes: Vec<&str> = changes.iter().map(|(p, _)| *p).collect();
+        assert_eq!(passes, ["propagate", "dce", "fold", "dce"]);
+    }
+
+    #[test]
+    fn propagate_skips_reassigned_and_borrowed() {
+        let src = "fn main() {\n    let a = 1;\n    let b = 2;\n    let p = &b;\n    a = a + 1;\n    println(a + b);\n    *p\n}";
+        let (main, changes) = optimized(src, &[Pass::Propagate]);
+        assert!(changes.is_empty(), "{}", main);
+    }
+
+    #[test]
+    fn licm_hoists_invariants() {
+        let src = r#"fn main() {
+            let a = 6;
+            let b = read_int();
+            let sum = 0;
+            for i in 0..3 {
+                let t = a * b;
+                let u = t + i;
+                let q = a / b;
+                sum = sum + u;
+            }
+            sum
+        }"#;
+        let (main, changes) = optimized(src, &[Pass::Licm]);
+        assert!(main.contains("let t = a * b;\n    for i in 0..3 {\n        let u = t + i;\n        let q = a / b;"), "{}", main);
+        assert_eq!(changes, [("licm", "main".to_string())]);
+    }
+
+    #[test]
+    fn pass_lists_and_diff() {
+        assert_eq!(Pass::parse_list("all"), Ok(Pass::ALL.to_vec()));
+        assert_eq!(Pass::parse_list("dce,fold"), Ok(vec![Pass::Dce, Pass::Fold]));
+        assert_eq!(
+            Pass::parse_list("fold,inline"),
+            Err("unknown pass inline (expected fold, propagate, dce, licm or all)".to_string())
+        );
+        assert_eq!(diff("a\nb\nc\n", "a\nx\nc\n"), "  a\n- b\n+ x\n  c\n");
+    }
+}
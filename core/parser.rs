@@ -1,23 +1,39 @@
-use crate::ast::{Expr, Stmt, Function};
-use crate::lexer::Token;
+use crate::ast::{Expr, ExternFn, ExternType, Import, MatchArm, Pattern, Stmt, StmtKind, Function, Program, StructDef, Type};
+use std::cell::Cell;
+
+use crate::lexer::{Span, Token};
 
 pub struct Parser {
-    tokens: Vec<Token>,
+    tokens: Vec<(Token, Span)>,
     pos: usize,
+    // самый дальний просмотренный токен: возврата нет, так что ошибка — там
+    seen: Cell<usize>,
+    // в заголовке `for` литерал структуры не разбираем: `0..n { }` — это тело цикла
+    no_struct_lit: bool,
 }
 
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, pos: 0 }
+    pub fn new(tokens: Vec<(Token, Span)>) -> Self {
+        Self { tokens, pos: 0, seen: Cell::new(0), no_struct_lit: false }
+    }
+
+    /// Где и почему разбор вернул None: токен, дальше которого парсер не продвинулся.
+    pub fn error(&self) -> (Span, String) {
+        match self.tokens.get(self.seen.get()) {
+            Some((tok, span)) => (span.clone(), format!("unexpected token {:?}", tok)),
+            None => (self.end()..self.end(), "unexpected end of input".to_string()),
+        }
     }
 
     fn peek(&self) -> Option<&Token> {
-        self.tokens.get(self.pos)
+        self.seen.set(self.seen.get().max(self.pos));
+        self.tokens.get(self.pos).map(|(t, _)| t)
     }
 
     fn next(&mut self) -> Option<Token> {
+        self.seen.set(self.seen.get().max(self.pos));
         if self.pos < self.tokens.len() {
-            let tok = self.tokens[self.pos].clone();
+            let tok = self.tokens[self.pos].0.clone();
             self.pos += 1;
             Some(tok)
         } else {
@@ -25,6 +41,15 @@ impl Parser {
         }
     }
 
+    // Начало текущего токена и конец последнего съеденного — из них собираются span'ы.
+    fn start(&self) -> usize {
+        self.tokens.get(self.pos).map_or_else(|| self.end(), |(_, sp)| sp.start)
+    }
+
+    fn end(&self) -> usize {
+        if self.pos == 0 { 0 } else { self.tokens[self.pos - 1].1.end }
+    }
+
     fn expect(&mut self, expected: Token) -> Option<()> {
         match self.next()? {
             t if t == expected => Some(()),
@@ -32,20 +57,230 @@ impl Parser {
         }
     }
 
-    fn lookahead_is_eq(&self) -> bool {
-        if self.pos + 1 >= self.tokens.len() {return false;}
-        matches!(self.tokens[self.pos + 1], Token::Eq)
+
+    // Подряд идущие `///` перед fn склеиваются в одну строку документации.
+    fn parse_doc(&mut self) -> Option<String> {
+        let mut lines = Vec::new();
+        while let Some(Token::DocComment(text)) = self.peek() {
+            lines.push(text.clone());
+            self.next();
+        }
+        if lines.is_empty() { None } else { Some(lines.join("\n")) }
+    }
+
+    pub fn parse_program(&mut self) -> Option<Program> {
+        let mut imports = Vec::new();
+        let mut externs = Vec::new();
+        let mut structs = Vec::new();
+        let mut functions = Vec::new();
+        while self.peek().is_some() {
+            let doc = self.parse_doc();
+            match self.peek()? {
+                Token::Struct => structs.push(self.parse_struct(doc)?),
+                Token::Import | Token::Use => imports.push(self.parse_import()?),
+                Token::Extern => externs.push(self.parse_extern()?),
+                _ => functions.push(self.parse_function(doc)?),
+            }
+        }
+        Some(Program { imports, externs, structs, functions })
     }
 
+    // import "math.src";  |  use math::abs;
+    fn parse_import(&mut self) -> Option<Import> {
+        let start = self.start();
+        let import = match self.next()? {
+            Token::Import => {
+                let path = if let Token::Str(p) = self.next()? { p } else { return None };
+                self.expect(Token::Semicolon)?;
+                Import::File { path, span: start..self.end() }
+            }
+            Token::Use => {
+                let module = if let Token::Ident(m) = self.next()? { m } else { return None };
+                self.expect(Token::ColonColon)?;
+                let item = if let Token::Ident(i) = self.next()? { i } else { return None };
+                self.expect(Token::Semicolon)?;
+                Import::Use { module, item, span: start..self.end() }
+            }
+            _ => return None,
+        };
+        Some(import)
+    }
 
-    pub fn parse_function(&mut self) -> Option<Function> {
+    // extern fn name(a: T, ...) [-> T];  — типы параметров обязательны
+    fn parse_extern(&mut self) -> Option<ExternFn> {
+        let start = self.start();
+        self.expect(Token::Extern)?;
         self.expect(Token::Fn)?;
         let name = if let Token::Ident(n) = self.next()? { n } else { return None };
         self.expect(Token::LParen)?;
+        let mut params = Vec::new();
+        while !matches!(self.peek()?, Token::RParen) {
+            let p = if let Token::Ident(p) = self.next()? { p } else { return None };
+            self.expect(Token::Colon)?;
+            params.push((p, self.parse_extern_type()?));
+            if matches!(self.peek(), Some(Token::Comma)) {
+                self.next();
+            }
+        }
         self.expect(Token::RParen)?;
+        let ret = if matches!(self.peek(), Some(Token::Arrow)) {
+            self.next();
+            self.parse_extern_type()?
+        } else {
+            ExternType::Void
+        };
+        self.expect(Token::Semicolon)?;
+        Some(ExternFn { name, params, ret, span: start..self.end() })
+    }
+
+    fn parse_extern_type(&mut self) -> Option<ExternType> {
+        match self.peek()? {
+            Token::Ident(n) if n == "i32" => {
+                self.next();
+                Some(ExternType::I32)
+            }
+            Token::Ident(n) if n == "void" => {
+                self.next();
+                Some(ExternType::Void)
+            }
+            _ => Some(ExternType::Value(self.parse_type()?)),
+        }
+    }
+
+    // `math::abs` — имя с модулем склеивается в одну строку, как его и называет загрузчик.
+    fn parse_path(&mut self, first: String) -> Option<String> {
+        let mut name = first;
+        while matches!(self.peek(), Some(Token::ColonColon)) {
+            self.next();
+            let seg = if let Token::Ident(s) = self.next()? { s } else { return None };
+            name = format!("{}::{}", name, seg);
+        }
+        Some(name)
+    }
+
+    pub fn parse_function(&mut self, doc: Option<String>) -> Option<Function> {
+        let start = self.start();
+        self.expect(Token::Fn)?;
+        let name = if let Token::Ident(n) = self.next()? { n } else { return None };
+        self.expect(Token::LParen)?;
+        let params = self.parse_params(Token::RParen)?;
+        let ret = self.parse_ret()?;
         self.expect(Token::LBrace)?;
         let  body = self.parse_block_stmts()?;
-        Some(Function { name, body })
+        Some(Function { name, doc, params, ret, body, span: start..self.end() })
+    }
+
+    // `a, b: bool, f: fn(i64) -> i64` до закрывающего токена (`)` у fn, `|` у лямбды).
+    fn parse_params(&mut self, close: Token) -> Option<Vec<(String, Type)>> {
+        let mut params = Vec::new();
+        while *self.peek()? != close {
+            let p = if let Token::Ident(p) = self.next()? { p } else { return None };
+            // без аннотации параметр — i64, как раньше
+            let ty = if matches!(self.peek(), Some(Token::Colon)) {
+                self.next();
+                self.parse_type()?
+            } else {
+                Type::Int
+            };
+            params.push((p, ty));
+            // между параметрами — обязательная запятая, висячая допустима
+            if *self.peek()? != close {
+                self.expect(Token::Comma)?;
+            }
+        }
+        self.expect(close)?;
+        Some(params)
+    }
+
+    // необязательное `-> T`; по умолчанию i64
+    fn parse_ret(&mut self) -> Option<Type> {
+        if matches!(self.peek(), Some(Token::Arrow)) {
+            self.next();
+            self.parse_type()
+        } else {
+            Some(Type::Int)
+        }
+    }
+
+    // struct Point { x: i64, y: i64 }
+    fn parse_struct(&mut self, doc: Option<String>) -> Option<StructDef> {
+        let start = self.start();
+        self.expect(Token::Struct)?;
+        let name = if let Token::Ident(n) = self.next()? { n } else { return None };
+        self.expect(Token::LBrace)?;
+        let mut fields = Vec::new();
+        while !matches!(self.peek()?, Token::RBrace) {
+            let field = if let Token::Ident(f) = self.next()? { f } else { return None };
+            self.expect(Token::Colon)?;
+            fields.push((field, self.parse_type()?));
+            if matches!(self.peek(), Some(Token::Comma)) {
+                self.next();
+            }
+        }
+        self.expect(Token::RBrace)?;
+        Some(StructDef { name, doc, fields, span: start..self.end() })
+    }
+
+    // i64 | bool | char | str | [T; N] | *T | fn(T, ...) -> T | ИмяСтруктуры
+    fn parse_type(&mut self) -> Option<Type> {
+        match self.next()? {
+            Token::Fn => {
+                self.expect(Token::LParen)?;
+                let mut params = Vec::new();
+                while !matches!(self.peek()?, Token::RParen) {
+                    params.push(self.parse_type()?);
+                    if matches!(self.peek(), Some(Token::Comma)) {
+                        self.next();
+                    }
+                }
+                self.expect(Token::RParen)?;
+                Some(Type::Fn(params, Box::new(self.parse_ret()?)))
+            }
+            Token::Star => Some(Type::Ptr(Box::new(self.parse_type()?))),
+            Token::Ident(name) => Some(match name.as_str() {
+                "i64" => Type::Int,
+                "bool" => Type::Bool,
+                "char" => Type::Char,
+                "str" => Type::Str,
+                _ => Type::Struct(self.parse_path(name)?),
+            }),
+            Token::LBracket => {
+                let elem = self.parse_type()?;
+                if matches!(self.peek(), Some(Token::RBracket)) {
+                    self.next();
+                    return Some(Type::Slice(Box::new(elem)));
+                }
+                self.expect(Token::Semicolon)?;
+                let n = if let Token::Number(n) = self.next()? { n } else { return None };
+                self.expect(Token::RBracket)?;
+                Some(Type::Array(Box::new(elem), usize::try_from(n).ok()?))
+            }
+            _ => None,
+        }
+    }
+
+    // `Name { field: ...` или `Name { }` — литерал структуры, а не начало блока.
+    fn at_struct_lit(&self) -> bool {
+        if self.no_struct_lit {
+            return false;
+        }
+        let tok = |i: usize| self.tokens.get(self.pos + i).map(|(t, _)| t);
+        matches!(tok(0), Some(Token::LBrace))
+            && (matches!(tok(1), Some(Token::RBrace))
+                || matches!((tok(1), tok(2)), (Some(Token::Ident(_)), Some(Token::Colon))))
+    }
+
+    // `(a, b, c)` после имени функции; открывающая скобка уже съедена.
+    fn parse_args(&mut self) -> Option<Vec<Expr>> {
+        let mut args = Vec::new();
+        while !matches!(self.peek()?, Token::RParen) {
+            args.push(self.parse_expr()?);
+            if !matches!(self.peek()?, Token::RParen) {
+                self.expect(Token::Comma)?;
+            }
+        }
+        self.expect(Token::RParen)?;
+        Some(args)
     }
 
     fn parse_block_stmts(&mut self) -> Option<Vec<Stmt>> {
@@ -55,6 +290,10 @@ impl Parser {
                 Token::RBrace => {
                     self.next(); break;
                 }
+                // doc-комментарий внутри тела ни к чему не относится
+                Token::DocComment(_) => {
+                    self.next();
+                }
                 _ => {
                     let st = self.parse_stmt()?;
                     out.push(st);
@@ -65,29 +304,36 @@ impl Parser {
     }
 
     fn parse_stmt(&mut self) -> Option<Stmt> {
+        let start = self.start();
+        let kind = self.parse_stmt_kind()?;
+        Some(Stmt { kind, span: start..self.end() })
+    }
+
+    fn parse_stmt_kind(&mut self) -> Option<StmtKind> {
         match self.peek()? {
             Token::Let => {
                 self.next(); // consume `let`
                 let name = if let Token::Ident(n) = self.next()? { n } else { return None;};
+                let ty = if matches!(self.peek(), Some(Token::Colon)) {
+                    self.next();
+                    Some(self.parse_type()?)
+                } else {
+                    None
+                };
                 self.expect(Token::Eq)?;
                 let expr =  self.parse_expr()?;
                 self.expect(Token::Semicolon)?;
-            Some(Stmt::Let(name, expr))
+            Some(StmtKind::Let(name, ty, expr))
         }
-        Token::If => {
-            self.next();
-            self.expect(Token::LParen)?;
-            let cond = self.parse_expr()?;
-            self.expect(Token::RParen)?;
-            self.expect(Token::LBrace)?;
-            let then_body = self.parse_block_stmts()?;
-
-            let else_body = if matches!(self.peek(), Some(Token::Else)) {
+        // `if`/блок в начале оператора — выражение без `;` (как в Rust): иначе
+        // `if (c) { } *p = 1;` разобралось бы как умножение
+        Token::If | Token::LBrace | Token::Match => {
+            let expr = self.parse_primary()?;
+            if matches!(self.peek(), Some(Token::Semicolon)) {
                 self.next();
-                self.expect(Token::LBrace)?;
-                Some(self.parse_block_stmts()?)
-            } else {None };
-            Some(Stmt::If { cond, then_body, else_body})
+                return Some(StmtKind::Semi(expr));
+            }
+            Some(StmtKind::Expr(expr))
         }
         Token::While => {
             self.next();
@@ -96,25 +342,76 @@ impl Parser {
             self.expect(Token::RParen)?;
             self.expect(Token::LBrace)?;
             let body = self.parse_block_stmts()?;
-            Some(Stmt::While { cond, body })
+            Some(StmtKind::While { cond, body })
         }
-        Token::Ident(name) if self.lookahead_is_eq() => {
-            let _name = name.clone();
+        Token::For => {
             self.next();
-            self.expect(Token::Eq)?;
-            let expr = self.parse_expr()?;
-            self.expect(Token::Semicolon)?;
-            Some(Stmt::Expr(expr))
-        } 
+            let var = if let Token::Ident(v) = self.next()? { v } else { return None };
+            self.expect(Token::In)?;
+            self.no_struct_lit = true;
+            let range = self.parse_range();
+            self.no_struct_lit = false;
+            let (start, end, inclusive, step) = range?;
+            self.expect(Token::LBrace)?;
+            let body = self.parse_block_stmts()?;
+            Some(StmtKind::For { var, start, end, inclusive, step, body })
+        }
         _ => {
             let expr = self.parse_expr()?;
+            // `x = ...` / `a[i] = ...`: присваивать можно только в место в памяти
+            if matches!(self.peek(), Some(Token::Eq)) {
+                if !matches!(expr, Expr::Ident(_) | Expr::Index { .. } | Expr::Field(..) | Expr::Deref(..)) {
+                    return None;
+                }
+                self.next();
+                let value = self.parse_expr()?;
+                self.expect(Token::Semicolon)?;
+                return Some(StmtKind::Assign(expr, value));
+            }
+            // последнее выражение блока можно писать без `;` — это его значение
+            if matches!(self.peek(), Some(Token::RBrace)) {
+                return Some(StmtKind::Expr(expr));
+            }
             self.expect(Token::Semicolon)?;
-            Some(Stmt::Expr(expr))
+            Some(StmtKind::Semi(expr))
+            }
+        }
+    }
+    // `a..b`, `a..=b`, необязательно `step k` / `step -k` (k — литерал, не 0).
+    fn parse_range(&mut self) -> Option<(Expr, Expr, bool, i64)> {
+        let start = self.parse_expr()?;
+        let inclusive = match self.next()? {
+            Token::DotDot => false,
+            Token::DotDotEq => true,
+            _ => return None,
+        };
+        let end = self.parse_expr()?;
+        let mut step = 1;
+        if matches!(self.peek(), Some(Token::Ident(s)) if s == "step") {
+            self.next();
+            let negative = matches!(self.peek(), Some(Token::Minus));
+            if negative {
+                self.next();
+            }
+            step = if let Token::Number(n) = self.next()? { n } else { return None };
+            if negative {
+                step = -step;
+            }
+            if step == 0 {
+                return None;
             }
         }
+        Some((start, end, inclusive, step))
     }
+
     fn parse_expr(&mut self) -> Option<Expr> { self.parse_cmp()}
 
+    /// Одно выражение на весь ввод — для print/watch в отладчике.
+    pub fn parse_expression(&mut self) -> Option<Expr> {
+        let expr = self.parse_expr()?;
+        if self.pos == self.tokens.len() { Some(expr) } else { None }
+    }
+
     fn parse_cmp(&mut self) -> Option<Expr> {
         let mut left = self.parse_add()?;
         loop {
@@ -139,7 +436,7 @@ impl Parser {
         loop {
             let op = match self.peek() {
                 Some(Token::Plus) => "+",
-                Some(Token::EqEq) => "-",
+                Some(Token::Minus) => "-",
                 _ => break,
             }.to_string();
             self.next();
@@ -149,7 +446,7 @@ impl Parser {
         Some(left)
     }
     fn parse_mul(&mut self) -> Option<Expr> {
-        let mut left = self.parse_primary()?;
+        let mut left = self.parse_unary()?;
         loop {
                         let op = match self.peek() {
                 Some(Token::Star) => "*",
@@ -157,22 +454,304 @@ impl Parser {
                 _ => break,
             }.to_string();
             self.next();
-            let right = self.parse_primary()?;
+            let right = self.parse_unary()?;
             left = Expr::BinaryOp(Box::new(left), op, Box::new(right));
         }
                Some(left)
     }
 
+    // `&x`, `*p`, `**pp` — префиксные, слабее постфиксных: `*p.next` это `*(p.next)`.
+    fn parse_unary(&mut self) -> Option<Expr> {
+        let start = self.start();
+        match self.peek() {
+            Some(Token::Amp) => {
+                self.next();
+                Some(Expr::AddrOf(Box::new(self.parse_unary()?)))
+            }
+            Some(Token::Star) => {
+                self.next();
+                let inner = self.parse_unary()?;
+                Some(Expr::Deref(Box::new(inner), start..self.end()))
+            }
+            _ => self.parse_postfix(),
+        }
+    }
+
+    // `a[i][j]`, `p.x`, `f(1)(2)` — индексация (span от начала базы до `]`),
+    // доступ к полю и вызов значения-функции.
+    fn parse_postfix(&mut self) -> Option<Expr> {
+        let start = self.start();
+        let mut expr = self.parse_primary()?;
+        loop {
+            match self.peek() {
+                Some(Token::LBracket) => {
+                    self.next();
+                    let index = self.parse_expr()?;
+                    self.expect(Token::RBracket)?;
+                    expr = Expr::Index { array: Box::new(expr), index: Box::new(index), span: start..self.end() };
+                }
+                Some(Token::Dot) => {
+                    self.next();
+                    let field = if let Token::Ident(f) = self.next()? { f } else { return None };
+                    expr = Expr::Field(Box::new(expr), field);
+                }
+                Some(Token::LParen) => {
+                    self.next();
+                    let args = self.parse_args()?;
+                    expr = Expr::Apply(Box::new(expr), args);
+                }
+                _ => break,
+            }
+        }
+        Some(expr)
+    }
+
     fn parse_primary(&mut self) -> Option<Expr> {
         match self.next()? {
             Token::Number(n) => Some(Expr::Number(n)),
-            Token::Ident(name) => Some(Expr::Ident(name)),
+            Token::Bool(b) => Some(Expr::Bool(b)),
+            Token::Char(c) => Some(Expr::Char(c)),
+            Token::Str(s) => Some(Expr::Str(s)),
+            Token::Null => Some(Expr::Null),
+            Token::Ident(first) => {
+                let name = self.parse_path(first)?;
+                if matches!(self.peek(), Some(Token::LParen)) {
+                    self.next();
+                    let args = self.parse_args()?;
+                    return Some(Expr::Call(name, args));
+                }
+                if !self.at_struct_lit() {
+                    return Some(Expr::Ident(name));
+                }
+                self.next();
+                let mut fields = Vec::new();
+                while !matches!(self.peek()?, Token::RBrace) {
+                    let field = if let Token::Ident(f) = self.next()? { f } else { return None };
+                    self.expect(Token::Colon)?;
+                    fields.push((field, self.parse_expr()?));
+                    if matches!(self.peek(), Some(Token::Comma)) {
+                        self.next();
+                    }
+                }
+                self.expect(Token::RBrace)?;
+                Some(Expr::StructLit(name, fields))
+            }
             Token::LParen => {
                 let e = self.parse_expr()?;
                 self.expect(Token::RParen)?;
                 Some(e)
             }
+            Token::LBracket => self.parse_array(),
+            Token::Pipe => self.parse_lambda(),
+            Token::If => self.parse_if(),
+            Token::Match => self.parse_match(),
+            Token::LBrace => Some(Expr::Block(self.parse_block_stmts()?)),
             _ => None,
         }
     }
+
+    // `if cond { ... } else if cond { ... } else { ... }`; `if` уже съеден.
+    // Скобки вокруг условия не обязательны: `(x == 5)` — просто выражение в скобках.
+    fn parse_if(&mut self) -> Option<Expr> {
+        let saved = self.no_struct_lit;
+        self.no_struct_lit = true;
+        let cond = self.parse_expr();
+        self.no_struct_lit = saved;
+        let cond = cond?;
+        self.expect(Token::LBrace)?;
+        let then_body = self.parse_block_stmts()?;
+        let else_body = if matches!(self.peek(), Some(Token::Else)) {
+            self.next();
+            if matches!(self.peek(), Some(Token::If)) {
+                let start = self.start();
+                self.next();
+                let nested = self.parse_if()?;
+                Some(vec![Stmt { kind: StmtKind::Expr(nested), span: start..self.end() }])
+            } else {
+                self.expect(Token::LBrace)?;
+                Some(self.parse_block_stmts()?)
+            }
+        } else {
+            None
+        };
+        Some(Expr::If { cond: Box::new(cond), then_body, else_body })
+    }
+
+    // `match x { 1 | 2 => a, 3..=9 => { b } _ => c }`; `match` уже съеден.
+    // Запятая после ветки-блока не обязательна.
+    fn parse_match(&mut self) -> Option<Expr> {
+        let start = self.tokens[self.pos - 1].1.start;
+        let saved = self.no_struct_lit;
+        self.no_struct_lit = true;
+        let scrutinee = self.parse_expr();
+        self.no_struct_lit = saved;
+        let scrutinee = scrutinee?;
+        self.expect(Token::LBrace)?;
+        let mut arms = Vec::new();
+        while !matches!(self.peek()?, Token::RBrace) {
+            let mut patterns = Vec::new();
+            loop {
+                let p_start = self.start();
+                let p = self.parse_pattern()?;
+                patterns.push((p, p_start..self.end()));
+                if !matches!(self.peek(), Some(Token::Pipe)) {
+                    break;
+                }
+                self.next();
+            }
+            self.expect(Token::FatArrow)?;
+            let body = self.parse_expr()?;
+            let is_block = matches!(body, Expr::Block(_) | Expr::If { .. } | Expr::Match { .. });
+            match self.peek()? {
+                Token::Comma => {
+                    self.next();
+                }
+                Token::RBrace => {}
+                _ if is_block => {}
+                _ => return None,
+            }
+            arms.push(MatchArm { patterns, body });
+        }
+        self.expect(Token::RBrace)?;
+        Some(Expr::Match { scrutinee: Box::new(scrutinee), arms, span: start..self.end() })
+    }
+
+    // `_`, `5`, `-1`, `'a'`, `1..=9`, `0..10` (без правой границы)
+    fn parse_pattern(&mut self) -> Option<Pattern> {
+        if matches!(self.peek(), Some(Token::Ident(n)) if n == "_") {
+            self.next();
+            return Some(Pattern::Wildcard);
+        }
+        let lo = self.parse_pattern_int()?;
+        match self.peek() {
+            Some(Token::DotDotEq) => {
+                self.next();
+                Some(Pattern::Range(lo, self.parse_pattern_int()?))
+            }
+            Some(Token::DotDot) => {
+                self.next();
+                Some(Pattern::Range(lo, self.parse_pattern_int()?.checked_sub(1)?))
+            }
+            _ => Some(Pattern::Int(lo)),
+        }
+    }
+
+    fn parse_pattern_int(&mut self) -> Option<i64> {
+        match self.next()? {
+            Token::Number(n) => Some(n),
+            Token::Char(c) => Some(c as i64),
+            Token::Minus => match self.next()? {
+                Token::Number(n) => Some(-n),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    // `|x, y| x + y` или `|x| -> bool { ...; }`; первая `|` уже съедена.
+    fn parse_lambda(&mut self) -> Option<Expr> {
+        let params = self.parse_params(Token::Pipe)?;
+        let ret = self.parse_ret()?;
+        let body = if matches!(self.peek(), Some(Token::LBrace)) {
+            self.next();
+            self.parse_block_stmts()?
+        } else {
+            let start = self.start();
+            let e = self.parse_expr()?;
+            vec![Stmt { kind: StmtKind::Expr(e), span: start..self.end() }]
+        };
+        Some(Expr::Lambda { params, ret, body })
+    }
+
+    // `[a, b, c]` или `[elem; N]`, где N — литерал; `[` уже съеден.
+    fn parse_array(&mut self) -> Option<Expr> {
+        let mut elems = Vec::new();
+        while !matches!(self.peek()?, Token::RBracket) {
+            let e = self.parse_expr()?;
+            if elems.is_empty() && matches!(self.peek(), Some(Token::Semicolon)) {
+                self.next();
+                let n = if let Token::Number(n) = self.next()? { n } else { return None };
+                self.expect(Token::RBracket)?;
+                return Some(Expr::ArrayRepeat(Box::new(e), usize::try_from(n).ok()?));
+            }
+            elems.push(e);
+            if matches!(self.peek(), Some(Token::Comma)) {
+                self.next();
+            }
+        }
+        self.expect(Token::RBracket)?;
+        Some(Expr::Array(elems))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn parse(src: &str) -> Option<Program> {
+        Parser::new(Lexer::new(src).tokenize_spanned()).parse_program()
+    }
+
+    #[test]
+    fn doc_comments_attach_to_the_next_item() {
+        let program = parse("/// Adds.\n/// Twice.\nfn f() { 1 }\n// plain\nfn g() { 2 }\n/// Point.\nstruct P { x: i64 }")
+            .expect("parse");
+        assert_eq!(program.functions[0].doc.as_deref(), Some("Adds.\nTwice."));
+        assert_eq!(program.functions[1].doc, None);
+        assert_eq!(program.structs[0].doc.as_deref(), Some("Point."));
+    }
+
+    #[test]
+    fn doc_comment_inside_a_body_is_ignored() {
+        let program = parse("fn f() {\n    /// not a doc\n    1\n}").expect("parse");
+        assert_eq!(program.functions[0].body.len(), 1);
+    }
+
+    #[test]
+    fn commas_are_required_between_params_and_args() {
+        assert!(parse("fn f(a, b: bool) { a }\nfn main() { f(1, true) }").is_some());
+        assert!(parse("fn f(a, b,) { a }\nfn main() { f(1, 2,) }").is_some());
+        assert!(parse("fn f(a b) { a }").is_none());
+        assert!(parse("fn main() { f(1 2) }").is_none());
+        assert!(parse("fn main() { let g = |x y| x; 0 }").is_none());
+    }
+
+    #[test]
+    fn trailing_semicolon_is_kept_in_the_ast() {
+        let program = parse("fn f() { g(); { 1 } if (x) { 2; } else { 3 }; 4 }").expect("parse");
+        let kinds: Vec<&str> = program.functions[0]
+            .body
+            .iter()
+            .map(|s| match s.kind {
+                StmtKind::Expr(_) => "expr",
+                StmtKind::Semi(_) => "semi",
+                _ => "other",
+            })
+            .collect();
+        assert_eq!(kinds, ["semi", "expr", "semi", "expr"]);
+    }
+
+    #[test]
+    fn match_patterns() {
+        let program = parse("fn f(x) { match x { 0 => 1, -1 | 'a' => 2, 3..7 => 3, 7..=9 => { 4 } _ => 5 } }")
+            .expect("parse");
+        let Some(StmtKind::Expr(Expr::Match { arms, .. })) = program.functions[0].body.first().map(|s| &s.kind) else {
+            panic!("expected match");
+        };
+        let patterns: Vec<Vec<Pattern>> =
+            arms.iter().map(|a| a.patterns.iter().map(|(p, _)| p.clone()).collect()).collect();
+        assert_eq!(
+            patterns,
+            [
+                vec![Pattern::Int(0)],
+                vec![Pattern::Int(-1), Pattern::Int('a' as i64)],
+                vec![Pattern::Range(3, 6)],
+                vec![Pattern::Range(7, 9)],
+                vec![Pattern::Wildcard],
+            ]
+        );
+        // без запятой после простого выражения — ошибка
+        assert!(parse("fn f(x) { match x { 0 => 1 _ => 2 } }").is_none());
+    }
 }
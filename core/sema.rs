@@ -0,0 +1,758 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::{Expr, ExternFn, Function, MatchArm, Program, Stmt, StmtKind, StructDef, Type};
+use crate::host::HostFunction;
+use crate::interpreter::BUILTINS;
+use crate::lexer::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// Сообщение семантической проверки с местом в исходнике.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+}
+
+/// Переменная функции и её тип, если он известен; `at` — начало оператора, который её
+/// ввёл (у параметров — начало fn).
+#[derive(Debug, Clone)]
+pub struct Binding {
+    pub name: String,
+    pub ty: Option<Type>,
+    pub at: usize,
+}
+
+/// Проверки после парсинга, до запуска. Ошибки останавливают запуск, предупреждения — нет.
+pub fn check_program(program: &Program) -> Vec<Diagnostic> {
+    let mut sema = Sema::new(program);
+    for f in &program.functions {
+        sema.function(f);
+    }
+    sema.diagnostics
+}
+
+/// Проверки перед Codegen: вдобавок к check_program вызов функции хоста без
+/// `with_native` — ошибка: в скомпилированном коде её нечем вызвать.
+pub fn check_compiled(program: &Program, hosts: &[HostFunction]) -> Vec<Diagnostic> {
+    let mut sema = Sema::new(program);
+    sema.interpreted_only = hosts.iter().filter(|h| h.native.is_none()).map(|h| h.sig.name.clone()).collect();
+    for f in &program.functions {
+        sema.function(f);
+    }
+    sema.diagnostics
+}
+
+/// Переменные функции в порядке появления (имя может повториться) — для подсказок LSP.
+pub fn bindings(program: &Program, func: &Function) -> Vec<Binding> {
+    let mut sema = Sema::new(program);
+    sema.function(func);
+    sema.bindings
+}
+
+struct Sema<'a> {
+    diagnostics: Vec<Diagnostic>,
+    // extern fn и функции хоста: их вызовы проверяются по сигнатуре
+    externs: HashMap<String, &'a ExternFn>,
+    // функции хоста без адреса для JIT (check_compiled)
+    interpreted_only: HashSet<String>,
+    // типы результатов функций — для вывода типов аргументов
+    rets: HashMap<String, Type>,
+    // число параметров функций — для проверки вызовов
+    arities: HashMap<String, usize>,
+    // поля структур — для проверки литералов
+    structs: HashMap<String, &'a StructDef>,
+    // переменные функции и их тип, если он известен
+    locals: HashMap<String, Option<Type>>,
+    // текущий оператор: у вызова своего span нет
+    at: Span,
+    bindings: Vec<Binding>,
+}
+
+impl<'a> Sema<'a> {
+    fn new(program: &'a Program) -> Self {
+        Sema {
+            diagnostics: Vec::new(),
+            externs: program.externs.iter().map(|e| (e.name.clone(), e)).collect(),
+            interpreted_only: HashSet::new(),
+            rets: program.functions.iter().map(|f| (f.name.clone(), f.ret.clone())).collect(),
+            arities: program.functions.iter().map(|f| (f.name.clone(), f.params.len())).collect(),
+            structs: program.structs.iter().map(|s| (s.name.clone(), s)).collect(),
+            locals: HashMap::new(),
+            at: 0..0,
+            bindings: Vec::new(),
+        }
+    }
+
+    fn function(&mut self, f: &Function) {
+        self.locals.clear();
+        self.at = f.span.clone();
+        // вызов `print(...)` всегда встроенный: одноимённую функцию было бы не вызвать
+        if BUILTINS.contains(&f.name.as_str()) {
+            let message = format!("`{}` is a builtin function and cannot be redefined", f.name);
+            self.report(Severity::Error, message, f.span.clone());
+        }
+        for (n, t) in &f.params {
+            self.bind(n, Some(t.clone()));
+        }
+        self.body(&f.body);
+        // значение тела — результат функции; так проверяются и результаты функций хоста
+        if let Some(Stmt { kind: StmtKind::Expr(e), span }) = f.body.last() {
+            if let Some(got) = self.infer(e).filter(|got| !assignable(&f.ret, got)) {
+                self.report(
+                    Severity::Error,
+                    format!("`{}` returns {}, but its body gives {}", f.name, type_name(&f.ret), type_name(&got)),
+                    span.clone(),
+                );
+            }
+        }
+    }
+
+    fn bind(&mut self, name: &str, ty: Option<Type>) {
+        if BUILTINS.contains(&name) && matches!(ty, Some(Type::Fn(..))) {
+            let message = format!("`{}` is a builtin function and cannot be redefined", name);
+            self.report(Severity::Error, message, self.at.clone());
+        }
+        self.locals.insert(name.to_string(), ty.clone());
+        self.bindings.push(Binding { name: name.to_string(), ty, at: self.at.start });
+    }
+
+    fn report(&mut self, severity: Severity, message: String, span: Span) {
+        self.diagnostics.push(Diagnostic { severity, message, span });
+    }
+
+    fn body(&mut self, body: &[Stmt]) {
+        for s in body {
+            self.stmt(s);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        self.at = stmt.span.clone();
+        match &stmt.kind {
+            StmtKind::Let(name, ty, e) => {
+                self.expr(e);
+                if let (Some(expected), Some(got)) = (ty, self.infer(e)) {
+                    if !assignable(expected, &got) {
+                        self.report(
+                            Severity::Error,
+                            format!("`{}` is declared as {}, but the value is {}", name, type_name(expected), type_name(&got)),
+                            self.at.clone(),
+                        );
+                    }
+                }
+                let ty = ty.clone().or_else(|| self.infer(e));
+                self.bind(name, ty);
+            }
+            StmtKind::Expr(e) | StmtKind::Semi(e) => self.expr(e),
+            StmtKind::Assign(target, e) => {
+                self.expr(target);
+                self.expr(e);
+                // присваивание не меняет тип места: bool в i64 не расширяем, иначе
+                // интерпретатор хранил бы bool, а Codegen — 0/1
+                if let (Some(expected), Some(got)) = (self.infer(target), self.infer(e)) {
+                    if !fits(&expected, &got) {
+                        let place = match target {
+                            Expr::Ident(name) => format!("`{}`", name),
+                            _ => "a place".to_string(),
+                        };
+                        self.report(
+                            Severity::Error,
+                            format!("cannot assign {} to {} of type {}", type_name(&got), place, type_name(&expected)),
+                            self.at.clone(),
+                        );
+                    }
+                }
+            }
+            StmtKind::While { cond, body } => {
+                self.expr(cond);
+                self.body(body);
+            }
+            StmtKind::For { var, start, end, body, .. } => {
+                self.expr(start);
+                self.expr(end);
+                self.bind(var, Some(Type::Int));
+                self.body(body);
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Number(_) | Expr::Bool(_) | Expr::Char(_) | Expr::Str(_) | Expr::Null | Expr::Ident(_) => {}
+            Expr::BinaryOp(l, _, r) => {
+                self.expr(l);
+                self.expr(r);
+            }
+            Expr::Call(name, args) => {
+                args.iter().for_each(|a| self.expr(a));
+                self.check_callee(name, args);
+            }
+            Expr::Apply(callee, args) => {
+                self.expr(callee);
+                args.iter().for_each(|a| self.expr(a));
+                if let Some(Type::Fn(params, _)) = self.infer(callee) {
+                    self.check_arity("closure", params.len(), params.len(), args.len());
+                }
+            }
+            Expr::Array(elems) => elems.iter().for_each(|e| self.expr(e)),
+            Expr::ArrayRepeat(e, _) | Expr::Field(e, _) | Expr::AddrOf(e) | Expr::Deref(e, _) => self.expr(e),
+            Expr::Index { array, index, .. } => {
+                self.expr(array);
+                self.expr(index);
+            }
+            Expr::StructLit(name, fields) => {
+                fields.iter().for_each(|(_, e)| self.expr(e));
+                self.check_struct_lit(name, fields);
+            }
+            Expr::Lambda { params, body, .. } => {
+                for (n, t) in params {
+                    self.bind(n, Some(t.clone()));
+                }
+                self.body(body);
+            }
+            Expr::Block(body) => self.body(body),
+            Expr::If { cond, then_body, else_body } => {
+                self.expr(cond);
+                self.body(then_body);
+                if let Some(eb) = else_body {
+                    self.body(eb);
+                    // ветки вычисляются в одно значение (phi в Codegen) — тип у них один
+                    if let (Some(t), Some(e)) = (self.block_type(then_body), self.block_type(eb)) {
+                        if t != e {
+                            self.report(
+                                Severity::Error,
+                                format!("`if` and `else` have different types: {} and {}", type_name(&t), type_name(&e)),
+                                self.at.clone(),
+                            );
+                        }
+                    }
+                } else if let Some(t) = self.block_type(then_body).filter(|t| *t != Type::Int) {
+                    // без else значение — 0, как у блока без значения; другого типа взять неоткуда
+                    self.report(
+                        Severity::Error,
+                        format!("`if` without `else` cannot give {}: add `else` or end the branch with `;`", type_name(&t)),
+                        self.at.clone(),
+                    );
+                }
+            }
+            Expr::Match { scrutinee, arms, span } => {
+                self.expr(scrutinee);
+                for arm in arms {
+                    self.expr(&arm.body);
+                }
+                // как у if/else: ветки сходятся в одно значение
+                let types: Vec<Type> = arms.iter().filter_map(|arm| self.infer(&arm.body)).collect();
+                if let Some(other) = types.iter().find(|t| **t != types[0]) {
+                    self.report(
+                        Severity::Error,
+                        format!("match arms have different types: {} and {}", type_name(&types[0]), type_name(other)),
+                        span.clone(),
+                    );
+                }
+                self.check_match(arms, span);
+            }
+        }
+    }
+
+    // Имя вызова ищется так же, как в бэкендах: встроенная функция, замыкание в
+    // переменной, extern/функция хоста, функция программы. Переменная другого типа
+    // функцию с тем же именем не перекрывает.
+    fn check_callee(&mut self, name: &str, args: &[Expr]) {
+        if let Some((min, max)) = builtin_arity(name) {
+            self.check_arity(name, min, max, args.len());
+            return;
+        }
+        let local = self.locals.get(name).cloned();
+        match local {
+            Some(Some(Type::Fn(params, _))) => {
+                self.check_arity(name, params.len(), params.len(), args.len());
+                return;
+            }
+            // тип переменной неизвестен — может быть и замыканием
+            Some(None) => return,
+            _ => {}
+        }
+        if let Some(sig) = self.externs.get(name).copied() {
+            self.check_call(sig, args);
+            if self.interpreted_only.contains(name) {
+                self.report(
+                    Severity::Error,
+                    format!("host function `{}` has no native implementation and cannot be compiled", name),
+                    self.at.clone(),
+                );
+            }
+        } else if let Some(&n) = self.arities.get(name) {
+            self.check_arity(name, n, n, args.len());
+        } else if local.is_some() {
+            self.report(Severity::Error, format!("`{}` is not a function", name), self.at.clone());
+        } else {
+            self.report(Severity::Error, format!("call to undefined function `{}`", name), self.at.clone());
+        }
+    }
+
+    fn check_arity(&mut self, name: &str, min: usize, max: usize, got: usize) {
+        if (min..=max).contains(&got) {
+            return;
+        }
+        let expected = if min == max { min.to_string() } else { format!("{} to {}", min, max) };
+        self.report(
+            Severity::Error,
+            format!("`{}` expects {} argument(s), got {}", name, expected, got),
+            self.at.clone(),
+        );
+    }
+
+    // Число аргументов и их типы, где тип удаётся вывести (assignable).
+    fn check_call(&mut self, sig: &ExternFn, args: &[Expr]) {
+        if sig.params.len() != args.len() {
+            self.report(
+                Severity::Error,
+                format!("`{}` expects {} argument(s), got {}", sig.name, sig.params.len(), args.len()),
+                self.at.clone(),
+            );
+            return;
+        }
+        for (i, ((_, ty), arg)) in sig.params.iter().zip(args).enumerate() {
+            let expected = ty.lang();
+            let Some(got) = self.infer(arg) else { continue };
+            if !assignable(&expected, &got) {
+                self.report(
+                    Severity::Error,
+                    format!(
+                        "argument {} of `{}`: expected {}, got {}",
+                        i + 1,
+                        sig.name,
+                        type_name(&expected),
+                        type_name(&got)
+                    ),
+                    self.at.clone(),
+                );
+            }
+        }
+    }
+
+    // Литерал задаёт каждое поле структуры ровно один раз, и тип значения — тип поля
+    // (массив годится для `[T]`). У литерала своего span нет — место оператора, как у вызова.
+    fn check_struct_lit(&mut self, name: &str, inits: &[(String, Expr)]) {
+        let Some(def) = self.structs.get(name).copied() else {
+            self.report(Severity::Error, format!("unknown struct `{}`", name), self.at.clone());
+            return;
+        };
+        for (i, (field, value)) in inits.iter().enumerate() {
+            let Some((_, ty)) = def.fields.iter().find(|(f, _)| f == field) else {
+                self.report(Severity::Error, format!("struct `{}` has no field `{}`", name, field), self.at.clone());
+                continue;
+            };
+            if inits[..i].iter().any(|(f, _)| f == field) {
+                self.report(Severity::Error, format!("field `{}` is set twice", field), self.at.clone());
+                continue;
+            }
+            let Some(got) = self.infer(value) else { continue };
+            if !fits(ty, &got) {
+                self.report(
+                    Severity::Error,
+                    format!("field `{}` of `{}`: expected {}, got {}", field, name, type_name(ty), type_name(&got)),
+                    self.at.clone(),
+                );
+            }
+        }
+        let missing: Vec<&str> =
+            def.fields.iter().filter(|(f, _)| !inits.iter().any(|(i, _)| i == f)).map(|(f, _)| f.as_str()).collect();
+        if !missing.is_empty() {
+            self.report(
+                Severity::Error,
+                format!("missing field(s) {} in `{}` literal", missing.join(", "), name),
+                self.at.clone(),
+            );
+        }
+    }
+
+    // Тип выражения, если его видно без полного вывода типов; None — не знаем.
+    fn infer(&self, expr: &Expr) -> Option<Type> {
+        match expr {
+            Expr::Number(_) => Some(Type::Int),
+            Expr::Bool(_) => Some(Type::Bool),
+            Expr::Char(_) => Some(Type::Char),
+            Expr::Str(_) => Some(Type::Str),
+            Expr::Ident(name) => self.locals.get(name).cloned().flatten(),
+            Expr::BinaryOp(_, op, _) => match op.as_str() {
+                "==" | "!=" | "<" | "<=" | ">" | ">=" => Some(Type::Bool),
+                _ => Some(Type::Int),
+            },
+            Expr::Call(name, _) if !self.locals.contains_key(name) => match self.externs.get(name) {
+                Some(sig) => Some(sig.ret.lang()),
+                None => self.rets.get(name).cloned(),
+            },
+            Expr::Array(elems) => {
+                let elem = elems.first().map_or(Some(Type::Int), |e| self.infer(e))?;
+                Some(Type::Array(Box::new(elem), elems.len()))
+            }
+            Expr::ArrayRepeat(e, n) => Some(Type::Array(Box::new(self.infer(e)?), *n)),
+            Expr::StructLit(name, _) => Some(Type::Struct(name.clone())),
+            Expr::AddrOf(e) => Some(Type::Ptr(Box::new(self.infer(e)?))),
+            Expr::Lambda { params, ret, .. } => {
+                Some(Type::Fn(params.iter().map(|(_, t)| t.clone()).collect(), Box::new(ret.clone())))
+            }
+            Expr::Deref(e, _) => match self.infer(e)? {
+                Type::Ptr(t) => Some(*t),
+                _ => None,
+            },
+            Expr::Index { array, .. } => match self.infer(array)? {
+                Type::Array(elem, _) | Type::Slice(elem) => Some(*elem),
+                _ => None,
+            },
+            // p.x при p: *P — поле P
+            Expr::Field(base, field) => {
+                let mut ty = self.infer(base)?;
+                while let Type::Ptr(inner) = ty {
+                    ty = *inner;
+                }
+                let Type::Struct(name) = ty else { return None };
+                let def = self.structs.get(&name)?;
+                def.fields.iter().find(|(f, _)| f == field).map(|(_, t)| t.clone())
+            }
+            Expr::Block(body) => self.block_type(body),
+            Expr::If { then_body, else_body: Some(eb), .. } => {
+                let t = self.block_type(then_body)?;
+                (self.block_type(eb)? == t).then_some(t)
+            }
+            Expr::Match { arms, .. } => {
+                let t = self.infer(&arms.first()?.body)?;
+                arms.iter().all(|arm| self.infer(&arm.body).as_ref() == Some(&t)).then_some(t)
+            }
+            _ => None,
+        }
+    }
+
+    // Тип значения блока: последнее выражение без `;`. Блок без значения даёт 0, как
+    // в обоих бэкендах. Переменные блока уже в locals — тело к этому времени пройдено.
+    fn block_type(&self, body: &[Stmt]) -> Option<Type> {
+        match body.last().map(|s| &s.kind) {
+            Some(StmtKind::Expr(e)) => self.infer(e),
+            _ => Some(Type::Int),
+        }
+    }
+
+    // Образцы — отрезки целых. Образец недостижим, если его отрезок уже покрыт
+    // более ранними; match полон, если покрыт весь i64.
+    fn check_match(&mut self, arms: &[MatchArm], span: &Span) {
+        let mut covered: Vec<(i64, i64)> = Vec::new();
+        for arm in arms {
+            let mut dead = Vec::new();
+            for (pat, pat_span) in &arm.patterns {
+                let (lo, hi) = pat.bounds();
+                if lo > hi {
+                    dead.push((format!("empty range pattern {}..={}", lo, hi), pat_span.clone()));
+                } else if is_covered(&covered, lo, hi) {
+                    dead.push(("unreachable pattern".to_string(), pat_span.clone()));
+                } else {
+                    covered.push((lo, hi));
+                }
+            }
+            let all_covered = dead.iter().all(|(m, _)| m == "unreachable pattern");
+            if dead.len() == arm.patterns.len() && all_covered {
+                let first = arm.patterns.first().map_or(0, |(_, s)| s.start);
+                let last = arm.patterns.last().map_or(0, |(_, s)| s.end);
+                self.report(Severity::Warning, "unreachable match arm".to_string(), first..last);
+            } else {
+                for (message, span) in dead {
+                    self.report(Severity::Warning, message, span);
+                }
+            }
+        }
+        if !is_covered(&covered, i64::MIN, i64::MAX) {
+            self.report(
+                Severity::Error,
+                "non-exhaustive match: some values have no arm (add `_ => ...`)".to_string(),
+                span.clone(),
+            );
+        }
+    }
+}
+
+// Годится ли значение типа `got` там, где ждут `expected`: bool и char — для целых
+// (Codegen их расширяет), массив — для `[T]`.
+fn assignable(expected: &Type, got: &Type) -> bool {
+    match (expected, got) {
+        (Type::Int, Type::Int | Type::Bool | Type::Char) => true,
+        (Type::Slice(a), Type::Array(b, _) | Type::Slice(b)) => a == b,
+        _ => expected == got,
+    }
+}
+
+// Сколько аргументов принимает встроенная функция (от и до); None — не встроенная.
+fn builtin_arity(name: &str) -> Option<(usize, usize)> {
+    match name {
+        "println" => Some((0, 1)),
+        "read_int" => Some((0, 0)),
+        _ if BUILTINS.contains(&name) => Some((1, 1)),
+        _ => None,
+    }
+}
+
+// Строже assignable: тип тот же, только массив годится для `[T]` (поля структур, присваивание).
+fn fits(expected: &Type, got: &Type) -> bool {
+    match (expected, got) {
+        (Type::Slice(a), Type::Array(b, _) | Type::Slice(b)) => a == b,
+        _ => expected == got,
+    }
+}
+
+// Покрыт ли [lo, hi] объединением отрезков (i128 — чтобы hi + 1 не переполнялся).
+fn is_covered(covered: &[(i64, i64)], lo: i64, hi: i64) -> bool {
+    let mut sorted = covered.to_vec();
+    sorted.sort();
+    let mut next = lo as i128;
+    for (a, b) in sorted {
+        if (a as i128) <= next && (b as i128) >= next {
+            next = b as i128 + 1;
+        }
+        if next > hi as i128 {
+            return true;
+        }
+    }
+    false
+}
+
+// Тип так, как он пишется в исходнике.
+pub fn type_name(ty: &Type) -> String {
+    match ty {
+        Type::Int => "i64".to_string(),
+        Type::Bool => "bool".to_string(),
+        Type::Char => "char".to_string(),
+        Type::Str => "str".to_string(),
+        Type::Array(elem, n) => format!("[{}; {}]", type_name(elem), n),
+        Type::Slice(elem) => format!("[{}]", type_name(elem)),
+        Type::Struct(name) => name.clone(),
+        Type::Ptr(inner) => format!("*{}", type_name(inner)),
+        Type::Fn(params, ret) => {
+            let params: Vec<String> = params.iter().map(type_name).collect();
+            format!("fn({}) -> {}", params.join(", "), type_name(ret))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    use crate::ast::ExternType;
+    use crate::interpreter::Value;
+    use crate::modules;
+    use crate::testutil::load;
+
+    fn errors(src: &str) -> Vec<String> {
+        let (program, _) = load(src);
+        check_program(&program)
+            .into_iter()
+            .filter(|d| d.severity == Severity::Error)
+            .map(|d| d.message)
+            .collect()
+    }
+
+    #[test]
+    fn struct_literal_fields() {
+        let def = "struct P { x: i64, flag: bool }\n";
+        assert!(errors(&format!("{}fn main() {{ let p = P {{ flag: true, x: 1 }}; p.x }}", def)).is_empty());
+        assert_eq!(
+            errors(&format!("{}fn main() {{ let p = P {{ x: 1 }}; 0 }}", def)),
+            ["missing field(s) flag in `P` literal"]
+        );
+        assert_eq!(
+            errors(&format!("{}fn main() {{ let p = P {{ x: 1, flag: false, y: 2 }}; 0 }}", def)),
+            ["struct `P` has no field `y`"]
+        );
+        assert_eq!(
+            errors(&format!("{}fn main() {{ let p = P {{ x: 1, x: 2, flag: true }}; 0 }}", def)),
+            ["field `x` is set twice"]
+        );
+        assert_eq!(
+            errors(&format!("{}fn main() {{ let p = P {{ x: true, flag: 1 }}; 0 }}", def)),
+            ["field `x` of `P`: expected i64, got bool", "field `flag` of `P`: expected bool, got i64"]
+        );
+    }
+
+    #[test]
+    fn struct_literal_error_points_at_the_statement() {
+        let src = "struct P { x: i64 }\nfn main() {\n    let p = P { y: 1 };\n    0\n}";
+        let (program, sources) = load(src);
+        let d = &check_program(&program)[0];
+        assert_eq!(sources.location(d.span.start), "3:5");
+        assert_eq!(d.span.len(), "let p = P { y: 1 };".len());
+    }
+
+    #[test]
+    fn if_and_else_must_agree() {
+        assert!(errors("fn main() { let x = if (true) { 1 } else { 2 }; x }").is_empty());
+        assert!(errors("fn main() { let s = if (true) { \"a\" } else if (false) { \"b\" } else { \"c\" }; 0 }").is_empty());
+        assert_eq!(
+            errors("fn main() { let x = if (true) { 1 } else { false }; 0 }"),
+            ["`if` and `else` have different types: i64 and bool"]
+        );
+        // `;` отнимает у ветки значение: остаётся 0
+        assert_eq!(
+            errors("fn main() { if (true) { \"a\"; } else { \"b\" } }"),
+            ["`if` and `else` have different types: i64 and str"]
+        );
+        assert_eq!(
+            errors("fn main() { let c = if (true) { 'a' } else if (false) { 1 } else { 2 }; 0 }"),
+            ["`if` and `else` have different types: char and i64"]
+        );
+    }
+
+    #[test]
+    fn if_without_else_has_no_value_of_other_types() {
+        assert!(errors("fn main() { let c = if (true) { 7 }; if (false) { \"s\"; } if (true) { println(c) } c }").is_empty());
+        assert_eq!(
+            errors("fn main() { let a = 1; let c = if (a > 100) { \"s\" }; 0 }"),
+            ["`if` without `else` cannot give str: add `else` or end the branch with `;`"]
+        );
+        assert_eq!(
+            errors("fn main() { if (true) { 1 == 1 } }"),
+            ["`if` without `else` cannot give bool: add `else` or end the branch with `;`"]
+        );
+    }
+
+    fn warnings(src: &str) -> Vec<String> {
+        let (program, _) = load(src);
+        check_program(&program)
+            .into_iter()
+            .filter(|d| d.severity == Severity::Warning)
+            .map(|d| d.message)
+            .collect()
+    }
+
+    #[test]
+    fn match_exhaustiveness() {
+        assert!(errors("fn main() { match 1 { 0 => 1, _ => 2 } }").is_empty());
+        assert_eq!(
+            errors("fn main() { match 1 { 0 => 1, 1..=5 => 2 } }"),
+            ["non-exhaustive match: some values have no arm (add `_ => ...`)"]
+        );
+    }
+
+    #[test]
+    fn match_arms_must_agree() {
+        assert!(errors("fn main() { let s = match 1 { 0 => \"a\", 1 => { \"b\" }, _ => \"c\" }; 0 }").is_empty());
+        assert_eq!(
+            errors("fn main() { let x = 0; match x { 0 => 1, _ => \"s\" } }"),
+            ["match arms have different types: i64 and str"]
+        );
+        assert_eq!(
+            errors("fn main() { let x = 0; let b = match x { 0 => true, _ => 5 }; 0 }"),
+            ["match arms have different types: bool and i64"]
+        );
+    }
+
+    #[test]
+    fn unreachable_match_arms() {
+        assert_eq!(
+            warnings("fn main() { match 1 { 1..=5 => 1, 2 | 9 => 2, 3 => 3, 5..1 => 4, _ => 5, 7 => 6 } }"),
+            ["unreachable pattern", "unreachable match arm", "empty range pattern 5..=0", "unreachable match arm"]
+        );
+    }
+
+    #[test]
+    fn extern_calls_are_checked_against_the_signature() {
+        let decl = "extern fn labs(x: i64) -> i64;\nextern fn puts(s: str) -> i32;\n";
+        assert!(errors(&format!("{}fn main() {{ puts(\"a\"); labs(1) }}", decl)).is_empty());
+        assert_eq!(errors(&format!("{}fn main() {{ labs(1, 2) }}", decl)).len(), 1);
+        assert_eq!(errors(&format!("{}fn main() {{ puts(1) }}", decl)).len(), 1);
+    }
+
+    #[test]
+    fn declared_types_are_checked() {
+        assert!(errors("fn main() { let a: [i64] = [1, 2]; let n: i64 = 'c'; n }").is_empty());
+        assert_eq!(
+            errors("extern fn strlen(s: str) -> i64;\nfn main() { let s: str = strlen(\"ab\"); 0 }"),
+            ["`s` is declared as str, but the value is i64"]
+        );
+        assert_eq!(errors("fn f() -> bool { 1 + 1 }\nfn main() { 0 }"), ["`f` returns bool, but its body gives i64"]);
+    }
+
+    #[test]
+    fn assignments_are_checked() {
+        let def = "struct P { x: i64, items: [i64] }\n";
+        let ok = format!("{}fn main() {{ let p = P {{ x: 1, items: [1] }}; p.items = [1, 2]; p.x = 2; 0 }}", def);
+        assert!(errors(&ok).is_empty());
+        assert_eq!(errors("fn main() { let x = 0; x = true; println(x); 0 }"), ["cannot assign bool to `x` of type i64"]);
+        assert_eq!(
+            errors(&format!("{}fn main() {{ let p = P {{ x: 1, items: [1] }}; let q = &p; q.x = \"s\"; 0 }}", def)),
+            ["cannot assign str to a place of type i64"]
+        );
+        assert_eq!(errors("fn main() { let a = [1, 2]; a[0] = 'c'; 0 }"), ["cannot assign char to a place of type i64"]);
+    }
+
+    #[test]
+    fn calls_are_checked() {
+        let ok = "fn add(a: i64, b: i64) -> i64 { a + b }\n\
+                  fn main() { let f = |x| x; let add1 = 1; println(); print(add(add1, f(2))); 0 }";
+        assert!(errors(ok).is_empty());
+        assert_eq!(errors("fn main() { nope(1) }"), ["call to undefined function `nope`"]);
+        assert_eq!(errors("fn main() { let x = 1; x(2) }"), ["`x` is not a function"]);
+        assert_eq!(
+            errors("fn add(a: i64, b: i64) -> i64 { a + b }\nfn main() { add(1) }"),
+            ["`add` expects 2 argument(s), got 1"]
+        );
+        assert_eq!(
+            errors("fn main() { let f = |x| x; f(1, 2) }"),
+            ["`f` expects 1 argument(s), got 2"]
+        );
+        assert_eq!(
+            errors("fn main() { println(1, 2); len(); read_int(3) }"),
+            [
+                "`println` expects 0 to 1 argument(s), got 2",
+                "`len` expects 1 argument(s), got 0",
+                "`read_int` expects 0 argument(s), got 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn builtin_names_cannot_be_redefined() {
+        assert_eq!(
+            errors("fn len(a: i64) -> i64 { a }\nfn main() { len(1) }"),
+            ["`len` is a builtin function and cannot be redefined"]
+        );
+        assert_eq!(
+            errors("fn main() { let print = |x| x; print(1) }"),
+            ["`print` is a builtin function and cannot be redefined"]
+        );
+        // переменные с такими именами — не функции, их можно
+        assert!(errors("fn main() { let len = 3; len + len([1, 2]) }").is_empty());
+    }
+
+    #[test]
+    fn host_functions_are_checked() {
+        let hosts = [
+            HostFunction::new("now", vec![], ExternType::Value(Type::Int), |_| Ok(Value::Int(1))).with_native(1),
+            HostFunction::new("name", vec![ExternType::I32], ExternType::Value(Type::Str), |_| Ok(Value::Int(0))),
+        ];
+        let sigs: Vec<ExternFn> = hosts.iter().map(|h| h.sig.clone()).collect();
+        let check = |src: &str| {
+            let (program, _) = modules::load_source(Path::new("test.src"), src.to_string(), &sigs).expect("load");
+            let message = |d: Diagnostic| d.message;
+            (check_program(&program).into_iter().map(message).collect::<Vec<_>>(),
+             check_compiled(&program, &hosts).into_iter().map(message).collect::<Vec<_>>())
+        };
+        assert_eq!(check("fn main() { now() }"), (vec![], vec![]));
+        let (interpreted, compiled) = check("fn main() {\n    let s: i64 = name(1);\n    0\n}");
+        assert_eq!(interpreted, ["`s` is declared as i64, but the value is str"]);
+        assert_eq!(compiled, [
+            "host function `name` has no native implementation and cannot be compiled",
+            "`s` is declared as i64, but the value is str",
+        ]);
+        assert_eq!(check("fn main() -> str { name(true) }").0, Vec::<String>::new());
+        assert_eq!(check("fn main() { name(\"x\") }").0, [
+            "argument 1 of `name`: expected i64, got str",
+            "`main` returns i64, but its body gives str",
+        ]);
+    }
+}
@@ -0,0 +1,108 @@
+use std::cell::RefCell;
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use inkwell::context::Context;
+
+use crate::ast::Program;
+use crate::codegen::Codegen;
+use crate::interpreter::{Interpreter, Outcome};
+use crate::modules::{self, SourceMap};
+use crate::sema::{self, Severity};
+
+// Общее для тестов: программа из строки и её запуск в разных бэкендах.
+
+pub fn load(src: &str) -> (Program, SourceMap) {
+    modules::load_source(Path::new("test.src"), src.to_string(), &[]).expect("load test program")
+}
+
+/// Вывод программы в буфер, который можно прочитать после запуска.
+#[derive(Clone, Default)]
+pub struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl Output {
+    pub fn text(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).expect("utf-8 output")
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Вывод интерпретатора и итог программы или ошибка в том виде, как её печатает main.rs.
+pub fn interpret(src: &str) -> (String, Result<Outcome, String>) {
+    interpret_with_input(src, "")
+}
+
+pub fn interpret_with_input(src: &str, input: &str) -> (String, Result<Outcome, String>) {
+    let (program, sources) = load(src);
+    interpret_program(&program, &sources, input)
+}
+
+/// То же для уже загруженной (например, оптимизированной) программы.
+pub fn interpret_program(program: &Program, sources: &SourceMap, input: &str) -> (String, Result<Outcome, String>) {
+    let mut interp = Interpreter::new();
+    interp.set_trace(false);
+    let out = Output::default();
+    interp.set_output(Box::new(out.clone()));
+    interp.set_input(Box::new(Cursor::new(input.as_bytes().to_vec())));
+    let result = interp
+        .run_program(program)
+        .map_err(|e| format!("runtime error at {}: {}", sources.location(e.span.start), e));
+    (out.text(), result)
+}
+
+/// Запуск собранной программы.
+pub struct Run {
+    pub stdout: String,
+    pub stderr: String,
+    pub code: i32,
+}
+
+pub fn scratch(name: &str) -> PathBuf {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let n = NEXT.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!("core-test-{}-{}-{}", std::process::id(), n, name))
+}
+
+/// Codegen -> исполняемый файл -> запуск со входом `input`. Sema должна пройти.
+pub fn compile_and_run(src: &str, input: &str) -> Run {
+    let (program, sources) = load(src);
+    let errors: Vec<_> = sema::check_program(&program).into_iter().filter(|d| d.severity == Severity::Error).collect();
+    assert!(errors.is_empty(), "sema errors: {:?}", errors.iter().map(|d| &d.message).collect::<Vec<_>>());
+    let context = Context::create();
+    let mut codegen = Codegen::new(&context, "test", &sources);
+    codegen.compile_program(&program);
+    let exe = scratch("prog");
+    codegen.write_executable(&exe).expect("build executable");
+    run_executable(&exe, input)
+}
+
+pub fn run_executable(exe: &Path, input: &str) -> Run {
+    let mut child = Command::new(exe)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("run executable");
+    child.stdin.take().expect("stdin").write_all(input.as_bytes()).expect("write stdin");
+    let out = child.wait_with_output().expect("wait executable");
+    let _ = std::fs::remove_file(exe);
+    let _ = std::fs::remove_file(exe.with_extension("o"));
+    Run {
+        stdout: String::from_utf8_lossy(&out.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&out.stderr).into_owned(),
+        code: out.status.code().unwrap_or(-1),
+    }
+}
@@ -0,0 +1,426 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::rc::Rc;
+use std::time::Instant;
+
+use crate::ast::Stmt;
+use crate::interpreter::{Hook, Interpreter, RuntimeError, Value};
+use crate::json::Json;
+use crate::modules::SourceMap;
+
+// Трасса и профиль интерпретатора — на хуке, как и отладчик. Запись об операторе
+// пишется, когда он выполнен: в ней шаг (номер оператора по порядку начала), место,
+// функция и переменные кадра, которые он изменил. Поэтому тело цикла в трассе раньше
+// самого цикла, а порядок начала восстанавливается по step.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    // одна JSON-запись на строку
+    JsonLines,
+    // Trace Event Format: chrome://tracing, Perfetto
+    Chrome,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Format> {
+        match name {
+            "jsonl" => Some(Format::JsonLines),
+            "chrome" => Some(Format::Chrome),
+            _ => None,
+        }
+    }
+}
+
+/// Запись, поставленная на интерпретатор; после run_program — finish().
+pub struct Recording(Rc<RefCell<Recorder>>);
+
+/// Счётчики профиля: сколько раз выполнялась каждая строка и каждая функция.
+pub struct Profile {
+    sources: SourceMap,
+    statements: u64,
+    // (файл, строка) -> выполнено операторов
+    lines: HashMap<(usize, usize), u64>,
+    functions: HashMap<String, FunctionStats>,
+    // время всего запуска, мкс
+    total: f64,
+}
+
+#[derive(Default)]
+struct FunctionStats {
+    calls: u64,
+    statements: u64,
+    // мкс; у рекурсивной функции считается только самый внешний вызов
+    time: f64,
+    active: usize,
+    since: f64,
+}
+
+struct Recorder {
+    sources: SourceMap,
+    start: Instant,
+    step: u64,
+    // начатые, но ещё не выполненные операторы
+    open: Vec<Open>,
+    // начало каждого активного вызова
+    calls: Vec<f64>,
+    trace: Option<(Format, Box<dyn Write>)>,
+    events: usize,
+    profile: Option<Profile>,
+}
+
+struct Open {
+    step: u64,
+    start: f64,
+    // переменные кадра до оператора — только для трассы
+    before: Vec<(String, Value)>,
+}
+
+struct Trace(Rc<RefCell<Recorder>>);
+
+/// `trace` — формат и куда писать трассу; `profile` — считать выполнения.
+pub fn attach(
+    interp: &mut Interpreter,
+    sources: &SourceMap,
+    trace: Option<(Format, Box<dyn Write>)>,
+    profile: bool,
+) -> Recording {
+    let profile = profile.then(|| Profile {
+        sources: sources.clone(),
+        statements: 0,
+        lines: HashMap::new(),
+        functions: HashMap::new(),
+        total: 0.0,
+    });
+    let recorder = Rc::new(RefCell::new(Recorder {
+        sources: sources.clone(),
+        start: Instant::now(),
+        step: 0,
+        open: Vec::new(),
+        calls: Vec::new(),
+        trace,
+        events: 0,
+        profile,
+    }));
+    recorder.borrow_mut().begin();
+    interp.set_hook(Box::new(Trace(recorder.clone())));
+    Recording(recorder)
+}
+
+impl Recording {
+    /// Закрывает трассу и возвращает профиль, если его считали.
+    pub fn finish(self) -> Option<Profile> {
+        let mut r = self.0.borrow_mut();
+        let now = r.now();
+        if let Some((Format::Chrome, out)) = &mut r.trace {
+            writeln!(out, "]").expect("write trace");
+        }
+        if let Some((_, out)) = &mut r.trace {
+            out.flush().expect("flush trace");
+        }
+        let mut profile = r.profile.take()?;
+        profile.total = now;
+        Some(profile)
+    }
+}
+
+impl Recorder {
+    fn now(&self) -> f64 {
+        self.start.elapsed().as_secs_f64() * 1e6
+    }
+
+    fn begin(&mut self) {
+        if let Some((Format::Chrome, out)) = &mut self.trace {
+            writeln!(out, "[").expect("write trace");
+        }
+    }
+
+    // Событие Chrome: элементы массива через запятую, по одному на строку.
+    fn event(&mut self, event: Json) {
+        let first = self.events == 0;
+        self.events += 1;
+        if let Some((format, out)) = &mut self.trace {
+            match format {
+                Format::JsonLines => writeln!(out, "{}", event),
+                Format::Chrome if first => writeln!(out, "{}", event),
+                Format::Chrome => writeln!(out, ",{}", event),
+            }
+            .expect("write trace");
+        }
+    }
+
+    fn statement(&mut self, interp: &Interpreter, stmt: &Stmt, open: Open) {
+        let Some((format, _)) = &self.trace else {
+            return;
+        };
+        let format = *format;
+        let now = self.now();
+        let changes: Vec<(String, Json)> = interp
+            .locals(0)
+            .into_iter()
+            .filter(|(name, v)| !open.before.iter().any(|(n, old)| n == name && old == v))
+            .map(|(name, v)| (name, value_json(&v)))
+            .collect();
+        let (idx, line, col) = self.sources.position(stmt.span.start).unwrap_or((0, 0, 0));
+        let file = &self.sources.files()[idx];
+        let event = match format {
+            Format::JsonLines => Json::object(vec![
+                ("step", (open.step as i64).into()),
+                ("depth", interp.depth().into()),
+                ("function", interp.function().into()),
+                ("file", file.path.display().to_string().into()),
+                ("line", line.into()),
+                ("column", col.into()),
+                ("span", vec![(stmt.span.start - file.base).into(), (stmt.span.end - file.base).into()].into()),
+                ("changes", Json::Object(changes)),
+                ("time_us", micros(now - open.start)),
+            ]),
+            Format::Chrome => Json::object(vec![
+                ("name", self.sources.location(stmt.span.start).into()),
+                ("cat", "stmt".into()),
+                ("ph", "X".into()),
+                ("ts", micros(open.start)),
+                ("dur", micros(now - open.start)),
+                ("pid", 1i64.into()),
+                ("tid", 1i64.into()),
+                (
+                    "args",
+                    Json::object(vec![
+                        ("step", (open.step as i64).into()),
+                        ("code", source_line(&self.sources, idx, line).into()),
+                        ("changes", Json::Object(changes)),
+                    ]),
+                ),
+            ]),
+        };
+        self.event(event);
+    }
+}
+
+impl Hook for Trace {
+    fn before_stmt(&mut self, interp: &mut Interpreter, stmt: &Stmt) -> Result<(), RuntimeError> {
+        let mut r = self.0.borrow_mut();
+        r.step += 1;
+        let open = Open {
+            step: r.step,
+            start: r.now(),
+            before: if r.trace.is_some() { interp.locals(0) } else { Vec::new() },
+        };
+        r.open.push(open);
+        let position = r.sources.position(stmt.span.start);
+        if let Some(p) = &mut r.profile {
+            p.statements += 1;
+            if let Some((idx, line, _)) = position {
+                *p.lines.entry((idx, line)).or_insert(0) += 1;
+            }
+            p.functions.entry(interp.function().to_string()).or_default().statements += 1;
+        }
+        Ok(())
+    }
+
+    fn after_stmt(&mut self, interp: &Interpreter, stmt: &Stmt) {
+        let mut r = self.0.borrow_mut();
+        let open = r.open.pop().expect("after_stmt without before_stmt");
+        r.statement(interp, stmt, open);
+    }
+
+    fn enter_call(&mut self, interp: &Interpreter) {
+        let mut r = self.0.borrow_mut();
+        let now = r.now();
+        r.calls.push(now);
+        if let Some(p) = &mut r.profile {
+            let f = p.functions.entry(interp.function().to_string()).or_default();
+            f.calls += 1;
+            if f.active == 0 {
+                f.since = now;
+            }
+            f.active += 1;
+        }
+    }
+
+    fn leave_call(&mut self, interp: &Interpreter) {
+        let mut r = self.0.borrow_mut();
+        let now = r.now();
+        let start = r.calls.pop().expect("leave_call without enter_call");
+        if let Some(p) = &mut r.profile {
+            let f = p.functions.entry(interp.function().to_string()).or_default();
+            f.active -= 1;
+            if f.active == 0 {
+                f.time += now - f.since;
+            }
+        }
+        // в JSON Lines только операторы; вызовы — полосы функций в Chrome
+        if matches!(r.trace, Some((Format::Chrome, _))) {
+            r.event(Json::object(vec![
+                ("name", interp.function().into()),
+                ("cat", "function".into()),
+                ("ph", "X".into()),
+                ("ts", micros(start)),
+                ("dur", micros(now - start)),
+                ("pid", 1i64.into()),
+                ("tid", 1i64.into()),
+            ]));
+        }
+    }
+}
+
+// Числа и bool — как есть, остальное — как печатает программа.
+fn value_json(v: &Value) -> Json {
+    match v {
+        Value::Int(n) => (*n).into(),
+        Value::Bool(b) => (*b).into(),
+        other => other.to_string().into(),
+    }
+}
+
+// Микросекунды с точностью до наносекунды — без хвостов вроде 16.317999999999998.
+fn micros(t: f64) -> Json {
+    Json::Number((t * 1000.0).round() / 1000.0)
+}
+
+fn source_line(sources: &SourceMap, file: usize, line: usize) -> String {
+    let text = &sources.files()[file].text;
+    text.lines().nth(line.saturating_sub(1)).unwrap_or("").trim().to_string()
+}
+
+/// Отчёт о горячих местах: функции по числу операторов, затем самые частые строки.
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "=== Profile: {} statements in {:.3} ms ===", self.statements, self.total / 1000.0)?;
+        let mut functions: Vec<(&String, &FunctionStats)> = self.functions.iter().collect();
+        functions.sort_by(|a, b| b.1.statements.cmp(&a.1.statements).then(a.0.cmp(b.0)));
+        writeln!(f, "{:<24} {:>10} {:>12} {:>10}", "function", "calls", "statements", "time ms")?;
+        for (name, s) in functions {
+            writeln!(f, "{:<24} {:>10} {:>12} {:>10.3}", name, s.calls, s.statements, s.time / 1000.0)?;
+        }
+        let mut lines: Vec<(&(usize, usize), &u64)> = self.lines.iter().collect();
+        lines.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        writeln!(f, "\nhot lines:")?;
+        writeln!(f, "{:>10}  {:<24} code", "count", "line")?;
+        for (&(file, line), count) in lines.into_iter().take(HOT_LINES) {
+            let source = &self.sources.files()[file];
+            // как SourceMap::location: путь только у импортированных файлов
+            let place = if source.path == self.sources.root().path {
+                line.to_string()
+            } else {
+                format!("{}:{}", source.path.display(), line)
+            };
+            writeln!(f, "{:>10}  {:<24} {}", count, place, source_line(&self.sources, file, line))?;
+        }
+        Ok(())
+    }
+}
+
+/// Сколько строк показывать в отчёте.
+const HOT_LINES: usize = 10;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json;
+    use crate::testutil::{load, Output};
+
+    const SRC: &str = "fn fact(n: i64) -> i64 {
+    if n < 2 { 1 } else { n * fact(n - 1) }
+}
+fn main() {
+    let x = 2;
+    x = fact(x + 1);
+    x
+}";
+
+    // Трасса в формате `format` (None — без трассы) и профиль запуска SRC.
+    fn record(format: Option<Format>, profile: bool) -> (String, Option<Profile>) {
+        let (program, sources) = load(SRC);
+        let mut interp = Interpreter::new();
+        interp.set_trace(false);
+        let out = Output::default();
+        let trace = format.map(|f| (f, Box::new(out.clone()) as Box<dyn Write>));
+        let recording = attach(&mut interp, &sources, trace, profile);
+        assert_eq!(interp.run_program(&program).expect("run").code(), Some(6));
+        let profile = recording.finish();
+        (out.text(), profile)
+    }
+
+    fn field(event: &Json, key: &str) -> String {
+        event.get(key).unwrap_or_else(|| panic!("no {} in {}", key, event)).to_string()
+    }
+
+    #[test]
+    fn json_lines() {
+        let (text, profile) = record(Some(Format::JsonLines), false);
+        assert!(profile.is_none());
+        let events: Vec<Json> = text.lines().map(|l| json::parse(l).expect("json line")).collect();
+        // (шаг, функция, строка, изменения) — в порядке завершения операторов
+        let summary: Vec<String> = events
+            .iter()
+            .map(|e| {
+                let fields = ["step", "function", "line", "changes"].map(|k| field(e, k));
+                fields.join(" ")
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                r#"1 "main" 5 {"x":2}"#,
+                r#"8 "fact" 2 {}"#,
+                r#"7 "fact" 2 {}"#,
+                r#"6 "fact" 2 {}"#,
+                r#"5 "fact" 2 {}"#,
+                r#"4 "fact" 2 {}"#,
+                r#"3 "fact" 2 {}"#,
+                r#"2 "main" 6 {"x":6}"#,
+                r#"9 "main" 7 {}"#,
+            ]
+        );
+        assert!(events.iter().all(|e| e.get("time_us").is_some() && e.get("file") == Some(&"test.src".into())));
+    }
+
+    #[test]
+    fn chrome_events() {
+        let (text, _) = record(Some(Format::Chrome), false);
+        let events = json::parse(&text).expect("trace is a JSON array");
+        let events = events.as_array().expect("array");
+        assert!(events.iter().all(|e| e.get("ph") == Some(&"X".into())));
+        let calls: Vec<String> =
+            events.iter().filter(|e| e.get("cat") == Some(&"function".into())).map(|e| field(e, "name")).collect();
+        // полоса функции пишется, когда вызов закончился: внутренний fact первым
+        assert_eq!(calls, [r#""fact""#, r#""fact""#, r#""fact""#, r#""main""#]);
+        let first = events.iter().find(|e| e.get("cat") == Some(&"stmt".into())).expect("statement event");
+        assert_eq!(field(first, "name"), r#""5:5""#);
+        assert_eq!(field(first.get("args").expect("args"), "code"), r#""let x = 2;""#);
+    }
+
+    #[test]
+    fn profile_counts() {
+        let (text, profile) = record(None, true);
+        assert!(text.is_empty());
+        let profile = profile.expect("profile");
+        let fact = &profile.functions["fact"];
+        assert_eq!((fact.calls, fact.statements, fact.active), (3, 6, 0));
+        let main = &profile.functions["main"];
+        assert_eq!((main.calls, main.statements), (1, 3));
+        assert_eq!(profile.statements, 9);
+        let line = |n: usize| profile.lines.get(&(profile.sources.files().len() - 1, n)).copied();
+        assert_eq!((line(2), line(3), line(6)), (Some(6), None, Some(1)));
+        let report = profile.to_string();
+        assert!(report.starts_with("=== Profile: "), "{}", report);
+        // время меняется от запуска к запуску — сравниваем остальное
+        let rows: Vec<Vec<&str>> =
+            report.lines().skip(2).take(2).map(|l| l.split_whitespace().take(3).collect()).collect();
+        assert_eq!(rows, [["fact", "3", "6"], ["main", "1", "3"]]);
+        let hot = report.split_once("hot lines:\n").expect("hot lines").1;
+        assert_eq!(
+            hot.lines().skip(1).map(|l| l.split_whitespace().collect::<Vec<_>>().join(" ")).collect::<Vec<_>>(),
+            ["6 2 if n < 2 { 1 } else { n * fact(n - 1) }", "1 5 let x = 2;", "1 6 x = fact(x + 1);", "1 7 x"]
+        );
+    }
+
+    #[test]
+    fn format_names() {
+        assert_eq!(Format::parse("jsonl"), Some(Format::JsonLines));
+        assert_eq!(Format::parse("chrome"), Some(Format::Chrome));
+        assert_eq!(Format::parse("json"), None);
+    }
+}
@@ -0,0 +1,1019 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::mem;
+use std::rc::Rc;
+
+use crate::ast::{referenced_names, Expr, ExternFn, Function, Pattern, Program, Stmt, StmtKind, Type};
+use crate::host::{self, HostFn, HostFunction};
+use crate::interpreter::{self, Closure, Outcome, RuntimeError, Value};
+use crate::lexer::Span;
+use crate::modules::SourceMap;
+
+/// Инструкция стековой машины: операнды снимаются со стека, результат кладётся на стек.
+/// Переменные — слоты кадра по индексу, а не строки в HashMap, как в Interpreter.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    Int(i64),
+    // константа из пула: bool, char, строки, null
+    Const(usize),
+    Load(usize),
+    Store(usize),
+    Pop,
+    Binary(BinOp),
+    Jump(usize),
+    JumpIfFalse(usize),
+    JumpIfTrue(usize),
+    // n значений -> массив
+    Array(usize),
+    // значение -> массив из n копий
+    Repeat(usize),
+    // массив, индекс -> элемент
+    Index,
+    // индекс -> элемент массива из слота (без копии всего массива)
+    LoadIndex(usize),
+    // структура -> поле
+    Field(String),
+    LoadField(usize, String),
+    // значение, индексы пути -> запись в слот по пути (`a[i].x = v`)
+    StorePath(usize, Vec<PathKey>),
+    // поля в порядке объявления -> структура (индекс в Bytecode::structs)
+    Struct(usize),
+    // аргументы -> результат; функция, число аргументов
+    Call(usize, usize),
+    // замыкание, аргументы -> результат
+    CallValue(usize),
+    CallBuiltin(Builtin, usize),
+    // extern fn или функция хоста (индекс в Bytecode::externs)
+    CallExtern(usize, usize),
+    // функция и слоты, значения которых захватываются
+    Closure(usize, Vec<usize>),
+    // ни одна ветка match не подошла к значению в слоте
+    NoMatch(usize),
+    Return,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl BinOp {
+    fn parse(op: &str) -> Option<BinOp> {
+        Some(match op {
+            "+" => BinOp::Add,
+            "-" => BinOp::Sub,
+            "*" => BinOp::Mul,
+            "/" => BinOp::Div,
+            "==" => BinOp::Eq,
+            "!=" => BinOp::Ne,
+            "<" => BinOp::Lt,
+            "<=" => BinOp::Le,
+            ">" => BinOp::Gt,
+            ">=" => BinOp::Ge,
+            _ => return None,
+        })
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Eq => "==",
+            BinOp::Ne => "!=",
+            BinOp::Lt => "<",
+            BinOp::Le => "<=",
+            BinOp::Gt => ">",
+            BinOp::Ge => ">=",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Builtin {
+    Print,
+    Println,
+    ReadInt,
+    Exit,
+    Len,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathKey {
+    // индекс лежит на стеке
+    Index,
+    Field(String),
+}
+
+/// Скомпилированная функция. Слоты: параметры, захваченные переменные, затем локальные.
+pub struct FnCode {
+    pub name: String,
+    pub params: usize,
+    // имена слотов — для дизассемблера и захвата
+    pub slots: Vec<String>,
+    pub code: Vec<Op>,
+    // место в исходнике для каждой инструкции
+    pub spans: Vec<Span>,
+    // AST — чтобы функция-значение была тем же Value::Closure, что и в Interpreter
+    ast: Rc<Function>,
+}
+
+pub struct Bytecode {
+    pub functions: Vec<FnCode>,
+    consts: Vec<Value>,
+    structs: Vec<(String, Vec<String>)>,
+    externs: Vec<ExternFn>,
+    by_name: HashMap<String, usize>,
+}
+
+/// Компилирует программу в байткод. Указатели (`&`, `*`, alloc/free) VM пока не умеет —
+/// это ошибка компиляции, такие программы выполняет Interpreter.
+pub fn compile(program: &Program) -> Result<Bytecode, RuntimeError> {
+    let mut c = Compiler {
+        bc: Bytecode {
+            functions: Vec::new(),
+            consts: Vec::new(),
+            structs: program
+                .structs
+                .iter()
+                .map(|s| (s.name.clone(), s.fields.iter().map(|(f, _)| f.clone()).collect()))
+                .collect(),
+            externs: program.externs.clone(),
+            by_name: HashMap::new(),
+        },
+        f: FnState::default(),
+        lambdas: 0,
+    };
+    // индексы всех функций — заранее, чтобы работали вызовы «вперёд»
+    for func in &program.functions {
+        c.reserve(Rc::new(func.clone()));
+    }
+    for (i, func) in program.functions.iter().enumerate() {
+        let state = c.function(&func.params, &[], &func.body)?;
+        c.finish(i, state);
+    }
+    Ok(c.bc)
+}
+
+#[derive(Default)]
+struct FnState {
+    code: Vec<Op>,
+    spans: Vec<Span>,
+    scope: HashMap<String, usize>,
+    slots: Vec<String>,
+    // текущий оператор — место для инструкций и ошибок
+    span: Span,
+}
+
+struct Compiler {
+    bc: Bytecode,
+    f: FnState,
+    lambdas: usize,
+}
+
+impl Compiler {
+    fn reserve(&mut self, ast: Rc<Function>) -> usize {
+        let idx = self.bc.functions.len();
+        self.bc.by_name.insert(ast.name.clone(), idx);
+        self.bc.functions.push(FnCode {
+            name: ast.name.clone(),
+            params: ast.params.len(),
+            slots: Vec::new(),
+            code: Vec::new(),
+            spans: Vec::new(),
+            ast,
+        });
+        idx
+    }
+
+    fn finish(&mut self, idx: usize, state: FnState) {
+        let f = &mut self.bc.functions[idx];
+        f.slots = state.slots;
+        f.code = state.code;
+        f.spans = state.spans;
+    }
+
+    fn error(&self, message: String) -> RuntimeError {
+        RuntimeError { message, span: self.f.span.clone(), notes: Vec::new() }
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        self.f.code.push(op);
+        self.f.spans.push(self.f.span.clone());
+        self.f.code.len() - 1
+    }
+
+    // Инструкция с собственным местом (индекс — для сообщения о выходе за границы).
+    fn emit_at(&mut self, op: Op, span: &Span) -> usize {
+        let at = self.emit(op);
+        self.f.spans[at] = span.clone();
+        at
+    }
+
+    fn here(&self) -> usize {
+        self.f.code.len()
+    }
+
+    fn patch(&mut self, at: usize, target: usize) {
+        match &mut self.f.code[at] {
+            Op::Jump(t) | Op::JumpIfFalse(t) | Op::JumpIfTrue(t) => *t = target,
+            other => panic!("cannot patch {:?}", other),
+        }
+    }
+
+    fn constant(&mut self, v: Value) -> Op {
+        self.bc.consts.push(v);
+        Op::Const(self.bc.consts.len() - 1)
+    }
+
+    fn new_slot(&mut self, name: &str) -> usize {
+        self.f.slots.push(name.to_string());
+        self.f.slots.len() - 1
+    }
+
+    // `let x` повторно пишет в тот же слот — как insert в кадр Interpreter.
+    fn declare(&mut self, name: &str) -> usize {
+        match self.f.scope.get(name) {
+            Some(&slot) => slot,
+            None => {
+                let slot = self.new_slot(name);
+                self.f.scope.insert(name.to_string(), slot);
+                slot
+            }
+        }
+    }
+
+    fn function(&mut self, params: &[(String, Type)], captures: &[String], body: &[Stmt]) -> Result<FnState, RuntimeError> {
+        let span = self.f.span.clone();
+        let saved = mem::replace(&mut self.f, FnState { span, ..FnState::default() });
+        for (p, _) in params {
+            self.declare(p);
+        }
+        for c in captures {
+            self.declare(c);
+        }
+        let result = self.body(body);
+        self.emit(Op::Return);
+        let state = mem::replace(&mut self.f, saved);
+        result.map(|_| state)
+    }
+
+    // Тело оставляет на стеке ровно одно значение: последнее выражение или 0.
+    fn body(&mut self, body: &[Stmt]) -> Result<(), RuntimeError> {
+        let Some((last, init)) = body.split_last() else {
+            self.emit(Op::Int(0));
+            return Ok(());
+        };
+        for s in init {
+            self.stmt(s)?;
+        }
+        match &last.kind {
+            StmtKind::Expr(e) => {
+                self.f.span = last.span.clone();
+                self.expr(e)
+            }
+            _ => {
+                self.stmt(last)?;
+                self.emit(Op::Int(0));
+                Ok(())
+            }
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Result<(), RuntimeError> {
+        self.f.span = stmt.span.clone();
+        match &stmt.kind {
+            StmtKind::Let(name, _, e) => {
+                self.expr(e)?;
+                let slot = self.declare(name);
+                self.emit(Op::Store(slot));
+            }
+            StmtKind::Assign(target, e) => self.assign(target, e)?,
+            StmtKind::Expr(e) | StmtKind::Semi(e) => {
+                self.expr(e)?;
+                self.emit(Op::Pop);
+            }
+            StmtKind::While { cond, body } => {
+                let start = self.here();
+                self.expr(cond)?;
+                let exit = self.emit(Op::JumpIfFalse(0));
+                for s in body {
+                    self.stmt(s)?;
+                }
+                self.emit(Op::Jump(start));
+                let end = self.here();
+                self.patch(exit, end);
+            }
+            StmtKind::For { var, start, end, inclusive, step, body } => {
+                // границы вычисляются один раз, переменная цикла — свой слот на время цикла
+                self.expr(start)?;
+                self.expr(end)?;
+                let end_slot = self.new_slot(".end");
+                self.emit(Op::Store(end_slot));
+                let var_slot = self.new_slot(var);
+                self.emit(Op::Store(var_slot));
+                let shadowed = self.f.scope.insert(var.clone(), var_slot);
+                let cmp = match (*step > 0, *inclusive) {
+                    (true, false) => BinOp::Lt,
+                    (true, true) => BinOp::Le,
+                    (false, false) => BinOp::Gt,
+                    (false, true) => BinOp::Ge,
+                };
+                let cond = self.here();
+                self.emit(Op::Load(var_slot));
+                self.emit(Op::Load(end_slot));
+                self.emit(Op::Binary(cmp));
+                let exit = self.emit(Op::JumpIfFalse(0));
+                for s in body {
+                    self.stmt(s)?;
+                }
+                self.f.span = stmt.span.clone();
+                // следующее значение переполнило бы i64 — цикл закончен (как checked_add в Interpreter)
+                let (limit, fits) = if *step > 0 { (i64::MAX - step, BinOp::Le) } else { (i64::MIN - step, BinOp::Ge) };
+                self.emit(Op::Load(var_slot));
+                self.emit(Op::Int(limit));
+                self.emit(Op::Binary(fits));
+                let overflow = self.emit(Op::JumpIfFalse(0));
+                self.emit(Op::Load(var_slot));
+                self.emit(Op::Int(*step));
+                self.emit(Op::Binary(BinOp::Add));
+                self.emit(Op::Store(var_slot));
+                self.emit(Op::Jump(cond));
+                let end = self.here();
+                self.patch(exit, end);
+                self.patch(overflow, end);
+                match shadowed {
+                    Some(slot) => self.f.scope.insert(var.clone(), slot),
+                    None => self.f.scope.remove(var),
+                };
+            }
+        }
+        Ok(())
+    }
+
+    // Значение вычисляется первым, потом индексы пути — в том же порядке, что и в Interpreter.
+    fn assign(&mut self, target: &Expr, value: &Expr) -> Result<(), RuntimeError> {
+        self.expr(value)?;
+        if let Expr::Ident(name) = target {
+            let slot = match self.f.scope.get(name) {
+                Some(&s) => s,
+                None => return Err(self.error(format!("assign to undefined var {}", name))),
+            };
+            self.emit(Op::Store(slot));
+            return Ok(());
+        }
+        let mut path = Vec::new();
+        let mut cur = target;
+        let root = loop {
+            match cur {
+                Expr::Index { array, index, span } => {
+                    path.push((Some((index.as_ref(), span)), None));
+                    cur = array;
+                }
+                Expr::Field(base, field) => {
+                    path.push((None, Some(field.clone())));
+                    cur = base;
+                }
+                Expr::Ident(name) => break name,
+                Expr::Deref(..) => return Err(self.error("pointers are not supported by the vm backend".to_string())),
+                _ => return Err(self.error("cannot assign to this expression".to_string())),
+            }
+        };
+        let slot = match self.f.scope.get(root) {
+            Some(&s) => s,
+            None => return Err(self.error(format!("use of undefined variable {}", root))),
+        };
+        path.reverse();
+        let mut keys = Vec::new();
+        let mut span = self.f.span.clone();
+        for (index, field) in path {
+            match (index, field) {
+                (Some((e, s)), _) => {
+                    self.expr(e)?;
+                    span = s.clone();
+                    keys.push(PathKey::Index);
+                }
+                (None, Some(f)) => keys.push(PathKey::Field(f)),
+                (None, None) => unreachable!(),
+            }
+        }
+        self.emit_at(Op::StorePath(slot, keys), &span);
+        Ok(())
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<(), RuntimeError> {
+        match expr {
+            Expr::Number(n) => {
+                self.emit(Op::Int(*n));
+            }
+            Expr::Bool(b) => {
+                let op = self.constant(Value::Bool(*b));
+                self.emit(op);
+            }
+            Expr::Char(c) => {
+                let op = self.constant(Value::Char(*c));
+                self.emit(op);
+            }
+            Expr::Str(s) => {
+                let op = self.constant(Value::Str(s.clone()));
+                self.emit(op);
+            }
+            Expr::Null => {
+                let op = self.constant(Value::Null);
+                self.emit(op);
+            }
+            Expr::Ident(name) => match (self.f.scope.get(name), self.bc.by_name.get(name)) {
+                (Some(&slot), _) => {
+                    self.emit(Op::Load(slot));
+                }
+                // имя функции как значение — замыкание без захвата
+                (None, Some(&idx)) => {
+                    self.emit(Op::Closure(idx, Vec::new()));
+                }
+                (None, None) => return Err(self.error(format!("use of undefined variable {}", name))),
+            },
+            Expr::BinaryOp(l, op, r) => {
+                let Some(bin) = BinOp::parse(op) else {
+                    return Err(self.error(format!("Unknown operator {}", op)));
+                };
+                self.expr(l)?;
+                self.expr(r)?;
+                self.emit(Op::Binary(bin));
+            }
+            Expr::Call(name, args) => self.call(name, args)?,
+            Expr::Apply(callee, args) => {
+                self.expr(callee)?;
+                for a in args {
+                    self.expr(a)?;
+                }
+                self.emit(Op::CallValue(args.len()));
+            }
+            Expr::Array(elems) => {
+                for e in elems {
+                    self.expr(e)?;
+                }
+                self.emit(Op::Array(elems.len()));
+            }
+            Expr::ArrayRepeat(e, n) => {
+                self.expr(e)?;
+                self.emit(Op::Repeat(*n));
+            }
+            Expr::Index { array, index, span } => match array.as_ref() {
+                Expr::Ident(name) if self.f.scope.contains_key(name) => {
+                    let slot = self.f.scope[name];
+                    self.expr(index)?;
+                    self.emit_at(Op::LoadIndex(slot), span);
+                }
+                _ => {
+                    self.expr(array)?;
+                    self.expr(index)?;
+                    self.emit_at(Op::Index, span);
+                }
+            },
+            Expr::StructLit(name, inits) => {
+                let Some(idx) = self.bc.structs.iter().position(|(n, _)| n == name) else {
+                    return Err(self.error(format!("unknown struct {}", name)));
+                };
+                let fields = self.bc.structs[idx].1.clone();
+                if let Some((extra, _)) = inits.iter().find(|(f, _)| !fields.contains(f)) {
+                    return Err(self.error(format!("struct {} has no field {}", name, extra)));
+                }
+                // поля — в порядке объявления, как в Interpreter::struct_lit
+                for field in &fields {
+                    match inits.iter().find(|(f, _)| f == field) {
+                        Some((_, e)) => self.expr(e)?,
+                        None => return Err(self.error(format!("missing field {} in {} literal", field, name))),
+                    }
+                }
+                self.emit(Op::Struct(idx));
+            }
+            Expr::Field(base, field) => match base.as_ref() {
+                Expr::Ident(name) if self.f.scope.contains_key(name) => {
+                    let slot = self.f.scope[name];
+                    self.emit(Op::LoadField(slot, field.clone()));
+                }
+                _ => {
+                    self.expr(base)?;
+                    self.emit(Op::Field(field.clone()));
+                }
+            },
+            Expr::AddrOf(_) | Expr::Deref(..) => {
+                return Err(self.error("pointers are not supported by the vm backend".to_string()));
+            }
+            Expr::Lambda { params, ret, body } => {
+                // захват по значению — те же имена, что и у Interpreter и Codegen
+                let captures: Vec<String> = referenced_names(body)
+                    .into_iter()
+                    .filter(|n| !params.iter().any(|(p, _)| p == n) && self.f.scope.contains_key(n))
+                    .collect();
+                let slots: Vec<usize> = captures.iter().map(|n| self.f.scope[n]).collect();
+                let name = format!("lambda.{}", self.lambdas);
+                self.lambdas += 1;
+                let ast = Rc::new(Function {
+                    name,
+                    doc: None,
+                    params: params.clone(),
+                    ret: ret.clone(),
+                    body: body.clone(),
+                    span: self.f.span.clone(),
+                });
+                let idx = self.reserve(ast);
+                let state = self.function(params, &captures, body)?;
+                self.finish(idx, state);
+                self.emit(Op::Closure(idx, slots));
+            }
+            Expr::If { cond, then_body, else_body } => {
+                self.expr(cond)?;
+                let to_else = self.emit(Op::JumpIfFalse(0));
+                self.body(then_body)?;
+                let to_end = self.emit(Op::Jump(0));
+                let else_at = self.here();
+                self.patch(to_else, else_at);
+                match else_body {
+                    Some(b) => self.body(b)?,
+                    None => {
+                        self.emit(Op::Int(0));
+                    }
+                }
+                let end = self.here();
+                self.patch(to_end, end);
+            }
+            Expr::Block(body) => self.body(body)?,
+            // ветки проверяются по порядку, как в Interpreter; значение — в скрытом слоте
+            Expr::Match { scrutinee, arms, span } => {
+                self.expr(scrutinee)?;
+                let tmp = self.new_slot(".match");
+                self.emit(Op::Store(tmp));
+                let mut ends = Vec::new();
+                for arm in arms {
+                    let mut to_body = Vec::new();
+                    for (pat, _) in &arm.patterns {
+                        match pat {
+                            Pattern::Wildcard => to_body.push(self.emit(Op::Jump(0))),
+                            Pattern::Int(n) => {
+                                self.emit(Op::Load(tmp));
+                                self.emit(Op::Int(*n));
+                                self.emit(Op::Binary(BinOp::Eq));
+                                to_body.push(self.emit(Op::JumpIfTrue(0)));
+                            }
+                            Pattern::Range(lo, hi) => {
+                                self.emit(Op::Load(tmp));
+                                self.emit(Op::Int(*lo));
+                                self.emit(Op::Binary(BinOp::Ge));
+                                let skip = self.emit(Op::JumpIfFalse(0));
+                                self.emit(Op::Load(tmp));
+                                self.emit(Op::Int(*hi));
+                                self.emit(Op::Binary(BinOp::Le));
+                                to_body.push(self.emit(Op::JumpIfTrue(0)));
+                                let next = self.here();
+                                self.patch(skip, next);
+                            }
+                        }
+                    }
+                    let next_arm = self.emit(Op::Jump(0));
+                    let body_at = self.here();
+                    for j in to_body {
+                        self.patch(j, body_at);
+                    }
+                    self.expr(&arm.body)?;
+                    ends.push(self.emit(Op::Jump(0)));
+                    let next = self.here();
+                    self.patch(next_arm, next);
+                }
+                self.emit_at(Op::NoMatch(tmp), span);
+                let end = self.here();
+                for j in ends {
+                    self.patch(j, end);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Порядок поиска — как в Interpreter::call: встроенные, локальное замыкание,
+    // функции программы, extern.
+    fn call(&mut self, name: &str, args: &[Expr]) -> Result<(), RuntimeError> {
+        let builtin = match (name, args.len()) {
+            ("print", 1) => Some(Builtin::Print),
+            ("println", 0 | 1) => Some(Builtin::Println),
+            ("read_int", 0) => Some(Builtin::ReadInt),
+            ("exit", 1) => Some(Builtin::Exit),
+            ("len", 1) => Some(Builtin::Len),
+            ("alloc" | "free", _) => {
+                return Err(self.error("pointers are not supported by the vm backend".to_string()));
+            }
+            ("print" | "println" | "read_int" | "exit" | "len", _) => {
+                return Err(self.error(format!("wrong number of arguments to {}", name)));
+            }
+            _ => None,
+        };
+        if let Some(b) = builtin {
+            for a in args {
+                self.expr(a)?;
+            }
+            self.emit(Op::CallBuiltin(b, args.len()));
+            return Ok(());
+        }
+        if let Some(&slot) = self.f.scope.get(name) {
+            self.emit(Op::Load(slot));
+            for a in args {
+                self.expr(a)?;
+            }
+            self.emit(Op::CallValue(args.len()));
+            return Ok(());
+        }
+        let op = if let Some(&idx) = self.bc.by_name.get(name) {
+            let expected = self.bc.functions[idx].params;
+            if expected != args.len() {
+                return Err(self.error(format!("{} expects {} arguments, got {}", name, expected, args.len())));
+            }
+            Op::Call(idx, args.len())
+        } else if let Some(idx) = self.bc.externs.iter().position(|e| e.name == name) {
+            let expected = self.bc.externs[idx].params.len();
+            if expected != args.len() {
+                return Err(self.error(format!("{} expects {} arguments, got {}", name, expected, args.len())));
+            }
+            Op::CallExtern(idx, args.len())
+        } else {
+            return Err(self.error(format!("call to undefined function {}", name)));
+        };
+        for a in args {
+            self.expr(a)?;
+        }
+        self.emit(op);
+        Ok(())
+    }
+}
+
+impl Bytecode {
+    /// Листинг по функциям: номер инструкции, место в исходнике (когда оно меняется)
+    /// и имена слотов, функций и констант вместо голых чисел.
+    pub fn disassemble(&self, sources: &SourceMap) -> String {
+        let mut out = String::new();
+        for f in &self.functions {
+            let _ = writeln!(out, "fn {} (params {}, slots {}):", f.name, f.params, f.slots.len());
+            let mut last = None;
+            for (i, (op, span)) in f.code.iter().zip(&f.spans).enumerate() {
+                let loc = if last != Some(span.start) {
+                    last = Some(span.start);
+                    sources.location(span.start)
+                } else {
+                    String::new()
+                };
+                let _ = writeln!(out, "  {:04}  {:>12}  {}", i, loc, self.describe(f, op));
+            }
+        }
+        out
+    }
+
+    fn describe(&self, f: &FnCode, op: &Op) -> String {
+        match op {
+            Op::Const(i) => match &self.consts[*i] {
+                Value::Str(s) => format!("Const {:?}", s),
+                Value::Char(c) => format!("Const {:?}", c),
+                v => format!("Const {}", v),
+            },
+            Op::Load(s) => format!("Load {} ; {}", s, f.slots[*s]),
+            Op::Store(s) => format!("Store {} ; {}", s, f.slots[*s]),
+            Op::LoadIndex(s) => format!("LoadIndex {} ; {}", s, f.slots[*s]),
+            Op::LoadField(s, field) => format!("LoadField {} .{} ; {}", s, field, f.slots[*s]),
+            Op::StorePath(s, keys) => {
+                let path: String = keys
+                    .iter()
+                    .map(|k| match k {
+                        PathKey::Index => "[_]".to_string(),
+                        PathKey::Field(name) => format!(".{}", name),
+                    })
+                    .collect();
+                format!("StorePath {} ; {}{}", s, f.slots[*s], path)
+            }
+            Op::Binary(b) => format!("Binary {}", b.symbol()),
+            Op::Struct(i) => format!("Struct {}", self.structs[*i].0),
+            Op::Call(i, n) => format!("Call {} {}", self.functions[*i].name, n),
+            Op::CallExtern(i, n) => format!("CallExtern {} {}", self.externs[*i].name, n),
+            Op::Closure(i, slots) => {
+                let names: Vec<&str> = slots.iter().map(|s| f.slots[*s].as_str()).collect();
+                format!("Closure {} [{}]", self.functions[*i].name, names.join(", "))
+            }
+            other => format!("{:?}", other),
+        }
+    }
+}
+
+/// Стековая VM. Локальные переменные всех активных вызовов лежат на том же стеке,
+/// кадр — смещение `base`; глубина рекурсии не ограничена стеком Rust.
+pub struct Vm<'a> {
+    bc: &'a Bytecode,
+    host: HashMap<String, HostFn>,
+    stack: Vec<Value>,
+}
+
+impl<'a> Vm<'a> {
+    pub fn new(bc: &'a Bytecode) -> Self {
+        Self { bc, host: host::libc(), stack: Vec::new() }
+    }
+
+    /// Как Interpreter::register_host: сигнатура — в program.externs, здесь реализация.
+    pub fn register_host(&mut self, f: &HostFunction) {
+        self.host.insert(f.sig.name.clone(), f.call.clone());
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("vm stack underflow")
+    }
+
+    /// Запускает `main` и возвращает её результат или код exit().
+    pub fn run_main(&mut self) -> Result<Outcome, RuntimeError> {
+        let bc = self.bc;
+        let Some(&main) = bc.by_name.get("main") else {
+            return Err(RuntimeError { message: "call to undefined function main".to_string(), span: 0..0, notes: Vec::new() });
+        };
+        // вызывающие кадры: функция, ip возврата, base
+        let mut frames: Vec<(usize, usize, usize)> = Vec::new();
+        let (mut func, mut ip, mut base) = (main, 0, 0);
+        self.stack.clear();
+        self.stack.resize(bc.functions[main].slots.len(), Value::Int(0));
+        loop {
+            let f = &bc.functions[func];
+            let op = &f.code[ip];
+            ip += 1;
+            let fail = |message: String| RuntimeError { message, span: f.spans[ip - 1].clone(), notes: Vec::new() };
+            match op {
+                Op::Int(n) => self.stack.push(Value::Int(*n)),
+                Op::Const(i) => self.stack.push(bc.consts[*i].clone()),
+                Op::Load(s) => {
+                    let v = self.stack[base + s].clone();
+                    self.stack.push(v);
+                }
+                Op::Store(s) => {
+                    let v = self.pop();
+                    self.stack[base + s] = v;
+                }
+                Op::Pop => {
+                    self.pop();
+                }
+                Op::Binary(b) => {
+                    let r = self.pop();
+                    let l = self.pop();
+                    let v = binary(*b, l, r).map_err(fail)?;
+                    self.stack.push(v);
+                }
+                Op::Jump(t) => ip = *t,
+                Op::JumpIfFalse(t) | Op::JumpIfTrue(t) => {
+                    let c = self.pop();
+                    let c = int(&c).map_err(fail)? != 0;
+                    if c == matches!(op, Op::JumpIfTrue(_)) {
+                        ip = *t;
+                    }
+                }
+                Op::Array(n) => {
+                    let items = self.stack.split_off(self.stack.len() - n);
+                    self.stack.push(Value::Array(items));
+                }
+                Op::Repeat(n) => {
+                    let v = self.pop();
+                    self.stack.push(Value::Array(vec![v; *n]));
+                }
+                Op::Index => {
+                    let i = self.pop();
+                    let mut arr = self.pop();
+                    let v = element(&mut arr, int(&i).map_err(fail)?).map_err(fail)?.clone();
+                    self.stack.push(v);
+                }
+                Op::LoadIndex(s) => {
+                    let i = self.pop();
+                    let i = int(&i).map_err(fail)?;
+                    let v = element(&mut self.stack[base + s], i).map_err(fail)?.clone();
+                    self.stack.push(v);
+                }
+                Op::Field(name) => {
+                    let mut obj = self.pop();
+                    let v = field(&mut obj, name).map_err(fail)?.clone();
+                    self.stack.push(v);
+                }
+                Op::LoadField(s, name) => {
+                    let v = field(&mut self.stack[base + s], name).map_err(fail)?.clone();
+                    self.stack.push(v);
+                }
+                Op::StorePath(s, keys) => {
+                    let n = keys.iter().filter(|k| **k == PathKey::Index).count();
+                    let indices = self.stack.split_off(self.stack.len() - n);
+                    let value = self.pop();
+                    let mut indices = indices.iter();
+                    let mut cur = &mut self.stack[base + s];
+                    for key in keys {
+                        cur = match key {
+                            PathKey::Index => {
+                                let i = int(indices.next().expect("index on stack")).map_err(fail)?;
+                                element(cur, i).map_err(fail)?
+                            }
+                            PathKey::Field(name) => field(cur, name).map_err(fail)?,
+                        };
+                    }
+                    *cur = value;
+                }
+                Op::Struct(i) => {
+                    let (name, fields) = &bc.structs[*i];
+                    let values = self.stack.split_off(self.stack.len() - fields.len());
+                    self.stack.push(Value::Struct(name.clone(), fields.iter().cloned().zip(values).collect()));
+                }
+                Op::Call(callee, n) => {
+                    frames.push((func, ip, base));
+                    base = self.stack.len() - n;
+                    func = *callee;
+                    ip = 0;
+                    self.stack.resize(base + bc.functions[func].slots.len(), Value::Int(0));
+                }
+                Op::CallValue(n) => {
+                    let callee = self.stack.remove(self.stack.len() - n - 1);
+                    let Value::Closure(c) = callee else {
+                        return Err(fail(format!("cannot call {}", callee)));
+                    };
+                    let idx = bc.by_name[&c.func.name];
+                    if bc.functions[idx].params != *n {
+                        return Err(fail(format!("{} expects {} arguments, got {}", c.func.name, bc.functions[idx].params, n)));
+                    }
+                    frames.push((func, ip, base));
+                    base = self.stack.len() - n;
+                    func = idx;
+                    ip = 0;
+                    self.stack.extend(c.captured.iter().map(|(_, v)| v.clone()));
+                    self.stack.resize(base + bc.functions[func].slots.len(), Value::Int(0));
+                }
+                // exit() кончает программу, но не процесс: код получает вызывающий run_main
+                Op::CallBuiltin(Builtin::Exit, _) => {
+                    let code = self.pop();
+                    return Ok(Outcome::Exit(int(&code).map_err(fail)? as i32));
+                }
+                Op::CallBuiltin(b, n) => {
+                    let v = self.builtin(*b, *n).map_err(fail)?;
+                    self.stack.push(v);
+                }
+                Op::CallExtern(i, n) => {
+                    let ext = &bc.externs[*i];
+                    let args = self.stack.split_off(self.stack.len() - n);
+                    let Some(h) = self.host.get(&ext.name) else {
+                        return Err(fail(format!("extern fn {} has no host implementation in the vm", ext.name)));
+                    };
+                    let v = host::call(ext, h, args).map_err(fail)?;
+                    self.stack.push(v);
+                }
+                Op::Closure(idx, slots) => {
+                    let captured = slots.iter().map(|s| (f.slots[*s].clone(), self.stack[base + s].clone())).collect();
+                    let func = bc.functions[*idx].ast.clone();
+                    self.stack.push(Value::Closure(Rc::new(Closure { func, captured })));
+                }
+                Op::NoMatch(s) => return Err(fail(format!("no match arm for value {}", self.stack[base + s]))),
+                Op::Return => {
+                    let result = self.pop();
+                    self.stack.truncate(base);
+                    match frames.pop() {
+                        Some((f, i, b)) => {
+                            (func, ip, base) = (f, i, b);
+                            self.stack.push(result);
+                        }
+                        None => return Ok(Outcome::Return(result)),
+                    }
+                }
+            }
+        }
+    }
+
+    fn builtin(&mut self, b: Builtin, n: usize) -> Result<Value, String> {
+        match b {
+            Builtin::Print => {
+                print!("{}", self.pop());
+                io::stdout().flush().expect("flush stdout");
+            }
+            Builtin::Println if n == 1 => println!("{}", self.pop()),
+            Builtin::Println => println!(),
+            Builtin::ReadInt => return Ok(Value::Int(interpreter::read_int(&mut io::stdin().lock()))),
+            Builtin::Exit => unreachable!("exit is handled by run_main"),
+            Builtin::Len => {
+                return match self.pop() {
+                    Value::Array(items) => Ok(Value::Int(items.len() as i64)),
+                    other => Err(format!("len() expects an array, got {}", other)),
+                };
+            }
+        }
+        Ok(Value::Int(0))
+    }
+}
+
+fn int(v: &Value) -> Result<i64, String> {
+    v.as_int().ok_or_else(|| format!("expected integer, got {}", v))
+}
+
+// Семантика операторов — как в Interpreter::eval_expr.
+fn binary(op: BinOp, a: Value, b: Value) -> Result<Value, String> {
+    if matches!((&a, &b), (Value::Str(_), Value::Str(_))) || matches!(a, Value::Null) || matches!(b, Value::Null) {
+        return match op {
+            BinOp::Eq => Ok(Value::Bool(a == b)),
+            BinOp::Ne => Ok(Value::Bool(a != b)),
+            _ => Err(format!("operator {} is not defined for {} and {}", op.symbol(), a, b)),
+        };
+    }
+    let (a, b) = (int(&a)?, int(&b)?);
+    Ok(match op {
+        // как в Interpreter: переполнение по модулю 2^64
+        BinOp::Add => Value::Int(a.wrapping_add(b)),
+        BinOp::Sub => Value::Int(a.wrapping_sub(b)),
+        BinOp::Mul => Value::Int(a.wrapping_mul(b)),
+        BinOp::Div if b == 0 => return Err("division by zero".to_string()),
+        BinOp::Div => Value::Int(a.wrapping_div(b)),
+        BinOp::Eq => Value::Bool(a == b),
+        BinOp::Ne => Value::Bool(a != b),
+        BinOp::Lt => Value::Bool(a < b),
+        BinOp::Le => Value::Bool(a <= b),
+        BinOp::Gt => Value::Bool(a > b),
+        BinOp::Ge => Value::Bool(a >= b),
+    })
+}
+
+fn element(arr: &mut Value, i: i64) -> Result<&mut Value, String> {
+    let items = match arr {
+        Value::Array(items) => items,
+        other => return Err(format!("cannot index into {}", other)),
+    };
+    let len = items.len();
+    usize::try_from(i)
+        .ok()
+        .and_then(|i| items.get_mut(i))
+        .ok_or_else(|| format!("index {} out of bounds for length {}", i, len))
+}
+
+fn field<'v>(obj: &'v mut Value, name: &str) -> Result<&'v mut Value, String> {
+    match obj {
+        Value::Struct(sname, fields) => {
+            let sname = sname.clone();
+            fields
+                .iter_mut()
+                .find(|(f, _)| f == name)
+                .map(|(_, v)| v)
+                .ok_or_else(|| format!("struct {} has no field {}", sname, name))
+        }
+        other => Err(format!("cannot access field {} of {}", name, other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{interpret, load};
+
+    // Результат main (или ошибка с местом) в VM — тот же, что в интерпретаторе.
+    fn same_as_interpreter(src: &str) -> Result<String, String> {
+        let (program, sources) = load(src);
+        let result = compile(&program)
+            .and_then(|bc| Vm::new(&bc).run_main())
+            .map(|v| v.to_string())
+            .map_err(|e| format!("runtime error at {}: {}", sources.location(e.span.start), e));
+        assert_eq!(result, interpret(src).1.map(|v| v.to_string()), "{}", src);
+        result
+    }
+
+    #[test]
+    fn integer_overflow_wraps() {
+        let src = r#"fn main() {
+            let max = 9223372036854775807;
+            let min = 0 - max - 1;
+            (max + 1) + (min - 1) + max * 2 + min / (0 - 1)
+        }"#;
+        // MIN + MAX + (-2) + MIN
+        assert_eq!(same_as_interpreter(src), Ok("9223372036854775805".to_string()));
+        let src = "fn main() {\n    let z = 0;\n    1 / z\n}";
+        assert_eq!(same_as_interpreter(src), Err("runtime error at 3:5: division by zero".to_string()));
+    }
+
+    #[test]
+    fn exit_returns_to_the_host() {
+        let src = "fn f(n: i64) -> i64 { exit(n); 1 }\nfn main() { let x = f(3); x + 1 }";
+        assert_eq!(same_as_interpreter(src), Ok("exit(3)".to_string()));
+        assert_eq!(same_as_interpreter("fn main() { 5 }"), Ok("5".to_string()));
+    }
+
+    #[test]
+    fn for_loops_stop_before_overflow() {
+        let src = r#"fn main() {
+            let max = 9223372036854775807;
+            let min = 0 - max - 1;
+            let n = 0;
+            for i in max - 2..=max { n = n + 1; }
+            for i in min + 5..=min step -3 { n = n + 10; }
+            for i in max - 10..max step 4 { n = n + 100; }
+            n
+        }"#;
+        assert_eq!(same_as_interpreter(src), Ok("323".to_string()));
+    }
+}
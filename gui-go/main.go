@@ -1,13 +1,15 @@
 package main
 
 import (
+	"bufio"
 	"bytes"
 	"encoding/json"
+	"io"
 	"net/http"
-    _"io"
 	"os"
 	"os/exec"
 	"path/filepath"
+	"sync"
 	"text/template"
 )
 
@@ -22,7 +24,14 @@ var page = template.Must(template.New("page").Parse(`
     textarea { width: 100%; height: 300px; font-family: monospace; }
     pre { background:#111; color:#eee; padding: 12px; white-space: pre-wrap; max-height: 400px; overflow:auto; }
     .row { display:flex; gap:8px; margin-top:8px; }
+    .graph { border:1px solid #ccc; padding:8px; overflow:auto; max-height:600px; }
+    .src { background:#fff; color:#000; border:1px solid #ccc; }
+    .cur { background:#ffe680; }
+    .panes { display:flex; gap:16px; }
+    .panes > div { flex:1; }
+    td { font-family: monospace; padding: 0 8px; }
   </style>
+  <script src="https://cdn.jsdelivr.net/npm/@viz-js/viz@3.2.4/lib/viz-standalone.js"></script>
 </head>
 <body>
   <h2>simple-llvm — GUI (demo)</h2>
@@ -31,12 +40,44 @@ var page = template.Must(template.New("page").Parse(`
     <div class="row">
       <button type="button" onclick="run()">Run (interp + IR)</button>
       <button type="button" onclick="compile()">Compile (IR only)</button>
+      <button type="button" onclick="graphs()">Graphs (AST + CFG)</button>
     </div>
   </form>
 
+  <h3>Debugger</h3>
+  <div class="row">
+    breakpoints (lines): <input id="bps" size="12" placeholder="4, 7"/>
+    <button type="button" onclick="debugStart()">Debug</button>
+    <button type="button" onclick="dbg('step')">Step into</button>
+    <button type="button" onclick="dbg('next')">Step over</button>
+    <button type="button" onclick="dbg('out')">Step out</button>
+    <button type="button" onclick="dbg('continue')">Continue</button>
+    <button type="button" onclick="debugStop()">Stop</button>
+  </div>
+  <div class="row">
+    watch: <input id="watch" size="24" placeholder="x + 1"/>
+    <button type="button" onclick="addWatch()">Add</button>
+    <span id="dbgstatus"></span>
+  </div>
+  <div class="panes">
+    <div><pre id="dbgsrc" class="src"></pre></div>
+    <div>
+      <b>Locals</b><table id="locals"></table>
+      <b>Watches</b><table id="watches"></table>
+      <b>Call stack</b><table id="stack"></table>
+    </div>
+  </div>
+  <b>Program output</b>
+  <pre id="dbgout"></pre>
+
   <h3>Output</h3>
   <pre id="out"></pre>
 
+  <h3>CFG</h3>
+  <div id="cfg" class="graph"></div>
+  <h3>AST</h3>
+  <div id="ast" class="graph"></div>
+
 <script>
 async function run() {
   const code = document.getElementById("code").value;
@@ -59,6 +100,114 @@ async function compile() {
   const j = await res.json();
   document.getElementById("out").textContent = j.stdout + "\n" + j.stderr;
 }
+
+// Пошаговая отладка: сервер держит процесс core debug --json, каждый запрос —
+// одна команда; в ответе события отладчика и новый вывод программы.
+let dbgFile = "";
+
+async function debugCall(body) {
+  const res = await fetch("/debug", {
+    method: "POST",
+    headers: {"Content-Type":"application/json"},
+    body: JSON.stringify(body)
+  });
+  const j = await res.json();
+  if (j.file) dbgFile = j.file;
+  document.getElementById("dbgout").textContent += j.output;
+  for (const e of j.events) showEvent(e);
+  if (j.done) status("finished");
+}
+
+function debugStart() {
+  const code = document.getElementById("code").value;
+  const bps = document.getElementById("bps").value.split(/[ ,]+/).filter(s => s).map(Number);
+  document.getElementById("dbgout").textContent = "";
+  showSource(0);
+  debugCall({ src: code, breakpoints: bps });
+}
+
+function debugStop() { debugCall({ stop: true }); }
+function dbg(command, args) { debugCall({ command: Object.assign({ command }, args || {}) }); }
+
+function addWatch() {
+  const expression = document.getElementById("watch").value.trim();
+  if (expression) dbg("watch", { expression });
+}
+
+function status(text) { document.getElementById("dbgstatus").textContent = text; }
+
+function showSource(line) {
+  const el = document.getElementById("dbgsrc");
+  el.textContent = "";
+  document.getElementById("code").value.split("\n").forEach((text, i) => {
+    const div = document.createElement("div");
+    div.textContent = String(i + 1).padStart(4) + " | " + text;
+    if (i + 1 === line) div.className = "cur";
+    el.appendChild(div);
+  });
+}
+
+function table(id, rows) {
+  const el = document.getElementById(id);
+  el.textContent = "";
+  for (const cells of rows) {
+    const tr = el.insertRow();
+    for (const c of cells) tr.insertCell().textContent = c;
+  }
+}
+
+function where(e) { return (e.file === dbgFile ? "" : e.file + ":") + e.line; }
+
+function showEvent(e) {
+  switch (e.event) {
+  case "stopped":
+    status(e.reason + " at " + where(e) + " in " + e.function);
+    showSource(e.file === dbgFile ? e.line : 0);
+    table("locals", e.locals.map(v => [v.name, v.value]));
+    table("watches", e.watches.map(w => [w.expression, w.value]));
+    table("stack", e.stack.map((f, i) => ["#" + i, f.function, where(f)]));
+    break;
+  case "watches":
+    table("watches", e.watches.map(w => [w.expression, w.value]));
+    break;
+  case "exited":
+    showSource(0);
+    status(e.error ? "runtime error at " + where(e) + ": " + e.error : "main => " + e.value);
+    break;
+  case "terminated":
+    showSource(0);
+    status("terminated");
+    break;
+  case "error":
+    status(e.message);
+    break;
+  }
+}
+
+// DOT от core --dot рисует viz.js; без него показываем сам текст графа
+async function graphs() {
+  const code = document.getElementById("code").value;
+  const res = await fetch("/run", {
+    method: "POST",
+    headers: {"Content-Type":"application/json"},
+    body: JSON.stringify({ src: code, mode: "dot" })
+  });
+  const j = await res.json();
+  document.getElementById("out").textContent = j.stderr;
+  for (const id of ["cfg", "ast"]) {
+    const el = document.getElementById(id);
+    el.textContent = "";
+    if (!j[id]) continue;
+    if (window.Viz) {
+      const viz = await Viz.instance();
+      el.appendChild(viz.renderSVGElement(j[id]));
+    } else {
+      const pre = document.createElement("pre");
+      pre.textContent = j[id];
+      el.appendChild(pre);
+    }
+  }
+}
 </script>
 </body>
 </html>
@@ -66,12 +215,230 @@ async function compile() {
 
 type runReq struct {
 	Src  string `json:"src"`
-	Mode string `json:"mode"` // "run" или "ir"
+	Mode string `json:"mode"` // "run", "ir" или "dot"
+}
+
+// Сессия `core debug --json`: одна на сервер. Команда уходит строкой JSON в stdin,
+// ответ на неё — одна строка в stderr; stdout программы копится отдельно.
+type debugSession struct {
+	mu     sync.Mutex
+	cmd    *exec.Cmd
+	stdin  io.WriteCloser
+	events *bufio.Reader
+	output *lockedBuffer
+}
+
+type lockedBuffer struct {
+	mu  sync.Mutex
+	buf bytes.Buffer
+}
+
+func (b *lockedBuffer) Write(p []byte) (int, error) {
+	b.mu.Lock()
+	defer b.mu.Unlock()
+	return b.buf.Write(p)
+}
+
+// всё накопленное с прошлого вызова
+func (b *lockedBuffer) take() string {
+	b.mu.Lock()
+	defer b.mu.Unlock()
+	s := b.buf.String()
+	b.buf.Reset()
+	return s
+}
+
+var (
+	debugMu sync.Mutex
+	session *debugSession
+)
+
+type debugReq struct {
+	// новая сессия: исходник и строки точек останова
+	Src         string `json:"src,omitempty"`
+	Breakpoints []int  `json:"breakpoints,omitempty"`
+	// команда отладчика как есть: {"command": "next"}, {"command": "watch", "expression": "x"}
+	Command json.RawMessage `json:"command,omitempty"`
+	// убить процесс (например, программа зациклилась после continue)
+	Stop bool `json:"stop,omitempty"`
+}
+
+type debugResp struct {
+	Events []json.RawMessage `json:"events"`
+	Output string            `json:"output"`
+	// процесс завершился, сессии больше нет
+	Done bool `json:"done"`
+	// главный файл — чтобы GUI отличал его строки от строк прелюдии
+	File string `json:"file,omitempty"`
+}
+
+func startDebug(coreBin, file string) (*debugSession, error) {
+	cmd := exec.Command(coreBin, "debug", "--json", file)
+	stdin, err := cmd.StdinPipe()
+	if err != nil {
+		return nil, err
+	}
+	stderr, err := cmd.StderrPipe()
+	if err != nil {
+		return nil, err
+	}
+	out := &lockedBuffer{}
+	cmd.Stdout = out
+	if err := cmd.Start(); err != nil {
+		return nil, err
+	}
+	return &debugSession{cmd: cmd, stdin: stdin, events: bufio.NewReader(stderr), output: out}, nil
+}
+
+// Одна строка-событие; конец потока — процесс завершился.
+func (s *debugSession) next() (json.RawMessage, bool) {
+	line, err := s.events.ReadBytes('\n')
+	if err != nil {
+		return nil, false
+	}
+	if !json.Valid(line) {
+		// не JSON — например, ошибка разбора до старта отладчика
+		msg, _ := json.Marshal(map[string]string{"event": "error", "message": string(line)})
+		return msg, true
+	}
+	return json.RawMessage(bytes.TrimSpace(line)), true
+}
+
+func (s *debugSession) send(command []byte) (json.RawMessage, bool) {
+	s.stdin.Write(append(command, '\n'))
+	return s.next()
+}
+
+func eventKind(event json.RawMessage) string {
+	var e struct {
+		Event string `json:"event"`
+	}
+	json.Unmarshal(event, &e)
+	return e.Event
+}
+
+// exited/terminated — последнее событие сессии
+func finished(event json.RawMessage) bool {
+	kind := eventKind(event)
+	return kind == "exited" || kind == "terminated"
+}
+
+func (s *debugSession) kill() {
+	s.cmd.Process.Kill()
+	s.cmd.Wait()
+}
+
+func handleDebug(w http.ResponseWriter, r *http.Request, coreBin string) {
+	var req debugReq
+	if err := json.NewDecoder(r.Body).Decode(&req); err != nil {
+		http.Error(w, err.Error(), 400)
+		return
+	}
+	// прежнюю сессию убиваем без её замка: она может ждать зациклившуюся программу
+	debugMu.Lock()
+	s := session
+	if req.Src != "" || req.Stop {
+		session = nil
+	}
+	debugMu.Unlock()
+	resp := debugResp{Events: []json.RawMessage{}}
+	if s != nil && (req.Src != "" || req.Stop) {
+		s.kill()
+		resp.Output = s.output.take()
+		s = nil
+	}
+	if req.Stop {
+		resp.Done = true
+		w.Header().Set("Content-Type", "application/json")
+		json.NewEncoder(w).Encode(resp)
+		return
+	}
+
+	if req.Src != "" {
+		file := filepath.Join(os.TempDir(), "simple_llvm_debug.rs")
+		if err := os.WriteFile(file, []byte(req.Src), 0644); err != nil {
+			http.Error(w, err.Error(), 500)
+			return
+		}
+		var err error
+		if s, err = startDebug(coreBin, file); err != nil {
+			http.Error(w, err.Error(), 500)
+			return
+		}
+		resp.File = file
+		debugMu.Lock()
+		session = s
+		debugMu.Unlock()
+	}
+	if s == nil {
+		http.Error(w, "no debug session, start one first", 400)
+		return
+	}
+
+	s.mu.Lock()
+	defer s.mu.Unlock()
+	alive := true
+	var event json.RawMessage
+	if req.Src != "" {
+		// первое событие — остановка на входе в main (или ошибка sema)
+		if event, alive = s.next(); alive {
+			resp.Events = append(resp.Events, event)
+			// ошибка до старта: core печатает её и завершается
+			alive = eventKind(event) != "error"
+		}
+		for _, line := range req.Breakpoints {
+			if !alive || finished(event) {
+				break
+			}
+			cmd, _ := json.Marshal(map[string]any{"command": "break", "line": line})
+			if event, alive = s.send(cmd); alive {
+				resp.Events = append(resp.Events, event)
+			}
+		}
+	} else if len(req.Command) > 0 {
+		var cmd bytes.Buffer
+		json.Compact(&cmd, req.Command)
+		if event, alive = s.send(cmd.Bytes()); alive {
+			resp.Events = append(resp.Events, event)
+		}
+	}
+	if !alive || finished(event) {
+		resp.Done = true
+		debugMu.Lock()
+		current := session == s
+		if current {
+			session = nil
+		}
+		debugMu.Unlock()
+		// убитую сессию дожидается kill
+		if current {
+			s.cmd.Wait()
+		}
+	}
+	resp.Output += s.output.take()
+	w.Header().Set("Content-Type", "application/json")
+	json.NewEncoder(w).Encode(resp)
 }
 
 type runResp struct {
 	Stdout string `json:"stdout"`
 	Stderr string `json:"stderr"`
+	// только для mode "dot": графы в формате Graphviz
+	Ast string `json:"ast,omitempty"`
+	Cfg string `json:"cfg,omitempty"`
+}
+
+// core с аргументами; ошибка запуска дописывается в stderr
+func runCore(coreBin string, args ...string) (string, string) {
+	cmd := exec.Command(coreBin, args...)
+	var outBuf, errBuf bytes.Buffer
+	cmd.Stdout = &outBuf
+	cmd.Stderr = &errBuf
+	stderr := ""
+	if err := cmd.Run(); err != nil {
+		stderr = "\nrun error: " + err.Error()
+	}
+	return outBuf.String(), errBuf.String() + stderr
 }
 
 func main() {
@@ -108,6 +475,16 @@ func main() {
 			return
 		}
 
+		// графы: два запуска core --dot, stdout каждого — готовый DOT
+		if req.Mode == "dot" {
+			ast, astErr := runCore(coreBin, "--dot", "ast", tmpfile)
+			cfg, cfgErr := runCore(coreBin, "--dot", "cfg", tmpfile)
+			resp := runResp{Stderr: astErr + cfgErr, Ast: ast, Cfg: cfg}
+			w.Header().Set("Content-Type", "application/json")
+			json.NewEncoder(w).Encode(resp)
+			return
+		}
+
 		// Запускаем core с аргументом — путь до временного файла
 		cmd := exec.Command(coreBin, tmpfile)
 		var outBuf, errBuf bytes.Buffer
@@ -128,6 +505,15 @@ func main() {
 		json.NewEncoder(w).Encode(resp)
 	})
 
+	http.HandleFunc("/debug", func(w http.ResponseWriter, r *http.Request) {
+		coreBin := filepath.Join("..", "core", "target", "debug", "core")
+		if _, err := os.Stat(coreBin); os.IsNotExist(err) {
+			http.Error(w, "core binary not found at "+coreBin+". Build with `cargo build` in core/.", 500)
+			return
+		}
+		handleDebug(w, r, coreBin)
+	})
+
 	addr := ":8080"
 	println("GUI server listening on http://localhost" + addr)
 	http.ListenAndServe(addr, nil)